@@ -4,9 +4,21 @@ use std::env;
 use std::fs;
 use std::path::Path;
 
+// Icon name marking a text-only key
+const NO_ICON: &str = "none";
+
+// Icon specs drawn from a font glyph instead of md-icons, e.g. "nf:nf-fa-wifi" or "u:f1eb"
+fn is_glyph_spec(spec: &str) -> bool {
+    spec.starts_with("nf:") || spec.starts_with("u:")
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct Config {
     menu: Menu,
+    #[serde(default)]
+    back_icon: Option<String>,
+    #[serde(default)]
+    default_icon_style: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -15,8 +27,9 @@ struct Menu {
     buttons: Vec<Button>,
 }
 
+// Mirrors the config: buttons may also be written as a single key naming their type
 #[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
+#[serde(remote = "Self", tag = "type", rename_all = "snake_case")]
 enum Button {
     Command {
         name: String,
@@ -25,9 +38,12 @@ enum Button {
         args: Vec<String>,
         #[serde(default)]
         icon: Option<String>,
+        #[serde(default)]
+        icon_map: Option<IconMap>,
     },
     Menu {
         name: String,
+        #[serde(default)]
         buttons: Vec<Button>,
         #[serde(default)]
         icon: Option<String>,
@@ -52,13 +68,103 @@ enum Button {
         off_icon: Option<String>,
         #[serde(default)]
         icon: Option<String>, // Fallback icon when state is unknown
+        #[serde(default)]
+        pending_icon: Option<String>,
+        #[serde(default)]
+        auto_style_by_state: bool,
+    },
+    Reload {
+        #[serde(default = "default_reload_name")]
+        name: String,
+        #[serde(default)]
+        icon: Option<String>,
+    },
+    Brightness {
+        name: String,
+        #[serde(default)]
+        icon: Option<String>,
+    },
+    Notification {
+        name: String,
+        #[serde(default)]
+        icon: Option<String>,
+    },
+    GroupAction {
+        name: String,
+        group: String,
+        action: String,
+        #[serde(default)]
+        icon: Option<String>,
+    },
+    Scene {
+        name: String,
+        scene: String,
+        #[serde(default)]
+        icon: Option<String>,
+    },
+    Match {
+        name: String,
+        #[serde(default)]
+        icon: Option<String>,
+    },
+    Branch {
+        name: String,
+        on_success: Menu,
+        on_failure: Menu,
+        #[serde(default)]
+        icon: Option<String>,
+    },
+    Input {
+        name: String,
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        icon: Option<String>,
     },
 }
 
+impl Serialize for Button {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Button::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Button {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = match serde_yaml::Value::deserialize(deserializer)? {
+            serde_yaml::Value::Mapping(outer) if outer.len() == 1 && !outer.contains_key("type") => {
+                let (kind, settings) = outer.into_iter().next().unwrap();
+                match settings {
+                    serde_yaml::Value::Mapping(mut button) => {
+                        button.insert("type".into(), kind);
+                        serde_yaml::Value::Mapping(button)
+                    }
+                    settings => serde_yaml::Value::Mapping(serde_yaml::Mapping::from_iter([(kind, settings)])),
+                }
+            }
+            value => value,
+        };
+        Button::deserialize(value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct IconMap {
+    #[serde(default)]
+    success: Option<String>,
+    #[serde(default)]
+    failure: Option<String>,
+}
+
 fn default_back_name() -> String {
     "Back".to_string()
 }
 
+fn default_reload_name() -> String {
+    "Reload".to_string()
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "mode", rename_all = "snake_case")]
 enum ToggleMode {
@@ -77,6 +183,22 @@ enum ToggleMode {
         #[serde(default)]
         off_args: Vec<String>,
     },
+    /// On while a flag file exists
+    File {
+        path: String,
+    },
+    /// A service with start, stop and status commands
+    Service {
+        start: String,
+        #[serde(default)]
+        start_args: Vec<String>,
+        stop: String,
+        #[serde(default)]
+        stop_args: Vec<String>,
+        status: String,
+        #[serde(default)]
+        status_args: Vec<String>,
+    },
 }
 
 #[derive(Debug)]
@@ -95,16 +217,38 @@ fn extract_icons_from_menu(menu: &Menu) -> Vec<String> {
 fn extract_icons_from_buttons(buttons: &[Button], icons: &mut Vec<String>) {
     for button in buttons {
         match button {
-            Button::Command { icon, .. }
-            | Button::Menu { icon, .. }
-            | Button::Back { icon, .. } => {
+            Button::Command { icon, icon_map, .. } => {
+                if let Some(icon_name) = icon {
+                    icons.push(icon_name.clone());
+                }
+                if let Some(icon_map) = icon_map {
+                    icons.extend(icon_map.success.iter().cloned());
+                    icons.extend(icon_map.failure.iter().cloned());
+                }
+            }
+            Button::Menu { icon, .. }
+            | Button::Back { icon, .. }
+            | Button::Reload { icon, .. }
+            | Button::Brightness { icon, .. }
+            | Button::Notification { icon, .. }
+            | Button::GroupAction { icon, .. }
+            | Button::Scene { icon, .. }
+            | Button::Match { icon, .. }
+            | Button::Branch { icon, .. }
+            | Button::Input { icon, .. } => {
                 if let Some(icon_name) = icon {
                     icons.push(icon_name.clone());
                 }
             }
-            Button::Toggle { icon, on_icon, off_icon, .. } => {
+            Button::Toggle { icon, on_icon, off_icon, pending_icon, auto_style_by_state, .. } => {
                 if let Some(icon_name) = icon {
                     icons.push(icon_name.clone());
+                    // Both styles the icon switches between with the state
+                    if *auto_style_by_state && icon_name != NO_ICON && !is_glyph_spec(icon_name) {
+                        let name = icon_name.split_once(':').map_or(icon_name.as_str(), |(_, name)| name);
+                        icons.push(format!("filled:{}", name));
+                        icons.push(format!("outlined:{}", name));
+                    }
                 }
                 if let Some(icon_name) = on_icon {
                     icons.push(icon_name.clone());
@@ -112,18 +256,26 @@ fn extract_icons_from_buttons(buttons: &[Button], icons: &mut Vec<String>) {
                 if let Some(icon_name) = off_icon {
                     icons.push(icon_name.clone());
                 }
+                if let Some(icon_name) = pending_icon {
+                    icons.push(icon_name.clone());
+                }
             }
         }
 
         // Recurse into submenus
-        if let Button::Menu { buttons, .. } = button {
-            extract_icons_from_buttons(buttons, icons);
+        match button {
+            Button::Menu { buttons, .. } => extract_icons_from_buttons(buttons, icons),
+            Button::Branch { on_success, on_failure, .. } => {
+                extract_icons_from_buttons(&on_success.buttons, icons);
+                extract_icons_from_buttons(&on_failure.buttons, icons);
+            }
+            _ => {}
         }
     }
 }
 
-// Parse icon specification (e.g., "terminal" or "sharp:home")
-fn parse_icon_spec(spec: &str) -> IconSpec {
+// Parse icon specification (e.g., "terminal" or "sharp:home"), bare names taking `default_style`
+fn parse_icon_spec(spec: &str, default_style: &str) -> IconSpec {
     if let Some(colon_pos) = spec.find(':') {
         IconSpec {
             style: spec[..colon_pos].to_string(),
@@ -131,7 +283,7 @@ fn parse_icon_spec(spec: &str) -> IconSpec {
         }
     } else {
         IconSpec {
-            style: "filled".to_string(),
+            style: default_style.to_string(),
             name: spec.to_string(),
         }
     }
@@ -161,8 +313,14 @@ fn main() {
     let config: Config = serde_yaml::from_str(&config_yaml).expect("Failed to parse config.yaml");
 
     // Extract all icons from the menu
-    let icon_strings = extract_icons_from_menu(&config.menu);
-    let icon_specs: Vec<IconSpec> = icon_strings.iter().map(|s| parse_icon_spec(s)).collect();
+    let mut icon_strings = extract_icons_from_menu(&config.menu);
+    // The default back icon takes the default style too
+    icon_strings.push(config.back_icon.clone().unwrap_or_else(|| "arrow_back".to_string()));
+    // Font glyphs are drawn at runtime and need no md-icons constant
+    icon_strings.retain(|icon| icon != NO_ICON && !is_glyph_spec(icon));
+    // Bare names are prefixed with `default_icon_style` when the config is loaded
+    let default_style = config.default_icon_style.as_deref().unwrap_or("filled");
+    let icon_specs: Vec<IconSpec> = icon_strings.iter().map(|s| parse_icon_spec(s, default_style)).collect();
 
     // Group icons by style and collect unique names
     let mut icons_by_style: HashMap<String, HashSet<String>> = HashMap::new();
@@ -176,13 +334,20 @@ fn main() {
     // Add default icons to ensure they're always available
     let default_icons = vec![
         "terminal", "home", "arrow_back", "settings",
-        "toggle_on", "toggle_off", "help", "wifi", "wifi_off"
+        "toggle_on", "toggle_off", "help", "wifi", "wifi_off",
+        "refresh", "check", "error", "sync",
+        "dialpad", "backspace", "close", "movie", "schedule", "hourglass_empty", "block",
+        "brightness_low", "brightness_medium", "brightness_high", "alt_route",
+        "notifications", "call_split"
     ];
-    for icon in default_icons {
-        icons_by_style
-            .entry("filled".to_string())
-            .or_insert_with(HashSet::new)
-            .insert(icon.to_string());
+    // Outlined too, for toggles rendering them with `auto_style_by_state`
+    for style in ["filled", "outlined"] {
+        for icon in &default_icons {
+            icons_by_style
+                .entry(style.to_string())
+                .or_insert_with(HashSet::new)
+                .insert(icon.to_string());
+        }
     }
 
     let mut generated = String::new();
@@ -191,9 +356,9 @@ fn main() {
     generated.push_str("// DO NOT EDIT MANUALLY\n\n");
     generated.push_str("use streamdeck_oxide::md_icons;\n\n");
 
-    // Generate resolve functions for each style
+    // Generate lookup and resolve functions for each style
     for (style, icon_names) in &icons_by_style {
-        let fn_name = format!("resolve_{}_icon", style);
+        let fn_name = format!("lookup_{}_icon", style);
         generated.push_str(&format!(
             "pub fn {}(const_name: &str) -> Option<&'static str> {{\n",
             fn_name
@@ -216,27 +381,61 @@ fn main() {
             ));
         }
 
-        // Add default case
+        generated.push_str("        _ => None,\n");
+        generated.push_str("    }\n");
+        generated.push_str("}\n\n");
+
+        generated.push_str(&format!(
+            "pub fn resolve_{}_icon(const_name: &str) -> Option<&'static str> {{\n",
+            style
+        ));
+        generated.push_str(&format!("    {}(const_name).or_else(|| {{\n", fn_name));
         generated.push_str(&format!(
-            "        _ => {{\n            tracing::warn!(\"Unknown {} icon: {{}}, using default terminal icon\", const_name);\n",
+            "        tracing::warn!(\"Unknown {} icon: {{}}, using default terminal icon\", const_name);\n",
             style
         ));
 
         // Use terminal as default fallback
         generated.push_str(&format!(
-            "            Some(md_icons::{}::ICON_TERMINAL)\n",
+            "        Some(md_icons::{}::ICON_TERMINAL)\n",
             style
         ));
-
-        generated.push_str("        }\n");
-        generated.push_str("    }\n");
+        generated.push_str("    })\n");
         generated.push_str("}\n\n");
     }
 
+    // Generate the strict lookup used when unknown icons must not fall back
+    generated.push_str("pub fn lookup_icon(icon_name: &str) -> Option<&'static str> {\n");
+    generated.push_str("    if let Some(glyph) = lookup_glyph(icon_name) {\n");
+    generated.push_str("        return glyph;\n");
+    generated.push_str("    }\n");
+    generated.push_str("    let (style, name) = icon_name.split_once(':').unwrap_or((\"filled\", icon_name));\n");
+    generated.push_str("    let const_name = name.to_uppercase();\n");
+    generated.push_str("    match style {\n");
+    for style in icons_by_style.keys() {
+        generated.push_str(&format!(
+            "        \"{}\" => lookup_{}_icon(&const_name),\n",
+            style, style
+        ));
+    }
+    generated.push_str("        _ => None,\n");
+    generated.push_str("    }\n");
+    generated.push_str("}\n\n");
+
     // Generate the main resolve_icon function
     generated
         .push_str("pub fn resolve_icon(icon_name: Option<&String>) -> Option<&'static str> {\n");
     generated.push_str("    let icon_name = icon_name?;\n");
+    generated.push_str(&format!("    if icon_name == \"{}\" {{\n", NO_ICON));
+    generated.push_str("        // Text-only key\n");
+    generated.push_str("        return None;\n");
+    generated.push_str("    }\n");
+    generated.push_str("    if let Some(glyph) = lookup_glyph(icon_name) {\n");
+    generated.push_str("        return glyph.or_else(|| {\n");
+    generated.push_str("            tracing::warn!(\"Unknown glyph: {}, using default terminal icon\", icon_name);\n");
+    generated.push_str("            Some(md_icons::filled::ICON_TERMINAL)\n");
+    generated.push_str("        });\n");
+    generated.push_str("    }\n");
     generated.push_str("    \n");
     generated.push_str(
         "    // Parse icon specification: \"style:name\" or just \"name\" (defaults to filled)\n",
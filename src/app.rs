@@ -0,0 +1,233 @@
+use crate::button::{initialize_toggle_states_from, CommanderContext, CommanderPlugin};
+use crate::config::{Config, RenderSettings};
+use crate::deck::{DeckDevice, Navigation};
+use crate::device::{prepare_device, read_device_info};
+use crate::file_toggle::expand_home;
+use crate::probe_backend::ProbeBackendRegistry;
+use crate::render::next_theme;
+use crate::services::{start_after_first_view, start_services};
+use crate::state_file::load_toggle_states;
+use crate::toggle_state::ToggleStateManager;
+use std::any::{Any, TypeId};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use streamdeck_oxide::elgato_streamdeck::info::Kind;
+use streamdeck_oxide::generic_array::typenum::{U3, U5};
+use streamdeck_oxide::plugins::{PluginContext, PluginNavigation};
+use streamdeck_oxide::ExternalTrigger;
+use tokio::sync::mpsc;
+use tracing::info;
+
+/// Runs the commander on the connected `deck` (of `kind`, with `serial`) until it is
+/// gone: prepares the device, probes the toggles, shows the main menu, then starts the
+/// background services, with `pedal` presses dispatched to the `pedal:` buttons
+pub async fn run_commander(
+    deck: Arc<dyn DeckDevice>,
+    kind: Kind,
+    serial: &str,
+    pedal: Option<Arc<dyn DeckDevice>>,
+    config: Arc<Config>,
+    config_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let device = read_device_info(deck.as_ref(), kind, serial).await;
+    prepare_device(deck.as_ref(), &device, &config).await;
+
+    // Create external trigger channel
+    let (sender, receiver) = mpsc::channel::<Navigation>(1);
+
+    // Create plugin context
+    let toggle_state_manager = ToggleStateManager::new();
+    let probe_backends = ProbeBackendRegistry::new();
+    let state_file = config.state_file.as_deref().map(|path| expand_home(Path::new(path)));
+    let persisted = state_file.as_deref().map(load_toggle_states).unwrap_or_default();
+    initialize_toggle_states_from(&config, &toggle_state_manager, &probe_backends, &persisted).await;
+    let commander_context = Arc::new(CommanderContext {
+        config_path,
+        toggle_state_manager: toggle_state_manager.clone(),
+        navigation_sender: Some(sender.clone()),
+        probe_backends,
+        ..CommanderContext::new(config.clone(), Some(deck.clone()))
+    });
+    let services_context = commander_context.clone();
+    let deck_context = commander_context.clone();
+
+    let context = PluginContext::new(BTreeMap::from([
+        (TypeId::of::<CommanderContext>(), Box::new(commander_context) as Box<dyn Any + Send + Sync>)
+    ]));
+
+    // Send initial navigation to main menu, then start the background services so
+    // none of them redraws the deck before the first render
+    let services = start_after_first_view(
+        &sender,
+        ExternalTrigger::new(
+            PluginNavigation::<U5, U3>::new(CommanderPlugin::new_with_state_manager(config.menu.clone(), toggle_state_manager)),
+            true,
+        ),
+        || start_services(&config, &services_context, pedal),
+    )
+    .await?;
+
+    info!("Starting Stream Deck application...");
+    info!("Press Ctrl+C to exit");
+
+    let result = run_deck(deck.as_ref(), context, deck_context, receiver).await;
+    services.shutdown();
+    result
+}
+
+/// Runs the deck renderer with the theme `theme_when` picks from the toggle states.
+/// The renderer only takes its theme when it starts, so whenever a toggle change picks
+/// another theme it is restarted with it and the menu that was shown.
+pub async fn run_deck(
+    deck: &dyn DeckDevice,
+    context: PluginContext,
+    commander: Arc<CommanderContext>,
+    mut navigation: mpsc::Receiver<Navigation>,
+) -> anyhow::Result<()> {
+    let mut changes = commander.toggle_state_manager.subscribe();
+    let mut queued = None;
+    loop {
+        let config = commander.current_config();
+        let theme = commander.deck_theme();
+        let (sender, receiver) = mpsc::channel::<Navigation>(1);
+        let mut renderer = deck.run(RenderSettings { theme, ..config.render.clone() }, context.clone(), receiver);
+        loop {
+            tokio::select! {
+                result = &mut renderer => return result.map_err(|e| anyhow::anyhow!("StreamDeck application error: {}", e)),
+                // Hold one navigation at a time, handing it over once the renderer has room
+                Some(trigger) = navigation.recv(), if queued.is_none() => queued = Some(trigger),
+                Ok(permit) = sender.reserve(), if queued.is_some() => permit.send(queued.take().expect("a queued navigation")),
+                Some(next) = next_theme(&commander, &mut changes, theme) => {
+                    info!("Switching the deck to the {:?} theme", next);
+                    break;
+                }
+            }
+        }
+        // Dropping the renderer stops it; the next one starts on the menu shown now,
+        // unless a navigation is still waiting to be shown
+        if queued.is_none() {
+            queued = Some(ExternalTrigger::new(PluginNavigation::<U5, U3>::new(commander.active_plugin()), true));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ThemeName;
+    use crate::deck::MockDeck;
+    use crate::test_support::TempDir;
+    use crate::toggle_state::ToggleState;
+    use streamdeck_oxide::elgato_streamdeck::DeviceStateUpdate;
+
+    fn config(yaml: &str) -> Arc<Config> {
+        Arc::new(serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_run_commander_shows_main_menu_on_deck() {
+        let deck = Arc::new(MockDeck::showing(1));
+        let config = config(
+            r#"
+brightness: 40
+render:
+  theme: dark
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Hello"
+      command: "true"
+"#,
+        );
+        run_commander(deck.clone(), Kind::Mk2, "MOCK", None, config, None).await.unwrap();
+
+        assert_eq!(deck.brightness_history(), vec![40]);
+        assert_eq!(deck.views(), vec![ThemeName::Dark]);
+    }
+
+    #[tokio::test]
+    async fn test_run_commander_presses_keys_on_deck() {
+        let dir = TempDir::new("app-presses");
+        let marker = dir.join("pressed");
+        let deck = Arc::new(MockDeck::showing_with_events(
+            3,
+            vec![
+                vec![DeviceStateUpdate::ButtonDown(1), DeviceStateUpdate::ButtonUp(1)],
+                vec![DeviceStateUpdate::ButtonDown(0), DeviceStateUpdate::ButtonUp(0)],
+            ],
+        ));
+        let config = config(&format!(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Hello"
+      command: "true"
+    - type: menu
+      name: "Tools"
+      icon: "build"
+      buttons:
+        - type: command
+          name: "Touch"
+          command: "touch"
+          args: ["{}"]
+          icon_map: {{ success: "check" }}
+"#,
+            marker.display()
+        ));
+        run_commander(deck.clone(), Kind::Mk2, "MOCK", None, config, None).await.unwrap();
+
+        // The main menu, the submenu its Tools key opened, then the submenu re-rendered
+        // once the pressed command finished
+        let views = deck.shown_keys();
+        assert_eq!(views.len(), 3);
+        let labels = |view: usize| views[view].iter().map(|key| (key.col, key.row, key.label.clone())).collect::<Vec<_>>();
+        assert_eq!(labels(0), vec![(0, 0, "Hello".to_string()), (1, 0, "Tools".to_string())]);
+        let icon = |name: &str| crate::icons::resolve_icon(Some(&name.to_string()));
+        assert_eq!(views[0][1].icon, icon("build"));
+        assert_eq!(labels(1), vec![(0, 0, "Touch".to_string()), (4, 2, "Back".to_string())]);
+        assert_eq!(labels(2), labels(1));
+        // The pressed key ran its command, and its icon shows the outcome
+        assert!(marker.exists());
+        assert_eq!(views[1][0].icon, None);
+        assert_eq!(views[2][0].icon, icon("check"));
+    }
+
+    #[tokio::test]
+    async fn test_run_deck_restarts_renderer_on_theme_change() {
+        let deck = MockDeck::showing(2);
+        let config = config(
+            r#"
+theme_when:
+  - { toggle: "VPN", state: on, theme: dark }
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "VPN"
+      mode: single
+      command: "true"
+"#,
+        );
+        let commander = Arc::new(CommanderContext::new(config.clone(), None));
+        let (sender, receiver) = mpsc::channel::<Navigation>(1);
+        let main_menu = CommanderPlugin::new_with_state_manager(config.menu.clone(), commander.toggle_state_manager.clone());
+        sender.send(ExternalTrigger::new(PluginNavigation::<U5, U3>::new(main_menu), true)).await.unwrap();
+
+        let toggle = async {
+            while deck.views().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            commander.toggle_state_manager.set_state("VPN", ToggleState::On);
+        };
+        let context = PluginContext::new(BTreeMap::new());
+        let (result, ()) = tokio::join!(run_deck(&deck, context, commander.clone(), receiver), toggle);
+        result.unwrap();
+
+        // The restarted renderer shows the same menu in the theme the toggle picked
+        assert_eq!(deck.views(), vec![ThemeName::Light, ThemeName::Dark]);
+    }
+}
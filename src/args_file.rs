@@ -0,0 +1,58 @@
+use crate::file_toggle::expand_home;
+use std::path::Path;
+
+/// The arguments listed in an `args_file`: one per line, trimmed, skipping blank lines
+/// and lines starting with `#`
+pub fn parse_args_file(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// `args` followed by the arguments of the file at `args_file`, if set. The file is
+/// read on every call, so edits apply to the next run.
+pub fn command_args(args: &[String], args_file: Option<&str>) -> std::io::Result<Vec<String>> {
+    let mut argv = args.to_vec();
+    if let Some(path) = args_file {
+        let contents = std::fs::read_to_string(expand_home(Path::new(path)))?;
+        argv.extend(parse_args_file(&contents));
+    }
+    Ok(argv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_parse_args_file() {
+        let contents = "# Hosts to sync\n--host\n  alpha.lan  \n\n#--host beta.lan\nwith spaces inside\n";
+        assert_eq!(parse_args_file(contents), vec!["--host", "alpha.lan", "with spaces inside"]);
+        assert!(parse_args_file("\n# only comments\n   \n").is_empty());
+    }
+
+    #[test]
+    fn test_command_args_appends_file() {
+        let dir = TempDir::new("args");
+        let path = dir.join("args");
+        std::fs::write(&path, "# excluded paths\n--exclude\n*.tmp\n\n--exclude\ncache/\n").unwrap();
+        let path_str = path.display().to_string();
+
+        let inline = vec!["-av".to_string()];
+        assert_eq!(
+            command_args(&inline, Some(&path_str)).unwrap(),
+            vec!["-av", "--exclude", "*.tmp", "--exclude", "cache/"]
+        );
+        // Edits apply on the next read
+        std::fs::write(&path, "--dry-run\n").unwrap();
+        assert_eq!(command_args(&inline, Some(&path_str)).unwrap(), vec!["-av", "--dry-run"]);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(command_args(&inline, None).unwrap(), inline);
+        assert_eq!(command_args(&inline, Some(&path_str)).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+}
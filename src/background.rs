@@ -0,0 +1,277 @@
+use crate::config::{Button, Config};
+use crate::error::CommanderError;
+use crate::file_toggle::expand_home;
+use crate::grid::GridSize;
+use crate::icon_layout::IconLayout;
+use base64::Engine;
+use image::ImageFormat;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use tracing::{debug, warn};
+
+/// Part of a menu's background image drawn on one key, in source image pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCrop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The sub-rectangle of a `width` × `height` image behind the key at (`col`, `row`).
+/// The image is split evenly across the grid; when it does not divide evenly, keys
+/// further right and down get the extra pixels, so the slices tile the image exactly.
+pub fn key_crop(width: u32, height: u32, grid: GridSize, col: usize, row: usize) -> KeyCrop {
+    let edge = |size: u32, index: usize, count: usize| (u64::from(size) * index as u64 / count as u64) as u32;
+    let (x, y) = (edge(width, col, grid.columns), edge(height, row, grid.rows));
+    KeyCrop {
+        x,
+        y,
+        width: edge(width, col + 1, grid.columns) - x,
+        height: edge(height, row + 1, grid.rows) - y,
+    }
+}
+
+/// A menu's background image sliced into one PNG per key of the grid
+#[derive(Debug)]
+pub struct MenuBackground {
+    grid: GridSize,
+    /// `data:` URIs of the slices, in key order
+    tiles: Vec<String>,
+    /// Icons drawn over a slice, by key index and icon. They are leaked, as the deck
+    /// keeps `'static` icons, so each image leaks one per key and icon it is drawn with.
+    composites: Mutex<HashMap<(usize, usize), &'static str>>,
+}
+
+impl MenuBackground {
+    /// Loads the image at `path` and slices it across `grid`
+    pub fn load(path: &Path, grid: GridSize) -> Result<Self, CommanderError> {
+        let image = image::open(path).map_err(|e| CommanderError::Background(format!("{}: {}", path.display(), e)))?;
+        debug!("Slicing {}x{} background {} across the keys", image.width(), image.height(), path.display());
+        let tiles = (0..grid.key_count())
+            .map(|index| {
+                let (col, row) = grid.position(index);
+                let crop = key_crop(image.width(), image.height(), grid, col, row);
+                let mut png = Vec::new();
+                image
+                    .crop_imm(crop.x, crop.y, crop.width, crop.height)
+                    .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                    .map_err(|e| CommanderError::Background(format!("{}: {}", path.display(), e)))?;
+                Ok(format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png)))
+            })
+            .collect::<Result<_, CommanderError>>()?;
+        Ok(Self {
+            grid,
+            tiles,
+            composites: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Draws the key's slice of the background under `icon`, or on its own for a
+    /// text-only key
+    pub fn under(&self, col: usize, row: usize, icon: Option<&'static str>) -> Option<&'static str> {
+        let index = row * self.grid.columns + col;
+        let tile = self.tiles.get(index)?;
+        let key = (index, icon.map_or(0, |icon| icon.as_ptr() as usize));
+        let mut composites = self.composites.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(*composites.entry(key).or_insert_with(|| {
+            let svg = format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 100 100\"><image width=\"100\" height=\"100\" preserveAspectRatio=\"none\" href=\"{}\"/>{}</svg>",
+                tile,
+                icon.map(|icon| IconLayout::default().transform_svg(icon)).unwrap_or_default()
+            );
+            Box::leak(svg.into_boxed_str())
+        }))
+    }
+}
+
+/// The background images of a config's menus, keyed by the path the menus give. Each
+/// is loaded and sliced once, when the config is loaded or reloaded, so building a view
+/// does no file I/O.
+#[derive(Debug, Default)]
+pub struct MenuBackgrounds {
+    images: RwLock<HashMap<String, Arc<MenuBackground>>>,
+}
+
+impl MenuBackgrounds {
+    /// Loads the background image of every menu of `config`, sliced across `grid`.
+    /// An image that cannot be loaded is logged and its menus keep plain keys.
+    pub fn load(config: &Config, grid: GridSize) -> Self {
+        Self {
+            images: RwLock::new(load_backgrounds(config, grid)),
+        }
+    }
+
+    /// Replaces the backgrounds with those of `config`, reading every image again so
+    /// edited ones are picked up; images no menu names any more are dropped
+    pub fn replace(&self, config: &Config, grid: GridSize) {
+        let images = load_backgrounds(config, grid);
+        *self.images.write().unwrap_or_else(PoisonError::into_inner) = images;
+    }
+
+    /// The background loaded for the menus naming `path`
+    pub fn get(&self, path: &str) -> Option<Arc<MenuBackground>> {
+        self.images.read().unwrap_or_else(PoisonError::into_inner).get(path).cloned()
+    }
+}
+
+fn load_backgrounds(config: &Config, grid: GridSize) -> HashMap<String, Arc<MenuBackground>> {
+    let mut paths: BTreeSet<&str> = config.menu.background_image.as_deref().into_iter().collect();
+    config.menu.walk_buttons(|button, _| match button {
+        Button::Menu { background_image, .. } => paths.extend(background_image.as_deref()),
+        Button::Branch { on_success, on_failure, .. } => {
+            paths.extend(on_success.background_image.as_deref());
+            paths.extend(on_failure.background_image.as_deref());
+        }
+        _ => {}
+    });
+    paths
+        .into_iter()
+        .filter_map(|path| match MenuBackground::load(&expand_home(Path::new(path)), grid) {
+            Ok(background) => Some((path.to_string(), Arc::new(background))),
+            Err(e) => {
+                warn!("Failed to load background image: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The icon layout of one key, drawing the key's slice of the menu background, if the
+/// menu has one, under the laid out icon
+pub struct KeyIconLayout<'a> {
+    pub layout: &'a IconLayout,
+    pub background: Option<&'a MenuBackground>,
+    pub col: usize,
+    pub row: usize,
+}
+
+impl KeyIconLayout<'_> {
+    /// Lays out and rotates the icon like [`IconLayout::apply_rotated`], then adds the background
+    pub fn apply_rotated(&self, icon: Option<&'static str>, rotate: u16) -> Option<&'static str> {
+        let icon = self.layout.apply_rotated(icon, rotate);
+        match self.background {
+            Some(background) => background.under(self.col, self.row, icon),
+            None => icon,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_key_crop() {
+        let grid = GridSize { columns: 5, rows: 3 };
+        assert_eq!(key_crop(500, 300, grid, 0, 0), KeyCrop { x: 0, y: 0, width: 100, height: 100 });
+        assert_eq!(key_crop(500, 300, grid, 2, 1), KeyCrop { x: 200, y: 100, width: 100, height: 100 });
+        assert_eq!(key_crop(500, 300, grid, 4, 2), KeyCrop { x: 400, y: 200, width: 100, height: 100 });
+
+        // A non-square image stretches, and uneven sizes spread the extra pixels
+        assert_eq!(key_crop(1920, 1080, grid, 1, 2), KeyCrop { x: 384, y: 720, width: 384, height: 360 });
+        assert_eq!(key_crop(502, 301, grid, 0, 0), KeyCrop { x: 0, y: 0, width: 100, height: 100 });
+        assert_eq!(key_crop(502, 301, grid, 4, 2), KeyCrop { x: 401, y: 200, width: 101, height: 101 });
+    }
+
+    #[test]
+    fn test_key_crops_tile_the_image() {
+        let grid = GridSize { columns: 8, rows: 4 };
+        let (width, height) = (1023, 517);
+        let mut covered = 0u64;
+        for index in 0..grid.key_count() {
+            let (col, row) = grid.position(index);
+            let crop = key_crop(width, height, grid, col, row);
+            assert!(crop.x + crop.width <= width && crop.y + crop.height <= height, "{:?}", crop);
+            if col + 1 < grid.columns {
+                assert_eq!(key_crop(width, height, grid, col + 1, row).x, crop.x + crop.width);
+            }
+            if row + 1 < grid.rows {
+                assert_eq!(key_crop(width, height, grid, col, row + 1).y, crop.y + crop.height);
+            }
+            covered += u64::from(crop.width) * u64::from(crop.height);
+        }
+        assert_eq!(covered, u64::from(width) * u64::from(height));
+    }
+
+    #[test]
+    fn test_background_slices_keys() {
+        let dir = TempDir::new("background");
+        let path = dir.join("background.png");
+        // Left half red, right half blue
+        let image = image::RgbImage::from_fn(60, 20, |x, _| if x < 30 { image::Rgb([255, 0, 0]) } else { image::Rgb([0, 0, 255]) });
+        image.save(&path).unwrap();
+        let grid = GridSize { columns: 2, rows: 1 };
+        let background = MenuBackground::load(&path, grid).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let decode = |col: usize| {
+            let uri = background.tiles[col].strip_prefix("data:image/png;base64,").unwrap();
+            let png = base64::engine::general_purpose::STANDARD.decode(uri).unwrap();
+            image::load_from_memory(&png).unwrap().to_rgb8()
+        };
+        assert_eq!((decode(0).dimensions(), decode(0).get_pixel(0, 0).0), ((30, 20), [255, 0, 0]));
+        assert_eq!((decode(1).dimensions(), decode(1).get_pixel(29, 19).0), ((30, 20), [0, 0, 255]));
+
+        // The slice goes under the icon, and text-only keys get the slice alone
+        let icon = "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 24 24\"><path d=\"M0 0h24v24H0z\"/></svg>";
+        let keyed = background.under(1, 0, Some(icon)).unwrap();
+        assert!(keyed.find(&background.tiles[1]).unwrap() < keyed.find("<path").unwrap());
+        assert!(background.under(0, 0, None).unwrap().contains(&background.tiles[0]));
+        assert_eq!(background.under(1, 0, Some(icon)).unwrap().as_ptr(), keyed.as_ptr());
+        assert_eq!(background.under(2, 0, Some(icon)), None);
+
+        assert!(matches!(MenuBackground::load(&path, grid), Err(CommanderError::Background(_))));
+    }
+
+    #[test]
+    fn test_backgrounds_load_with_config() {
+        let dir = TempDir::new("backgrounds");
+        let path = dir.join("background.png");
+        let fill = |color: [u8; 3]| image::RgbImage::from_pixel(10, 10, image::Rgb(color)).save(&path).unwrap();
+        let config = |menu_background: &str| -> Config {
+            serde_yaml::from_str(&format!(
+                r#"
+menu:
+  name: "Main"
+  background_image: "{}"
+  buttons:
+    - type: menu
+      name: "Sub"
+      background_image: "{}"
+      buttons: []
+"#,
+                menu_background,
+                dir.join("missing.png").display()
+            ))
+            .unwrap()
+        };
+        let key = path.display().to_string();
+        let grid = GridSize { columns: 1, rows: 1 };
+        let first_pixel = |backgrounds: &MenuBackgrounds| {
+            let background = backgrounds.get(&key).unwrap();
+            let uri = background.tiles[0].strip_prefix("data:image/png;base64,").unwrap();
+            let png = base64::engine::general_purpose::STANDARD.decode(uri).unwrap();
+            image::load_from_memory(&png).unwrap().to_rgb8().get_pixel(0, 0).0
+        };
+
+        fill([255, 0, 0]);
+        let backgrounds = MenuBackgrounds::load(&config(&key), grid);
+        assert_eq!(first_pixel(&backgrounds), [255, 0, 0]);
+        // An image that cannot be loaded leaves its menu without a background
+        assert!(backgrounds.get(&dir.join("missing.png").display().to_string()).is_none());
+
+        // An edited image shows once the config is reloaded, not before
+        fill([0, 0, 255]);
+        assert_eq!(first_pixel(&backgrounds), [255, 0, 0]);
+        backgrounds.replace(&config(&key), grid);
+        assert_eq!(first_pixel(&backgrounds), [0, 0, 255]);
+
+        // Images the reloaded config no longer names are dropped
+        backgrounds.replace(&config(&dir.join("other.png").display().to_string()), grid);
+        assert!(backgrounds.get(&key).is_none());
+    }
+}
@@ -1,10 +1,42 @@
-use crate::config::{Button, Config, Menu};
+use crate::args_file::command_args;
+use crate::background::{KeyIconLayout, MenuBackgrounds};
+use crate::config::{load_config_from, Button, Config, DynamicMenu, MatchCase, MatchCommand, Menu, MenuSort, StateSource, ThemeName, ToggleMode};
+use crate::cooldown::Cooldowns;
+use crate::delay::DelayedCommands;
+use crate::deck::DeckDevice;
+use crate::device::{BrightnessControl, MAX_BRIGHTNESS};
+use crate::error::CommanderError;
+use crate::events::{EventBus, PressEvent};
+use crate::file_toggle::probe_file;
+use crate::grid::{GridSize, DECK_GRID};
+use crate::handler::{await_command, spawn_handler, ActionOutcome};
+use crate::history::{recent_buttons, Execution, ExecutionHistory};
+use crate::hooks::run_hook;
+use crate::idle::ActivityTracker;
+use crate::probe::{execute_probe_command_with_spawn, ProbeContext};
+use crate::probe_backend::{ProbeBackendRegistry, ProbeRequest};
+use crate::queue::CommandQueues;
+use crate::render::select_theme;
+use crate::expand::{expand_all_args, ExpandContext};
+use crate::icon_layout::{highlight_icon, IconLayout};
 use crate::icons;
-use crate::toggle_command::execute_toggle_command;
-use crate::toggle_icons::resolve_toggle_icon;
-use crate::toggle_state::ToggleStateManager;
-use std::{process::Stdio, sync::Arc};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use crate::keypad::KeypadPlugin;
+use crate::label::LabelConfig;
+use crate::locks::ButtonLocks;
+use crate::notification::{render_notification_text, ActivitySummary, DesktopNotification, DesktopNotifier, Notifier};
+use crate::last_result::{CommandOutcome, LastResultManager};
+use crate::result_view::{last_output_line, show_transient_result, show_transient_result_for};
+use crate::retry::RetryPolicy;
+use crate::scene::restore_scene;
+use crate::runtime_vars::RuntimeVars;
+use crate::spawn::{log_command_output, next_run_id, SpawnConfig};
+use crate::toggle_command::{execute_toggle_command_with_config, read_to_end, ToggleCommandConfig, ToggleCommandResult};
+use crate::toggle_group::{execute_group_action_with_config, GroupActionConfig, ToggleGroupRegistry};
+use crate::toggle_icons::{get_toggle_label_localized, get_toggle_sublabel, resolve_button_icon, resolve_command_icon, resolve_toggle_icon};
+use crate::toggle_state::{ToggleState, ToggleStateManager};
+use chrono::Local;
+use std::{borrow::Cow, collections::HashMap, path::PathBuf, process::Stdio, sync::{Arc, PoisonError, RwLock}, time::{Duration, Instant}};
+use tokio::task::JoinHandle;
 use streamdeck_oxide::{
     generic_array::typenum::{U3, U5},
     plugins::{Plugin, PluginContext, PluginNavigation},
@@ -14,9 +46,95 @@ use streamdeck_oxide::{
         View, 
     },
 };
-use tokio::process::Command;
 use tracing::{debug, error, info, warn};
 
+/// Key of the automatic back button, the last key of the deck's grid
+pub const BACK_BUTTON_POSITION: (usize, usize) = DECK_GRID.back_button_position();
+
+/// Icon of a button outside its `active_hours`
+pub const DISABLED_ICON: &str = "block";
+
+/// Label and icon of the back button added to every submenu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackButtonStyle {
+    pub label: String,
+    /// Icon name, resolved like any button icon
+    pub icon: String,
+    /// Color of the border drawn around the icon, if any
+    pub highlight: Option<String>,
+}
+
+impl Default for BackButtonStyle {
+    fn default() -> Self {
+        Self {
+            label: "Back".to_string(),
+            icon: "arrow_back".to_string(),
+            highlight: None,
+        }
+    }
+}
+
+impl BackButtonStyle {
+    /// Builds the back button style from the global configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            label: config.back_label.clone(),
+            icon: config.back_icon.clone(),
+            highlight: config.back_highlight.clone(),
+        }
+    }
+
+    /// The back button's icon laid out with `layout`, with the highlight border if set
+    pub fn render_icon(&self, layout: &IconLayout) -> Option<&'static str> {
+        let icon = layout.apply(icons::resolve_icon(Some(&self.icon)));
+        match &self.highlight {
+            Some(color) => Some(highlight_icon(icon, color)),
+            None => icon,
+        }
+    }
+}
+
+/// Assigns each button of a menu its (column, row) key on the deck's grid, see [`menu_layout_in`]
+pub fn menu_layout(menu: &Menu) -> Vec<(usize, usize, &Button)> {
+    menu_layout_in(menu, DECK_GRID)
+}
+
+/// Assigns each button of a menu its (column, row) key in order. Menus with `sort: name`
+/// are sorted first, keeping user-defined back buttons last. Otherwise user-defined back
+/// buttons keep their slot; buttons beyond the keys before the grid's reserved back
+/// button key are not shown.
+pub fn menu_layout_in(menu: &Menu, grid: GridSize) -> Vec<(usize, usize, &Button)> {
+    let mut buttons: Vec<&Button> = menu.buttons.iter().collect();
+    if menu.sort == MenuSort::Name {
+        // Stable, so buttons with equal keys keep their config order; `None` sorts last
+        buttons.sort_by_cached_key(|button| (button.sort_key().is_none(), button.sort_key().map(str::to_lowercase)));
+    }
+    buttons
+        .into_iter()
+        .take(grid.menu_button_slots())
+        .enumerate()
+        .map(|(index, button)| {
+            let (col, row) = grid.position(index);
+            (col, row, button)
+        })
+        .collect()
+}
+
+/// A press of a command button, with its args already read, as run by
+/// [`CommanderContext::execute_command_spawned`]
+#[derive(Debug, Clone, Default)]
+pub struct CommandRun {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub no_prefix: bool,
+    pub store_output_as: Option<String>,
+    pub success_exit_codes: Vec<i32>,
+    pub retry: RetryPolicy,
+    /// Wait for the button's earlier runs before starting
+    pub queue: bool,
+}
+
 #[derive(Clone)]
 pub struct CommanderPlugin {
     menu: Menu,
@@ -25,9 +143,508 @@ pub struct CommanderPlugin {
 }
 
 pub struct CommanderContext {
-    pub config: Arc<Config>,
+    pub config: RwLock<Arc<Config>>,
+    pub config_path: Option<PathBuf>,
     pub toggle_state_manager: ToggleStateManager,
+    pub last_result_manager: LastResultManager,
     pub navigation_sender: Option<tokio::sync::mpsc::Sender<ExternalTrigger<PluginNavigation<U5, U3>, U5, U3, PluginContext>>>,
+    pub active_plugin: RwLock<Option<CommanderPlugin>>, // Menu currently shown on the deck
+    pub runtime_vars: RuntimeVars, // Values captured by `store_output_as`
+    pub events: EventBus, // Button presses published to the event socket
+    pub probe_backends: ProbeBackendRegistry, // Backends toggles select with `probe_backend`
+    pub activity: ActivityTracker, // Last key press, for `idle_home_secs`
+    pub delayed_commands: DelayedCommands, // Command buttons waiting out their `delay_ms`
+    pub command_queues: CommandQueues, // Presses of `queue` buttons waiting for the previous run
+    pub button_locks: ButtonLocks, // Locks named by the `lock` of command and toggle buttons
+    pub execution_history: ExecutionHistory, // Latest runs, listed by `dynamic: recent` menus
+    pub cooldowns: Cooldowns, // Command buttons ignoring presses during their `cooldown_ms`
+    pub brightness: BrightnessControl, // Deck brightness changed by brightness buttons
+    pub notifier: Arc<dyn Notifier>, // Shows the notifications of notification buttons
+    pub backgrounds: MenuBackgrounds, // Menu background images, sliced when the config is loaded
+}
+
+impl CommanderContext {
+    /// Creates the context for `config` with no navigation sender and fresh state.
+    /// Brightness buttons change `deck`, starting from the configured `brightness`.
+    pub fn new(config: Arc<Config>, deck: Option<Arc<dyn DeckDevice>>) -> Self {
+        let brightness = BrightnessControl::new(deck, config.brightness.unwrap_or(MAX_BRIGHTNESS));
+        let backgrounds = MenuBackgrounds::load(&config, DECK_GRID);
+        Self {
+            config: RwLock::new(config),
+            config_path: None,
+            toggle_state_manager: ToggleStateManager::new(),
+            last_result_manager: LastResultManager::new(),
+            navigation_sender: None,
+            active_plugin: RwLock::new(None),
+            runtime_vars: RuntimeVars::new(),
+            events: EventBus::new(),
+            probe_backends: ProbeBackendRegistry::new(),
+            activity: ActivityTracker::new(),
+            delayed_commands: DelayedCommands::new(),
+            command_queues: CommandQueues::new(),
+            button_locks: ButtonLocks::new(),
+            execution_history: ExecutionHistory::new(),
+            cooldowns: Cooldowns::new(),
+            brightness,
+            notifier: Arc::new(DesktopNotifier),
+            backgrounds,
+        }
+    }
+
+    /// Returns the currently active configuration
+    pub fn current_config(&self) -> Arc<Config> {
+        match self.config.read() {
+            Ok(config) => config.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Re-reads the config from `config_path` (or the embedded config), makes it
+    /// the active config and returns a fresh root plugin for it
+    pub fn reload(&self) -> anyhow::Result<CommanderPlugin> {
+        let config = Arc::new(load_config_from(self.config_path.as_deref())?);
+        info!("Configuration reloaded, main menu: {}", config.menu.name);
+
+        let root = CommanderPlugin::new_with_state_manager(
+            config.menu.clone(),
+            self.toggle_state_manager.clone(),
+        );
+        self.backgrounds.replace(&config, DECK_GRID);
+        match self.config.write() {
+            Ok(mut current) => *current = config,
+            Err(poisoned) => *poisoned.into_inner() = config,
+        }
+        Ok(root)
+    }
+
+    /// Runs a command button's command with runtime variables expanded in its args,
+    /// recording the outcome and storing the trimmed stdout of a successful run
+    /// under `store_output_as`. Exit codes outside `success_exit_codes` count as
+    /// failures and are retried as `retry` allows.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn run_command(
+        &self,
+        name: &str,
+        command: &str,
+        args: &[String],
+        no_prefix: bool,
+        store_output_as: Option<&str>,
+        success_exit_codes: &[i32],
+        retry: RetryPolicy,
+    ) -> CommandOutcome {
+        self.run_command_with_input(name, command, args, None, no_prefix, store_output_as, success_exit_codes, retry).await
+    }
+
+    /// Like [`CommanderContext::run_command`], also expanding `{input}` to `input`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn run_command_with_input(
+        &self,
+        name: &str,
+        command: &str,
+        args: &[String],
+        input: Option<&str>,
+        no_prefix: bool,
+        store_output_as: Option<&str>,
+        success_exit_codes: &[i32],
+        retry: RetryPolicy,
+    ) -> CommandOutcome {
+        let config = self.current_config();
+        let spawn = SpawnConfig {
+            log_output: config.log_output_for(name),
+            ..SpawnConfig::for_button(&config, no_prefix)
+        };
+        let expand = ExpandContext {
+            vars: Some(&self.runtime_vars),
+            input,
+            secrets: true,
+            ..Default::default()
+        };
+        let _lock = self.button_locks.acquire(name, config.lock_for(name).as_deref()).await;
+        // Keypad entries are left out, as their args only make sense with the input
+        if input.is_none() {
+            self.execution_history.record(Execution {
+                name: name.to_string(),
+                command: command.to_string(),
+                args: args.to_vec(),
+                no_prefix,
+            });
+        }
+        self.run_pre_hook(name).await;
+        let started = Instant::now();
+        let result = retry
+            .run(
+                name,
+                || CommanderPlugin::execute_command(command, args, &expand, &spawn, success_exit_codes),
+                |result| matches!(result, Ok((exit_code, _)) if !success_exit_codes.contains(exit_code)),
+            )
+            .await;
+        let (outcome, stdout) = match result {
+            Ok((exit_code, stdout)) => {
+                let outcome = CommandOutcome::from_exit_code_with(exit_code, success_exit_codes);
+                self.runtime_vars.set_output(name, stdout.trim());
+                if let (true, Some(var)) = (outcome.success, store_output_as) {
+                    self.runtime_vars.set(var, stdout.trim());
+                }
+                (outcome, stdout)
+            }
+            Err(e) => {
+                error!("Command execution failed: {}", e);
+                (CommandOutcome::execution_error(), String::new())
+            }
+        };
+        let outcome = outcome.with_duration(started.elapsed());
+        debug!("Command for '{}' took {:?}", name, outcome.duration);
+
+        self.last_result_manager.record(name, outcome);
+        self.events.publish(PressEvent::new(name, "command", Some(outcome_name(outcome.success))));
+        self.run_post_hook(name, outcome.exit_code).await;
+        if let Some(duration) = config.toast_for(name) {
+            self.show_output_toast(name, &stdout, outcome.success, duration);
+        }
+        outcome
+    }
+
+    /// Runs a command button's press in its own task and returns right away. The task
+    /// resolves to the command's outcome, which is recorded like any run's.
+    pub fn execute_command_spawned(self: &Arc<Self>, run: CommandRun) -> JoinHandle<CommandOutcome> {
+        let ctx = self.clone();
+        tokio::spawn(async move {
+            let _turn = match run.queue {
+                true => Some(ctx.command_queues.turn(&run.name).await),
+                false => None,
+            };
+            ctx.run_command(
+                &run.name,
+                &run.command,
+                &run.args,
+                run.no_prefix,
+                run.store_output_as.as_deref(),
+                &run.success_exit_codes,
+                run.retry,
+            )
+            .await
+        })
+    }
+
+    /// Runs a command button's press and waits up to `timeout`, or until it finishes if
+    /// `None`, for its outcome. A command still running then goes on in the background.
+    pub async fn execute_command_awaited(self: &Arc<Self>, run: CommandRun, timeout: Option<Duration>) -> ActionOutcome {
+        await_command(&mut self.execute_command_spawned(run), timeout).await
+    }
+
+    /// Shows the last non-empty line of a command's `stdout` for `duration` in place of
+    /// the menu, which then returns. Output without such a line shows nothing.
+    /// Returns whether a toast was shown.
+    pub fn show_output_toast(&self, name: &str, stdout: &str, success: bool, duration: Duration) -> bool {
+        let Some(line) = last_output_line(stdout) else {
+            debug!("No output of '{}' to show", name);
+            return false;
+        };
+        let Some(sender) = self.navigation_sender.clone() else {
+            return false;
+        };
+        let (line, next) = (line.to_string(), self.active_plugin());
+        // The toast waits out its duration, so it does not hold up the caller
+        tokio::spawn(async move { show_transient_result_for(&sender, line, success, next, duration).await });
+        true
+    }
+
+    /// The args of the command button `name`: its `args`, then those of its `args_file`
+    /// read now. Returns `None` to skip the run, with a warning, if the file cannot be read.
+    pub fn command_args(&self, name: &str, args: &[String], args_file: Option<&str>) -> Option<Vec<String>> {
+        match command_args(args, args_file) {
+            Ok(args) => Some(args),
+            Err(e) => {
+                warn!("Skipping '{}': cannot read args_file {}: {}", name, args_file.unwrap_or_default(), e);
+                self.events.publish(PressEvent::new(name, "command", Some("skipped")));
+                None
+            }
+        }
+    }
+
+    /// Shows the notification of a notification button, with its placeholders filled in
+    /// from the deck's current activity, and publishes the press. Returns false if it
+    /// could not be shown, e.g. because no notification daemon is running.
+    pub async fn send_notification(&self, name: &str, title: &str, body: &str, icon: Option<&str>) -> bool {
+        let config = self.current_config();
+        let summary = ActivitySummary::collect(&config, &self.toggle_state_manager, &self.last_result_manager);
+        let notification = DesktopNotification {
+            title: render_notification_text(title, &summary, &self.runtime_vars),
+            body: render_notification_text(body, &summary, &self.runtime_vars),
+            icon: icon.map(str::to_string),
+        };
+        debug!("Notification of '{}': {:?}", name, notification);
+        let success = match self.notifier.notify(&notification).await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Notification of '{}' not shown: {}", name, e);
+                false
+            }
+        };
+        self.events.publish(PressEvent::new(name, "notification", Some(outcome_name(success))));
+        success
+    }
+
+    /// Runs a `match` button: runs its probe, then the command of the case matching the
+    /// probe's trimmed stdout, or the default. A failing probe selects the default.
+    /// Returns `None` if nothing ran because no case matched and there is no default.
+    pub async fn run_match(
+        &self,
+        name: &str,
+        probe: &MatchCommand,
+        cases: &[MatchCase],
+        default: Option<&MatchCommand>,
+    ) -> Option<CommandOutcome> {
+        let result = execute_probe_command_with_spawn(
+            &probe.command,
+            &probe.args,
+            name,
+            &SpawnConfig::from_config(&self.current_config()),
+        ).await;
+        let output = result.success.then(|| result.stdout.trim());
+        if output.is_none() {
+            warn!("Probe of match button '{}' failed, using its default", name);
+        }
+        let Some((command, args)) = select_match_command(cases, default, output) else {
+            info!("No case of '{}' matches {:?} and it has no default", name, output);
+            self.events.publish(PressEvent::new(name, "match", Some("skipped")));
+            return None;
+        };
+        debug!("Match button '{}' got {:?}, running '{}'", name, output, command);
+        Some(self.run_command(name, command, args, false, None, &[0], RetryPolicy::default()).await)
+    }
+
+    /// Runs the probe of the branch button `name` and returns the submenu it picks:
+    /// `on_success` when the probe exits with 0, `on_failure` otherwise
+    pub async fn run_branch<'a>(&self, name: &str, probe: &MatchCommand, on_success: &'a Menu, on_failure: &'a Menu) -> &'a Menu {
+        let result = execute_probe_command_with_spawn(
+            &probe.command,
+            &probe.args,
+            name,
+            &SpawnConfig::from_config(&self.current_config()),
+        ).await;
+        self.events.publish(PressEvent::new(name, "branch", Some(outcome_name(result.success))));
+        let target = if result.success { on_success } else { on_failure };
+        debug!("Branch button '{}' opens '{}'", name, target.name);
+        target
+    }
+
+    /// Runs the configured `hooks.pre_command` before the button's action
+    async fn run_pre_hook(&self, name: &str) {
+        let config = self.current_config();
+        if let Some(hook) = &config.hooks.pre_command {
+            run_hook("pre_command", hook, name, None, &SpawnConfig::from_config(&config)).await;
+        }
+    }
+
+    /// Runs the configured `hooks.post_command` after the button's action
+    async fn run_post_hook(&self, name: &str, exit_code: Option<i32>) {
+        let config = self.current_config();
+        if let Some(hook) = &config.hooks.post_command {
+            run_hook("post_command", hook, name, exit_code, &SpawnConfig::from_config(&config)).await;
+        }
+    }
+
+    /// Publishes the press of toggle `name` and runs the post hook. A command refused by
+    /// `allowed_commands` or `denied_commands` is recorded as a failed run, like a command button's.
+    async fn finish_toggle(&self, name: &str, result: &ToggleCommandResult) {
+        if result.not_allowed {
+            self.last_result_manager.record(name, CommandOutcome::execution_error().with_duration(result.duration));
+        }
+        let outcome = if result.success { result.new_state.as_str() } else { "failure" };
+        self.events.publish(PressEvent::new(name, "toggle", Some(outcome)));
+        self.run_post_hook(name, result.exit_code).await;
+    }
+
+    /// Theme the menu of `plugin` is drawn with: that of the first matching `theme_when`
+    /// rule, else the menu's own theme or the one of the `render` block
+    pub fn theme_for(&self, plugin: &CommanderPlugin) -> ThemeName {
+        let config = self.current_config();
+        select_theme(&config.theme_when, &self.toggle_state_manager, plugin.theme(config.render.theme))
+    }
+
+    /// Theme the deck renderer runs with: that of the first matching `theme_when` rule,
+    /// else the one of the `render` block
+    pub fn deck_theme(&self) -> ThemeName {
+        let config = self.current_config();
+        select_theme(&config.theme_when, &self.toggle_state_manager, config.render.theme)
+    }
+
+    /// Icon size and margin applied when rendering keys
+    pub fn icon_layout(&self) -> IconLayout {
+        IconLayout::from_config(&self.current_config())
+    }
+
+    /// Remembers the menu plugin currently shown on the deck
+    pub fn set_active_plugin(&self, plugin: CommanderPlugin) {
+        let mut active = self.active_plugin.write().unwrap_or_else(PoisonError::into_inner);
+        // Moving to another menu counts as activity, re-rendering the same one does not
+        if active.as_ref().is_none_or(|previous| previous.menu_path() != plugin.menu_path()) {
+            self.activity.touch();
+        }
+        *active = Some(plugin);
+    }
+
+    /// Shows the root menu if a submenu is shown. Returns whether the deck was sent home.
+    pub async fn navigate_home(&self) -> bool {
+        if self.active_plugin().parent().is_none() {
+            return false;
+        }
+        let Some(sender) = &self.navigation_sender else {
+            return false;
+        };
+        info!("Returning to the main menu");
+        let root = CommanderPlugin::new_with_state_manager(
+            self.current_config().menu.clone(),
+            self.toggle_state_manager.clone(),
+        );
+        if let Err(e) = sender.send(ExternalTrigger::new(PluginNavigation::<U5, U3>::new(root), false)).await {
+            error!("Failed to send home navigation: {}", e);
+            return false;
+        }
+        true
+    }
+
+    /// Shows `plugin` on the deck through the trigger channel. Returns whether it was sent.
+    pub async fn navigate_to(&self, plugin: CommanderPlugin) -> bool {
+        let Some(sender) = &self.navigation_sender else {
+            warn!("No navigation sender available to open '{}'", plugin.menu().name);
+            return false;
+        };
+        if let Err(e) = sender.send(ExternalTrigger::new(PluginNavigation::<U5, U3>::new(plugin), false)).await {
+            error!("Failed to send navigation: {}", e);
+            return false;
+        }
+        true
+    }
+
+    /// Returns the menu plugin currently shown, or a fresh root plugin if none was shown yet
+    pub fn active_plugin(&self) -> CommanderPlugin {
+        let active = match self.active_plugin.read() {
+            Ok(active) => active.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        active.unwrap_or_else(|| {
+            CommanderPlugin::new_with_state_manager(
+                self.current_config().menu.clone(),
+                self.toggle_state_manager.clone(),
+            )
+        })
+    }
+
+    /// Re-probes every toggle of the active config and re-renders the current view.
+    /// The config itself is left untouched. Returns the number of probed toggles whose state changed.
+    pub async fn refresh_toggle_states(&self) -> usize {
+        let config = self.current_config();
+        let changed = initialize_toggle_states_with(&config, &self.toggle_state_manager, &self.probe_backends).await;
+        info!("Re-probed toggle states, {} changed", changed);
+        self.refresh_active_view().await;
+        changed
+    }
+
+    /// Re-renders the menu currently shown on the deck
+    pub async fn refresh_active_view(&self) {
+        if let Some(sender) = &self.navigation_sender {
+            let refresh_trigger = ExternalTrigger::new(PluginNavigation::<U5, U3>::new(self.active_plugin()), false);
+            if let Err(e) = sender.send(refresh_trigger).await {
+                error!("Failed to send refresh trigger: {}", e);
+            }
+        }
+    }
+
+    /// Runs the `repeat` command of the command button named `name`, as for each
+    /// interval its key stays held. Returns false if it has no such button or `repeat`.
+    pub async fn repeat_button(&self, name: &str) -> bool {
+        let config = self.current_config();
+        let mut found = None;
+        config.walk_buttons(|button, _| {
+            if found.is_none() && button.name() == name {
+                found = Some(button);
+            }
+        });
+
+        match found {
+            Some(Button::Command { name, repeat: Some(repeat), no_prefix, success_exit_codes, .. }) => {
+                self.run_command(name, &repeat.command, &repeat.args, *no_prefix, None, success_exit_codes, RetryPolicy::default())
+                    .await;
+                true
+            }
+            _ => {
+                warn!("No command button named '{}' with a repeat", name);
+                false
+            }
+        }
+    }
+
+    /// Presses the command or toggle button named `name` from outside the deck's keys
+    /// (e.g. a pedal). The first button with that name anywhere in the menu tree is used.
+    /// Returns false if there is no such command or toggle button.
+    pub async fn execute_button(&self, name: &str) -> bool {
+        let config = self.current_config();
+        let mut found = None;
+        config.walk_buttons(|button, _| {
+            if found.is_none() && button.name() == name {
+                found = Some(button);
+            }
+        });
+
+        if found.is_some_and(|button| !button.is_active_at(Local::now().naive_local())) {
+            info!("Ignoring press of '{}' outside its active_hours", name);
+            return true;
+        }
+        match found {
+            Some(button @ Button::Command { name, command, args, args_file, no_prefix, store_output_as, success_exit_codes, queue, cooldown_ms, .. }) => {
+                if cooldown_ms.is_some_and(|cooldown| !self.cooldowns.try_press(name, Duration::from_millis(cooldown))) {
+                    info!("Ignoring press of '{}' during its cooldown", name);
+                    return true;
+                }
+                let retry = RetryPolicy::for_button(button);
+                let _turn = match queue {
+                    true => Some(self.command_queues.turn(name).await),
+                    false => None,
+                };
+                let Some(args) = self.command_args(name, args, args_file.as_deref()) else {
+                    return true;
+                };
+                self.run_command(name, command, &args, *no_prefix, store_output_as.as_deref(), success_exit_codes, retry).await;
+            }
+            Some(button @ Button::Toggle { name, mode, probe_command, probe_args, no_prefix, concurrent_verify, readonly, .. }) => {
+                let toggle_config = ToggleCommandConfig {
+                    spawn: SpawnConfig {
+                        log_output: config.log_output_for(name),
+                        ..SpawnConfig::for_button(&config, *no_prefix)
+                    },
+                    probe: ProbeContext::from_button(button),
+                    backends: self.probe_backends.clone(),
+                    retry: RetryPolicy::for_button(button),
+                    concurrent_verify: *concurrent_verify,
+                    readonly: *readonly,
+                    locks: self.button_locks.clone(),
+                    lock: button.lock().map(str::to_string),
+                };
+                self.run_pre_hook(name).await;
+                let result = execute_toggle_command_with_config(
+                    name,
+                    mode,
+                    probe_command.as_deref(),
+                    probe_args,
+                    &self.toggle_state_manager,
+                    &toggle_config,
+                ).await;
+                self.finish_toggle(name, &result).await;
+            }
+            Some(_) => {
+                warn!("Button '{}' is not a command or toggle and cannot be pressed remotely", name);
+                return false;
+            }
+            None => {
+                warn!("No button named '{}'", name);
+                return false;
+            }
+        }
+        self.refresh_active_view().await;
+        true
+    }
 }
 
 
@@ -57,13 +674,135 @@ impl CommanderPlugin {
         }
     }
 
+    /// The menu rendered by this plugin
+    pub fn menu(&self) -> &Menu {
+        &self.menu
+    }
+
+    /// The menu as shown on the deck: for a dynamic menu, with the buttons it generates
+    /// now in place of its listed ones, at most as many as fit next to the back button
+    pub fn shown_menu(&self, commander_ctx: Option<&CommanderContext>) -> Cow<'_, Menu> {
+        match self.menu.dynamic {
+            Some(DynamicMenu::Recent) => {
+                let buttons = commander_ctx
+                    .map(|ctx| recent_buttons(&ctx.execution_history, &ctx.current_config(), DECK_GRID.menu_button_slots()))
+                    .unwrap_or_default();
+                Cow::Owned(Menu {
+                    buttons,
+                    ..self.menu.clone()
+                })
+            }
+            None => Cow::Borrowed(&self.menu),
+        }
+    }
+
+    /// Names of the menus from the root down to this one
+    fn menu_path(&self) -> Vec<&str> {
+        let mut path = self.parent.as_ref().map(|parent| parent.menu_path()).unwrap_or_default();
+        path.push(&self.menu.name);
+        path
+    }
+
+    /// The menu this plugin's back button returns to, if any
+    pub fn parent(&self) -> Option<&CommanderPlugin> {
+        self.parent.as_deref()
+    }
+
+    /// Theme of this menu's view: its own override, else the nearest parent's, else `default`
+    pub fn theme(&self, default: ThemeName) -> ThemeName {
+        match self.menu.theme {
+            Some(theme) => theme,
+            None => self.parent.as_ref().map_or(default, |parent| parent.theme(default)),
+        }
+    }
+
+
+    /// Runs the toggle `button` of this menu as a press of its key does, showing its
+    /// pending icon while the command runs and its new state once it resolves. The
+    /// menu is re-rendered with this plugin, so a submenu keeps its Back key.
+    pub(crate) async fn press_toggle(&self, context: &PluginContext, button: &Button) {
+        let Button::Toggle { name, mode, probe_command, probe_args, no_prefix, concurrent_verify, readonly, lock, .. } = button else {
+            return;
+        };
+        info!("Toggle button '{}' clicked", name);
+        // Show the pending icon while the command runs
+        let pending = self.toggle_state_manager.begin_pending(name);
+        refresh_view(context, self.clone()).await;
+        let commander_ctx = context.get_context::<CommanderContext>().await;
+        if let Some(commander_ctx) = &commander_ctx {
+            commander_ctx.run_pre_hook(name).await;
+        }
+        let toggle_config = match &commander_ctx {
+            Some(commander_ctx) => ToggleCommandConfig {
+                spawn: {
+                    let config = commander_ctx.current_config();
+                    SpawnConfig {
+                        log_output: config.log_output_for(name),
+                        ..SpawnConfig::for_button(&config, *no_prefix)
+                    }
+                },
+                probe: ProbeContext::from_button(button),
+                backends: commander_ctx.probe_backends.clone(),
+                retry: RetryPolicy::for_button(button),
+                concurrent_verify: *concurrent_verify,
+                readonly: *readonly,
+                locks: commander_ctx.button_locks.clone(),
+                lock: lock.clone(),
+            },
+            None => ToggleCommandConfig {
+                probe: ProbeContext::from_button(button),
+                retry: RetryPolicy::for_button(button),
+                concurrent_verify: *concurrent_verify,
+                readonly: *readonly,
+                ..Default::default()
+            },
+        };
+        let result = execute_toggle_command_with_config(
+            name,
+            mode,
+            probe_command.as_deref(),
+            probe_args,
+            &self.toggle_state_manager,
+            &toggle_config,
+        ).await;
+        drop(pending);
 
-    async fn execute_command(command: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Executing command: {} {:?}", command, args);
+        if let Some(commander_ctx) = &commander_ctx {
+            commander_ctx.finish_toggle(name, &result).await;
+        }
+
+        if result.success {
+            info!("Toggle '{}' executed successfully, new state: {:?}", name, result.new_state);
+        } else {
+            error!("Toggle '{}' execution failed: {:?}", name, result.error_message);
+        }
+
+        // Replace the pending icon with the resolved state's
+        info!("Refreshing view to update toggle icon for '{}'", name);
+        refresh_view(context, self.clone()).await;
+    }
+
+    /// Runs `command` with its args expanded and returns its exit code and stdout
+    #[tracing::instrument(name = "exec", skip_all, fields(run = %next_run_id(), command = command))]
+    pub async fn execute_command(
+        command: &str,
+        args: &[String],
+        expand: &ExpandContext<'_>,
+        spawn: &SpawnConfig,
+        success_exit_codes: &[i32],
+    ) -> Result<(i32, String), CommanderError> {
+        info!("Executing command: {}", spawn.command_line(command, args));
+        if !spawn.policy.allows(command) {
+            return Err(CommanderError::NotAllowed { command: command.to_string() });
+        }
+        // Placeholders are expanded after logging so secrets never reach the logs
+        let spawn_args = expand_all_args(args, expand).map_err(|e| CommanderError::InvalidArgs {
+            command: command.to_string(),
+            message: format!("{:#}", e),
+        })?;
         
-        let mut cmd = Command::new(command);
-        cmd.args(args)
-           .stdout(Stdio::piped())
+        let mut cmd = spawn.command(command, &spawn_args);
+        cmd.stdout(Stdio::piped())
            .stderr(Stdio::piped());
 
         match cmd.spawn() {
@@ -72,182 +811,305 @@ impl CommanderPlugin {
                 let stdout = child.stdout.take().expect("Failed to capture stdout");
                 let stderr = child.stderr.take().expect("Failed to capture stderr");
                 
-                // Create async readers
-                let stdout_reader = BufReader::new(stdout);
-                let stderr_reader = BufReader::new(stderr);
-                
-                // Spawn tasks to read stdout and stderr concurrently
-                let stdout_task = {
-                    let cmd_str = format!("{} {:?}", command, args);
-                    tokio::spawn(async move {
-                        let mut lines = stdout_reader.lines();
-                        while let Ok(Some(line)) = lines.next_line().await {
-                            debug!("STDOUT [{}]: {}", cmd_str, line);
-                        }
-                    })
-                };
-                
-                let stderr_task = {
-                    let cmd_str = format!("{} {:?}", command, args);
-                    tokio::spawn(async move {
-                        let mut lines = stderr_reader.lines();
-                        while let Ok(Some(line)) = lines.next_line().await {
-                            debug!("STDERR [{}]: {}", cmd_str, line);
-                        }
-                    })
-                };
-                
-                // Wait for the process to complete
-                match child.wait().await {
+                // Read raw bytes to completion and decode once, so the stored output is
+                // exactly what the command printed, line endings and all
+                let (status, stdout, stderr) = tokio::join!(child.wait(), read_to_end(stdout), read_to_end(stderr));
+                let stdout = String::from_utf8_lossy(&stdout).into_owned();
+                let cmd_str = format!("{} {:?}", command, args);
+                for line in stdout.lines() {
+                    log_command_output(spawn.log_output, format_args!("STDOUT [{}]: {}", cmd_str, line));
+                }
+                for line in String::from_utf8_lossy(&stderr).lines() {
+                    log_command_output(spawn.log_output, format_args!("STDERR [{}]: {}", cmd_str, line));
+                }
+
+                match status {
                     Ok(status) => {
-                        // Wait for output reading tasks to complete
-                        let _ = tokio::join!(stdout_task, stderr_task);
-                        
-                        if status.success() {
+                        if status.code().is_some_and(|code| success_exit_codes.contains(&code)) {
                             info!("Command executed successfully: {} {:?} (exit code: {})", 
                                   command, args, status.code().unwrap_or(0));
                         } else {
-                            warn!("Command exited with non-zero status: {} {:?} (exit code: {})", 
+                            warn!("Command exited with a failure status: {} {:?} (exit code: {})", 
                                   command, args, status.code().unwrap_or(-1));
                         }
-                        Ok(())
+                        Ok((status.code().unwrap_or(-1), stdout))
                     }
                     Err(e) => {
                         error!("Failed to wait for command: {} {:?} - {}", command, args, e);
-                        Err(Box::new(e))
+                        Err(CommanderError::Spawn { command: command.to_string(), source: e })
                     }
                 }
             }
             Err(e) => {
                 error!("Failed to execute command: {} {:?} - {}", command, args, e);
-                Err(Box::new(e))
+                Err(CommanderError::Spawn { command: command.to_string(), source: e })
             }
         }
     }
 
-    fn create_view_from_menu(
+    pub(crate) fn create_view_from_menu(
         &self,
-    ) -> Result<Box<dyn View<U5, U3, PluginContext, PluginNavigation<U5, U3>>>, Box<dyn std::error::Error>> {
+        commander_ctx: Option<&CommanderContext>,
+    ) -> Result<Box<dyn View<U5, U3, PluginContext, PluginNavigation<U5, U3>>>, CommanderError> {
         let mut view = CustomizableView::new();
+        let last_results = commander_ctx
+            .map(|ctx| ctx.last_result_manager.clone())
+            .unwrap_or_default();
+        let menu_icon_layout = commander_ctx
+            .map(CommanderContext::icon_layout)
+            .unwrap_or_default();
+        let background = self
+            .menu
+            .background_image
+            .as_deref()
+            .zip(commander_ctx)
+            .and_then(|(path, ctx)| ctx.backgrounds.get(path));
+        let label_config = commander_ctx
+            .map(|ctx| LabelConfig::from_config(&ctx.current_config()))
+            .unwrap_or_default();
+        let locale = commander_ctx.and_then(|ctx| ctx.current_config().locale.clone());
+        let label = |text: &str| {
+            let layout = label_config.layout(text);
+            debug!("Label '{}' laid out at font size {}", text, layout.font_size);
+            layout.text
+        };
+        let label_with_sublabel = |text: &str, sublabel: Option<String>| {
+            let layout = label_config.layout_with_sublabel(text, sublabel.as_deref());
+            debug!(
+                "Label '{}' laid out at font size {} (sublabel: {:?})",
+                text, layout.font_size, layout.sublabel_font_size
+            );
+            layout.text
+        };
         
-        let mut row = 0;
-        let mut col = 0;
-        let mut button_index = 0;
-        
-        for button in &self.menu.buttons {
-            // Reserve position 14 (index 14 = row 2, col 4) for the automatic back button
-            if button_index == 14 {
-                // Skip to next position, leaving space for back button
-                button_index += 1;
-                col = 0;
-                row = 3;
-            }
-            
-            if row >= 3 { // Stream Deck has 3 rows
-                break;
+        let menu = self.shown_menu(commander_ctx);
+        let now = Local::now().naive_local();
+        for (col, row, button) in menu_layout(&menu) {
+            let icon_layout = KeyIconLayout {
+                layout: &menu_icon_layout,
+                background: background.as_deref(),
+                col,
+                row,
+            };
+            // Outside its active_hours a button keeps its label but does nothing
+            if !button.is_active_at(now) {
+                view.set_button(
+                    col,
+                    row,
+                    ClickButton::new(
+                        &label(button.localized_name(locale.as_deref())),
+                        icon_layout.apply_rotated(icons::resolve_icon(Some(&DISABLED_ICON.to_string())), button.icon_rotate()),
+                        |_: PluginContext| async move { Ok(()) },
+                    ),
+                )?;
+                continue;
             }
-            
             match button {
-                Button::Command { name, command, args, icon } => {
+                Button::Command { name, command, args, args_file, icon_map, no_prefix, store_output_as, delay_ms, success_exit_codes, queue, wait_ms, cooldown_ms, .. } => {
+                    let no_prefix = *no_prefix;
+                    let queue = *queue;
+                    let delay = (*delay_ms > 0).then(|| Duration::from_millis(*delay_ms));
+                    let wait = wait_ms.map(Duration::from_millis);
+                    let cooldown = cooldown_ms.map(Duration::from_millis);
+                    let scheduled = commander_ctx.is_some_and(|ctx| ctx.delayed_commands.is_pending(name));
+                    let cooling = commander_ctx.is_some_and(|ctx| ctx.cooldowns.is_cooling(name));
                     let command_clone = command.clone();
                     let args_clone = args.clone();
+                    let args_file = args_file.clone();
                     let name_clone = name.clone();
+                    let store_output_as = store_output_as.clone();
+                    let success_exit_codes = success_exit_codes.clone();
+                    let refresh_on_result = icon_map.is_some() || delay.is_some();
+                    let retry = RetryPolicy::for_button(button);
+                    let plugin_for_refresh = self.clone();
                     
                     view.set_button(
                         col,
                         row,
                         ClickButton::new(
-                            &name_clone,
-                            icons::resolve_icon(icon.as_ref()),
-                            move |_context: PluginContext| {
+                            &label(button.localized_name(locale.as_deref())),
+                            icon_layout.apply_rotated(
+                                if scheduled {
+                                    icons::resolve_icon(Some(&"schedule".to_string()))
+                                } else if cooling {
+                                    icons::resolve_icon(Some(&"hourglass_empty".to_string()))
+                                } else {
+                                    resolve_command_icon(button, &last_results)
+                                },
+                                button.icon_rotate(),
+                            ),
+                            move |context: PluginContext| {
                                 let cmd = command_clone.clone();
                                 let args = args_clone.clone();
-                                // Spawn command execution in a separate task to avoid blocking UI
-                                tokio::spawn(async move {
-                                    if let Err(e) = Self::execute_command(&cmd, &args).await {
-                                        error!("Command execution failed: {}", e);
+                                let args_file = args_file.clone();
+                                let name = name_clone.clone();
+                                let plugin = plugin_for_refresh.clone();
+                                let store_output_as = store_output_as.clone();
+                                let success_exit_codes = success_exit_codes.clone();
+                                let press_name = name.clone();
+                                let press = async move {
+                                    let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+                                        error!("Failed to get CommanderContext from plugin context");
+                                        return ActionOutcome::Spawned;
+                                    };
+                                    if let Some(cooldown) = cooldown {
+                                        if !commander_ctx.cooldowns.try_press(&name, cooldown) {
+                                            info!("Ignoring press of '{}' during its cooldown", name);
+                                            return ActionOutcome::Spawned;
+                                        }
+                                        // Show the wait icon for the cooldown, then the usual icon again
+                                        refresh_view(&context, plugin.clone()).await;
+                                        let (cooled_context, cooled_plugin) = (context.clone(), plugin.clone());
+                                        spawn_handler(name.clone(), async move {
+                                            tokio::time::sleep(cooldown).await;
+                                            refresh_view(&cooled_context, cooled_plugin).await;
+                                        });
                                     }
-                                });
-                                async move { Ok(()) }
+                                    if let Some(delay) = delay {
+                                        let delayed_commands = commander_ctx.delayed_commands.clone();
+                                        let (run_context, run_plugin) = (context.clone(), plugin.clone());
+                                        let run_name = name.clone();
+                                        let run = async move {
+                                            if let Some(args) = commander_ctx.command_args(&run_name, &args, args_file.as_deref()) {
+                                                commander_ctx
+                                                    .run_command(&run_name, &cmd, &args, no_prefix, store_output_as.as_deref(), &success_exit_codes, retry)
+                                                    .await;
+                                            }
+                                            refresh_view(&run_context, run_plugin).await;
+                                        };
+                                        let press = delayed_commands.press(&name, delay, run);
+                                        debug!("Delayed press of '{}': {:?}", name, press);
+                                        // Show or clear the scheduled icon
+                                        refresh_view(&context, plugin).await;
+                                        return ActionOutcome::Spawned;
+                                    }
+                                    let Some(args) = commander_ctx.command_args(&name, &args, args_file.as_deref()) else {
+                                        return ActionOutcome::Spawned;
+                                    };
+                                    let run = CommandRun {
+                                        name: name.clone(),
+                                        command: cmd,
+                                        args,
+                                        no_prefix,
+                                        store_output_as,
+                                        success_exit_codes,
+                                        retry,
+                                        queue,
+                                    };
+                                    let mut task = commander_ctx.execute_command_spawned(run);
+                                    let outcome = await_command(&mut task, wait).await;
+                                    debug!("Press of '{}': {:?}", name, outcome);
+                                    match outcome {
+                                        ActionOutcome::Completed(_) if refresh_on_result => refresh_view(&context, plugin).await,
+                                        // Update the key once the command left running in the background is done
+                                        ActionOutcome::TimedOut if refresh_on_result => {
+                                            spawn_handler(name, async move {
+                                                let _ = task.await;
+                                                refresh_view(&context, plugin).await;
+                                            });
+                                        }
+                                        _ => {}
+                                    }
+                                    outcome
+                                };
+                                async move {
+                                    match wait {
+                                        // The key waits up to `wait_ms` for the outcome
+                                        Some(_) => {
+                                            press.await;
+                                        }
+                                        // Spawn command execution in a separate task to avoid blocking UI
+                                        None => {
+                                            spawn_handler(press_name, async move {
+                                                press.await;
+                                            });
+                                        }
+                                    }
+                                    Ok(())
+                                }
                             },
                         ),
                     )?;
                 }
-                Button::Menu { name, buttons, icon } => {
-                    let submenu = Menu {
-                        name: name.clone(),
-                        buttons: buttons.clone(),
-                    };
-                    
+                Button::Menu { .. } => {
+                    let submenu = button.submenu().expect("a menu button opens a menu");
                     view.set_navigation(
                         col,
                         row,
                         PluginNavigation::<U5, U3>::new(CommanderPlugin::new_with_parent(submenu, self.clone())),
-                        name,
-                        icons::resolve_icon(icon.as_ref()),
+                        &label(button.localized_name(locale.as_deref())),
+                        icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
                     )?;
                 }
-                Button::Toggle { name, mode, probe_command, probe_args, .. } => {
+                Button::Input { name, command, args, no_prefix, .. } => {
+                    view.set_navigation(
+                        col,
+                        row,
+                        PluginNavigation::<U5, U3>::new(KeypadPlugin::new(name, command, args, *no_prefix, self.clone())),
+                        &label(button.localized_name(locale.as_deref())),
+                        icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
+                    )?;
+                }
+                Button::Toggle { name, .. } => {
                     let button_name = name.clone();
-                    let toggle_mode = mode.clone();
-                    let probe_cmd = probe_command.clone();
-                    let probe_args_clone = probe_args.clone();
-                    let state_manager = self.toggle_state_manager.clone();
                     let button_clone = button.clone();
                     let state_manager_for_icon = self.toggle_state_manager.clone();
-                    let menu_clone = self.menu.clone();
-                    let toggle_state_mgr_clone = self.toggle_state_manager.clone();
-                    
-                    
+                    let plugin = self.clone();
+
                     view.set_button(
                         col,
                         row,
                         ClickButton::new(
-                            &button_name.clone(),
-                            resolve_toggle_icon(&button_clone, &state_manager_for_icon),
+                            &label_with_sublabel(
+                                &get_toggle_label_localized(button, &state_manager_for_icon, locale.as_deref()),
+                                get_toggle_sublabel(button, &state_manager_for_icon),
+                            ),
+                            icon_layout.apply_rotated(resolve_toggle_icon(&button_clone, &state_manager_for_icon), button.icon_rotate()),
                             move |context: PluginContext| {
-                                let name = button_name.clone();
-                                let mode = toggle_mode.clone();
-                                let probe = probe_cmd.clone();
-                                let probe_args = probe_args_clone.clone();
-                                let state_mgr = state_manager.clone();
-                                let menu_for_refresh = menu_clone.clone();
-                                let toggle_state_mgr_for_refresh = toggle_state_mgr_clone.clone();
-                                
+                                let plugin = plugin.clone();
+                                let button = button_clone.clone();
                                 // Spawn toggle execution in a separate task to avoid blocking UI
-                                tokio::spawn(async move {
-                                    info!("Toggle button '{}' clicked", name);
-                                    let result = execute_toggle_command(
-                                        &name,
-                                        &mode,
-                                        probe.as_deref(),
-                                        &probe_args,
-                                        &state_mgr,
-                                    ).await;
-                                    
-                                    if result.success {
-                                        info!("Toggle '{}' executed successfully, new state: {:?}", name, result.new_state);
-                                        
-                                        // Get the navigation sender from context and refresh the view
-                                        if let Some(commander_ctx) = context.get_context::<CommanderContext>().await {
-                                            if let Some(sender) = &commander_ctx.navigation_sender {
-                                                info!("Refreshing view to update toggle icon for '{}'", name);
-                                                let refreshed_plugin = CommanderPlugin::new_with_state_manager(menu_for_refresh, toggle_state_mgr_for_refresh);
-                                                let refresh_trigger = ExternalTrigger::new(
-                                                    PluginNavigation::<U5, U3>::new(refreshed_plugin),
-                                                    false
-                                                );
-                                                if let Err(e) = sender.send(refresh_trigger).await {
-                                                    error!("Failed to send refresh trigger: {}", e);
-                                                }
-                                            } else {
-                                                warn!("No navigation sender available for view refresh");
-                                            }
-                                        } else {
-                                            error!("Failed to get CommanderContext from plugin context");
+                                spawn_handler(button_name.clone(), async move {
+                                    plugin.press_toggle(&context, &button).await;
+                                });
+                                async move { Ok(()) }
+                            },
+                        ),
+                    )?;
+                }
+                Button::Reload { name, .. } => {
+                    let current_plugin = self.clone();
+                    let reload_name = name.clone();
+
+                    view.set_button(
+                        col,
+                        row,
+                        ClickButton::new(
+                            &label(button.localized_name(locale.as_deref())),
+                            icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
+                            move |context: PluginContext| {
+                                let current_plugin = current_plugin.clone();
+                                let reload_name = reload_name.clone();
+                                spawn_handler(reload_name.clone(), async move {
+                                    info!("Reload button pressed");
+                                    let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+                                        error!("Failed to get CommanderContext from plugin context");
+                                        return;
+                                    };
+                                    let Some(sender) = &commander_ctx.navigation_sender else {
+                                        warn!("No navigation sender available for config reload");
+                                        return;
+                                    };
+                                    let reloaded = commander_ctx.reload();
+                                    commander_ctx.events.publish(PressEvent::new(&reload_name, "reload", Some(outcome_name(reloaded.is_ok()))));
+                                    match reloaded {
+                                        Ok(root) => {
+                                            show_transient_result(sender, "Reloaded", true, root).await;
+                                        }
+                                        Err(e) => {
+                                            error!("Failed to reload configuration: {:#}", e);
+                                            show_transient_result(sender, "Reload failed", false, current_plugin).await;
                                         }
-                                    } else {
-                                        error!("Toggle '{}' execution failed: {:?}", name, result.error_message);
                                     }
                                 });
                                 async move { Ok(()) }
@@ -255,36 +1117,247 @@ impl CommanderPlugin {
                         ),
                     )?;
                 }
-                Button::Back { name: _, icon: _ } => {
+                Button::Brightness { name, action, value, .. } => {
+                    let button_name = name.clone();
+                    let action = *action;
+                    let value = *value;
+
+                    view.set_button(
+                        col,
+                        row,
+                        ClickButton::new(
+                            &label(button.localized_name(locale.as_deref())),
+                            icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
+                            move |context: PluginContext| {
+                                let button_name = button_name.clone();
+                                spawn_handler(button_name.clone(), async move {
+                                    info!("Brightness {:?} {} pressed", action, value);
+                                    let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+                                        error!("Failed to get CommanderContext from plugin context");
+                                        return;
+                                    };
+                                    commander_ctx.brightness.apply(action, value).await;
+                                    commander_ctx.events.publish(PressEvent::new(&button_name, "brightness", Some(outcome_name(true))));
+                                });
+                                async move { Ok(()) }
+                            },
+                        ),
+                    )?;
+                }
+                Button::Notification { name, title, body, notification_icon, .. } => {
+                    let button_name = name.clone();
+                    let title = title.clone();
+                    let body = body.clone();
+                    let notification_icon = notification_icon.clone();
+
+                    view.set_button(
+                        col,
+                        row,
+                        ClickButton::new(
+                            &label(button.localized_name(locale.as_deref())),
+                            icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
+                            move |context: PluginContext| {
+                                let button_name = button_name.clone();
+                                let title = title.clone();
+                                let body = body.clone();
+                                let notification_icon = notification_icon.clone();
+                                spawn_handler(button_name.clone(), async move {
+                                    info!("Notification button '{}' pressed", button_name);
+                                    let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+                                        error!("Failed to get CommanderContext from plugin context");
+                                        return;
+                                    };
+                                    commander_ctx
+                                        .send_notification(&button_name, &title, &body, notification_icon.as_deref())
+                                        .await;
+                                });
+                                async move { Ok(()) }
+                            },
+                        ),
+                    )?;
+                }
+                Button::GroupAction { name, group, action, .. } => {
+                    let button_name = name.clone();
+                    let group = group.clone();
+                    let action = *action;
+                    let plugin_for_refresh = self.clone();
+
+                    view.set_button(
+                        col,
+                        row,
+                        ClickButton::new(
+                            &label(button.localized_name(locale.as_deref())),
+                            icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
+                            move |context: PluginContext| {
+                                let group = group.clone();
+                                let plugin = plugin_for_refresh.clone();
+                                let button_name = button_name.clone();
+                                spawn_handler(button_name.clone(), async move {
+                                    info!("Group action {:?} pressed for group '{}'", action, group);
+                                    let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+                                        error!("Failed to get CommanderContext from plugin context");
+                                        return;
+                                    };
+                                    let config = commander_ctx.current_config();
+                                    let registry = ToggleGroupRegistry::from_menu(&config.menu);
+                                    let settings = GroupActionConfig {
+                                        spawn: SpawnConfig::from_config(&config),
+                                        backends: commander_ctx.probe_backends.clone(),
+                                        locks: commander_ctx.button_locks.clone(),
+                                        ..Default::default()
+                                    };
+                                    let result = execute_group_action_with_config(
+                                        &registry,
+                                        &group,
+                                        action,
+                                        &commander_ctx.toggle_state_manager,
+                                        &settings,
+                                    ).await;
+                                    commander_ctx.events.publish(PressEvent::new(&button_name, "group_action", Some(outcome_name(result.success()))));
+                                    refresh_view(&context, plugin).await;
+                                });
+                                async move { Ok(()) }
+                            },
+                        ),
+                    )?;
+                }
+                Button::Match { name, probe, cases, default, .. } => {
+                    let button_name = name.clone();
+                    let probe = probe.clone();
+                    let cases = cases.clone();
+                    let default = default.clone();
+                    let plugin_for_refresh = self.clone();
+
+                    view.set_button(
+                        col,
+                        row,
+                        ClickButton::new(
+                            &label(button.localized_name(locale.as_deref())),
+                            icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
+                            move |context: PluginContext| {
+                                let button_name = button_name.clone();
+                                let probe = probe.clone();
+                                let cases = cases.clone();
+                                let default = default.clone();
+                                let plugin = plugin_for_refresh.clone();
+                                spawn_handler(button_name.clone(), async move {
+                                    info!("Match button '{}' pressed", button_name);
+                                    let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+                                        error!("Failed to get CommanderContext from plugin context");
+                                        return;
+                                    };
+                                    commander_ctx.run_match(&button_name, &probe, &cases, default.as_ref()).await;
+                                    refresh_view(&context, plugin).await;
+                                });
+                                async move { Ok(()) }
+                            },
+                        ),
+                    )?;
+                }
+                Button::Branch { name, probe, on_success, on_failure, .. } => {
+                    let button_name = name.clone();
+                    let probe = probe.clone();
+                    let on_success = on_success.clone();
+                    let on_failure = on_failure.clone();
+                    let parent = self.clone();
+
+                    view.set_button(
+                        col,
+                        row,
+                        ClickButton::new(
+                            &label(button.localized_name(locale.as_deref())),
+                            icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
+                            move |context: PluginContext| {
+                                let button_name = button_name.clone();
+                                let probe = probe.clone();
+                                let on_success = on_success.clone();
+                                let on_failure = on_failure.clone();
+                                let parent = parent.clone();
+                                spawn_handler(button_name.clone(), async move {
+                                    info!("Branch button '{}' pressed", button_name);
+                                    let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+                                        error!("Failed to get CommanderContext from plugin context");
+                                        return;
+                                    };
+                                    let target = commander_ctx.run_branch(&button_name, &probe, &on_success, &on_failure).await;
+                                    commander_ctx.navigate_to(CommanderPlugin::new_with_parent(target.clone(), parent)).await;
+                                });
+                                async move { Ok(()) }
+                            },
+                        ),
+                    )?;
+                }
+                Button::Scene { name, scene, state_only, .. } => {
+                    let button_name = name.clone();
+                    let scene = scene.clone();
+                    let run_commands = !*state_only;
+                    let plugin_for_refresh = self.clone();
+
+                    view.set_button(
+                        col,
+                        row,
+                        ClickButton::new(
+                            &label(button.localized_name(locale.as_deref())),
+                            icon_layout.apply_rotated(resolve_button_icon(button, &self.toggle_state_manager), button.icon_rotate()),
+                            move |context: PluginContext| {
+                                let scene = scene.clone();
+                                let plugin = plugin_for_refresh.clone();
+                                let button_name = button_name.clone();
+                                spawn_handler(button_name.clone(), async move {
+                                    info!("Scene '{}' pressed", scene);
+                                    let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+                                        error!("Failed to get CommanderContext from plugin context");
+                                        return;
+                                    };
+                                    let config = commander_ctx.current_config();
+                                    let Some(states) = config.scenes.get(&scene) else {
+                                        error!("Scene '{}' is not configured", scene);
+                                        return;
+                                    };
+                                    let settings = GroupActionConfig {
+                                        spawn: SpawnConfig::from_config(&config),
+                                        backends: commander_ctx.probe_backends.clone(),
+                                        locks: commander_ctx.button_locks.clone(),
+                                        ..Default::default()
+                                    };
+                                    let result = restore_scene(
+                                        &config.menu,
+                                        states,
+                                        &commander_ctx.toggle_state_manager,
+                                        run_commands,
+                                        &settings,
+                                    ).await;
+                                    commander_ctx.events.publish(PressEvent::new(&button_name, "scene", Some(outcome_name(result.success()))));
+                                    refresh_view(&context, plugin).await;
+                                });
+                                async move { Ok(()) }
+                            },
+                        ),
+                    )?;
+                }
+                Button::Back { .. } => {
                     // Skip user-defined back buttons - we'll add our own automatically
                     debug!("Skipping user-defined back button at position {},{}", col, row);
-                    button_index += 1;
-                    col += 1;
-                    if col >= 5 {
-                        col = 0;
-                        row += 1;
-                    }
-                    continue;
                 }
             }
-            
-            button_index += 1;
-            col += 1;
-            if col >= 5 { // Stream Deck has 5 columns
-                col = 0;
-                row += 1;
-            }
         }
         
-        // Always add a back button at position 15 (row 2, col 4) if we have a parent menu
+        // Always add a back button on the grid's last key if we have a parent menu
         if self.parent.is_some() {
             if let Some(parent) = &self.parent {
+                let (back_col, back_row) = BACK_BUTTON_POSITION;
+                let back_style = commander_ctx
+                    .map(|ctx| BackButtonStyle::from_config(&ctx.current_config()))
+                    .unwrap_or_default();
                 view.set_navigation(
-                    4, // column 5 (0-indexed)
-                    2, // row 3 (0-indexed)
+                    back_col,
+                    back_row,
                     PluginNavigation::<U5, U3>::new(parent.as_ref().clone()),
-                    "Back",
-                    icons::resolve_icon(Some(&"arrow_back".to_string())),
+                    &label(&back_style.label),
+                    match &background {
+                        Some(background) => background.under(back_col, back_row, back_style.render_icon(&menu_icon_layout)),
+                        None => back_style.render_icon(&menu_icon_layout),
+                    },
                 )?;
             }
         }
@@ -295,22 +1368,47 @@ impl CommanderPlugin {
     /// Probe initial states for all toggle buttons and trigger a refresh if needed
     async fn probe_initial_toggle_states(&self, context: &PluginContext) {
         let mut needs_refresh = false;
+        let commander_ctx = context.get_context::<CommanderContext>().await;
+        let config = commander_ctx.as_ref().map(|commander_ctx| commander_ctx.current_config());
+        let backends = commander_ctx
+            .map(|commander_ctx| commander_ctx.probe_backends.clone())
+            .unwrap_or_default();
         
         for button in &self.menu.buttons {
-            if let Button::Toggle { name, probe_command, probe_args, .. } = button {
-                if let Some(probe_cmd) = probe_command {
-                    let probe_result = crate::probe::execute_probe_command(
-                        probe_cmd,
-                        probe_args,
-                        name,
-                    ).await;
-                    
+            if let Button::Toggle { name, mode: ToggleMode::File { path }, .. } = button {
+                let state = probe_file(path);
+                if self.toggle_state_manager.get_state(name) != state {
+                    debug!("Flag file state for '{}': {:?}", name, state);
+                    self.toggle_state_manager.set_state(name, state);
+                    needs_refresh = true;
+                }
+                continue;
+            }
+            if let Button::Toggle { name, mode, probe_command, probe_args, no_prefix, label_from_probe, .. } = button {
+                let spawn = config
+                    .as_ref()
+                    .map(|config| SpawnConfig::for_button(config, *no_prefix))
+                    .unwrap_or_default();
+                let probe_context = ProbeContext::from_button(button);
+                let (probe_command, probe_args) = mode.probe(probe_command.as_deref(), probe_args);
+                let request = ProbeRequest {
+                    button_name: name,
+                    command: probe_command,
+                    args: probe_args,
+                    spawn: &spawn,
+                    context: &probe_context,
+                };
+                if let Some(probe_result) = backends.probe(&request).await {
                     let initial_state = if probe_result.is_success() {
                         crate::toggle_state::ToggleState::On
                     } else {
                         crate::toggle_state::ToggleState::Off
                     };
                     
+                    if self.toggle_state_manager.set_probe_output(name, &probe_result.stdout) && *label_from_probe {
+                        needs_refresh = true;
+                    }
+
                     // Check if this changes the state from Unknown to a known state
                     let old_state = self.toggle_state_manager.get_state(name);
                     if matches!(old_state, crate::toggle_state::ToggleState::Unknown) {
@@ -327,12 +1425,9 @@ impl CommanderPlugin {
             if let Some(commander_ctx) = context.get_context::<CommanderContext>().await {
                 if let Some(sender) = &commander_ctx.navigation_sender {
                     info!("Refreshing view after initial state probing");
-                    let refreshed_plugin = CommanderPlugin::new_with_state_manager(
-                        self.menu.clone(), 
-                        self.toggle_state_manager.clone()
-                    );
+                    // Keep the parent so a submenu keeps its Back key
                     let refresh_trigger = ExternalTrigger::new(
-                        PluginNavigation::<U5, U3>::new(refreshed_plugin),
+                        PluginNavigation::<U5, U3>::new(self.clone()),
                         false
                     );
                     if let Err(e) = sender.send(refresh_trigger).await {
@@ -346,6 +1441,219 @@ impl CommanderPlugin {
     }
 }
 
+/// The command a `match` button runs for its probe's trimmed `output`: the first case
+/// that `equals` it, else the default. Without output only the default applies.
+pub fn select_match_command<'a>(
+    cases: &'a [MatchCase],
+    default: Option<&'a MatchCommand>,
+    output: Option<&str>,
+) -> Option<(&'a str, &'a [String])> {
+    output
+        .and_then(|output| cases.iter().find(|case| case.equals == output))
+        .map(|case| (case.command.as_str(), case.args.as_slice()))
+        .or_else(|| default.map(|default| (default.command.as_str(), default.args.as_slice())))
+}
+
+/// Result reported in press events for buttons that either succeed or fail
+fn outcome_name(success: bool) -> &'static str {
+    if success {
+        "success"
+    } else {
+        "failure"
+    }
+}
+
+/// Re-renders `plugin` through the navigation sender stored in the context, keeping it as
+/// the active plugin
+async fn refresh_view(context: &PluginContext, plugin: CommanderPlugin) {
+    if let Some(commander_ctx) = context.get_context::<CommanderContext>().await {
+        commander_ctx.set_active_plugin(plugin.clone());
+        if let Some(sender) = &commander_ctx.navigation_sender {
+            let refresh_trigger = ExternalTrigger::new(PluginNavigation::<U5, U3>::new(plugin), false);
+            if let Err(e) = sender.send(refresh_trigger).await {
+                error!("Failed to send refresh trigger: {}", e);
+            }
+        } else {
+            warn!("No navigation sender available for view refresh");
+        }
+    } else {
+        error!("Failed to get CommanderContext from plugin context");
+    }
+}
+
+/// Seeds declared initial states and probes every toggle with a `probe_command` in the
+/// config's menu tree. Toggles are probed group by group following `probe_order`, each
+/// group concurrently; unlisted toggles probe last. Returns the number of probed toggles
+/// whose state, or displayed probe output, changed.
+pub async fn initialize_toggle_states(config: &Config, state_manager: &ToggleStateManager) -> usize {
+    initialize_toggle_states_with(config, state_manager, &ProbeBackendRegistry::default()).await
+}
+
+/// Like [`initialize_toggle_states`], looking up each toggle's `probe_backend` in `backends`
+pub async fn initialize_toggle_states_with(
+    config: &Config,
+    state_manager: &ToggleStateManager,
+    backends: &ProbeBackendRegistry,
+) -> usize {
+    initialize_toggle_states_from(config, state_manager, backends, &HashMap::new()).await
+}
+
+/// Like [`initialize_toggle_states_with`], also using the `persisted` states of a
+/// `state_file` in the order the config's `state_source` sets: `persisted` skips the
+/// probe of toggles with a saved state, `probe_then_persisted` falls back to the saved
+/// state of toggles whose probe could not run, and `probe` ignores saved states.
+pub async fn initialize_toggle_states_from(
+    config: &Config,
+    state_manager: &ToggleStateManager,
+    backends: &ProbeBackendRegistry,
+    persisted: &HashMap<String, ToggleState>,
+) -> usize {
+    seed_initial_toggle_states(&config.menu, state_manager);
+
+    let mut toggles = collect_toggles(&config.menu);
+    let mut changed = 0;
+    if config.state_source == StateSource::Persisted {
+        toggles.retain(|button| match persisted.get(button.name()) {
+            Some(state) => {
+                debug!("Saved state for '{}': {:?}", button.name(), state);
+                if state_manager.get_state(button.name()) != *state {
+                    state_manager.set_state(button.name(), *state);
+                    changed += 1;
+                }
+                false
+            }
+            None => true,
+        });
+    }
+    for group in probe_groups(&toggles, &config.probe_order) {
+        changed += probe_toggle_group(config, &group, state_manager, backends).await;
+    }
+    if config.state_source == StateSource::ProbeThenPersisted {
+        for button in &toggles {
+            let Some(state) = persisted.get(button.name()) else {
+                continue;
+            };
+            if !state_manager.get_state(button.name()).is_known() {
+                debug!("No probed state for '{}', using its saved state {:?}", button.name(), state);
+                state_manager.set_state(button.name(), *state);
+                changed += 1;
+            }
+        }
+    }
+    changed
+}
+
+/// Splits toggles into the groups of `probe_order`, followed by a group of unlisted toggles
+fn probe_groups<'a>(toggles: &[&'a Button], probe_order: &[Vec<String>]) -> Vec<Vec<&'a Button>> {
+    let mut groups = vec![Vec::new(); probe_order.len() + 1];
+    for button in toggles {
+        if let Button::Toggle { name, .. } = button {
+            let index = probe_order
+                .iter()
+                .position(|group| group.contains(name))
+                .unwrap_or(probe_order.len());
+            groups[index].push(*button);
+        }
+    }
+    groups.retain(|group| !group.is_empty());
+    groups
+}
+
+/// Probes a group of toggles concurrently and waits for all of them to finish
+async fn probe_toggle_group(
+    config: &Config,
+    toggles: &[&Button],
+    state_manager: &ToggleStateManager,
+    backends: &ProbeBackendRegistry,
+) -> usize {
+    let mut changed = 0;
+    let mut probes = tokio::task::JoinSet::new();
+    for button in toggles {
+        // Flag files are checked in place, they need no process
+        if let Button::Toggle { name, mode: ToggleMode::File { path }, .. } = button {
+            let state = probe_file(path);
+            if state_manager.get_state(name) != state {
+                debug!("Flag file state for '{}': {:?}", name, state);
+                state_manager.set_state(name, state);
+                changed += 1;
+            }
+            continue;
+        }
+        if let Button::Toggle { name, mode, probe_command, probe_args, no_prefix, label_from_probe, .. } = button {
+            let probe_context = ProbeContext::from_button(button);
+            let spawn = SpawnConfig::for_button(config, *no_prefix);
+            let label_from_probe = *label_from_probe;
+            let name = name.clone();
+            let (probe_command, probe_args) = mode.probe(probe_command.as_deref(), probe_args);
+            let probe_command = probe_command.map(str::to_string);
+            let probe_args = probe_args.to_vec();
+            let backends = backends.clone();
+            probes.spawn(async move {
+                let request = ProbeRequest {
+                    button_name: &name,
+                    command: probe_command.as_deref(),
+                    args: &probe_args,
+                    spawn: &spawn,
+                    context: &probe_context,
+                };
+                // Toggles with neither a probe command nor a backend keep their state
+                let probe_result = backends.probe(&request).await?;
+                let state = if probe_result.is_success() {
+                    ToggleState::On
+                } else if probe_result.is_command_failure() {
+                    ToggleState::Off
+                } else {
+                    ToggleState::Unknown
+                };
+                Some((name, state, probe_result.stdout, label_from_probe))
+            });
+        }
+    }
+
+    while let Some(joined) = probes.join_next().await {
+        match joined {
+            Ok(None) => {}
+            Ok(Some((name, state, stdout, label_from_probe))) => {
+                let output_changed = state_manager.set_probe_output(&name, &stdout) && label_from_probe;
+                if state_manager.get_state(&name) != state {
+                    debug!("Probed state for '{}': {:?}", name, state);
+                    state_manager.set_state(&name, state);
+                    changed += 1;
+                } else if output_changed {
+                    debug!("Probed output for '{}' changed", name);
+                    changed += 1;
+                }
+            }
+            Err(e) => error!("Probe task failed: {}", e),
+        }
+    }
+    changed
+}
+
+/// Collects every toggle button in the menu tree, depth first
+fn collect_toggles(menu: &Menu) -> Vec<&Button> {
+    let mut toggles = Vec::new();
+    menu.walk_buttons(|button, _| {
+        if let Button::Toggle { .. } = button {
+            toggles.push(button);
+        }
+    });
+    toggles
+}
+
+/// Seeds the declared `initial_state` of every probe-less toggle in the menu tree
+pub fn seed_initial_toggle_states(menu: &Menu, state_manager: &ToggleStateManager) {
+    menu.walk_buttons(|button, _| {
+        if let Button::Toggle { name, mode, probe_command, probe_args, initial_state: Some(state), .. } = button {
+            let probed = mode.probe(probe_command.as_deref(), probe_args).0.is_some();
+            if !probed && matches!(state_manager.get_state(name), ToggleState::Unknown) {
+                debug!("Seeding declared initial state for '{}': {:?}", name, state);
+                state_manager.set_state(name, *state);
+            }
+        }
+    });
+}
+
 #[async_trait::async_trait]
 impl Plugin<U5, U3> for CommanderPlugin {
     fn name(&self) -> &'static str {
@@ -358,6 +1666,223 @@ impl Plugin<U5, U3> for CommanderPlugin {
         // Probe initial states for all toggle buttons in this menu
         self.probe_initial_toggle_states(&context).await;
         
-        self.create_view_from_menu()
+        let commander_ctx = context.get_context::<CommanderContext>().await;
+        if let Some(commander_ctx) = &commander_ctx {
+            commander_ctx.set_active_plugin(self.clone());
+        }
+        Ok(self.create_view_from_menu(commander_ctx.as_deref())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::context_for;
+
+    fn match_context(probe_output: &str, with_default: bool) -> CommanderContext {
+        let default = if with_default { "      default:\n        command: \"true\"\n" } else { "" };
+        let yaml = format!(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: match
+      name: "Focused App"
+      probe:
+        command: "printf"
+        args: ["{}\n"]
+      cases:
+        - equals: "kitty"
+          command: "false"
+        - equals: "firefox"
+          command: "true"
+{}"#,
+            probe_output, default
+        );
+        context_for(serde_yaml::from_str(&yaml).unwrap())
+    }
+
+    async fn press_match(context: &CommanderContext) -> Option<CommandOutcome> {
+        let config = context.current_config();
+        let Some(Button::Match { name, probe, cases, default, .. }) = config.menu.buttons.first() else {
+            panic!("expected a match button");
+        };
+        context.run_match(name, probe, cases, default.as_ref()).await
+    }
+
+    #[tokio::test]
+    async fn test_match_runs_case_selected_by_probe() {
+        let context = match_context("firefox", true);
+        let mut events = context.events.subscribe();
+
+        // The probe's trimmed output selects the "firefox" case, which succeeds
+        assert!(press_match(&context).await.unwrap().success);
+        assert!(context.last_result_manager.get("Focused App").unwrap().success);
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.button.as_str(), event.result.as_deref()), ("Focused App", Some("success")));
+
+        let context = match_context("kitty", true);
+        assert!(!press_match(&context).await.unwrap().success);
+    }
+
+    #[tokio::test]
+    async fn test_match_falls_back_to_default() {
+        // No case equals "emacs", so the default runs
+        let context = match_context("emacs", true);
+        assert!(press_match(&context).await.unwrap().success);
+
+        // Without a default nothing runs
+        let context = match_context("emacs", false);
+        let mut events = context.events.subscribe();
+        assert_eq!(press_match(&context).await, None);
+        assert_eq!(context.last_result_manager.get("Focused App"), None);
+        assert_eq!(events.try_recv().unwrap().result.as_deref(), Some("skipped"));
+    }
+
+    #[test]
+    fn test_select_match_command() {
+        let case = |equals: &str, command: &str| MatchCase {
+            equals: equals.to_string(),
+            command: command.to_string(),
+            args: vec![],
+        };
+        let cases = [case("firefox", "browser-keys"), case("firefox", "shadowed"), case("kitty", "terminal-keys")];
+        let default = MatchCommand { command: "fallback".to_string(), args: vec![] };
+
+        assert_eq!(select_match_command(&cases, Some(&default), Some("firefox")).unwrap().0, "browser-keys");
+        assert_eq!(select_match_command(&cases, Some(&default), Some("kitty")).unwrap().0, "terminal-keys");
+        assert_eq!(select_match_command(&cases, Some(&default), Some("emacs")).unwrap().0, "fallback");
+        // A failed probe only runs the default
+        assert_eq!(select_match_command(&cases, Some(&default), None).unwrap().0, "fallback");
+        assert_eq!(select_match_command(&cases, None, Some("emacs")), None);
+    }
+
+    fn branch_config(probe: &str) -> Config {
+        serde_yaml::from_str(&format!(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: branch
+      name: "Network"
+      probe:
+        command: "{}"
+      on_success:
+        name: "Online"
+        buttons:
+          - type: command
+            name: "Sync"
+            command: "true"
+      on_failure:
+        name: "Offline"
+        buttons:
+          - type: command
+            name: "Reconnect"
+            command: "true"
+"#,
+            probe
+        ))
+        .unwrap()
     }
-}
\ No newline at end of file
+
+    /// Presses the branch button and returns the name of the submenu sent through the trigger channel
+    async fn press_branch(probe: &str) -> (String, Option<String>) {
+        let config = branch_config(probe);
+        assert!(config.validate().is_ok());
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let mut context = context_for(config.clone());
+        context.navigation_sender = Some(sender);
+        let mut events = context.events.subscribe();
+
+        let Some(Button::Branch { name, probe, on_success, on_failure, .. }) = config.menu.buttons.first() else {
+            panic!("expected a branch button");
+        };
+        let root = CommanderPlugin::new_with_state_manager(config.menu.clone(), context.toggle_state_manager.clone());
+        let target = context.run_branch(name, probe, on_success, on_failure).await;
+        let submenu = CommanderPlugin::new_with_parent(target.clone(), root);
+        assert_eq!(submenu.parent().map(|parent| parent.menu().name.as_str()), Some("Main"));
+        assert!(context.navigate_to(submenu).await);
+        assert!(receiver.try_recv().is_ok());
+        (target.name.clone(), events.try_recv().unwrap().result)
+    }
+
+    #[tokio::test]
+    async fn test_branch_opens_submenu_picked_by_probe() {
+        assert_eq!(press_branch("true").await, ("Online".to_string(), Some("success".to_string())));
+        assert_eq!(press_branch("false").await, ("Offline".to_string(), Some("failure".to_string())));
+        // A probe that cannot run counts as a failure
+        assert_eq!(
+            press_branch("streamdeck-no-such-command").await,
+            ("Offline".to_string(), Some("failure".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_branch_submenus_are_part_of_the_menu_tree() {
+        let config = branch_config("true");
+        let mut visited = Vec::new();
+        config.walk_buttons(|button, path| visited.push(format!("{}/{}", path.join("/"), button.name())));
+        assert_eq!(visited, vec!["Main/Network", "Main/Online/Sync", "Main/Offline/Reconnect"]);
+        assert_eq!(config.menu.buttons[0].commands(), vec!["true"]);
+
+        // Without a trigger channel there is nowhere to navigate
+        let context = context_for(config.clone());
+        let menu = CommanderPlugin::new(config.menu.clone());
+        assert!(!context.navigate_to(menu).await);
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_awaited_returns_exit_status() {
+        let config: Config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        let context = Arc::new(context_for(config));
+        let run = CommandRun {
+            name: "Check".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), "exit 3".to_string()],
+            success_exit_codes: vec![0],
+            ..Default::default()
+        };
+
+        let outcome = context.execute_command_awaited(run.clone(), Some(Duration::from_secs(5))).await;
+        let outcome = outcome.completed().unwrap();
+        assert_eq!(outcome.exit_code, Some(3));
+        assert!(!outcome.success);
+        assert_eq!(context.last_result_manager.get("Check"), Some(outcome));
+
+        // A command outliving the wait keeps running and is recorded once done
+        let slow = CommandRun {
+            name: "Slow".to_string(),
+            command: "sleep".to_string(),
+            args: vec!["0.3".to_string()],
+            ..run
+        };
+        let outcome = context.execute_command_awaited(slow, Some(Duration::from_millis(50))).await;
+        assert_eq!(outcome, ActionOutcome::TimedOut);
+        assert!(context.last_result_manager.get("Slow").is_none());
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(context.last_result_manager.get("Slow").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_spawned_returns_immediately() {
+        let config: Config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        let context = Arc::new(context_for(config));
+        let run = CommandRun {
+            name: "Nap".to_string(),
+            command: "sleep".to_string(),
+            args: vec!["0.3".to_string()],
+            success_exit_codes: vec![0],
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        let task = context.execute_command_spawned(run);
+        assert!(started.elapsed() < Duration::from_millis(100), "{:?}", started.elapsed());
+        assert!(context.last_result_manager.get("Nap").is_none());
+
+        let outcome = task.await.unwrap();
+        assert!(outcome.success);
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(context.last_result_manager.get("Nap"), Some(outcome));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::config::Config;
+use std::path::{Path, PathBuf};
+
+/// Executables buttons may run, from the top-level `allowed_commands` and `denied_commands`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandPolicy {
+    /// Executables that may run; empty allows every one that is not denied
+    allowed: Vec<PathBuf>,
+    denied: Vec<PathBuf>,
+}
+
+impl CommandPolicy {
+    /// Builds the policy from the global configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(&config.allowed_commands, &config.denied_commands)
+    }
+
+    /// Builds a policy from lists of command names or paths
+    pub fn new(allowed: &[String], denied: &[String]) -> Self {
+        Self {
+            allowed: allowed.iter().map(|command| resolve_executable(command)).collect(),
+            denied: denied.iter().map(|command| resolve_executable(command)).collect(),
+        }
+    }
+
+    /// Whether a button may run `command`. A command in both lists is denied.
+    pub fn allows(&self, command: &str) -> bool {
+        if self.allowed.is_empty() && self.denied.is_empty() {
+            return true;
+        }
+        let executable = resolve_executable(command);
+        !self.denied.contains(&executable) && (self.allowed.is_empty() || self.allowed.contains(&executable))
+    }
+}
+
+/// The executable a command runs, so `ls` and `/usr/bin/ls` compare equal: bare names
+/// are looked up in `PATH` and the directory of the executable is canonicalized to
+/// follow symlinks. The executable itself is not followed, as multicall binaries such
+/// as coreutils or busybox are one file that every command name links to. A command
+/// that cannot be found resolves to itself.
+pub fn resolve_executable(command: &str) -> PathBuf {
+    let path = Path::new(command);
+    let found = if command.contains('/') {
+        Some(path.to_path_buf()).filter(|path| path.exists())
+    } else {
+        std::env::var_os("PATH").and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(command))
+                .find(|candidate| candidate.is_file())
+        })
+    };
+    found
+        .and_then(|found| {
+            let dir = found.parent()?;
+            let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+            Some(dir.canonicalize().ok()?.join(found.file_name()?))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context_for, TempDir};
+    use crate::toggle_state::ToggleState;
+
+    fn policy(allowed: &[&str], denied: &[&str]) -> CommandPolicy {
+        let owned = |list: &[&str]| list.iter().map(|command| command.to_string()).collect::<Vec<_>>();
+        CommandPolicy::new(&owned(allowed), &owned(denied))
+    }
+
+    #[test]
+    fn test_resolve_executable() {
+        let sh = resolve_executable("sh");
+        assert!(sh.is_absolute(), "{:?}", sh);
+        assert_eq!(resolve_executable(sh.to_str().unwrap()), sh);
+        assert_eq!(resolve_executable("streamdeck-no-such-command"), PathBuf::from("streamdeck-no-such-command"));
+    }
+
+    #[test]
+    fn test_policy_lists() {
+        assert!(policy(&[], &[]).allows("anything"));
+
+        let allow = policy(&["sh", "true"], &[]);
+        assert!(allow.allows("true"));
+        assert!(allow.allows(resolve_executable("sh").to_str().unwrap()));
+        assert!(!allow.allows("false"));
+
+        let deny = policy(&[], &["false"]);
+        assert!(deny.allows("true"));
+        assert!(!deny.allows("false"));
+        assert!(!deny.allows(resolve_executable("false").to_str().unwrap()));
+
+        // Denying wins over allowing
+        assert!(!policy(&["false"], &["false"]).allows("false"));
+    }
+
+    #[test]
+    fn test_policy_with_multicall_binary() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new("multicall");
+        let bin = dir.join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(dir.join("coreutils"), "#!/bin/sh\n").unwrap();
+        for name in ["ls", "rm"] {
+            symlink(dir.join("coreutils"), bin.join(name)).unwrap();
+        }
+        symlink(&bin, dir.join("sbin")).unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        // Both names link to the same binary, yet stay different commands
+        let allow = policy(&[&path("bin/ls")], &[]);
+        assert!(allow.allows(&path("bin/ls")));
+        assert!(!allow.allows(&path("bin/rm")));
+        let deny = policy(&[], &[&path("bin/rm")]);
+        assert!(deny.allows(&path("bin/ls")));
+        assert!(!deny.allows(&path("bin/rm")));
+
+        // A symlinked directory still resolves to the directory it points to
+        assert_eq!(resolve_executable(&path("sbin/ls")), resolve_executable(&path("bin/ls")));
+        assert!(!deny.allows(&path("sbin/rm")));
+    }
+
+    #[tokio::test]
+    async fn test_command_allow_and_deny_lists() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+allowed_commands: ["true", "/bin/echo"]
+denied_commands: ["echo"]
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Allowed"
+      command: "true"
+    - type: command
+      name: "Blocked"
+      command: "false"
+    - type: toggle
+      name: "Echo"
+      mode: single
+      command: "echo"
+      args: ["{state}"]
+"#,
+        )
+        .unwrap();
+        // Denying `echo` wins over allowing it by its path
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "commands not allowed by allowed_commands or denied_commands: 'false' on 'Blocked', 'echo' on 'Echo'"
+        );
+
+        // Spawning refuses them too, recording the press as an error
+        let context = context_for(config);
+        assert!(context.execute_button("Allowed").await);
+        assert!(context.last_result_manager.get("Allowed").unwrap().success);
+
+        assert!(context.execute_button("Blocked").await);
+        let outcome = context.last_result_manager.get("Blocked").unwrap();
+        assert!(!outcome.success);
+        assert_eq!(outcome.exit_code, None);
+
+        assert!(context.execute_button("Echo").await);
+        assert_eq!(context.toggle_state_manager.get_state("Echo"), ToggleState::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_denied_toggle_command_records_failure() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+denied_commands: ["touch"]
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Marker"
+      mode: single
+      command: "touch"
+"#,
+        )
+        .unwrap();
+        let context = context_for(config);
+        let mut events = context.events.subscribe();
+
+        assert!(context.execute_button("Marker").await);
+        assert_eq!(context.toggle_state_manager.get_state("Marker"), ToggleState::Unknown);
+        let outcome = context.last_result_manager.get("Marker").unwrap();
+        assert!(!outcome.success);
+        assert_eq!(outcome.exit_code, None);
+        assert_eq!(events.try_recv().unwrap().result.as_deref(), Some("failure"));
+    }
+}
@@ -1,45 +1,755 @@
-use anyhow::Result;
+use crate::command_policy::CommandPolicy;
+use crate::device::MAX_BRIGHTNESS;
+use crate::expand::expand_env;
+use crate::icon_layout::{is_hex_color, normalize_icon_layout, ICON_ROTATIONS};
+use crate::icons::{is_no_icon, lookup_icon, with_icon_style, ICON_STYLES};
+use crate::last_result::{CommandOutcome, DEFAULT_SUCCESS_EXIT_CODES};
+use crate::render::{MAX_RENDER_FONT_SIZE, MIN_RENDER_FONT_SIZE};
+use crate::result_view::RESULT_DISPLAY_DURATION;
+use crate::toggle_state::ToggleState;
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 // Embed config.yaml at compile time if it exists
 const EMBEDDED_CONFIG: &str = include_str!("../config.yaml");
 
+/// Environment variable pointing at a config file to use instead of the embedded one
+pub const CONFIG_PATH_ENV: &str = "STREAMDECK_CONFIG";
+
+/// A config problem reported by [`Config::warnings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigWarning {
+    /// A single-mode toggle without a probe or `initial_state`, whose state is only
+    /// tracked in memory
+    UntrackedToggle { toggle: String },
+    /// A button setting an option that only applies to pedal keys, such as `trigger_on`,
+    /// while no pedal key is mapped to it
+    PedalOnlyOption { button: String, option: &'static str },
+}
+
+impl fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigWarning::UntrackedToggle { toggle } => write!(
+                f,
+                "toggle '{}' has no probe and no initial_state, so its state is only tracked in memory: \
+                 it starts Unknown (the first press assumes it turns on) and drifts when it changes \
+                 outside the deck; add a probe_command or an initial_state",
+                toggle
+            ),
+            ConfigWarning::PedalOnlyOption { button, option } => write!(
+                f,
+                "button '{}' sets {}, which only applies to pedal keys, but no pedal key is mapped to it; \
+                 deck keys ignore it",
+                button, option
+            ),
+        }
+    }
+}
+
+/// Directory under the XDG config home that holds the config file
+const CONFIG_DIR_NAME: &str = "streamdeck-nix";
+
+/// Name of the config file looked up in the config directories
+const CONFIG_FILE_NAME: &str = "config.yaml";
+
+/// Environment variable that, when set to "1" or "true", substitutes `${VAR}` and
+/// `${VAR:-fallback}` in the raw config text before it is parsed
+pub const CONFIG_TEMPLATE_ENV: &str = "STREAMDECK_CONFIG_TEMPLATE";
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     pub menu: Menu,
+    /// Program and arguments prepended to every spawned command, toggle and probe
+    #[serde(default)]
+    pub command_prefix: Vec<String>,
+    /// Spawn commands, toggles and probes without the daemon's environment
+    #[serde(default)]
+    pub clean_env: bool,
+    /// Variables passed through from the daemon's environment when `clean_env` is set
+    #[serde(default = "default_clean_env_keep")]
+    pub clean_env_keep: Vec<String>,
+    /// Level the stdout and stderr of commands and toggles are logged at
+    #[serde(default)]
+    pub log_output: LogOutput,
+    /// Probes taking longer than this many milliseconds are logged as slow; unset never warns
+    #[serde(default)]
+    pub slow_probe_warn_ms: Option<u64>,
+    /// Commands buttons may run, by name or path; empty allows all but `denied_commands`
+    #[serde(default)]
+    pub allowed_commands: Vec<String>,
+    /// Commands buttons may never run, even if listed in `allowed_commands`
+    #[serde(default)]
+    pub denied_commands: Vec<String>,
+    /// Icon size relative to its default size, clamped to 0.25..=2.0
+    #[serde(default = "default_icon_scale")]
+    pub icon_scale: f32,
+    /// Blank margin around icons as a fraction of the key, clamped to 0.0..=0.4
+    #[serde(default)]
+    pub icon_padding: f32,
+    /// Groups of toggle names probed one group after another; unlisted toggles probe last
+    #[serde(default)]
+    pub probe_order: Vec<Vec<String>>,
+    /// Smallest font size used for button labels
+    #[serde(default = "default_label_font_min")]
+    pub label_font_min: u32,
+    /// Largest font size used for button labels
+    #[serde(default = "default_label_font_max")]
+    pub label_font_max: u32,
+    /// Locale used to pick translated button names (e.g. "pl" or "pl_PL")
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Label of the back button added to every submenu
+    #[serde(default = "default_back_name")]
+    pub back_label: String,
+    /// Icon of the back button added to every submenu
+    #[serde(default = "default_back_icon")]
+    pub back_icon: String,
+    /// Color of a border drawn around the back button, e.g. "#ff9800", so the way
+    /// back home stands out in nested menus
+    #[serde(default)]
+    pub back_highlight: Option<String>,
+    /// Settings passed to the deck renderer
+    #[serde(default)]
+    pub render: RenderSettings,
+    /// Themes used while a toggle is in a given state, e.g. dark while the VPN is on.
+    /// The first matching rule wins, also over the menus' own themes.
+    #[serde(default)]
+    pub theme_when: Vec<ThemeRule>,
+    /// Unix socket path on which every button press is published as a JSON line
+    #[serde(default)]
+    pub event_socket: Option<String>,
+    /// Reject unknown icon names instead of showing the terminal icon in their place
+    #[serde(default)]
+    pub strict_icons: bool,
+    /// Style of icons given without a `style:` prefix, e.g. "outlined"; "filled" if unset
+    #[serde(default)]
+    pub default_icon_style: Option<String>,
+    /// Skip buttons that fail to parse instead of rejecting the whole config
+    #[serde(default)]
+    pub lenient: bool,
+    /// Command or toggle buttons triggered by the keys of a Stream Deck Pedal
+    #[serde(default)]
+    pub pedal: Option<PedalConfig>,
+    /// Whether actions fire when a key goes down or when it is released
+    #[serde(default)]
+    pub trigger_on: TriggerOn,
+    /// Seconds without key presses after which the deck returns to the main menu
+    #[serde(default)]
+    pub idle_home_secs: Option<u64>,
+    /// Named sets of toggle states that scene buttons restore, keyed by toggle name
+    #[serde(default, serialize_with = "serialize_scenes")]
+    pub scenes: BTreeMap<String, HashMap<String, ToggleState>>,
+    /// Commands run before and after every command or toggle action
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// Deck brightness in percent set on connect; the deck's own brightness is kept when unset
+    #[serde(default)]
+    pub brightness: Option<u8>,
+    /// File the known toggle states are saved to, and read back from on startup
+    #[serde(default)]
+    pub state_file: Option<String>,
+    /// Whether toggles start from their probe or from the states saved in `state_file`
+    #[serde(default)]
+    pub state_source: StateSource,
+    /// Named colors, e.g. `accent: "#ff9800"`, that color fields reference as `$accent`
+    #[serde(default)]
+    pub colors: BTreeMap<String, String>,
+    /// PNG file the current view is mirrored to on SIGUSR1, for looking at the deck remotely
+    #[serde(default)]
+    pub dump_render_path: Option<String>,
+}
+
+impl Config {
+    /// Replaces `$name` references in the color fields with the hex code defined for
+    /// them under `colors`. Fails on a reference to a color that is not defined.
+    pub fn resolve_colors(&mut self) -> Result<()> {
+        let colors = &self.colors;
+        let resolve = |field: &str, color: &mut String| -> Result<()> {
+            if let Some(name) = color.strip_prefix('$') {
+                match colors.get(name) {
+                    Some(hex) => *color = hex.clone(),
+                    None => anyhow::bail!("{} references undefined color '${}'", field, name),
+                }
+            }
+            Ok(())
+        };
+        if let Some(color) = self.back_highlight.as_mut() {
+            resolve("back_highlight", color)?;
+        }
+        Ok(())
+    }
+
+    /// Prefixes icons given without a style, including the back icon, with
+    /// `default_icon_style`. Fails if it is not a Material Design icon style.
+    pub fn apply_default_icon_style(&mut self) -> Result<()> {
+        let Some(style) = self.default_icon_style.clone() else {
+            return Ok(());
+        };
+        if !ICON_STYLES.contains(&style.as_str()) {
+            anyhow::bail!("unknown default_icon_style '{}', expected one of {}", style, ICON_STYLES.join(", "));
+        }
+        self.back_icon = with_icon_style(&self.back_icon, &style);
+        self.walk_buttons_mut(|button, _| {
+            for icon in button.icon_specs_mut() {
+                *icon = with_icon_style(icon, &style);
+            }
+        });
+        Ok(())
+    }
+
+    /// Checks references between parts of the config that serde cannot verify
+    pub fn validate(&self) -> Result<()> {
+        let mut toggle_names = HashSet::new();
+        let mut pressable = HashSet::new();
+        let mut groups = HashSet::new();
+        let mut group_actions = Vec::new();
+        let mut scene_buttons = Vec::new();
+        let mut unknown_icons = Vec::new();
+        let mut invalid_urls = Vec::new();
+        let mut invalid_rotations = Vec::new();
+        let mut no_success_codes = Vec::new();
+        let mut zero_repeat_intervals = Vec::new();
+        let mut repeated_holds = Vec::new();
+        let mut delayed_queues = Vec::new();
+        let mut zero_holds = Vec::new();
+        let mut zero_samples = Vec::new();
+        let mut mixed_probes = Vec::new();
+        let mut disallowed = Vec::new();
+        let mut state_owners = HashMap::new();
+        let mut state_collisions = Vec::new();
+        let policy = CommandPolicy::from_config(self);
+        let is_unknown_icon = |icon: &String| self.strict_icons && !is_no_icon(Some(icon)) && lookup_icon(icon).is_none();
+        self.walk_buttons(|button, _| {
+            // Buttons of one type may share a name and its state, different types may not
+            if button.is_stateful() {
+                let owner = *state_owners.entry(button.name()).or_insert(button.type_name());
+                if owner != button.type_name() {
+                    state_collisions.push(format!("'{}' ({} and {})", button.name(), owner, button.type_name()));
+                }
+            }
+            for command in button.commands() {
+                if !policy.allows(command) {
+                    disallowed.push(format!("'{}' on '{}'", command, button.name()));
+                }
+            }
+            unknown_icons.extend(
+                button
+                    .icon_specs()
+                    .into_iter()
+                    .filter(|icon| is_unknown_icon(icon))
+                    .map(|icon| format!("'{}' on '{}'", icon, button.name())),
+            );
+            if !ICON_ROTATIONS.contains(&button.icon_rotate()) {
+                invalid_rotations.push(format!("{} on '{}'", button.icon_rotate(), button.name()));
+            }
+            match button {
+                Button::Command { name, success_exit_codes, repeat, confirm_hold_ms, delay_ms, queue, .. } => {
+                    if success_exit_codes.is_empty() {
+                        no_success_codes.push(format!("'{}'", name));
+                    }
+                    if repeat.as_ref().is_some_and(|repeat| repeat.interval_ms == 0) {
+                        zero_repeat_intervals.push(format!("'{}'", name));
+                    }
+                    if repeat.is_some() && confirm_hold_ms.is_some() {
+                        repeated_holds.push(format!("'{}'", name));
+                    }
+                    if *confirm_hold_ms == Some(0) {
+                        zero_holds.push(format!("'{}'", name));
+                    }
+                    if *queue && *delay_ms > 0 {
+                        delayed_queues.push(format!("'{}'", name));
+                    }
+                    pressable.insert(name.as_str());
+                }
+                Button::Toggle { name, group, probe_command, probe_backend, probe_http, probe_all, probe_samples, confirm_hold_ms, .. } => {
+                    if *confirm_hold_ms == Some(0) {
+                        zero_holds.push(format!("'{}'", name));
+                    }
+                    if *probe_samples == 0 {
+                        zero_samples.push(format!("'{}'", name));
+                    }
+                    if let Some(check) = probe_http.as_ref().filter(|check| !check.url.starts_with("http://") && !check.url.starts_with("https://")) {
+                        invalid_urls.push(format!("'{}' on '{}'", check.url, name));
+                    }
+                    if !probe_all.is_empty() && (probe_command.is_some() || probe_backend.is_some() || probe_http.is_some()) {
+                        mixed_probes.push(format!("'{}'", name));
+                    }
+                    toggle_names.insert(name.as_str());
+                    pressable.insert(name.as_str());
+                    groups.extend(group.as_deref());
+                }
+                Button::GroupAction { name, group, .. } => group_actions.push((name, group)),
+                Button::Scene { name, scene, .. } => scene_buttons.push((name, scene)),
+                _ => {}
+            }
+        });
+        if !state_collisions.is_empty() {
+            anyhow::bail!(
+                "buttons of different types keep state under the same name: {}",
+                state_collisions.join(", ")
+            );
+        }
+        if is_unknown_icon(&self.back_icon) {
+            unknown_icons.push(format!("'{}' on the back button", self.back_icon));
+        }
+        if !unknown_icons.is_empty() {
+            anyhow::bail!("unknown icons: {}", unknown_icons.join(", "));
+        }
+        if !disallowed.is_empty() {
+            anyhow::bail!(
+                "commands not allowed by allowed_commands or denied_commands: {}",
+                disallowed.join(", ")
+            );
+        }
+        if !mixed_probes.is_empty() {
+            anyhow::bail!(
+                "probe_all cannot be combined with probe_command, probe_backend or probe_http: {}",
+                mixed_probes.join(", ")
+            );
+        }
+        if !no_success_codes.is_empty() {
+            anyhow::bail!("success_exit_codes must not be empty: {}", no_success_codes.join(", "));
+        }
+        if !zero_repeat_intervals.is_empty() {
+            anyhow::bail!("repeat interval_ms must be greater than 0: {}", zero_repeat_intervals.join(", "));
+        }
+        if !zero_holds.is_empty() {
+            anyhow::bail!("confirm_hold_ms must be greater than 0: {}", zero_holds.join(", "));
+        }
+        if !zero_samples.is_empty() {
+            anyhow::bail!("probe_samples must be greater than 0: {}", zero_samples.join(", "));
+        }
+        if !repeated_holds.is_empty() {
+            anyhow::bail!("confirm_hold_ms cannot be combined with repeat: {}", repeated_holds.join(", "));
+        }
+        if !delayed_queues.is_empty() {
+            anyhow::bail!("queue cannot be combined with delay_ms: {}", delayed_queues.join(", "));
+        }
+        if !invalid_rotations.is_empty() {
+            anyhow::bail!("icon_rotate must be 0, 90, 180 or 270: {}", invalid_rotations.join(", "));
+        }
+        if !invalid_urls.is_empty() {
+            anyhow::bail!("probe_http URLs must start with http:// or https://: {}", invalid_urls.join(", "));
+        }
+
+        if self.label_font_min == 0 || self.label_font_min > self.label_font_max {
+            anyhow::bail!(
+                "label_font_min ({}) must be at least 1 and not above label_font_max ({})",
+                self.label_font_min,
+                self.label_font_max
+            );
+        }
+
+        let invalid_colors: Vec<String> = self
+            .colors
+            .iter()
+            .filter(|(_, color)| !is_hex_color(color))
+            .map(|(name, color)| format!("{} ('{}')", name, color))
+            .collect();
+        if !invalid_colors.is_empty() {
+            anyhow::bail!("colors must be like \"#ff9800\": {}", invalid_colors.join(", "));
+        }
+
+        if let Some(color) = self.back_highlight.as_ref().filter(|color| !is_hex_color(color)) {
+            anyhow::bail!("back_highlight must be a color like \"#ff9800\": '{}'", color);
+        }
+
+        if self.state_source != StateSource::Probe && self.state_file.is_none() {
+            anyhow::bail!("state_source persisted and probe_then_persisted need a state_file to read saved states from");
+        }
+
+        if let Some(brightness) = self.brightness.filter(|brightness| *brightness > MAX_BRIGHTNESS) {
+            anyhow::bail!("brightness ({}) must be between 0 and {}", brightness, MAX_BRIGHTNESS);
+        }
+
+        if let Some(font_size) = self.render.font_size {
+            if !(MIN_RENDER_FONT_SIZE..=MAX_RENDER_FONT_SIZE).contains(&font_size) {
+                anyhow::bail!(
+                    "render.font_size ({}) must be between {} and {}",
+                    font_size,
+                    MIN_RENDER_FONT_SIZE,
+                    MAX_RENDER_FONT_SIZE
+                );
+            }
+        }
+
+        for name in self.probe_order.iter().flatten() {
+            if !toggle_names.contains(name.as_str()) {
+                anyhow::bail!("probe_order references unknown toggle '{}'", name);
+            }
+        }
+
+        for name in self.pedal.iter().flat_map(|pedal| [&pedal.left, &pedal.middle, &pedal.right]).flatten() {
+            if !pressable.contains(name.as_str()) {
+                anyhow::bail!("pedal references '{}', which is not a command or toggle button", name);
+            }
+        }
+
+        for (name, group) in group_actions {
+            if !groups.contains(group.as_str()) {
+                anyhow::bail!("group action '{}' references group '{}' with no toggles", name, group);
+            }
+        }
+
+        for rule in &self.theme_when {
+            if !toggle_names.contains(rule.toggle.as_str()) {
+                anyhow::bail!("theme_when references unknown toggle '{}'", rule.toggle);
+            }
+        }
+
+        for (name, scene) in scene_buttons {
+            if !self.scenes.contains_key(scene) {
+                anyhow::bail!("scene button '{}' references unknown scene '{}'", name, scene);
+            }
+        }
+        for (scene, states) in &self.scenes {
+            let mut names: Vec<_> = states.keys().filter(|name| !toggle_names.contains(name.as_str())).collect();
+            names.sort();
+            if let Some(name) = names.first() {
+                anyhow::bail!("scene '{}' references unknown toggle '{}'", scene, name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Problems that do not stop the config from loading but likely make the deck
+    /// misbehave. Logged as warnings when the config loads.
+    pub fn warnings(&self) -> Vec<ConfigWarning> {
+        let mut warnings = Vec::new();
+        self.walk_buttons(|button, _| {
+            if let Button::Toggle {
+                name,
+                mode: ToggleMode::Single { .. },
+                probe_command: None,
+                probe_backend: None,
+                probe_http: None,
+                initial_state: None,
+                probe_all,
+                ..
+            } = button
+            {
+                if probe_all.is_empty() {
+                    warnings.push(ConfigWarning::UntrackedToggle { toggle: name.clone() });
+                }
+            }
+        });
+
+        let pedal_keys: Vec<&str> = self
+            .pedal
+            .iter()
+            .flat_map(|pedal| [&pedal.left, &pedal.middle, &pedal.right])
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        self.walk_buttons(|button, _| {
+            let (name, trigger_on, repeat, confirm_hold_ms) = match button {
+                Button::Command { name, trigger_on, repeat, confirm_hold_ms, .. } => (name, trigger_on, repeat.is_some(), confirm_hold_ms),
+                Button::Toggle { name, trigger_on, confirm_hold_ms, .. } => (name, trigger_on, false, confirm_hold_ms),
+                _ => return,
+            };
+            if pedal_keys.contains(&name.as_str()) {
+                return;
+            }
+            let options = [("trigger_on", trigger_on.is_some()), ("repeat", repeat), ("confirm_hold_ms", confirm_hold_ms.is_some())];
+            for (option, set) in options {
+                if set {
+                    warnings.push(ConfigWarning::PedalOnlyOption { button: name.clone(), option });
+                }
+            }
+        });
+        warnings
+    }
+
+    /// When the named button fires: its own `trigger_on`, else the global one
+    pub fn trigger_for(&self, name: &str) -> TriggerOn {
+        let mut trigger = None;
+        self.walk_buttons(|button, _| {
+            if trigger.is_none() && button.name() == name {
+                trigger = Some(match button {
+                    Button::Command { trigger_on, .. } | Button::Toggle { trigger_on, .. } => {
+                        trigger_on.unwrap_or(self.trigger_on)
+                    }
+                    _ => self.trigger_on,
+                });
+            }
+        });
+        trigger.unwrap_or(self.trigger_on)
+    }
+
+    /// Level the named button's command output is logged at: its own `log_output`, else
+    /// the global one
+    pub fn log_output_for(&self, name: &str) -> LogOutput {
+        let mut level = None;
+        self.walk_buttons(|button, _| {
+            if level.is_none() && button.name() == name {
+                level = Some(match button {
+                    Button::Command { log_output, .. } | Button::Toggle { log_output, .. } => {
+                        log_output.unwrap_or(self.log_output)
+                    }
+                    _ => self.log_output,
+                });
+            }
+        });
+        level.unwrap_or(self.log_output)
+    }
+
+    /// How long the last output line of the named command button is shown after it ran,
+    /// if it sets `toast_last_line`
+    pub fn toast_for(&self, name: &str) -> Option<Duration> {
+        let mut toast = None;
+        self.walk_buttons(|button, _| {
+            if toast.is_none() && button.name() == name {
+                toast = Some(match button {
+                    Button::Command { toast_last_line: true, toast_ms, .. } => {
+                        Some(toast_ms.map_or(RESULT_DISPLAY_DURATION, Duration::from_millis))
+                    }
+                    _ => None,
+                });
+            }
+        });
+        toast.flatten()
+    }
+
+    /// Name of the lock the named button's runs hold, if it sets a `lock`
+    pub fn lock_for(&self, name: &str) -> Option<String> {
+        let mut lock = None;
+        self.walk_buttons(|button, _| {
+            if lock.is_none() && button.name() == name {
+                lock = Some(button.lock().map(str::to_string));
+            }
+        });
+        lock.flatten()
+    }
+
+    /// Names of the buttons with `active_hours` that are outside them at the local time `now`
+    pub fn inactive_buttons(&self, now: NaiveDateTime) -> Vec<String> {
+        let mut inactive = Vec::new();
+        self.walk_buttons(|button, _| {
+            if !button.is_active_at(now) {
+                inactive.push(button.name().to_string());
+            }
+        });
+        inactive
+    }
+
+    /// The `repeat` of the named command button, if it has one
+    pub fn repeat_for(&self, name: &str) -> Option<&RepeatConfig> {
+        let mut found = None;
+        self.walk_buttons(|button, _| {
+            if found.is_none() && button.name() == name {
+                found = Some(button);
+            }
+        });
+        match found {
+            Some(Button::Command { repeat, .. }) => repeat.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// How long the named command or toggle button must be held before it fires, if
+    /// it sets `confirm_hold_ms`
+    pub fn confirm_hold_for(&self, name: &str) -> Option<u64> {
+        let mut hold = None;
+        self.walk_buttons(|button, _| {
+            if hold.is_none() && button.name() == name {
+                hold = Some(match button {
+                    Button::Command { confirm_hold_ms, .. } | Button::Toggle { confirm_hold_ms, .. } => *confirm_hold_ms,
+                    _ => None,
+                });
+            }
+        });
+        hold.flatten()
+    }
+
+    /// Visits every button of the menu tree, see [`Menu::walk_buttons`]
+    pub fn walk_buttons<'a>(&'a self, f: impl FnMut(&'a Button, &[&'a str])) {
+        self.menu.walk_buttons(f);
+    }
+
+    /// Visits every button of the menu tree mutably, see [`Menu::walk_buttons_mut`]
+    pub fn walk_buttons_mut(&mut self, f: impl FnMut(&mut Button, &[&str])) {
+        self.menu.walk_buttons_mut(f);
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Menu {
     pub name: String,
     pub buttons: Vec<Button>,
+    #[serde(default)]
+    pub theme: Option<ThemeName>, // Overrides the render theme for this menu and its submenus
+    #[serde(default)]
+    pub sort: MenuSort, // Order the buttons are laid out in
+    #[serde(default)]
+    pub background_image: Option<String>, // Image sliced across the keys as their backgrounds
+    #[serde(default)]
+    pub dynamic: Option<DynamicMenu>, // Buttons generated in place of `buttons` whenever the menu is shown
+}
+
+impl Menu {
+    /// Visits every button in the menu tree depth first, submenu buttons right after
+    /// their menu button (or the branch button opening them). `f` also receives the names of the menus leading to the
+    /// button, starting with this menu.
+    pub fn walk_buttons<'a>(&'a self, mut f: impl FnMut(&'a Button, &[&'a str])) {
+        let mut path = vec![self.name.as_str()];
+        walk_buttons(&self.buttons, &mut path, &mut f);
+    }
+
+    /// Like [`Menu::walk_buttons`], but lets `f` modify the buttons. Changes `f` makes to a
+    /// menu button's `buttons` are visited.
+    pub fn walk_buttons_mut(&mut self, mut f: impl FnMut(&mut Button, &[&str])) {
+        let mut path = vec![self.name.clone()];
+        walk_buttons_mut(&mut self.buttons, &mut path, &mut f);
+    }
+}
+
+fn walk_buttons<'a>(buttons: &'a [Button], path: &mut Vec<&'a str>, f: &mut impl FnMut(&'a Button, &[&'a str])) {
+    for button in buttons {
+        f(button, path);
+        match button {
+            Button::Menu { name, buttons, .. } => {
+                path.push(name);
+                walk_buttons(buttons, path, f);
+                path.pop();
+            }
+            Button::Branch { on_success, on_failure, .. } => {
+                for menu in [on_success, on_failure] {
+                    path.push(&menu.name);
+                    walk_buttons(&menu.buttons, path, f);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn walk_buttons_mut(buttons: &mut [Button], path: &mut Vec<String>, f: &mut impl FnMut(&mut Button, &[&str])) {
+    for button in buttons {
+        f(button, &path.iter().map(String::as_str).collect::<Vec<_>>());
+        match button {
+            Button::Menu { name, buttons, .. } => {
+                path.push(name.clone());
+                walk_buttons_mut(buttons, path, f);
+                path.pop();
+            }
+            Button::Branch { on_success, on_failure, .. } => {
+                for menu in [on_success, on_failure] {
+                    path.push(menu.name.clone());
+                    walk_buttons_mut(&mut menu.buttons, path, f);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
+// Toggles carry far more settings than other buttons; boxing them is not worth it
+// for a tree that is parsed once per load
+//
+// The derives are inherent (`remote = "Self"`) so the trait impls below can also accept
+// the single-key form of a button before handing it to the `type`-tagged one
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
+#[serde(remote = "Self", tag = "type", rename_all = "snake_case")]
 pub enum Button {
     Command {
         name: String,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
         command: String,
         #[serde(default)]
         args: Vec<String>,
         #[serde(default)]
+        args_file: Option<String>, // One more argument per line, read on every run
+        #[serde(default)]
         icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+        #[serde(default)]
+        icon_map: Option<IconMap>, // Icons chosen by the last run's outcome
+        #[serde(default)]
+        no_prefix: bool, // Skip the global command_prefix
+        #[serde(default)]
+        store_output_as: Option<String>, // Runtime variable receiving the trimmed stdout
+        #[serde(default)]
+        trigger_on: Option<TriggerOn>, // Overrides the global trigger_on
+        #[serde(default)]
+        confirm_hold_ms: Option<u64>, // Only fire once the pedal key is held this long
+        #[serde(default)]
+        log_output: Option<LogOutput>, // Overrides the global log_output
+        #[serde(default)]
+        retries: u32, // Extra attempts after a non-zero exit
+        #[serde(default)]
+        retry_delay_ms: u64, // Pause before each extra attempt
+        #[serde(default)]
+        delay_ms: u64, // Wait before running the command; pressing again meanwhile cancels it
+        #[serde(default)]
+        queue: bool, // Presses wait for the button's earlier runs instead of overlapping them
+        #[serde(default)]
+        lock: Option<String>, // Runs wait for other buttons holding the lock of the same name
+        #[serde(default)]
+        toast_last_line: bool, // Briefly show the last line of the command's stdout
+        #[serde(default)]
+        toast_ms: Option<u64>, // How long the toast stays, 1500 ms by default
+        #[serde(default)]
+        wait_ms: Option<u64>, // How long a press waits for the outcome before leaving the command to run in the background
+        #[serde(default)]
+        cooldown_ms: Option<u64>, // Presses this soon after the last accepted one are ignored
+        #[serde(default = "default_success_exit_codes")]
+        success_exit_codes: Vec<i32>, // Exit codes recorded as success
+        #[serde(default)]
+        repeat: Option<RepeatConfig>, // Command re-run on an interval while the pedal key is held
     },
     Menu {
         name: String,
-        buttons: Vec<Button>,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        #[serde(default)]
+        buttons: Vec<Button>, // Left out by dynamic menus
         #[serde(default)]
         icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+        #[serde(default)]
+        theme: Option<ThemeName>, // Theme of the submenu's view, inherited by its submenus
+        #[serde(default)]
+        sort: MenuSort, // Order of the submenu's buttons
+        #[serde(default)]
+        background_image: Option<String>, // Image sliced across the submenu's keys
+        #[serde(default)]
+        dynamic: Option<DynamicMenu>, // Buttons generated whenever the submenu is shown
     },
     Back {
         #[serde(default = "default_back_name")]
         name: String,
         #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
         icon: Option<String>,
     },
     Toggle {
         name: String,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
         #[serde(flatten)]
         mode: ToggleMode,
         #[serde(default)]
@@ -52,57 +762,1153 @@ pub enum Button {
         off_icon: Option<String>,
         #[serde(default)]
         icon: Option<String>, // Fallback icon when state is unknown
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+        #[serde(default)]
+        pending_icon: Option<String>, // Icon shown while the toggle's command runs
+        #[serde(default)]
+        icon_priority: IconPriority, // Whether on_icon/off_icon or the general icon is tried first
+        #[serde(default)]
+        auto_style_by_state: bool, // Render `icon` filled when On and outlined when Off
+        #[serde(default)]
+        initial_state: Option<ToggleState>, // Assumed state for toggles without a probe
+        #[serde(default)]
+        no_prefix: bool, // Skip the global command_prefix for the toggle and its probe
+        #[serde(default)]
+        group: Option<String>, // Toggle group driven by GroupAction buttons
+        #[serde(default)]
+        label_from_probe: bool, // Show the last probe's stdout in the label
+        #[serde(default)]
+        sublabel: Option<Sublabel>, // Smaller second line beneath the label
+        #[serde(default)]
+        probe_cwd: Option<PathBuf>, // Working directory of the probe
+        #[serde(default)]
+        probe_env: BTreeMap<String, String>, // Extra environment variables for the probe
+        #[serde(default)]
+        probe_shell: bool, // Run probe_command as an `sh -c` script
+        #[serde(default)]
+        probe_backend: Option<String>, // Registered probe backend that checks the state
+        #[serde(default)]
+        probe_http: Option<HttpProbeConfig>, // GET check of the `http` probe backend
+        #[serde(default)]
+        probe_all: Vec<ProbeSpec>, // Probes run together instead of probe_command, see `combine`
+        #[serde(default)]
+        combine: ProbeCombine, // Whether all or any of `probe_all` must succeed for On
+        #[serde(default = "default_probe_samples")]
+        probe_samples: u32, // Times the probe runs per check, its results combined by `probe_vote`
+        #[serde(default)]
+        probe_vote: ProbeVote, // How the samples of a check make up the state
+        #[serde(default)]
+        probe_timeout_ms: Option<u64>, // Probes running longer are stopped and count as `timeout_state`
+        #[serde(default)]
+        timeout_state: Option<ToggleState>, // State a timed-out probe stands for, Unknown if unset
+        #[serde(default)]
+        success_indicators: Vec<String>, // Probe output text meaning On, whatever the exit code
+        #[serde(default)]
+        failure_indicators: Vec<String>, // Probe output text meaning Off, winning over success_indicators
+        #[serde(default)]
+        check_stderr: bool, // Also look for the indicators in the probe's stderr
+        #[serde(default)]
+        retries: u32, // Extra attempts of the on/off command after a non-zero exit
+        #[serde(default)]
+        retry_delay_ms: u64, // Pause before each extra attempt
+        #[serde(default)]
+        concurrent_verify: bool, // Poll the probe while the command runs and settle on the first match
+        #[serde(default)]
+        readonly: bool, // Status indicator: a press only re-probes and never runs the command
+        #[serde(default)]
+        trigger_on: Option<TriggerOn>, // Overrides the global trigger_on
+        #[serde(default)]
+        confirm_hold_ms: Option<u64>, // Only fire once the pedal key is held this long
+        #[serde(default)]
+        log_output: Option<LogOutput>, // Overrides the global log_output
+        #[serde(default)]
+        lock: Option<String>, // Runs wait for other buttons holding the lock of the same name
     },
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(tag = "mode", rename_all = "snake_case")]
-pub enum ToggleMode {
-    /// Single command that toggles between states
-    Single {
-        command: String,
+    Reload {
+        #[serde(default = "default_reload_name")]
+        name: String,
         #[serde(default)]
-        args: Vec<String>,
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
     },
-    /// Separate commands for on and off states
-    Separate {
-        on_command: String,
+    Brightness {
+        name: String,
         #[serde(default)]
-        on_args: Vec<String>,
-        off_command: String,
+        names: BTreeMap<String, String>, // Translated names keyed by locale
         #[serde(default)]
-        off_args: Vec<String>,
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        action: BrightnessKind,
+        value: i32, // Level in percent for `set`, change in percent for `step`
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
     },
-}
-
-fn default_back_name() -> String {
-    "Back".to_string()
-}
-
-pub fn load_config() -> Result<Config> {
-    tracing::info!("Using embedded configuration");
-    let config: Config = serde_yaml::from_str(EMBEDDED_CONFIG)?;
-    Ok(config)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_config() {
-        let yaml = r#"
-menu:
-  name: "Main Menu"
-  buttons:
-    - type: command
-      name: "List Files"
-      command: "ls"
-      args: ["-la"]
-    - type: menu
-      name: "Git Commands"
-      buttons:
-        - type: command
+    Notification {
+        name: String,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        title: String, // Notification title; placeholders are filled in when pressed
+        #[serde(default)]
+        body: String, // Notification text, with the same placeholders as `title`
+        #[serde(default)]
+        notification_icon: Option<String>, // Icon name or image path shown in the notification
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+    },
+    GroupAction {
+        name: String,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        group: String,
+        action: GroupActionKind,
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+    },
+    Scene {
+        name: String,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        scene: String, // Name of the entry in `scenes` to restore
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+        #[serde(default)]
+        state_only: bool, // Only record the scene's states instead of running the toggles' commands
+    },
+    Match {
+        name: String,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        probe: MatchCommand, // Its trimmed stdout selects the case to run
+        #[serde(default)]
+        cases: Vec<MatchCase>,
+        #[serde(default)]
+        default: Option<MatchCommand>, // Run when no case matches
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+    },
+    Branch {
+        name: String,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        probe: MatchCommand, // Its exit code picks the submenu to open
+        on_success: Menu, // Opened when the probe exits with 0
+        on_failure: Menu, // Opened when the probe fails or cannot run
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+    },
+    Input {
+        name: String,
+        #[serde(default)]
+        names: BTreeMap<String, String>, // Translated names keyed by locale
+        #[serde(default)]
+        sort_key: Option<String>, // Sorted by instead of the name in menus with `sort: name`
+        command: String,
+        #[serde(default)]
+        args: Vec<String>, // `{input}` expands to the number entered on the keypad
+        #[serde(default)]
+        icon: Option<String>,
+        #[serde(default)]
+        icon_rotate: u16, // Clockwise icon rotation in degrees: 0, 90, 180 or 270
+        #[serde(default)]
+        active_hours: Option<ActiveHours>, // Days and hours it can be pressed; drawn disabled outside them
+        #[serde(default)]
+        no_prefix: bool, // Skip the global command_prefix
+    },
+}
+
+impl Serialize for Button {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Button::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Button {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_yaml::Value::deserialize(deserializer)?;
+        Button::deserialize(tagged_button(value)).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Rewrites a button written as a single key naming its type, like
+/// `command: { name: "Build", command: "make" }`, to the canonical `type: command` form.
+/// Buttons already carrying a `type`, or in any other shape, are returned unchanged.
+pub fn tagged_button(value: serde_yaml::Value) -> serde_yaml::Value {
+    match value {
+        serde_yaml::Value::Mapping(outer) if outer.len() == 1 && !outer.contains_key("type") => {
+            let Some((kind, settings)) = outer.into_iter().next() else {
+                return serde_yaml::Value::Null;
+            };
+            match settings {
+                serde_yaml::Value::Mapping(mut button) => {
+                    button.insert("type".into(), kind);
+                    serde_yaml::Value::Mapping(button)
+                }
+                settings => serde_yaml::Value::Mapping(serde_yaml::Mapping::from_iter([(kind, settings)])),
+            }
+        }
+        value => value,
+    }
+}
+
+impl Button {
+    /// A command button running `command` with `args` and every other setting at its default
+    pub fn plain_command(name: String, command: String, args: Vec<String>, no_prefix: bool) -> Button {
+        Button::Command {
+            name,
+            names: BTreeMap::new(),
+            sort_key: None,
+            command,
+            args,
+            args_file: None,
+            icon: None,
+            icon_rotate: 0,
+            active_hours: None,
+            icon_map: None,
+            no_prefix,
+            store_output_as: None,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            retries: 0,
+            retry_delay_ms: 0,
+            delay_ms: 0,
+            queue: false,
+            lock: None,
+            toast_last_line: false,
+            toast_ms: None,
+            wait_ms: None,
+            cooldown_ms: None,
+            success_exit_codes: default_success_exit_codes(),
+            repeat: None,
+        }
+    }
+
+    /// The button's default (untranslated) name
+    pub fn name(&self) -> &str {
+        match self {
+            Button::Command { name, .. }
+            | Button::Menu { name, .. }
+            | Button::Back { name, .. }
+            | Button::Toggle { name, .. }
+            | Button::Reload { name, .. }
+            | Button::Brightness { name, .. }
+            | Button::Notification { name, .. }
+            | Button::GroupAction { name, .. }
+            | Button::Scene { name, .. }
+            | Button::Match { name, .. }
+            | Button::Branch { name, .. }
+            | Button::Input { name, .. } => name,
+        }
+    }
+
+    /// The button's `type` as written in the config
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Button::Command { .. } => "command",
+            Button::Menu { .. } => "menu",
+            Button::Back { .. } => "back",
+            Button::Toggle { .. } => "toggle",
+            Button::Reload { .. } => "reload",
+            Button::Brightness { .. } => "brightness",
+            Button::Notification { .. } => "notification",
+            Button::GroupAction { .. } => "group_action",
+            Button::Scene { .. } => "scene",
+            Button::Match { .. } => "match",
+            Button::Branch { .. } => "branch",
+            Button::Input { .. } => "input",
+        }
+    }
+
+    /// Name of the lock the button's runs hold, for command and toggle buttons with a `lock`
+    pub fn lock(&self) -> Option<&str> {
+        match self {
+            Button::Command { lock, .. } | Button::Toggle { lock, .. } => lock.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the button keeps state under its name: a toggle's on/off state, or the
+    /// last result of a command it runs. The state managers are keyed by name only.
+    pub fn is_stateful(&self) -> bool {
+        match self {
+            Button::Command { .. } | Button::Toggle { .. } | Button::Match { .. } | Button::Input { .. } => true,
+            Button::Menu { .. }
+            | Button::Back { .. }
+            | Button::Reload { .. }
+            | Button::Brightness { .. }
+            | Button::Notification { .. }
+            | Button::GroupAction { .. }
+            | Button::Scene { .. }
+            | Button::Branch { .. } => false,
+        }
+    }
+
+    /// Key the button is sorted by in menus with `sort: name`: its `sort_key`, else its name.
+    /// Back buttons have none, they always stay last.
+    pub fn sort_key(&self) -> Option<&str> {
+        match self {
+            Button::Command { sort_key, .. }
+            | Button::Menu { sort_key, .. }
+            | Button::Toggle { sort_key, .. }
+            | Button::Reload { sort_key, .. }
+            | Button::Brightness { sort_key, .. }
+            | Button::Notification { sort_key, .. }
+            | Button::GroupAction { sort_key, .. }
+            | Button::Scene { sort_key, .. }
+            | Button::Match { sort_key, .. }
+            | Button::Branch { sort_key, .. }
+            | Button::Input { sort_key, .. } => Some(sort_key.as_deref().unwrap_or(self.name())),
+            Button::Back { .. } => None,
+        }
+    }
+
+    /// Clockwise rotation applied to the button's icon, in degrees
+    pub fn icon_rotate(&self) -> u16 {
+        match self {
+            Button::Command { icon_rotate, .. }
+            | Button::Menu { icon_rotate, .. }
+            | Button::Toggle { icon_rotate, .. }
+            | Button::Reload { icon_rotate, .. }
+            | Button::Brightness { icon_rotate, .. }
+            | Button::Notification { icon_rotate, .. }
+            | Button::GroupAction { icon_rotate, .. }
+            | Button::Scene { icon_rotate, .. }
+            | Button::Match { icon_rotate, .. }
+            | Button::Branch { icon_rotate, .. }
+            | Button::Input { icon_rotate, .. } => *icon_rotate,
+            // Back buttons are drawn by the menu with the global back_icon
+            Button::Back { .. } => 0,
+        }
+    }
+
+    /// The `icon` written on the button, if any
+    pub fn icon(&self) -> Option<&String> {
+        match self {
+            Button::Command { icon, .. }
+            | Button::Menu { icon, .. }
+            | Button::Back { icon, .. }
+            | Button::Toggle { icon, .. }
+            | Button::Reload { icon, .. }
+            | Button::Brightness { icon, .. }
+            | Button::Notification { icon, .. }
+            | Button::GroupAction { icon, .. }
+            | Button::Scene { icon, .. }
+            | Button::Match { icon, .. }
+            | Button::Branch { icon, .. }
+            | Button::Input { icon, .. } => icon.as_ref(),
+        }
+    }
+
+    /// The menu a menu button opens, `None` for other buttons
+    pub fn submenu(&self) -> Option<Menu> {
+        match self {
+            Button::Menu { name, buttons, theme, sort, background_image, dynamic, .. } => Some(Menu {
+                name: name.clone(),
+                buttons: buttons.clone(),
+                theme: *theme,
+                sort: *sort,
+                background_image: background_image.clone(),
+                dynamic: *dynamic,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The `active_hours` of the button; back buttons are always active
+    pub fn active_hours(&self) -> Option<&ActiveHours> {
+        match self {
+            Button::Command { active_hours, .. }
+            | Button::Menu { active_hours, .. }
+            | Button::Toggle { active_hours, .. }
+            | Button::Reload { active_hours, .. }
+            | Button::Brightness { active_hours, .. }
+            | Button::Notification { active_hours, .. }
+            | Button::GroupAction { active_hours, .. }
+            | Button::Scene { active_hours, .. }
+            | Button::Match { active_hours, .. }
+            | Button::Branch { active_hours, .. }
+            | Button::Input { active_hours, .. } => active_hours.as_ref(),
+            Button::Back { .. } => None,
+        }
+    }
+
+    /// Whether the button can be pressed at the local time `now`
+    pub fn is_active_at(&self, now: NaiveDateTime) -> bool {
+        self.active_hours().is_none_or(|hours| hours.is_active_at(now))
+    }
+
+    /// Commands the button runs when pressed, including the `repeat` command
+    pub fn commands(&self) -> Vec<&str> {
+        match self {
+            Button::Command { command, repeat, .. } => {
+                std::iter::once(command.as_str()).chain(repeat.iter().map(|repeat| repeat.command.as_str())).collect()
+            }
+            Button::Input { command, .. } => vec![command.as_str()],
+            Button::Match { probe, cases, default, .. } => std::iter::once(probe.command.as_str())
+                .chain(cases.iter().map(|case| case.command.as_str()))
+                .chain(default.iter().map(|default| default.command.as_str()))
+                .collect(),
+            Button::Branch { probe, .. } => vec![probe.command.as_str()],
+            Button::Toggle { mode, .. } => match mode {
+                ToggleMode::Single { command, .. } => vec![command.as_str()],
+                ToggleMode::Separate { on_command, off_command, .. } => vec![on_command.as_str(), off_command.as_str()],
+                ToggleMode::Service { start, stop, .. } => vec![start.as_str(), stop.as_str()],
+                ToggleMode::File { .. } => vec![],
+            },
+            Button::Menu { .. }
+            | Button::Back { .. }
+            | Button::Reload { .. }
+            | Button::Brightness { .. }
+            | Button::Notification { .. }
+            | Button::GroupAction { .. }
+            | Button::Scene { .. } => vec![],
+        }
+    }
+
+    /// Icon specs set on the button as written in the config
+    pub fn icon_specs(&self) -> Vec<&String> {
+        match self {
+            Button::Command { icon, icon_map, .. } => {
+                let mapped = icon_map.iter().flat_map(|map| map.success.iter().chain(map.failure.iter()));
+                icon.iter().chain(mapped).collect()
+            }
+            Button::Toggle { icon, on_icon, off_icon, pending_icon, .. } => {
+                icon.iter().chain(on_icon.iter()).chain(off_icon.iter()).chain(pending_icon.iter()).collect()
+            }
+            Button::Menu { icon, .. }
+            | Button::Back { icon, .. }
+            | Button::Reload { icon, .. }
+            | Button::Brightness { icon, .. }
+            | Button::Notification { icon, .. }
+            | Button::GroupAction { icon, .. }
+            | Button::Scene { icon, .. }
+            | Button::Match { icon, .. }
+            | Button::Branch { icon, .. }
+            | Button::Input { icon, .. } => icon.iter().collect(),
+        }
+    }
+
+    /// Mutable access to the icon specs of [`Button::icon_specs`]
+    pub fn icon_specs_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Button::Command { icon, icon_map, .. } => {
+                let mapped = icon_map.iter_mut().flat_map(|map| map.success.iter_mut().chain(map.failure.iter_mut()));
+                icon.iter_mut().chain(mapped).collect()
+            }
+            Button::Toggle { icon, on_icon, off_icon, pending_icon, .. } => icon
+                .iter_mut()
+                .chain(on_icon.iter_mut())
+                .chain(off_icon.iter_mut())
+                .chain(pending_icon.iter_mut())
+                .collect(),
+            Button::Menu { icon, .. }
+            | Button::Back { icon, .. }
+            | Button::Reload { icon, .. }
+            | Button::Brightness { icon, .. }
+            | Button::Notification { icon, .. }
+            | Button::GroupAction { icon, .. }
+            | Button::Scene { icon, .. }
+            | Button::Match { icon, .. }
+            | Button::Branch { icon, .. }
+            | Button::Input { icon, .. } => icon.iter_mut().collect(),
+        }
+    }
+
+    /// The button's name for `locale`, trying the full locale (e.g. "pl_PL") and then
+    /// its language ("pl") before falling back to the default name
+    pub fn localized_name<'a>(&'a self, locale: Option<&str>) -> &'a str {
+        let names = match self {
+            Button::Command { names, .. }
+            | Button::Menu { names, .. }
+            | Button::Back { names, .. }
+            | Button::Toggle { names, .. }
+            | Button::Reload { names, .. }
+            | Button::Brightness { names, .. }
+            | Button::Notification { names, .. }
+            | Button::GroupAction { names, .. }
+            | Button::Scene { names, .. }
+            | Button::Match { names, .. }
+            | Button::Branch { names, .. }
+            | Button::Input { names, .. } => names,
+        };
+        let Some(locale) = locale else {
+            return self.name();
+        };
+        let language = locale.split(['_', '-', '.']).next().unwrap_or(locale);
+        names
+            .get(locale)
+            .or_else(|| names.get(language))
+            .map(String::as_str)
+            .unwrap_or_else(|| self.name())
+    }
+}
+
+/// `render:` block tuning how keys are drawn
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RenderSettings {
+    #[serde(default)]
+    pub font_size: Option<f32>, // Label font size, the renderer's default when unset
+    #[serde(default)]
+    pub theme: ThemeName,
+}
+
+/// Entry of `theme_when`: the theme of the whole deck while `toggle` is in `state`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ThemeRule {
+    pub toggle: String,
+    pub state: ToggleState,
+    pub theme: ThemeName,
+}
+
+/// `pedal:` block naming the button each key of a Stream Deck Pedal presses
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PedalConfig {
+    #[serde(default)]
+    pub left: Option<String>,
+    #[serde(default)]
+    pub middle: Option<String>,
+    #[serde(default)]
+    pub right: Option<String>,
+}
+
+/// Second, smaller line of a toggle's label: fixed text or `{ from_probe: true }`
+/// for the trimmed stdout of the last probe
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Sublabel {
+    Text(String),
+    FromProbe { from_probe: bool },
+}
+
+/// `hooks:` block of commands run around every command or toggle action
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HooksConfig {
+    /// Run before the action; `{button}` expands to the button's name
+    #[serde(default)]
+    pub pre_command: Option<HookCommand>,
+    /// Run after the action; `{button}` and `{exit_code}` are expanded
+    #[serde(default)]
+    pub post_command: Option<HookCommand>,
+}
+
+/// A hook command and its args
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HookCommand {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// A command and its args run by a `match` button, as its probe or its default, or
+/// by a `branch` button as its probe
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MatchCommand {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// One case of a `match` button: its command runs when the probe prints `equals`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MatchCase {
+    pub equals: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// `repeat:` block of a command button that keeps running while its key is held,
+/// e.g. to keep lowering the volume
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RepeatConfig {
+    /// Pause between runs while the key stays down
+    pub interval_ms: u64,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// `active_hours:` of a button: the days and times of day it can be pressed. Outside
+/// them it is drawn disabled. A window whose `end` is before its `start` runs past
+/// midnight into the next day; one whose `end` equals its `start` lasts all day.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ActiveHours {
+    /// Local time the window opens, as "HH:MM"
+    #[serde(with = "hour_minute")]
+    pub start: NaiveTime,
+    /// Local time the window closes, as "HH:MM"
+    #[serde(with = "hour_minute")]
+    pub end: NaiveTime,
+    /// Days the window opens on; every day when empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+}
+
+impl ActiveHours {
+    /// Whether the button is active at the local time `now`
+    pub fn is_active_at(&self, now: NaiveDateTime) -> bool {
+        let opens_on = |date: NaiveDate| self.days.is_empty() || self.days.iter().any(|day| day.chrono() == date.weekday());
+        let time = now.time();
+        if self.start <= self.end {
+            opens_on(now.date()) && (self.start == self.end || (self.start..self.end).contains(&time))
+        } else {
+            // A window past midnight is open from `start` on its own day until `end` on the next
+            (time >= self.start && opens_on(now.date()))
+                || (time < self.end && now.date().pred_opt().is_some_and(opens_on))
+        }
+    }
+}
+
+/// Day of the week in `active_hours`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    fn chrono(self) -> chrono::Weekday {
+        match self {
+            Weekday::Mon => chrono::Weekday::Mon,
+            Weekday::Tue => chrono::Weekday::Tue,
+            Weekday::Wed => chrono::Weekday::Wed,
+            Weekday::Thu => chrono::Weekday::Thu,
+            Weekday::Fri => chrono::Weekday::Fri,
+            Weekday::Sat => chrono::Weekday::Sat,
+            Weekday::Sun => chrono::Weekday::Sun,
+        }
+    }
+}
+
+/// Times of day written as "HH:MM"
+mod hour_minute {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%H:%M";
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&time.format(FORMAT).to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&value, FORMAT)
+            .map_err(|e| serde::de::Error::custom(format!("invalid time '{}', expected HH:MM: {}", value, e)))
+    }
+}
+
+/// One probe command of a toggle's `probe_all`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProbeSpec {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Order in which a toggle's state icon and general `icon` are tried
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IconPriority {
+    /// `on_icon`/`off_icon`, then `icon`, then the built-in default
+    #[default]
+    StateFirst,
+    /// `icon` in every state, falling back to `on_icon`/`off_icon`, then the default
+    GeneralFirst,
+}
+
+/// How the results of a toggle's `probe_all` make up its state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeCombine {
+    /// On when every probe succeeds
+    #[default]
+    All,
+    /// On when at least one probe succeeds
+    Any,
+}
+
+/// How the `probe_samples` results of one check of a flaky probe make up its state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeVote {
+    /// On when more than half of the samples succeed
+    #[default]
+    Majority,
+    /// On when every sample succeeds
+    All,
+    /// On when at least one sample succeeds
+    Any,
+}
+
+/// `probe_http:` block of a toggle whose state is an HTTP endpoint answering
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HttpProbeConfig {
+    /// `http://` or `https://` URL fetched with a GET request
+    pub url: String,
+    #[serde(flatten)]
+    pub expected: HttpExpect,
+    #[serde(default = "default_http_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+/// Response that means a toggle checked over HTTP is On
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct HttpExpect {
+    /// Required status code; any 2xx status when unset
+    #[serde(default)]
+    pub status: Option<u16>,
+    /// Text the response body must contain
+    #[serde(default)]
+    pub body_contains: Option<String>,
+}
+
+/// Level command output is logged at, from `log_output`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogOutput {
+    /// Not logged at all
+    Off,
+    #[default]
+    Debug,
+    /// Logged with the daemon's regular messages
+    Info,
+}
+
+/// Where toggles take their state from on startup, from `state_source`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateSource {
+    /// Probe every toggle; saved states are not read
+    #[default]
+    Probe,
+    /// Use the saved state; only toggles without one are probed
+    Persisted,
+    /// Probe every toggle and use the saved state of toggles whose probe could not run
+    ProbeThenPersisted,
+}
+
+/// Key transition that fires a button's action
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerOn {
+    #[default]
+    Press,
+    /// Fire when the key is let go, so a press can be aborted by sliding off the key
+    Release,
+}
+
+/// Order a menu lays out its buttons in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MenuSort {
+    /// As listed in the config
+    #[default]
+    None,
+    /// Alphabetically by `sort_key` or name, ignoring case
+    Name,
+}
+
+/// Buttons a dynamic menu generates each time it is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DynamicMenu {
+    /// Re-runs of the most recently run commands, one per button, newest first
+    Recent,
+}
+
+/// Built-in color theme used for the deck
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeName {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// What a `Brightness` button does to the deck's brightness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrightnessKind {
+    /// Sets the brightness to `value` percent
+    Set,
+    /// Raises the brightness by `value` percent, or lowers it for a negative value
+    Step,
+}
+
+/// What a `GroupAction` button does to every member of its group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupActionKind {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ToggleMode {
+    /// Single command that toggles between states, or that only turns the toggle on
+    /// or off as `single_behavior` says
+    Single {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        single_behavior: SingleBehavior,
+    },
+    /// Separate commands for on and off states
+    Separate {
+        on_command: String,
+        #[serde(default)]
+        on_args: Vec<String>,
+        off_command: String,
+        #[serde(default)]
+        off_args: Vec<String>,
+    },
+    /// On while a flag file exists; turning on creates it and turning off removes it
+    File {
+        path: PathBuf,
+    },
+    /// A service with start, stop and status commands: `status` is the probe unless
+    /// `probe_command` is set, and `start` and `stop` are the on and off commands
+    Service {
+        start: String,
+        #[serde(default)]
+        start_args: Vec<String>,
+        stop: String,
+        #[serde(default)]
+        stop_args: Vec<String>,
+        status: String,
+        #[serde(default)]
+        status_args: Vec<String>,
+    },
+}
+
+/// What the command of a single-mode toggle does to its state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SingleBehavior {
+    /// Every run flips the state
+    #[default]
+    Flip,
+    /// The command idempotently turns the toggle on, whatever its state
+    EnsureOn,
+    /// The command idempotently turns the toggle off, whatever its state
+    EnsureOff,
+}
+
+impl SingleBehavior {
+    /// The state a press drives a toggle in `current` state to. An unknown state is
+    /// assumed to be off, so flipping it turns the toggle on.
+    pub fn target(self, current: ToggleState) -> ToggleState {
+        match (self, current) {
+            (SingleBehavior::EnsureOn, _) => ToggleState::On,
+            (SingleBehavior::EnsureOff, _) => ToggleState::Off,
+            (SingleBehavior::Flip, ToggleState::On) => ToggleState::Off,
+            (SingleBehavior::Flip, ToggleState::Off | ToggleState::Unknown) => ToggleState::On,
+        }
+    }
+}
+
+impl ToggleMode {
+    /// The probe of a toggle in this mode: its `probe_command` if it has one, else the
+    /// `status` command of a service
+    pub fn probe<'a>(&'a self, probe_command: Option<&'a str>, probe_args: &'a [String]) -> (Option<&'a str>, &'a [String]) {
+        match (probe_command, self) {
+            (None, ToggleMode::Service { status, status_args, .. }) => (Some(status.as_str()), status_args),
+            _ => (probe_command, probe_args),
+        }
+    }
+}
+
+/// Icons shown on a command button depending on how its last run ended
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct IconMap {
+    #[serde(default)]
+    pub success: Option<String>,
+    #[serde(default)]
+    pub failure: Option<String>,
+}
+
+impl IconMap {
+    /// Picks the icon for the given outcome, falling back to `icon` when there is
+    /// no outcome yet or no icon is mapped for it
+    pub fn select<'a>(&'a self, outcome: Option<CommandOutcome>, icon: Option<&'a String>) -> Option<&'a String> {
+        let mapped = match outcome {
+            Some(outcome) if outcome.success => self.success.as_ref(),
+            Some(_) => self.failure.as_ref(),
+            None => None,
+        };
+        mapped.or(icon)
+    }
+}
+
+fn default_success_exit_codes() -> Vec<i32> {
+    DEFAULT_SUCCESS_EXIT_CODES.to_vec()
+}
+
+fn default_back_name() -> String {
+    "Back".to_string()
+}
+
+fn default_probe_samples() -> u32 {
+    1
+}
+
+fn default_back_icon() -> String {
+    "arrow_back".to_string()
+}
+
+fn default_reload_name() -> String {
+    "Reload".to_string()
+}
+
+fn default_clean_env_keep() -> Vec<String> {
+    vec!["PATH".to_string(), "HOME".to_string()]
+}
+
+fn default_http_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_icon_scale() -> f32 {
+    1.0
+}
+
+fn default_label_font_min() -> u32 {
+    10
+}
+
+fn default_label_font_max() -> u32 {
+    18
+}
+
+/// Finds the config file to load: the file named by `STREAMDECK_CONFIG`, else the first
+/// existing one of `$XDG_CONFIG_HOME/streamdeck-nix/config.yaml`,
+/// `~/.config/streamdeck-nix/config.yaml` and `./config.yaml`. `None` means the
+/// embedded config is used.
+pub fn resolve_config_path() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    resolve_config_path_with(|name| std::env::var_os(name), &cwd)
+}
+
+/// Like [`resolve_config_path`], reading environment variables from `env` and
+/// `./config.yaml` relative to `cwd`
+pub fn resolve_config_path_with(env: impl Fn(&str) -> Option<OsString>, cwd: &Path) -> Option<PathBuf> {
+    if let Some(path) = env(CONFIG_PATH_ENV) {
+        return Some(PathBuf::from(path));
+    }
+
+    // Relative XDG_CONFIG_HOME values are invalid per the spec and ignored
+    let xdg_config = env("XDG_CONFIG_HOME").map(PathBuf::from).filter(|dir| dir.is_absolute());
+    let home_config = env("HOME").map(|home| PathBuf::from(home).join(".config"));
+    let candidates = [xdg_config, home_config]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+        .chain([cwd.join(CONFIG_FILE_NAME)]);
+    for candidate in candidates {
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        tracing::debug!("No config file at {}", candidate.display());
+    }
+    None
+}
+
+/// Loads the config from `resolve_config_path()` if one is found, otherwise the embedded config
+pub fn load_config() -> Result<Config> {
+    load_config_from(resolve_config_path().as_deref())
+}
+
+/// Returns true if the config text should be templated from the environment
+fn config_template_enabled() -> bool {
+    std::env::var(CONFIG_TEMPLATE_ENV).is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+/// Substitutes environment placeholders in raw config text if templating is enabled
+fn template_config(contents: &str) -> Result<String> {
+    if config_template_enabled() {
+        expand_env(contents, |name| std::env::var(name).ok())
+    } else {
+        Ok(contents.to_string())
+    }
+}
+
+/// Loads the config from the given path, or the embedded config when `None`
+pub fn load_config_from(path: Option<&Path>) -> Result<Config> {
+    match path {
+        Some(path) => {
+            tracing::info!("Loading configuration from {}", path.display());
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file {}", path.display()))?;
+            let contents = template_config(&contents)
+                .with_context(|| format!("Failed to template config file {}", path.display()))?;
+            let mut config = parse_config(&contents)
+                .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+            config
+                .resolve_colors()
+                .with_context(|| format!("Invalid config file {}", path.display()))?;
+            config
+                .apply_default_icon_style()
+                .with_context(|| format!("Invalid config file {}", path.display()))?;
+            normalize_icon_layout(&mut config);
+            config
+                .validate()
+                .with_context(|| format!("Invalid config file {}", path.display()))?;
+            log_warnings(&config);
+            Ok(config)
+        }
+        None => {
+            tracing::info!("Using embedded configuration");
+            let mut config = parse_config(&template_config(EMBEDDED_CONFIG)?)?;
+            config.resolve_colors()?;
+            config.apply_default_icon_style()?;
+            normalize_icon_layout(&mut config);
+            config.validate()?;
+            log_warnings(&config);
+            Ok(config)
+        }
+    }
+}
+
+/// Writes `config` to `path` as YAML, e.g. to look at a config after templating.
+/// Keys keep the order of the config's fields, so saving the same config twice gives
+/// the same file, and loading it back gives the same config.
+pub fn save_config(config: &Config, path: &Path) -> Result<()> {
+    let contents = serde_yaml::to_string(config).context("Failed to serialize config")?;
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, contents).with_context(|| format!("Failed to write config file {}", path.display()))?;
+    std::fs::rename(&temporary, path).with_context(|| format!("Failed to write config file {}", path.display()))
+}
+
+/// Writes the toggles of each scene sorted by name, which a `HashMap` would not keep
+fn serialize_scenes<S: serde::Serializer>(scenes: &BTreeMap<String, HashMap<String, ToggleState>>, serializer: S) -> Result<S::Ok, S::Error> {
+    let sorted: BTreeMap<_, BTreeMap<_, _>> = scenes.iter().map(|(name, states)| (name, states.iter().collect())).collect();
+    sorted.serialize(serializer)
+}
+
+fn log_warnings(config: &Config) {
+    for warning in config.warnings() {
+        tracing::warn!("Config warning: {}", warning);
+    }
+}
+
+/// Parses config YAML. With `lenient: true`, buttons that fail to parse are logged
+/// and dropped so the rest of the deck still loads.
+pub fn parse_config(contents: &str) -> Result<Config> {
+    let mut value: serde_yaml::Value = serde_yaml::from_str(contents)?;
+    let lenient = value.get("lenient").and_then(serde_yaml::Value::as_bool).unwrap_or(false);
+    if lenient {
+        if let Some(menu) = value.get_mut("menu") {
+            let menu_name = menu.get("name").and_then(serde_yaml::Value::as_str).unwrap_or_default().to_string();
+            if let Some(buttons) = menu.get_mut("buttons") {
+                drop_invalid_buttons(buttons, &menu_name);
+            }
+        }
+    }
+    Ok(serde_yaml::from_value(value)?)
+}
+
+/// Removes the buttons of a menu (and its submenus) that do not parse as a [`Button`]
+fn drop_invalid_buttons(buttons: &mut serde_yaml::Value, menu_name: &str) {
+    let Some(buttons) = buttons.as_sequence_mut() else {
+        return;
+    };
+    let mut index = 0;
+    buttons.retain_mut(|button| {
+        index += 1;
+        *button = tagged_button(std::mem::take(button));
+        let name = button.get("name").and_then(serde_yaml::Value::as_str).map(str::to_string);
+        if let (Some(name), Some(children)) = (&name, button.get_mut("buttons")) {
+            drop_invalid_buttons(children, name);
+        }
+        match serde_yaml::from_value::<Button>(button.clone()) {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::warn!(
+                    "Skipping invalid button {} ({}) in menu '{}': {}",
+                    index,
+                    name.as_deref().unwrap_or("unnamed"),
+                    menu_name,
+                    e
+                );
+                false
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_parse_config() {
+        let yaml = r#"
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: command
+      name: "List Files"
+      command: "ls"
+      args: ["-la"]
+    - type: menu
+      name: "Git Commands"
+      buttons:
+        - type: command
           name: "Git Status"
           command: "git"
           args: ["status"]
@@ -112,85 +1918,1091 @@ menu:
           args: ["log", "--oneline", "-10"]
         - type: back
     - type: command
-      name: "System Info"
-      command: "uname"
-      args: ["-a"]
+      name: "System Info"
+      command: "uname"
+      args: ["-a"]
+    - type: toggle
+      name: "WiFi Toggle"
+      mode: single
+      command: "nmcli"
+      args: ["radio", "wifi"]
+      probe_command: "nmcli"
+      probe_args: ["radio", "wifi"]
+      on_icon: "wifi"
+      off_icon: "wifi_off"
+    - type: toggle
+      name: "VPN Toggle" 
+      mode: separate
+      on_command: "nmcli"
+      on_args: ["connection", "up", "vpn"]
+      off_command: "nmcli"
+      off_args: ["connection", "down", "vpn"]
+      probe_command: "nmcli"
+      probe_args: ["connection", "show", "--active"]
+"#;
+
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.menu.name, "Main Menu");
+        assert_eq!(config.menu.buttons.len(), 5);
+        
+        // Check first button
+        match &config.menu.buttons[0] {
+            Button::Command { name, command, .. } => {
+                assert_eq!(name, "List Files");
+                assert_eq!(command, "ls");
+            }
+            _ => panic!("Expected command button"),
+        }
+        
+        // Check nested menu
+        match &config.menu.buttons[1] {
+            Button::Menu { name, buttons, .. } => {
+                assert_eq!(name, "Git Commands");
+                assert_eq!(buttons.len(), 3);
+            }
+            _ => panic!("Expected menu button"),
+        }
+        
+        // Check toggle button with single mode
+        match &config.menu.buttons[3] {
+            Button::Toggle { name, mode, probe_command, on_icon, off_icon, .. } => {
+                assert_eq!(name, "WiFi Toggle");
+                match mode {
+                    ToggleMode::Single { command, args, .. } => {
+                        assert_eq!(command, "nmcli");
+                        assert_eq!(args, &vec!["radio".to_string(), "wifi".to_string()]);
+                    }
+                    _ => panic!("Expected single mode toggle"),
+                }
+                assert_eq!(probe_command.as_ref().unwrap(), "nmcli");
+                assert_eq!(on_icon.as_ref().unwrap(), "wifi");
+                assert_eq!(off_icon.as_ref().unwrap(), "wifi_off");
+            }
+            _ => panic!("Expected toggle button"),
+        }
+        
+        // Check toggle button with separate mode
+        match &config.menu.buttons[4] {
+            Button::Toggle { name, mode, probe_command, .. } => {
+                assert_eq!(name, "VPN Toggle");
+                match mode {
+                    ToggleMode::Separate { on_command, on_args, off_command, off_args } => {
+                        assert_eq!(on_command, "nmcli");
+                        assert_eq!(on_args, &vec!["connection".to_string(), "up".to_string(), "vpn".to_string()]);
+                        assert_eq!(off_command, "nmcli");
+                        assert_eq!(off_args, &vec!["connection".to_string(), "down".to_string(), "vpn".to_string()]);
+                    }
+                    _ => panic!("Expected separate mode toggle"),
+                }
+                assert_eq!(probe_command.as_ref().unwrap(), "nmcli");
+            }
+            _ => panic!("Expected toggle button"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_named_colors() {
+        let dir = TempDir::new("colors");
+        let path = dir.join("config.yaml");
+        std::fs::write(
+            &path,
+            "colors:\n  accent: \"#ff9800\"\nback_highlight: \"$accent\"\nmenu:\n  name: \"Main\"\n  buttons: []\n",
+        )
+        .unwrap();
+        let config = load_config_from(Some(&path)).unwrap();
+        assert_eq!(config.back_highlight.as_deref(), Some("#ff9800"));
+
+        // Plain hex codes are left alone
+        let mut config: Config =
+            serde_yaml::from_str("back_highlight: \"#00ff00\"\nmenu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        config.resolve_colors().unwrap();
+        assert_eq!(config.back_highlight.as_deref(), Some("#00ff00"));
+    }
+
+    #[test]
+    fn test_undefined_color_reference() {
+        let mut config: Config = serde_yaml::from_str(
+            "colors:\n  accent: \"#ff9800\"\nback_highlight: \"$warning\"\nmenu:\n  name: \"Main\"\n  buttons: []\n",
+        )
+        .unwrap();
+        let err = config.resolve_colors().unwrap_err();
+        assert_eq!(err.to_string(), "back_highlight references undefined color '$warning'");
+
+        let config: Config =
+            serde_yaml::from_str("colors:\n  accent: orange\nmenu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.to_string(), "colors must be like \"#ff9800\": accent ('orange')");
+    }
+
+    #[test]
+    fn test_parse_single_behavior() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Monitor"
+      mode: single
+      command: "ensure-monitor-on"
+      single_behavior: ensure_on
+    - type: toggle
+      name: "Mute"
+      mode: single
+      command: "pactl"
+"#,
+        )
+        .unwrap();
+        let behaviors: Vec<_> = config
+            .menu
+            .buttons
+            .iter()
+            .map(|button| match button {
+                Button::Toggle { mode: ToggleMode::Single { single_behavior, .. }, .. } => *single_behavior,
+                _ => panic!("Expected single mode toggle"),
+            })
+            .collect();
+        assert_eq!(behaviors, vec![SingleBehavior::EnsureOn, SingleBehavior::Flip]);
+
+        assert_eq!(SingleBehavior::Flip.target(ToggleState::Unknown), ToggleState::On);
+        assert_eq!(SingleBehavior::Flip.target(ToggleState::On), ToggleState::Off);
+        assert_eq!(SingleBehavior::EnsureOff.target(ToggleState::Off), ToggleState::Off);
+        assert!(serde_yaml::from_str::<SingleBehavior>("toggle").is_err());
+    }
+
+    #[test]
+    fn test_parse_service_toggle() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Docker"
+      mode: service
+      start: "systemctl"
+      start_args: ["start", "docker"]
+      stop: "systemctl"
+      stop_args: ["stop", "docker"]
+      status: "systemctl"
+      status_args: ["is-active", "--quiet", "docker"]
+"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.warnings().is_empty());
+        let Button::Toggle { mode, probe_command, probe_args, .. } = &config.menu.buttons[0] else {
+            panic!("Expected toggle button");
+        };
+        match mode {
+            ToggleMode::Service { start, start_args, stop, stop_args, .. } => {
+                assert_eq!((start.as_str(), start_args.as_slice()), ("systemctl", ["start", "docker"].map(String::from).as_slice()));
+                assert_eq!((stop.as_str(), stop_args.as_slice()), ("systemctl", ["stop", "docker"].map(String::from).as_slice()));
+            }
+            _ => panic!("Expected service mode toggle"),
+        }
+        let (probe, args) = mode.probe(probe_command.as_deref(), probe_args);
+        assert_eq!(probe, Some("systemctl"));
+        assert_eq!(args, ["is-active", "--quiet", "docker"].map(String::from).as_slice());
+        assert_eq!(config.menu.buttons[0].commands(), vec!["systemctl", "systemctl"]);
+
+        // All three commands are required
+        let missing_status = r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Docker"
+      mode: service
+      start: "systemctl"
+      stop: "systemctl"
+"#;
+        assert!(serde_yaml::from_str::<Config>(missing_status).is_err());
+    }
+
+    #[test]
+    fn test_parse_toggle_initial_state() {
+        let yaml = r#"
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: toggle
+      name: "Fan"
+      mode: single
+      command: "fanctl"
+      initial_state: "ON"
+    - type: toggle
+      name: "Lamp"
+      mode: single
+      command: "lampctl"
+"#;
+
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        match &config.menu.buttons[0] {
+            Button::Toggle { initial_state, .. } => assert_eq!(*initial_state, Some(ToggleState::On)),
+            _ => panic!("Expected toggle button"),
+        }
+        match &config.menu.buttons[1] {
+            Button::Toggle { initial_state, .. } => assert_eq!(*initial_state, None),
+            _ => panic!("Expected toggle button"),
+        }
+    }
+
+    #[test]
+    fn test_parse_toggle_invalid_initial_state() {
+        let yaml = r#"
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: toggle
+      name: "Fan"
+      mode: single
+      command: "fanctl"
+      initial_state: "maybe"
+"#;
+
+        let err = serde_yaml::from_str::<Config>(yaml).unwrap_err();
+        assert!(err.to_string().contains("invalid toggle state 'maybe'"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_probe_order() {
+        let yaml = r#"
+probe_order:
+  - ["VPN"]
+  - ["Mount"]
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: toggle
+      name: "VPN"
+      mode: single
+      command: "vpnctl"
+    - type: menu
+      name: "Storage"
+      buttons:
+        - type: toggle
+          name: "Mount"
+          mode: single
+          command: "mountctl"
+"#;
+
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.probe_order, vec![vec!["VPN".to_string()], vec!["Mount".to_string()]]);
+        assert!(config.validate().is_ok());
+
+        config.probe_order.push(vec!["Missing".to_string()]);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("unknown toggle 'Missing'"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_label_font_bounds() {
+        let mut config: Config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        assert_eq!((config.label_font_min, config.label_font_max), (10, 18));
+        assert!(config.validate().is_ok());
+
+        config.label_font_min = 20;
+        assert!(config.validate().is_err());
+        config.label_font_min = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_templated_config_from_env() {
+        let yaml = r#"
+menu:
+  name: "${DECK_NAME:-Main Menu}"
+  buttons:
+    - type: command
+      name: "Edit"
+      command: ${EDITOR}
+      args: ["${var:file}"]
+"#;
+        let lookup = |name: &str| (name == "EDITOR").then(|| "nvim".to_string());
+
+        let config = parse_config(&expand_env(yaml, lookup).unwrap()).unwrap();
+        assert_eq!(config.menu.name, "Main Menu");
+        match &config.menu.buttons[0] {
+            Button::Command { command, args, .. } => {
+                assert_eq!(command, "nvim");
+                // Runtime variables are still expanded per run
+                assert_eq!(args, &vec!["${var:file}".to_string()]);
+            }
+            _ => panic!("Expected command button"),
+        }
+
+        assert!(expand_env(yaml, |_| None).is_err());
+    }
+
+    #[test]
+    fn test_lenient_load_skips_invalid_buttons() {
+        let yaml = r#"
+lenient: true
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: command
+      name: "Terminal"
+      command: "alacritty"
+    - type: command
+      name: "Missing Command"
+    - type: menu
+      name: "Tools"
+      buttons:
+        - type: toggle
+          name: "Lamp"
+          mode: sometimes
+          command: "lampctl"
+        - type: reload
+    - type: teleport
+      name: "Unknown Type"
+"#;
+
+        let config = parse_config(yaml).unwrap();
+        assert!(config.lenient);
+        let names: Vec<_> = config.menu.buttons.iter().map(Button::name).collect();
+        assert_eq!(names, vec!["Terminal", "Tools"]);
+        match &config.menu.buttons[1] {
+            Button::Menu { buttons, .. } => {
+                assert_eq!(buttons.len(), 1);
+                assert!(matches!(buttons[0], Button::Reload { .. }));
+            }
+            _ => panic!("Expected menu button"),
+        }
+
+        // Strict mode stays the default and rejects the whole config
+        let strict = yaml.replace("lenient: true", "lenient: false");
+        assert!(parse_config(&strict).is_err());
+        assert!(parse_config(&yaml.replace("lenient: true\n", "")).is_err());
+    }
+
+    #[test]
+    fn test_save_config_round_trips() {
+        let yaml = r#"
+default_icon_style: outlined
+menu:
+  name: "${DECK_NAME:-Main Menu}"
+  buttons:
+    - command: { name: "Edit", command: "${EDITOR}", args: ["${var:file}"], icon: "edit" }
+    - type: toggle
+      name: "Lights"
+      mode: separate
+      on_command: "lights"
+      on_args: ["on"]
+      off_command: "lights"
+      off_args: ["off"]
+      probe_command: "lights-on"
+    - type: scene
+      name: "Movie"
+      scene: "movie"
+scenes:
+  movie:
+    Lights: off
+"#;
+        let lookup = |name: &str| (name == "EDITOR").then(|| "nvim".to_string());
+        let dir = TempDir::new("save");
+        let source = dir.join("source.yaml");
+        let saved = dir.join("saved.yaml");
+        std::fs::write(&source, expand_env(yaml, lookup).unwrap()).unwrap();
+        let config = load_config_from(Some(&source)).unwrap();
+
+        save_config(&config, &saved).unwrap();
+        let reloaded = load_config_from(Some(&saved)).unwrap();
+        let written = std::fs::read_to_string(&saved).unwrap();
+        save_config(&reloaded, &saved).unwrap();
+        let rewritten = std::fs::read_to_string(&saved).unwrap();
+
+        assert_eq!(serde_yaml::to_value(&reloaded).unwrap(), serde_yaml::to_value(&config).unwrap());
+        assert_eq!(rewritten, written);
+        assert!(written.contains("type: command") && written.contains("command: nvim"), "{}", written);
+        match &reloaded.menu.buttons[0] {
+            Button::Command { icon, .. } => assert_eq!(icon.as_deref(), Some("outlined:edit")),
+            _ => panic!("Expected command button"),
+        }
+    }
+
+    #[test]
+    fn test_active_hours() {
+        let yaml = r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Standup"
+      command: "open-meeting"
+      active_hours: { start: "09:00", end: "17:00", days: [mon, tue, wed, thu, fri] }
+    - type: command
+      name: "Night Backup"
+      command: "backup"
+      active_hours: { start: "22:30", end: "06:00", days: [fri] }
+    - type: command
+      name: "Always"
+      command: "true"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        // 2026-10-16 is a Friday
+        let at = |day: u32, time: &str| NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap());
+        let inactive = |day: u32, time: &str| config.inactive_buttons(at(day, time));
+
+        assert_eq!(inactive(16, "09:00"), ["Night Backup"]);
+        assert_eq!(inactive(16, "16:59"), ["Night Backup"]);
+        assert_eq!(inactive(16, "17:00"), ["Standup", "Night Backup"]);
+        assert_eq!(inactive(16, "08:59"), ["Standup", "Night Backup"]);
+        assert_eq!(inactive(17, "12:00"), ["Standup", "Night Backup"]);
+
+        // The Friday night window runs into Saturday morning, but not into Friday morning
+        assert_eq!(inactive(16, "22:30"), ["Standup"]);
+        assert_eq!(inactive(17, "05:59"), ["Standup"]);
+        assert_eq!(inactive(17, "06:00"), ["Standup", "Night Backup"]);
+        assert_eq!(inactive(16, "05:00"), ["Standup", "Night Backup"]);
+        assert_eq!(inactive(17, "22:30"), ["Standup", "Night Backup"]);
+
+        let all_day = ActiveHours { start: NaiveTime::MIN, end: NaiveTime::MIN, days: Vec::new() };
+        assert!(all_day.is_active_at(at(17, "03:00")));
+        // Back buttons and buttons without a window are always active
+        assert!(config.menu.buttons[2].is_active_at(at(17, "03:00")));
+
+        let written = serde_yaml::to_string(&config.menu.buttons[1]).unwrap();
+        assert!(written.contains("start: 22:30") && written.contains("- fri"), "{}", written);
+        let err = serde_yaml::from_str::<Config>(&yaml.replace("\"22:30\"", "\"late\"")).unwrap_err();
+        assert!(err.to_string().contains("invalid time 'late', expected HH:MM"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_single_key_buttons() {
+        let tagged = r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Build"
+      command: "make"
+      args: ["release"]
+    - type: menu
+      name: "Tools"
+      buttons:
+        - type: toggle
+          name: "Lamp"
+          mode: single
+          command: "lampctl"
+        - type: reload
+"#;
+        let keyed = r#"
+menu:
+  name: "Main"
+  buttons:
+    - command:
+        name: "Build"
+        command: "make"
+        args: ["release"]
+    - menu:
+        name: "Tools"
+        buttons:
+          - toggle: { name: "Lamp", mode: single, command: "lampctl" }
+          - reload: {}
+"#;
+        let tagged = parse_config(tagged).unwrap();
+        let keyed = parse_config(keyed).unwrap();
+        let as_value = |config: &Config| serde_yaml::to_value(&config.menu.buttons).unwrap();
+        assert_eq!(as_value(&keyed), as_value(&tagged));
+
+        // Buttons are written back in the tagged form, which parses to the same buttons
+        let written = serde_yaml::to_string(&keyed.menu.buttons).unwrap();
+        assert!(written.contains("type: command"), "{}", written);
+        let reparsed: Vec<Button> = serde_yaml::from_str(&written).unwrap();
+        assert_eq!(serde_yaml::to_value(&reparsed).unwrap(), as_value(&tagged));
+
+        let err = parse_config("menu:\n  name: \"Main\"\n  buttons:\n    - teleport: { name: \"Away\" }\n").unwrap_err();
+        assert!(err.to_string().contains("unknown variant `teleport`"), "{}", err);
+        let lenient = parse_config("lenient: true\nmenu:\n  name: \"Main\"\n  buttons:\n    - reload: {}\n    - command: { name: \"Missing Command\" }\n").unwrap();
+        assert_eq!(lenient.menu.buttons.len(), 1);
+    }
+
+    #[test]
+    fn test_validate_strict_icons() {
+        let yaml = r#"
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: command
+      name: "Terminal"
+      command: "alacritty"
+      icon: "termnial"
+    - type: menu
+      name: "Tools"
+      icon: "none"
+      buttons:
+        - type: toggle
+          name: "Lamp"
+          mode: single
+          command: "lampctl"
+          on_icon: "bogus_style:lamp"
+          off_icon: "terminal"
+"#;
+
+        // Lenient mode keeps falling back to the terminal icon
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(!config.strict_icons);
+        assert!(config.validate().is_ok());
+
+        config.strict_icons = true;
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("'termnial' on 'Terminal'"), "{}", err);
+        assert!(err.contains("'bogus_style:lamp' on 'Lamp'"), "{}", err);
+        assert!(!err.contains("'none'") && !err.contains("'terminal'"), "{}", err);
+    }
+
+    #[test]
+    fn test_default_icon_style() {
+        let yaml = r#"
+default_icon_style: outlined
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: command
+      name: "Home"
+      command: "true"
+      icon: "home"
+    - type: menu
+      name: "Tools"
+      icon: "sharp:build"
+      buttons:
+        - type: toggle
+          name: "Lamp"
+          mode: single
+          command: "lampctl"
+          on_icon: "none"
+          off_icon: "u:f1eb"
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        config.apply_default_icon_style().unwrap();
+        let mut icons = Vec::new();
+        config.walk_buttons(|button, _| icons.extend(button.icon_specs().into_iter().cloned()));
+        assert_eq!(icons, ["outlined:home", "sharp:build", "none", "u:f1eb"]);
+        assert_eq!(config.back_icon, "outlined:arrow_back");
+
+        // A bare home resolves to the outlined constant, not the filled one
+        let home = crate::icons::resolve_icon(config.menu.buttons[0].icon_specs().first().copied());
+        assert_eq!(home, crate::icons::lookup_outlined_icon("HOME"));
+        assert!(home.is_some());
+
+        let mut config: Config = serde_yaml::from_str(&yaml.replace("outlined", "bold")).unwrap();
+        let err = config.apply_default_icon_style().unwrap_err().to_string();
+        assert!(err.contains("unknown default_icon_style 'bold'"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_state_source() {
+        let config: Config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        assert_eq!(config.state_source, StateSource::Probe);
+        assert!(config.validate().is_ok());
+
+        let config: Config = serde_yaml::from_str(
+            "state_file: \"~/.local/state/streamdeck/toggles.yaml\"\nstate_source: probe_then_persisted\nmenu:\n  name: \"Main\"\n  buttons: []\n",
+        )
+        .unwrap();
+        assert_eq!(config.state_source, StateSource::ProbeThenPersisted);
+        assert!(config.validate().is_ok());
+
+        let config: Config =
+            serde_yaml::from_str("state_source: persisted\nmenu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(err, "state_source persisted and probe_then_persisted need a state_file to read saved states from");
+    }
+
+    #[test]
+    fn test_validate_queue() {
+        let yaml = |delay_ms: u64| {
+            format!(
+                "menu:\n  name: \"Main\"\n  buttons:\n    - type: command\n      name: \"Deploy\"\n      command: \"deploy\"\n      queue: true\n      delay_ms: {}\n",
+                delay_ms
+            )
+        };
+        let config: Config = serde_yaml::from_str(&yaml(0)).unwrap();
+        assert!(config.validate().is_ok());
+        assert!(matches!(&config.menu.buttons[0], Button::Command { queue: true, .. }));
+
+        let config: Config = serde_yaml::from_str(&yaml(500)).unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(err, "queue cannot be combined with delay_ms: 'Deploy'");
+    }
+
+    #[test]
+    fn test_validate_state_name_collisions() {
+        let yaml = |second_type: &str, second_name: &str| {
+            format!(
+                r#"
+menu:
+  name: "Main"
+  buttons:
     - type: toggle
-      name: "WiFi Toggle"
+      name: "Mode"
       mode: single
-      command: "nmcli"
-      args: ["radio", "wifi"]
-      probe_command: "nmcli"
-      probe_args: ["radio", "wifi"]
-      on_icon: "wifi"
-      off_icon: "wifi_off"
+      command: "modectl"
+    - type: menu
+      name: "More"
+      buttons:
+        - type: {}
+          name: "{}"
+          command: "modectl"
+"#,
+                second_type, second_name
+            )
+        };
+
+        let config: Config = serde_yaml::from_str(&yaml("command", "Mode")).unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(err, "buttons of different types keep state under the same name: 'Mode' (toggle and command)");
+
+        let config: Config = serde_yaml::from_str(&yaml("input", "Mode")).unwrap();
+        assert!(config.validate().is_err());
+
+        // Distinct names pass
+        let config: Config = serde_yaml::from_str(&yaml("command", "Set Mode")).unwrap();
+        assert!(config.validate().is_ok());
+
+        // A menu keeps no state, so it may share a toggle's name
+        let config: Config = serde_yaml::from_str(
+            "menu:\n  name: \"Main\"\n  buttons:\n    - type: toggle\n      name: \"Mode\"\n      mode: single\n      command: \"modectl\"\n    - type: menu\n      name: \"Mode\"\n      buttons: []\n",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_resolve_config_path_precedence() {
+        let root = TempDir::new("xdg");
+        let xdg = root.join("xdg");
+        let home = root.join("home");
+        let cwd = root.join("cwd");
+        let xdg_config = xdg.join("streamdeck-nix/config.yaml");
+        let home_config = home.join(".config/streamdeck-nix/config.yaml");
+        let cwd_config = cwd.join("config.yaml");
+        for path in [&xdg_config, &home_config, &cwd_config] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "menu: { name: Main, buttons: [] }\n").unwrap();
+        }
+
+        let vars = |explicit: Option<&str>| {
+            let xdg = xdg.clone();
+            let home = home.clone();
+            let explicit = explicit.map(OsString::from);
+            move |name: &str| match name {
+                CONFIG_PATH_ENV => explicit.clone(),
+                "XDG_CONFIG_HOME" => Some(xdg.clone().into_os_string()),
+                "HOME" => Some(home.clone().into_os_string()),
+                _ => None,
+            }
+        };
+        let resolve = || resolve_config_path_with(vars(None), &cwd);
+
+        // An explicit path wins even if the file does not exist
+        assert_eq!(resolve_config_path_with(vars(Some("/etc/deck.yaml")), &cwd), Some(PathBuf::from("/etc/deck.yaml")));
+        assert_eq!(resolve(), Some(xdg_config.clone()));
+        std::fs::remove_file(&xdg_config).unwrap();
+        assert_eq!(resolve(), Some(home_config.clone()));
+        std::fs::remove_file(&home_config).unwrap();
+        assert_eq!(resolve(), Some(cwd_config.clone()));
+        std::fs::remove_file(&cwd_config).unwrap();
+        assert_eq!(resolve(), None);
+
+        // Without XDG_CONFIG_HOME the home directory's .config is searched
+        std::fs::write(&home_config, "").unwrap();
+        let home_only = |name: &str| (name == "HOME").then(|| home.clone().into_os_string());
+        assert_eq!(resolve_config_path_with(home_only, &cwd), Some(home_config));
+        // A relative XDG_CONFIG_HOME is ignored
+        let relative = |name: &str| (name == "XDG_CONFIG_HOME").then(|| OsString::from("xdg"));
+        assert_eq!(resolve_config_path_with(relative, root.path()), None);
+    }
+
+    #[test]
+    fn test_validate_probe_all() {
+        let yaml = r#"
+menu:
+  name: "Main"
+  buttons:
     - type: toggle
-      name: "VPN Toggle" 
+      name: "Services"
+      mode: single
+      command: "services"
+      combine: any
+      probe_all:
+        - command: "systemctl"
+          args: ["is-active", "nginx"]
+        - command: "systemctl"
+          args: ["is-active", "postgresql"]
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        // The combined probes track the toggle's state
+        assert!(config.warnings().is_empty());
+
+        if let Button::Toggle { probe_command, .. } = &mut config.menu.buttons[0] {
+            *probe_command = Some("services status".to_string());
+        }
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("probe_all cannot be combined") && err.contains("'Services'"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_probe_samples() {
+        let yaml = r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Sensor"
+      mode: single
+      command: "true"
+      probe_command: "check-sensor"
+      probe_samples: 3
+      probe_vote: all
+    - type: toggle
+      name: "Plain"
+      mode: single
+      command: "true"
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        assert!(matches!(config.menu.buttons[0], Button::Toggle { probe_samples: 3, probe_vote: ProbeVote::All, .. }));
+        assert!(matches!(config.menu.buttons[1], Button::Toggle { probe_samples: 1, probe_vote: ProbeVote::Majority, .. }));
+
+        if let Button::Toggle { probe_samples, .. } = &mut config.menu.buttons[0] {
+            *probe_samples = 0;
+        }
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(err, "probe_samples must be greater than 0: 'Sensor'");
+    }
+
+    #[test]
+    fn test_parse_probe_timeout_and_indicators() {
+        let yaml = r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Nginx"
+      mode: single
+      command: "true"
+      probe_command: "systemctl"
+      probe_args: ["status", "nginx"]
+      probe_timeout_ms: 2000
+      timeout_state: "off"
+      success_indicators: ["active (running)"]
+      failure_indicators: ["inactive"]
+      check_stderr: true
+    - type: toggle
+      name: "Plain"
+      mode: single
+      command: "true"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        let reloaded: Config = serde_yaml::from_str(&serde_yaml::to_string(&config).unwrap()).unwrap();
+
+        for config in [&config, &reloaded] {
+            let context = crate::probe::ProbeContext::from_button(&config.menu.buttons[0]);
+            assert_eq!(context.timeout_ms, Some(2000));
+            assert_eq!(context.timeout_state, ToggleState::Off);
+            assert_eq!(context.success_indicators, ["active (running)"]);
+            assert_eq!(context.failure_indicators, ["inactive"]);
+            assert!(context.check_stderr);
+
+            let plain = crate::probe::ProbeContext::from_button(&config.menu.buttons[1]);
+            assert_eq!(plain.timeout_ms, None);
+            assert_eq!(plain.timeout_state, ToggleState::Unknown);
+            assert!(!plain.check_stderr);
+        }
+    }
+
+    #[test]
+    fn test_validate_icon_rotate() {
+        let config_with = |rotate: u16| {
+            let yaml = format!(
+                "menu:\n  name: \"Main\"\n  buttons:\n    - type: command\n      name: \"Fan\"\n      command: \"fanctl\"\n      icon_rotate: {}\n",
+                rotate
+            );
+            serde_yaml::from_str::<Config>(&yaml).unwrap()
+        };
+        let config = config_with(270);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.menu.buttons[0].icon_rotate(), 270);
+
+        let err = config_with(45).validate().unwrap_err().to_string();
+        assert!(err.contains("icon_rotate must be 0, 90, 180 or 270: 45 on 'Fan'"), "{}", err);
+    }
+
+    #[test]
+    fn test_untracked_toggle_warning() {
+        let yaml = r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Fan"
+      mode: single
+      command: "fanctl"
+    - type: toggle
+      name: "Lamp"
       mode: separate
-      on_command: "nmcli"
-      on_args: ["connection", "up", "vpn"]
-      off_command: "nmcli"
-      off_args: ["connection", "down", "vpn"]
-      probe_command: "nmcli"
-      probe_args: ["connection", "show", "--active"]
+      on_command: "lampctl"
+      off_command: "lampctl"
 "#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        let warnings = config.warnings();
+        assert_eq!(warnings, [ConfigWarning::UntrackedToggle { toggle: "Fan".to_string() }]);
+        assert!(warnings[0].to_string().contains("no probe and no initial_state"), "{}", warnings[0]);
+
+        if let Button::Toggle { initial_state, .. } = &mut config.menu.buttons[0] {
+            *initial_state = Some(ToggleState::Off);
+        }
+        assert!(config.warnings().is_empty());
 
+        if let Button::Toggle { initial_state, probe_command, .. } = &mut config.menu.buttons[0] {
+            *initial_state = None;
+            *probe_command = Some("fanctl status".to_string());
+        }
+        assert!(config.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_pedal_only_option_warnings() {
+        let yaml = r#"
+pedal:
+  left: "Volume Down"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Volume Down"
+      command: "pactl"
+      trigger_on: release
+      repeat: { interval_ms: 150, command: "pactl", args: ["set-sink-volume", "@DEFAULT_SINK@", "-2%"] }
+    - type: command
+      name: "Wipe"
+      command: "wipe"
+      trigger_on: release
+      confirm_hold_ms: 2000
+    - type: command
+      name: "Nudge"
+      command: "nudge"
+      repeat: { interval_ms: 500, command: "nudge" }
+    - type: toggle
+      name: "Lamp"
+      mode: separate
+      on_command: "lampctl"
+      off_command: "lampctl"
+      probe_command: "lamp-on"
+      trigger_on: press
+      confirm_hold_ms: 500
+"#;
         let config: Config = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(config.menu.name, "Main Menu");
-        assert_eq!(config.menu.buttons.len(), 5);
-        
-        // Check first button
-        match &config.menu.buttons[0] {
-            Button::Command { name, command, .. } => {
-                assert_eq!(name, "List Files");
-                assert_eq!(command, "ls");
-            }
-            _ => panic!("Expected command button"),
+        assert!(config.validate().is_ok());
+        let warnings = config.warnings();
+        let warning = |button: &str, option| ConfigWarning::PedalOnlyOption { button: button.to_string(), option };
+        assert_eq!(
+            warnings,
+            [warning("Wipe", "trigger_on"), warning("Wipe", "confirm_hold_ms"), warning("Nudge", "repeat"), warning("Lamp", "trigger_on"), warning("Lamp", "confirm_hold_ms")]
+        );
+        assert!(warnings[0].to_string().contains("'Wipe' sets trigger_on"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn test_validate_scenes() {
+        let yaml = r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Lights"
+      mode: single
+      command: "lightctl"
+    - type: scene
+      name: "Movie"
+      scene: "movie"
+scenes:
+  movie:
+    Lights: off
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.scenes["movie"]["Lights"], ToggleState::Off);
+
+        config.scenes.get_mut("movie").unwrap().insert("Projector".to_string(), ToggleState::On);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("unknown toggle 'Projector'"), "{}", err);
+
+        let scene = config.scenes.remove("movie").unwrap();
+        config.scenes.insert("cinema".to_string(), scene);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("unknown scene 'movie'"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_input_button() {
+        let yaml = r#"
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: input
+      name: "Volume"
+      command: "pamixer"
+      args: ["--set-volume", "{input}"]
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        let button = &config.menu.buttons[0];
+        assert!(matches!(button, Button::Input { command, args, icon: None, .. } if command == "pamixer" && args[1] == "{input}"));
+        assert_eq!(button.sort_key(), Some("Volume"));
+    }
+
+    #[test]
+    fn test_parse_probe_http() {
+        let yaml = r#"
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: toggle
+      name: "API"
+      mode: single
+      command: "apictl"
+      probe_http:
+        url: "http://localhost:8080/health"
+        status: 204
+        body_contains: "ok"
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        let context = crate::probe::ProbeContext::from_button(&config.menu.buttons[0]);
+        assert_eq!(context.backend.as_deref(), Some("http"));
+        let check = context.http.unwrap();
+        assert_eq!(check.expected, HttpExpect { status: Some(204), body_contains: Some("ok".to_string()) });
+        assert_eq!(check.timeout_ms, 2000);
+
+        if let Button::Toggle { probe_http: Some(check), .. } = &mut config.menu.buttons[0] {
+            check.url = "https://localhost/health".to_string();
         }
-        
-        // Check nested menu
+        assert!(config.validate().is_ok());
+
+        if let Button::Toggle { probe_http: Some(check), .. } = &mut config.menu.buttons[0] {
+            check.url = "ftp://localhost/health".to_string();
+        }
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("'ftp://localhost/health' on 'API'"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_group_action() {
+        let yaml = r#"
+menu:
+  name: "Main Menu"
+  buttons:
+    - type: toggle
+      name: "Lamp"
+      mode: single
+      command: "lampctl"
+      group: "lights"
+    - type: group_action
+      name: "Lights Off"
+      group: "lights"
+      action: "off"
+"#;
+
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
         match &config.menu.buttons[1] {
-            Button::Menu { name, buttons, .. } => {
-                assert_eq!(name, "Git Commands");
-                assert_eq!(buttons.len(), 3);
+            Button::GroupAction { group, action, icon, .. } => {
+                assert_eq!(group, "lights");
+                assert_eq!(*action, GroupActionKind::Off);
+                assert_eq!(*icon, None);
             }
-            _ => panic!("Expected menu button"),
+            _ => panic!("Expected group action button"),
         }
-        
-        // Check toggle button with single mode
-        match &config.menu.buttons[3] {
-            Button::Toggle { name, mode, probe_command, on_icon, off_icon, .. } => {
-                assert_eq!(name, "WiFi Toggle");
-                match mode {
-                    ToggleMode::Single { command, args } => {
-                        assert_eq!(command, "nmcli");
-                        assert_eq!(args, &vec!["radio".to_string(), "wifi".to_string()]);
-                    }
-                    _ => panic!("Expected single mode toggle"),
-                }
-                assert_eq!(probe_command.as_ref().unwrap(), "nmcli");
-                assert_eq!(on_icon.as_ref().unwrap(), "wifi");
-                assert_eq!(off_icon.as_ref().unwrap(), "wifi_off");
+        assert!(config.validate().is_ok());
+
+        config.menu.buttons.remove(0);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("group 'lights' with no toggles"), "{}", err);
+    }
+
+    #[test]
+    fn test_walk_buttons_paths() {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Terminal"
+      command: "true"
+    - type: menu
+      name: "System"
+      buttons:
+        - type: toggle
+          name: "WiFi"
+          mode: single
+          command: "true"
+        - type: menu
+          name: "Power"
+          buttons:
+            - type: command
+              name: "Suspend"
+              command: "true"
+            - type: back
+        - type: back
+    - type: reload
+"#,
+        )
+        .unwrap();
+
+        let mut visited = Vec::new();
+        config.walk_buttons(|button, path| visited.push(format!("{} > {}", path.join(" > "), button.name())));
+        assert_eq!(
+            visited,
+            vec![
+                "Main > Terminal",
+                "Main > System",
+                "Main > System > WiFi",
+                "Main > System > Power",
+                "Main > System > Power > Suspend",
+                "Main > System > Power > Back",
+                "Main > System > Back",
+                "Main > Reload",
+            ]
+        );
+
+        config.walk_buttons_mut(|button, path| {
+            if let Button::Command { name, .. } = button {
+                *name = format!("{}/{}", path.last().unwrap(), name);
             }
-            _ => panic!("Expected toggle button"),
-        }
-        
-        // Check toggle button with separate mode
-        match &config.menu.buttons[4] {
-            Button::Toggle { name, mode, probe_command, .. } => {
-                assert_eq!(name, "VPN Toggle");
-                match mode {
-                    ToggleMode::Separate { on_command, on_args, off_command, off_args } => {
-                        assert_eq!(on_command, "nmcli");
-                        assert_eq!(on_args, &vec!["connection".to_string(), "up".to_string(), "vpn".to_string()]);
-                        assert_eq!(off_command, "nmcli");
-                        assert_eq!(off_args, &vec!["connection".to_string(), "down".to_string(), "vpn".to_string()]);
-                    }
-                    _ => panic!("Expected separate mode toggle"),
-                }
-                assert_eq!(probe_command.as_ref().unwrap(), "nmcli");
+        });
+        let mut commands = Vec::new();
+        config.walk_buttons(|button, _| {
+            if let Button::Command { name, .. } = button {
+                commands.push(name.as_str());
             }
-            _ => panic!("Expected toggle button"),
-        }
+        });
+        assert_eq!(commands, vec!["Main/Terminal", "Power/Suspend"]);
+    }
+
+    #[test]
+    fn test_render_settings() {
+        let mut config: Config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        assert_eq!(config.render, RenderSettings::default());
+
+        let config_with_render: Config = serde_yaml::from_str(
+            "render:\n  font_size: 14.5\n  theme: dark\nmenu:\n  name: \"Main\"\n  buttons: []\n",
+        )
+        .unwrap();
+        assert_eq!(config_with_render.render.font_size, Some(14.5));
+        assert_eq!(config_with_render.render.theme, ThemeName::Dark);
+        assert!(config_with_render.validate().is_ok());
+
+        config.render.font_size = Some(200.0);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("render.font_size (200)"), "{}", err);
+        config.render.font_size = Some(f32::NAN);
+        assert!(config.validate().is_err());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// End of the `cooldown_ms` window of each button pressed recently, keyed by button name
+#[derive(Debug, Clone, Default)]
+pub struct Cooldowns {
+    until: Arc<RwLock<HashMap<String, Instant>>>,
+}
+
+impl Cooldowns {
+    /// Creates a tracker with no button cooling down
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts a press of the button and starts its `cooldown`, unless the button is
+    /// still cooling down from an earlier press. Returns whether the press is accepted.
+    pub fn try_press(&self, button_name: &str, cooldown: Duration) -> bool {
+        let now = Instant::now();
+        let mut until = self.until.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(end) = until.get(button_name).filter(|end| **end > now) {
+            debug!("'{}' is cooling down for another {:?}", button_name, *end - now);
+            return false;
+        }
+        until.insert(button_name.to_string(), now + cooldown);
+        true
+    }
+
+    /// Time left until the button takes presses again; `None` if it already does
+    pub fn remaining(&self, button_name: &str) -> Option<Duration> {
+        let until = self.until.read().unwrap_or_else(PoisonError::into_inner);
+        let left = until.get(button_name)?.saturating_duration_since(Instant::now());
+        (!left.is_zero()).then_some(left)
+    }
+
+    /// Returns true while presses of the button are ignored, shown by a wait icon on its key
+    pub fn is_cooling(&self, button_name: &str) -> bool {
+        self.remaining(button_name).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_is_cooling_during_window() {
+        let cooldowns = Cooldowns::new();
+        assert!(!cooldowns.is_cooling("Deploy"));
+
+        assert!(cooldowns.try_press("Deploy", Duration::from_secs(10)));
+        assert!(cooldowns.is_cooling("Deploy"));
+        assert!(!cooldowns.is_cooling("Build"));
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert_eq!(cooldowns.remaining("Deploy"), Some(Duration::from_secs(6)));
+        // A press during the window is ignored and does not extend it
+        assert!(!cooldowns.try_press("Deploy", Duration::from_secs(10)));
+
+        tokio::time::advance(Duration::from_secs(6)).await;
+        assert!(!cooldowns.is_cooling("Deploy"));
+        assert_eq!(cooldowns.remaining("Deploy"), None);
+        assert!(cooldowns.try_press("Deploy", Duration::from_secs(10)));
+        assert!(cooldowns.is_cooling("Deploy"));
+    }
+}
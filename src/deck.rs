@@ -0,0 +1,249 @@
+use crate::config::RenderSettings;
+use crate::error::CommanderError;
+use crate::render::{render_config_from, theme_from};
+use async_trait::async_trait;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use streamdeck_oxide::elgato_streamdeck::{AsyncDeviceStateReader, AsyncStreamDeck, DeviceStateUpdate};
+use streamdeck_oxide::generic_array::typenum::{U3, U5};
+use streamdeck_oxide::plugins::{PluginContext, PluginNavigation};
+use streamdeck_oxide::{run_with_external_triggers, ExternalTrigger};
+use tokio::sync::mpsc;
+
+/// Navigation sent to the deck renderer
+pub type Navigation = ExternalTrigger<PluginNavigation<U5, U3>, U5, U3, PluginContext>;
+
+/// A running renderer; it need not be `Send`, so it runs on the task awaiting it
+pub type Renderer<'a> = Pin<Box<dyn Future<Output = Result<(), CommanderError>> + 'a>>;
+
+/// The device operations of the commander: the renderer drawing the menus and
+/// handling key presses, and the reads and writes the commander does itself
+#[async_trait]
+pub trait DeckDevice: Send + Sync {
+    /// Reads the firmware version the device reports
+    async fn firmware_version(&self) -> Result<String, CommanderError>;
+
+    /// Sets the brightness, in percent
+    async fn set_brightness(&self, percent: u8) -> Result<(), CommanderError>;
+
+    /// Waits up to `timeout` for key events; an error means the device is gone
+    async fn read_events(&self, timeout: Option<Duration>) -> Result<Vec<DeviceStateUpdate>, CommanderError>;
+
+    /// Draws the menus sent through `navigation` with the `settings` theme and font, and
+    /// runs the buttons pressed on them, until the device is gone
+    fn run(&self, settings: RenderSettings, context: PluginContext, navigation: mpsc::Receiver<Navigation>) -> Renderer<'_>;
+}
+
+/// A connected deck or pedal, with the reader that tracks its key states between reads
+pub struct HidDeck {
+    deck: Arc<AsyncStreamDeck>,
+    reader: Arc<AsyncDeviceStateReader>,
+}
+
+impl HidDeck {
+    pub fn new(deck: Arc<AsyncStreamDeck>) -> Self {
+        let reader = deck.get_reader();
+        Self { deck, reader }
+    }
+}
+
+#[async_trait]
+impl DeckDevice for HidDeck {
+    async fn firmware_version(&self) -> Result<String, CommanderError> {
+        self.deck
+            .firmware_version()
+            .await
+            .map_err(|e| CommanderError::Device(e.to_string()))
+    }
+
+    async fn set_brightness(&self, percent: u8) -> Result<(), CommanderError> {
+        self.deck
+            .set_brightness(percent)
+            .await
+            .map_err(|e| CommanderError::Device(e.to_string()))
+    }
+
+    async fn read_events(&self, timeout: Option<Duration>) -> Result<Vec<DeviceStateUpdate>, CommanderError> {
+        self.reader
+            .read(timeout)
+            .await
+            .map_err(|e| CommanderError::Device(e.to_string()))
+    }
+
+    fn run(&self, settings: RenderSettings, context: PluginContext, navigation: mpsc::Receiver<Navigation>) -> Renderer<'_> {
+        Box::pin(async move {
+            run_with_external_triggers::<PluginNavigation<U5, U3>, U5, U3, PluginContext>(
+                theme_from(&settings),
+                render_config_from(&settings),
+                self.deck.clone(),
+                context,
+                navigation,
+            )
+            .await
+            .map_err(|e| CommanderError::Device(e.to_string()))
+        })
+    }
+}
+
+/// An in-memory device for tests: it records every brightness it is set to and
+/// reports scripted key events, one batch per read, then disconnects. Its renderer
+/// records the theme and keys of each view it shows and stops after `view_limit` views.
+/// After each view it presses the keys of the next batch of events on the commander's
+/// active menu: menu and back keys show their menu right away, as the real renderer's
+/// do, other keys are pressed through `CommanderContext::execute_button` and the
+/// renderer waits for the navigation they send.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockDeck {
+    brightness: std::sync::Mutex<Vec<u8>>,
+    events: std::sync::Mutex<std::collections::VecDeque<Vec<DeviceStateUpdate>>>,
+    views: std::sync::Mutex<Vec<crate::config::ThemeName>>,
+    keys: std::sync::Mutex<Vec<Vec<crate::render_dump::DumpKey>>>,
+    view_limit: usize,
+}
+
+#[cfg(test)]
+impl MockDeck {
+    /// A device that reports `batches` of key events, in order
+    pub fn with_events(batches: Vec<Vec<DeviceStateUpdate>>) -> Self {
+        Self {
+            events: std::sync::Mutex::new(batches.into()),
+            ..Default::default()
+        }
+    }
+
+    /// A device whose renderer shows `views` views, then stops
+    pub fn showing(views: usize) -> Self {
+        Self {
+            view_limit: views,
+            ..Default::default()
+        }
+    }
+
+    /// A device whose renderer shows `views` views, pressing the keys of `batches` of
+    /// key events on them, in order
+    pub fn showing_with_events(views: usize, batches: Vec<Vec<DeviceStateUpdate>>) -> Self {
+        Self {
+            view_limit: views,
+            ..Self::with_events(batches)
+        }
+    }
+
+    /// The theme of every view the renderer showed, oldest first
+    pub fn views(&self) -> Vec<crate::config::ThemeName> {
+        self.views.lock().unwrap().clone()
+    }
+
+    /// The keys of every view the renderer showed, with their labels and icons, oldest first
+    pub fn shown_keys(&self) -> Vec<Vec<crate::render_dump::DumpKey>> {
+        self.keys.lock().unwrap().clone()
+    }
+
+    /// Every brightness the device was set to, oldest first
+    pub fn brightness_history(&self) -> Vec<u8> {
+        self.brightness.lock().unwrap().clone()
+    }
+
+    /// Records the commander's active menu as shown in `theme`
+    fn show(&self, theme: crate::config::ThemeName, commander: Option<&crate::button::CommanderContext>) {
+        self.views.lock().unwrap().push(theme);
+        self.keys.lock().unwrap().push(commander.map(crate::render_dump::active_view_keys).unwrap_or_default());
+    }
+
+    /// Presses the keys of the next batch of events on the active menu. Returns whether
+    /// a press showed another menu, so the next batch can be pressed on it.
+    async fn press_next(&self, theme: crate::config::ThemeName, commander: Option<&crate::button::CommanderContext>) -> bool {
+        let Some(commander) = commander else {
+            return false;
+        };
+        let Some(batch) = self.events.lock().unwrap().pop_front() else {
+            return false;
+        };
+        let mut shown = false;
+        for update in batch {
+            let DeviceStateUpdate::ButtonDown(key) = update else {
+                continue;
+            };
+            let plugin = commander.active_plugin();
+            let key = usize::from(key);
+            if key == crate::grid::DECK_GRID.back_button_index() {
+                if let Some(parent) = plugin.parent() {
+                    commander.set_active_plugin(parent.clone());
+                    self.show(theme, Some(commander));
+                    shown = true;
+                    continue;
+                }
+            }
+            let (col, row) = crate::grid::DECK_GRID.position(key);
+            let menu = plugin.shown_menu(Some(commander));
+            let Some((_, _, button)) = crate::button::menu_layout(&menu).into_iter().find(|(c, r, _)| (*c, *r) == (col, row)) else {
+                continue;
+            };
+            match button.submenu() {
+                Some(submenu) => {
+                    commander.set_active_plugin(crate::button::CommanderPlugin::new_with_parent(submenu, plugin.clone()));
+                    self.show(theme, Some(commander));
+                    shown = true;
+                }
+                None => {
+                    commander.execute_button(button.name()).await;
+                }
+            }
+        }
+        shown
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl DeckDevice for MockDeck {
+    async fn firmware_version(&self) -> Result<String, CommanderError> {
+        Ok("mock".to_string())
+    }
+
+    async fn set_brightness(&self, percent: u8) -> Result<(), CommanderError> {
+        self.brightness.lock().unwrap().push(percent);
+        Ok(())
+    }
+
+    async fn read_events(&self, _timeout: Option<Duration>) -> Result<Vec<DeviceStateUpdate>, CommanderError> {
+        self.events
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| CommanderError::Device("mock deck disconnected".to_string()))
+    }
+
+    fn run(&self, settings: RenderSettings, context: PluginContext, mut navigation: mpsc::Receiver<Navigation>) -> Renderer<'_> {
+        Box::pin(async move {
+            let commander = context.get_context::<crate::button::CommanderContext>().await;
+            while self.views().len() < self.view_limit && navigation.recv().await.is_some() {
+                self.show(settings.theme, commander.as_deref());
+                while self.views().len() < self.view_limit && self.press_next(settings.theme, commander.as_deref()).await {}
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_deck_replays_events_then_disconnects() {
+        let deck = MockDeck::with_events(vec![
+            vec![DeviceStateUpdate::ButtonDown(1)],
+            vec![DeviceStateUpdate::ButtonUp(1)],
+        ]);
+        assert_eq!(deck.read_events(None).await.unwrap(), vec![DeviceStateUpdate::ButtonDown(1)]);
+        assert_eq!(deck.read_events(None).await.unwrap(), vec![DeviceStateUpdate::ButtonUp(1)]);
+        assert!(matches!(deck.read_events(None).await, Err(CommanderError::Device(_))));
+
+        deck.set_brightness(40).await.unwrap();
+        deck.set_brightness(60).await.unwrap();
+        assert_eq!(deck.brightness_history(), vec![40, 60]);
+    }
+}
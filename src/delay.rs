@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use tracing::{debug, info, warn};
+
+/// What pressing a button with a `delay_ms` did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelayedPress {
+    /// The command was scheduled to run once the delay passes
+    Scheduled,
+    /// The press cancelled the command scheduled by an earlier press
+    Cancelled,
+}
+
+/// Commands waiting out their button's `delay_ms`, keyed by button name
+#[derive(Debug, Clone, Default)]
+pub struct DelayedCommands {
+    pending: Arc<RwLock<HashMap<String, (u64, AbortHandle)>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl DelayedCommands {
+    /// Creates a scheduler with nothing pending
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedules `run` to start after `delay`. If a run of the button is already
+    /// pending, it is cancelled instead and `run` is dropped.
+    pub fn press<F>(&self, button_name: &str, delay: Duration, run: F) -> DelayedPress
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut pending = match self.pending.write() {
+            Ok(pending) => pending,
+            Err(e) => {
+                warn!("Failed to schedule the command of '{}': {}", button_name, e);
+                return DelayedPress::Cancelled;
+            }
+        };
+        if let Some((_, task)) = pending.remove(button_name) {
+            task.abort();
+            info!("Cancelled the scheduled command of '{}'", button_name);
+            return DelayedPress::Cancelled;
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let scheduler = self.clone();
+        let name = button_name.to_string();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            // The run is no longer cancellable once it starts
+            scheduler.finish(&name, id);
+            debug!("Delay of '{}' passed, running its command", name);
+            run.await;
+        });
+        pending.insert(button_name.to_string(), (id, task.abort_handle()));
+        info!("Scheduled the command of '{}' in {:?}", button_name, delay);
+        DelayedPress::Scheduled
+    }
+
+    /// Returns true while the button's command waits for its delay to pass
+    pub fn is_pending(&self, button_name: &str) -> bool {
+        match self.pending.read() {
+            Ok(pending) => pending.contains_key(button_name),
+            Err(e) => {
+                warn!("Failed to read the scheduled command of '{}': {}", button_name, e);
+                false
+            }
+        }
+    }
+
+    /// Forgets the pending run `id`, unless a later press already replaced it
+    fn finish(&self, button_name: &str, id: u64) {
+        match self.pending.write() {
+            Ok(mut pending) => {
+                if pending.get(button_name).is_some_and(|(pending_id, _)| *pending_id == id) {
+                    pending.remove(button_name);
+                }
+            }
+            Err(e) => warn!("Failed to clear the scheduled command of '{}': {}", button_name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn counting_run(runs: &Arc<AtomicUsize>) -> impl Future<Output = ()> + Send + 'static {
+        let runs = runs.clone();
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delayed_command_runs_after_delay() {
+        let delayed = DelayedCommands::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let press = delayed.press("Sleep", Duration::from_secs(300), counting_run(&runs));
+        assert_eq!(press, DelayedPress::Scheduled);
+        assert!(delayed.is_pending("Sleep"));
+
+        tokio::time::sleep(Duration::from_secs(299)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        assert!(delayed.is_pending("Sleep"));
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(!delayed.is_pending("Sleep"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_second_press_cancels() {
+        let delayed = DelayedCommands::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        delayed.press("Sleep", Duration::from_secs(5), counting_run(&runs));
+        let press = delayed.press("Sleep", Duration::from_secs(5), counting_run(&runs));
+        assert_eq!(press, DelayedPress::Cancelled);
+        assert!(!delayed.is_pending("Sleep"));
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+        // A press after cancelling schedules again
+        assert_eq!(delayed.press("Sleep", Duration::from_secs(5), counting_run(&runs)), DelayedPress::Scheduled);
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::config::{BrightnessKind, Config};
+use crate::deck::DeckDevice;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use streamdeck_oxide::elgato_streamdeck::info::Kind;
+use tracing::{debug, info, warn};
+
+/// Largest accepted `brightness`, in percent
+pub const MAX_BRIGHTNESS: u8 = 100;
+
+/// What the connected deck reports about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub kind: Kind,
+    pub serial: String,
+    /// `None` if the deck did not answer the firmware query
+    pub firmware: Option<String>,
+}
+
+/// Whether the deck's brightness is changed when it connects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrightnessAction {
+    /// Leave the brightness the deck currently has
+    Keep,
+    /// Set the brightness to this many percent
+    Set(u8),
+}
+
+/// The deck's brightness is only overridden when the config sets `brightness`.
+/// The device cannot report its current brightness, so otherwise it is left alone.
+pub fn brightness_action(config: &Config) -> BrightnessAction {
+    match config.brightness {
+        Some(percent) => BrightnessAction::Set(percent.min(MAX_BRIGHTNESS)),
+        None => BrightnessAction::Keep,
+    }
+}
+
+/// The brightness after pressing a brightness button at `current`: `set` clamps its
+/// value to 0–100 and `step` moves from `current`, saturating at either end
+pub fn brightness_after(current: u8, action: BrightnessKind, value: i32) -> u8 {
+    let level = match action {
+        BrightnessKind::Set => value,
+        BrightnessKind::Step => i32::from(current).saturating_add(value),
+    };
+    level.clamp(0, i32::from(MAX_BRIGHTNESS)) as u8
+}
+
+/// The deck brightness buttons change, and the level they last set it to. The deck
+/// cannot report its brightness, so steps start from the configured `brightness`.
+#[derive(Clone)]
+pub struct BrightnessControl {
+    deck: Option<Arc<dyn DeckDevice>>,
+    level: Arc<AtomicU8>,
+}
+
+impl BrightnessControl {
+    /// Controls `deck`, currently at `level` percent; without a deck only the level changes
+    pub fn new(deck: Option<Arc<dyn DeckDevice>>, level: u8) -> Self {
+        Self {
+            deck,
+            level: Arc::new(AtomicU8::new(level.min(MAX_BRIGHTNESS))),
+        }
+    }
+
+    /// The brightness last set, in percent
+    pub fn level(&self) -> u8 {
+        self.level.load(Ordering::SeqCst)
+    }
+
+    /// Applies a brightness button to the deck and returns the new level
+    pub async fn apply(&self, action: BrightnessKind, value: i32) -> u8 {
+        let previous = self
+            .level
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |current| Some(brightness_after(current, action, value)))
+            .unwrap_or_else(|current| current);
+        let level = brightness_after(previous, action, value);
+        match &self.deck {
+            Some(deck) => {
+                info!("Setting the deck's brightness to {}%", level);
+                if let Err(e) = deck.set_brightness(level).await {
+                    warn!("Failed to set the brightness: {}", e);
+                }
+            }
+            None => debug!("No deck connected, brightness is now {}%", level),
+        }
+        level
+    }
+}
+
+/// Queries the connected deck's firmware version
+pub async fn read_device_info(deck: &dyn DeckDevice, kind: Kind, serial: &str) -> DeviceInfo {
+    let firmware = match deck.firmware_version().await {
+        Ok(firmware) => Some(firmware),
+        Err(e) => {
+            warn!("Failed to read the firmware version of {}: {}", serial, e);
+            None
+        }
+    };
+    DeviceInfo {
+        kind,
+        serial: serial.to_string(),
+        firmware,
+    }
+}
+
+/// Logs the deck's info and applies the configured brightness, if any
+pub async fn prepare_device(deck: &dyn DeckDevice, device: &DeviceInfo, config: &Config) {
+    info!(
+        "Stream Deck {:?} (serial {}, firmware {})",
+        device.kind,
+        device.serial,
+        device.firmware.as_deref().unwrap_or("unknown")
+    );
+    match brightness_action(config) {
+        BrightnessAction::Keep => info!("Keeping the deck's current brightness"),
+        BrightnessAction::Set(percent) => {
+            info!("Setting the deck's brightness to {}%", percent);
+            if let Err(e) = deck.set_brightness(percent).await {
+                warn!("Failed to set the brightness: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(brightness: &str) -> Config {
+        serde_yaml::from_str(&format!("{}menu:\n  name: \"Main\"\n  buttons: []\n", brightness)).unwrap()
+    }
+
+    #[test]
+    fn test_brightness_action() {
+        assert_eq!(brightness_action(&config_with("")), BrightnessAction::Keep);
+        assert_eq!(brightness_action(&config_with("brightness: 40\n")), BrightnessAction::Set(40));
+        assert_eq!(brightness_action(&config_with("brightness: 0\n")), BrightnessAction::Set(0));
+
+        let err = config_with("brightness: 150\n").validate().unwrap_err();
+        assert_eq!(err.to_string(), "brightness (150) must be between 0 and 100");
+    }
+
+    #[test]
+    fn test_parse_brightness_button() {
+        let config: Config = serde_yaml::from_str(
+            "menu:\n  name: \"Main\"\n  buttons:\n    - type: brightness\n      name: \"Dimmer\"\n      action: step\n      value: -10\n",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        match &config.menu.buttons[0] {
+            crate::config::Button::Brightness { action, value, .. } => {
+                assert_eq!(*action, BrightnessKind::Step);
+                assert_eq!(*value, -10);
+            }
+            other => panic!("expected a brightness button, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_brightness_after() {
+        assert_eq!(brightness_after(30, BrightnessKind::Set, 75), 75);
+        assert_eq!(brightness_after(30, BrightnessKind::Set, 150), 100);
+        assert_eq!(brightness_after(30, BrightnessKind::Set, -5), 0);
+
+        assert_eq!(brightness_after(30, BrightnessKind::Step, 10), 40);
+        assert_eq!(brightness_after(30, BrightnessKind::Step, -10), 20);
+        // Stepping past either end saturates
+        assert_eq!(brightness_after(95, BrightnessKind::Step, 10), 100);
+        assert_eq!(brightness_after(5, BrightnessKind::Step, -10), 0);
+        assert_eq!(brightness_after(50, BrightnessKind::Step, i32::MAX), 100);
+        assert_eq!(brightness_after(50, BrightnessKind::Step, i32::MIN), 0);
+    }
+
+    #[tokio::test]
+    async fn test_brightness_control_sets_the_deck() {
+        let deck = Arc::new(crate::deck::MockDeck::default());
+        let control = BrightnessControl::new(Some(deck.clone()), 50);
+        control.apply(BrightnessKind::Step, -20).await;
+        control.apply(BrightnessKind::Set, 120).await;
+        assert_eq!(deck.brightness_history(), vec![30, 100]);
+    }
+
+    #[tokio::test]
+    async fn test_brightness_control_steps_from_last_level() {
+        let control = BrightnessControl::new(None, 90);
+        assert_eq!(control.apply(BrightnessKind::Step, 25).await, 100);
+        assert_eq!(control.apply(BrightnessKind::Step, -25).await, 75);
+        assert_eq!(control.apply(BrightnessKind::Set, 10).await, 10);
+        assert_eq!(control.apply(BrightnessKind::Step, -25).await, 0);
+        assert_eq!(control.level(), 0);
+        assert_eq!(BrightnessControl::new(None, 200).level(), 100);
+    }
+}
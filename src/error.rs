@@ -0,0 +1,89 @@
+use std::error::Error;
+
+/// Errors of running a button's command and building a menu's view
+#[derive(Debug, thiserror::Error)]
+pub enum CommanderError {
+    /// The command could not be started or waited for
+    #[error("failed to run '{command}': {source}")]
+    Spawn {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The command is not allowed by `allowed_commands` or `denied_commands`
+    #[error("'{command}' is not allowed by allowed_commands or denied_commands")]
+    NotAllowed { command: String },
+    /// A placeholder in the args could not be expanded, e.g. a missing secret file
+    #[error("invalid arguments for '{command}': {message}")]
+    InvalidArgs { command: String, message: String },
+    /// The deck library rejected a key of the view
+    #[error("failed to render the view: {0}")]
+    Render(String),
+    /// The deck or pedal rejected an operation or could not be read
+    #[error("device error: {0}")]
+    Device(String),
+    /// No notification daemon answered, or it rejected the notification
+    #[error("failed to show the notification: {0}")]
+    Notification(String),
+
+    /// A menu's background image could not be read or sliced
+    #[error("failed to load the background image {0}")]
+    Background(String),
+    /// The mirrored view could not be written to `dump_render_path`
+    #[error("failed to write the render dump {0}")]
+    RenderDump(String),
+}
+
+impl From<Box<dyn Error>> for CommanderError {
+    fn from(error: Box<dyn Error>) -> Self {
+        CommanderError::Render(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::button::CommanderPlugin;
+    use crate::expand::ExpandContext;
+    use crate::spawn::SpawnConfig;
+
+    async fn run(command: &str, args: &[String]) -> Result<(i32, String), CommanderError> {
+        let expand = ExpandContext {
+            secrets: true,
+            ..Default::default()
+        };
+        CommanderPlugin::execute_command(command, args, &expand, &SpawnConfig::default(), &[0]).await
+    }
+
+    #[tokio::test]
+    async fn test_missing_command_is_spawn_error() {
+        let err = run("streamdeck-no-such-command", &[]).await.unwrap_err();
+        match &err {
+            CommanderError::Spawn { command, source } => {
+                assert_eq!(command, "streamdeck-no-such-command");
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("expected a spawn error, got {:?}", other),
+        }
+        assert!(err.source().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_missing_secret_is_invalid_args() {
+        let args = ["{secret:/nonexistent/streamdeck-token}".to_string()];
+        let err = run("echo", &args).await.unwrap_err();
+        assert!(
+            matches!(&err, CommanderError::InvalidArgs { command, .. } if command == "echo"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_render_error_from_boxed() {
+        let boxed: Box<dyn Error> = "key out of range".into();
+        let err = CommanderError::from(boxed);
+        assert!(matches!(&err, CommanderError::Render(message) if message == "key out of range"));
+        assert_eq!(err.to_string(), "failed to render the view: key out of range");
+    }
+}
@@ -0,0 +1,163 @@
+use serde::Serialize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Number of press events buffered for clients that fall behind
+const EVENT_BUFFER: usize = 64;
+
+/// A button press, written to event socket clients as one JSON line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PressEvent {
+    pub button: String,
+    /// Button type as written in the config (e.g. "command" or "toggle")
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Outcome of the press, e.g. "success", "failure" or a toggle's new state
+    pub result: Option<String>,
+}
+
+impl PressEvent {
+    /// Creates an event for a press that happened now
+    pub fn new(button: &str, kind: &str, result: Option<&str>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            button: button.to_string(),
+            kind: kind.to_string(),
+            timestamp,
+            result: result.map(str::to_string),
+        }
+    }
+}
+
+/// Broadcasts press events to every listener
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<PressEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// Creates a bus with no listeners
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self { sender }
+    }
+
+    /// Sends an event to the current listeners; events without listeners are dropped
+    pub fn publish(&self, event: PressEvent) {
+        debug!("Press event for '{}': {:?}", event.button, event.result);
+        let _ = self.sender.send(event);
+    }
+
+    /// Starts listening for events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<PressEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Listens on a Unix socket at `path` and streams every press event to connected
+/// clients as JSON lines. A stale socket file left at `path` is replaced.
+pub fn serve_event_socket(path: &Path, events: EventBus) -> std::io::Result<JoinHandle<()>> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("Publishing press events on {}", path.display());
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    debug!("Event socket client connected");
+                    tokio::spawn(stream_events(stream, events.subscribe()));
+                }
+                Err(e) => warn!("Failed to accept event socket client: {}", e),
+            }
+        }
+    }))
+}
+
+/// Writes events to one client until it disconnects
+async fn stream_events(mut stream: UnixStream, mut receiver: broadcast::Receiver<PressEvent>) {
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("Event socket client fell behind, skipped {} events", skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let mut line = match serde_json::to_string(&event) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to encode press event: {}", e);
+                continue;
+            }
+        };
+        line.push('\n');
+        if let Err(e) = stream.write_all(line.as_bytes()).await {
+            debug!("Event socket client disconnected: {}", e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    #[test]
+    fn test_press_event_json() {
+        let event = PressEvent {
+            button: "Terminal".to_string(),
+            kind: "command".to_string(),
+            timestamp: 1700000000000,
+            result: Some("success".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"button":"Terminal","type":"command","timestamp":1700000000000,"result":"success"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_event_socket_streams_presses() {
+        let dir = TempDir::new("events");
+        let path = dir.join("events.sock");
+        let events = EventBus::new();
+        let server = serve_event_socket(&path, events.clone()).unwrap();
+
+        // A client that disconnects right away must not stop the others
+        drop(UnixStream::connect(&path).await.unwrap());
+        let client = UnixStream::connect(&path).await.unwrap();
+        let mut lines = BufReader::new(client).lines();
+        while events.sender.receiver_count() < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        events.publish(PressEvent::new("WiFi", "toggle", Some("on")));
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(line.starts_with(r#"{"button":"WiFi","type":"toggle","timestamp":"#), "{}", line);
+        assert!(line.ends_with(r#","result":"on"}"#), "{}", line);
+
+        server.abort();
+    }
+}
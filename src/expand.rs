@@ -0,0 +1,413 @@
+use crate::file_toggle::expand_home;
+use crate::runtime_vars::RuntimeVars;
+use anyhow::{Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Local, NaiveDateTime};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tracing::warn;
+
+// Spawn-time placeholders, expanded by `expand_all`
+const VAR_PREFIX: &str = "${var:";
+const OUTPUT_PREFIX: &str = "{output:";
+const SECRET_PREFIX: &str = "{secret:";
+const STATE_PLACEHOLDER: &str = "{state}";
+const INPUT_PLACEHOLDER: &str = "{input}";
+const DATE_PLACEHOLDER: &str = "{date}";
+const DATE_PREFIX: &str = "{date:";
+/// Format of `{date}`
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Values available to the placeholders of one spawned command. Placeholders whose
+/// value is not available are kept as written.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExpandContext<'a> {
+    /// Values for `${var:NAME}` and `{output:NAME}`
+    pub vars: Option<&'a RuntimeVars>,
+    /// Value for `{state}`
+    pub state: Option<&'a str>,
+    /// Value for `{input}`, the number entered on an input button's keypad
+    pub input: Option<&'a str>,
+    /// Read `{secret:PATH}` files
+    pub secrets: bool,
+    /// Time `{date}` and `{date:FORMAT}` show; the current local time when unset
+    pub date: Option<NaiveDateTime>,
+}
+
+/// Expands the spawn-time placeholders of one argument: `${var:NAME}`, `{output:NAME}`
+/// (the trimmed stdout of the latest run of the button NAME), `{state}`, `{input}`,
+/// `{date}` (the local date, `%Y-%m-%d`), `{date:FORMAT}` (strftime) and `{secret:PATH}`.
+///
+/// Placeholders are expanded in two stages. `${VAR}` environment placeholders are
+/// substituted into the raw config text when it loads (see [`expand_env`]), so their
+/// values become part of the config and may contain spawn-time placeholders. All
+/// spawn-time placeholders are then expanded in a single left-to-right pass when the
+/// command runs, so an expanded value (a secret containing `${`, a variable containing
+/// `{state}`) is never expanded again. An environment value containing `{date}` thus
+/// gives the date of each run, not of the config load.
+///
+/// A backslash right before a placeholder keeps it literal, `\{state}` gives
+/// `{state}`; two backslashes give one backslash followed by the expanded value.
+/// Backslashes anywhere else are left alone. Unset variables and outputs of buttons
+/// that have not run yet expand to an empty string, unterminated placeholders are
+/// kept as-is without stopping the expansion of the rest, as are dates with an invalid
+/// format; an unreadable secret fails the whole expansion.
+pub fn expand_all(input: &str, context: &ExpandContext) -> Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some((start, prefix)) = [VAR_PREFIX, OUTPUT_PREFIX, SECRET_PREFIX, STATE_PLACEHOLDER, INPUT_PLACEHOLDER, DATE_PLACEHOLDER, DATE_PREFIX]
+        .into_iter()
+        .filter_map(|prefix| rest.find(prefix).map(|start| (start, prefix)))
+        .min_by_key(|(start, _)| *start)
+    {
+        let after = &rest[start + prefix.len()..];
+        let (argument, consumed) = if [STATE_PLACEHOLDER, INPUT_PLACEHOLDER, DATE_PLACEHOLDER].contains(&prefix) {
+            ("", 0)
+        } else {
+            // A name cannot contain `{`, so a later placeholder does not close this one
+            match after.find(['{', '}']).filter(|end| after[*end..].starts_with('}')) {
+                Some(end) => (&after[..end], end + 1),
+                None => {
+                    // Not a placeholder, keep it and any backslashes before it as written
+                    output.push_str(&rest[..start + prefix.len()]);
+                    rest = after;
+                    continue;
+                }
+            }
+        };
+
+        let escaped = push_unescaped(&mut output, &rest[..start]);
+        let placeholder = &rest[start..start + prefix.len() + consumed];
+        rest = &after[consumed..];
+
+        if escaped {
+            output.push_str(placeholder);
+            continue;
+        }
+        match prefix {
+            VAR_PREFIX => match context.vars {
+                Some(vars) => match vars.get(argument) {
+                    Some(value) => output.push_str(&value),
+                    None => warn!("Runtime variable '{}' is not set, expanding to an empty string", argument),
+                },
+                None => output.push_str(placeholder),
+            },
+            OUTPUT_PREFIX => match context.vars {
+                Some(vars) => match vars.output(argument) {
+                    Some(value) => output.push_str(&value),
+                    None => warn!("No output of '{}' recorded yet, expanding to an empty string", argument),
+                },
+                None => output.push_str(placeholder),
+            },
+            SECRET_PREFIX if context.secrets => output.push_str(&read_secret(Path::new(argument))?),
+            STATE_PLACEHOLDER => output.push_str(context.state.unwrap_or(placeholder)),
+            INPUT_PLACEHOLDER => output.push_str(context.input.unwrap_or(placeholder)),
+            DATE_PLACEHOLDER | DATE_PREFIX => {
+                let format = if prefix == DATE_PLACEHOLDER { DEFAULT_DATE_FORMAT } else { argument };
+                match format_date(context.date, format) {
+                    Some(date) => output.push_str(&date),
+                    None => {
+                        warn!("Invalid date format '{}', keeping {} as written", format, placeholder);
+                        output.push_str(placeholder);
+                    }
+                }
+            }
+            _ => output.push_str(placeholder),
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Formats `date`, or the current local time, with the strftime `format`; `None` if
+/// the format is invalid
+fn format_date(date: Option<NaiveDateTime>, format: &str) -> Option<String> {
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.contains(&Item::Error) {
+        return None;
+    }
+    let date = date.unwrap_or_else(|| Local::now().naive_local());
+    Some(date.format_with_items(items.into_iter()).to_string())
+}
+
+/// Pushes the text before a placeholder, halving the run of backslashes right before
+/// it, and returns whether an odd run escaped the placeholder
+fn push_unescaped(output: &mut String, before: &str) -> bool {
+    let literal = before.trim_end_matches('\\');
+    let slashes = before.len() - literal.len();
+    output.push_str(literal);
+    output.push_str(&"\\".repeat(slashes / 2));
+    slashes % 2 == 1
+}
+
+/// Expands the spawn-time placeholders of every argument with [`expand_all`]. A missing
+/// secret file or one readable by other users fails the whole expansion, so the command
+/// is skipped instead of run with a missing argument.
+pub fn expand_all_args(args: &[String], context: &ExpandContext) -> Result<Vec<String>> {
+    args.iter()
+        .map(|arg| expand_all(arg, context))
+        .collect::<Result<_>>()
+        .inspect_err(|e| warn!("Not running command: {:#}", e))
+}
+
+/// Substitutes `${VAR}` and `${VAR:-fallback}` in raw text with values from `lookup`,
+/// before the text is parsed. Substitution is purely textual, so a value containing
+/// YAML syntax (a colon, a newline, quotes) changes the document's structure; quote
+/// the placeholder in the config when that matters. Placeholders whose name is not a
+/// valid environment variable name, such as `${var:NAME}`, are left untouched. Escaping
+/// follows the same rule as [`expand_all`]: `\${HOME}` gives a literal `${HOME}`.
+pub fn expand_env(input: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    const PREFIX: &str = "${";
+
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find(PREFIX) {
+        let after = &rest[start + PREFIX.len()..];
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..name_len];
+        let tail = &after[name_len..];
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+
+        let (fallback, consumed) = if !valid_name {
+            (None, None)
+        } else if tail.starts_with('}') {
+            (None, Some(name_len + 1))
+        } else if let Some(default) = tail.strip_prefix(":-") {
+            match default.find('}') {
+                Some(end) => (Some(&default[..end]), Some(name_len + 2 + end + 1)),
+                None => (None, None),
+            }
+        } else {
+            (None, None)
+        };
+
+        let Some(consumed) = consumed else {
+            // Not an environment placeholder, keep it as written
+            output.push_str(&rest[..start + PREFIX.len()]);
+            rest = after;
+            continue;
+        };
+        if push_unescaped(&mut output, &rest[..start]) {
+            output.push_str(&rest[start..start + PREFIX.len() + consumed]);
+            rest = &after[consumed..];
+            continue;
+        }
+        match (lookup(name), fallback) {
+            (Some(value), _) => output.push_str(&value),
+            (None, Some(fallback)) => output.push_str(fallback),
+            (None, None) => anyhow::bail!("environment variable '{}' is not set and has no default", name),
+        }
+        rest = &after[consumed..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Reads a secret file, refusing files that other users can read
+fn read_secret(path: &Path) -> Result<String> {
+    let path = expand_home(path);
+    let metadata = std::fs::metadata(&path)
+        .with_context(|| format!("failed to read secret file {}", path.display()))?;
+    if metadata.permissions().mode() & 0o004 != 0 {
+        anyhow::bail!("secret file {} is world-readable, restrict it with `chmod o-r`", path.display());
+    }
+    let secret = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read secret file {}", path.display()))?;
+    Ok(secret.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context_for, TempDir};
+
+    #[test]
+    fn test_expand_vars() {
+        let vars = RuntimeVars::new();
+        vars.set("branch", "main");
+        let context = ExpandContext { vars: Some(&vars), ..Default::default() };
+        let expand = |input: &str| expand_all(input, &context).unwrap();
+
+        assert_eq!(expand("${var:branch}"), "main");
+        assert_eq!(expand("origin/${var:branch}..${var:branch}"), "origin/main..main");
+        assert_eq!(expand("no placeholders"), "no placeholders");
+        assert_eq!(expand("[${var:missing}]"), "[]");
+        assert_eq!(expand("${var:branch"), "${var:branch");
+        assert_eq!(expand("$HOME ${branch}"), "$HOME ${branch}");
+    }
+
+    #[test]
+    fn test_expand_unterminated_keeps_scanning() {
+        let vars = RuntimeVars::new();
+        vars.set("branch", "main");
+        let context = ExpandContext { vars: Some(&vars), state: Some("on"), secrets: true, ..Default::default() };
+        let expand = |input: &str| expand_all(input, &context).unwrap();
+
+        assert_eq!(expand("${var:x {state}"), "${var:x on");
+        assert_eq!(expand("{secret:/missing {state}"), "{secret:/missing on");
+        assert_eq!(expand("{output:a ${var:branch}"), "{output:a main");
+        assert_eq!(expand(r"\${var:x \{state}"), r"\${var:x {state}");
+    }
+
+    #[test]
+    fn test_expand_state() {
+        let context = ExpandContext { state: Some("on"), ..Default::default() };
+        let args = vec!["--set".to_string(), "{state}".to_string(), "mode={state}".to_string()];
+        assert_eq!(expand_all_args(&args, &context).unwrap(), vec!["--set", "on", "mode=on"]);
+        assert_eq!(expand_all("${var:state}", &context).unwrap(), "${var:state}");
+    }
+
+    #[test]
+    fn test_expand_secrets() {
+        let dir = TempDir::new("secret");
+        let path = dir.join("token");
+        std::fs::write(&path, "s3cret-token\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let context = ExpandContext { secrets: true, ..Default::default() };
+
+        let args = vec!["--token".to_string(), format!("Bearer {{secret:{}}}", path.display()), "{state}".to_string()];
+        assert_eq!(expand_all_args(&args, &context).unwrap(), vec!["--token", "Bearer s3cret-token", "{state}"]);
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let err = expand_all_args(&args, &context).unwrap_err();
+        assert!(err.to_string().contains("world-readable"), "{}", err);
+
+        std::fs::remove_file(&path).unwrap();
+        let err = expand_all_args(&args, &context).unwrap_err();
+        assert!(err.to_string().contains("failed to read secret file"), "{}", err);
+    }
+
+    #[test]
+    fn test_expand_all_does_not_reexpand_values() {
+        let dir = TempDir::new("secret-dollar");
+        let path = dir.join("token");
+        std::fs::write(&path, "pa$$-${var:branch}-{state}\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let vars = RuntimeVars::new();
+        vars.set("branch", "main");
+        vars.set("injected", format!("{{secret:{}}}", path.display()));
+        let context = ExpandContext { vars: Some(&vars), state: Some("on"), secrets: true, ..Default::default() };
+
+        // A secret is substituted as-is, placeholders inside it stay literal
+        let arg = format!("{{state}}:{{secret:{}}}", path.display());
+        assert_eq!(expand_all(&arg, &context).unwrap(), "on:pa$$-${var:branch}-{state}");
+        // A variable holding a secret placeholder does not read the secret
+        assert_eq!(
+            expand_all("${var:injected}", &context).unwrap(),
+            format!("{{secret:{}}}", path.display())
+        );
+
+        // Placeholders without a value in the context are kept
+        let vars_only = ExpandContext { vars: Some(&vars), ..Default::default() };
+        assert_eq!(expand_all("{state} ${var:branch} {secret:/missing}", &vars_only).unwrap(), "{state} main {secret:/missing}");
+        assert_eq!(expand_all("{secret:/missing", &context).unwrap(), "{secret:/missing");
+
+        // Button outputs are substituted as-is too
+        vars.set_output("Pick", "${var:branch}");
+        assert_eq!(expand_all("{output:Pick}|{output:Unrun}", &vars_only).unwrap(), "${var:branch}|");
+        assert_eq!(expand_all("{output:Pick}", &ExpandContext::default()).unwrap(), "{output:Pick}");
+    }
+
+    #[test]
+    fn test_env_values_expand_at_spawn() {
+        // An environment value lands in the config text and is expanded when the command runs
+        let lookup = |name: &str| (name == "STAMP").then(|| "--state={state}".to_string());
+        let arg = expand_env("${STAMP}", lookup).unwrap();
+        assert_eq!(arg, "--state={state}");
+
+        let context = ExpandContext { state: Some("off"), ..Default::default() };
+        assert_eq!(expand_all(&arg, &context).unwrap(), "--state=off");
+    }
+
+    #[test]
+    fn test_expand_date() {
+        let date = chrono::NaiveDate::from_ymd_opt(2026, 3, 7).unwrap().and_hms_opt(9, 5, 0).unwrap();
+        let vars = RuntimeVars::new();
+        vars.set("stamp", "{date}");
+        let context = ExpandContext { vars: Some(&vars), date: Some(date), ..Default::default() };
+
+        assert_eq!(expand_all("backup-{date}.tar", &context).unwrap(), "backup-2026-03-07.tar");
+        assert_eq!(expand_all("{date:%Y%m%d-%H%M}", &context).unwrap(), "20260307-0905");
+        // Escaped dates, dates with an invalid format and dates inside values stay literal
+        assert_eq!(expand_all(r"\\{date} {date:%Q} ${var:stamp}", &context).unwrap(), r"\2026-03-07 {date:%Q} {date}");
+        assert_eq!(expand_all(r"\{date:%Y}", &context).unwrap(), "{date:%Y}");
+
+        // A date inside an environment value is expanded when the command runs
+        let lookup = |name: &str| (name == "BACKUP_DIR").then(|| "/backups/{date}".to_string());
+        let arg = expand_env("${BACKUP_DIR}/db.sql", lookup).unwrap();
+        assert_eq!(arg, "/backups/{date}/db.sql");
+        assert_eq!(expand_all(&arg, &context).unwrap(), "/backups/2026-03-07/db.sql");
+    }
+
+    #[test]
+    fn test_expand_escapes() {
+        let vars = RuntimeVars::new();
+        vars.set("branch", "main");
+        let context = ExpandContext { vars: Some(&vars), state: Some("on"), input: Some("50"), secrets: true, date: None };
+
+        assert_eq!(expand_all(r"\{state}", &context).unwrap(), "{state}");
+        assert_eq!(expand_all(r"--volume={input} \{input}", &context).unwrap(), "--volume=50 {input}");
+        assert_eq!(expand_all(r"\\{state}", &context).unwrap(), r"\on");
+        assert_eq!(expand_all(r"\\\{state}", &context).unwrap(), r"\{state}");
+        assert_eq!(expand_all(r"\${var:branch} \{secret:/missing}", &context).unwrap(), "${var:branch} {secret:/missing}");
+        // Backslashes elsewhere are left alone
+        assert_eq!(expand_all(r"C:\dir\ {state}\", &context).unwrap(), r"C:\dir\ on\");
+
+        let lookup = |_: &str| Some("nvim".to_string());
+        assert_eq!(expand_env(r"\${EDITOR} \\${EDITOR} \${var:x}", lookup).unwrap(), r"${EDITOR} \nvim \${var:x}");
+    }
+
+    #[test]
+    fn test_expand_env() {
+        let lookup = |name: &str| match name {
+            "EDITOR" => Some("nvim".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+
+        assert_eq!(expand_env("command: ${EDITOR}", lookup).unwrap(), "command: nvim");
+        assert_eq!(expand_env("${TERMINAL:-alacritty} -e ${EDITOR}", lookup).unwrap(), "alacritty -e nvim");
+        assert_eq!(expand_env("[${EMPTY:-unused}]", lookup).unwrap(), "[]");
+        assert_eq!(expand_env("${MISSING:-}", lookup).unwrap(), "");
+
+        // Runtime and state placeholders pass through for later expansion
+        assert_eq!(expand_env("${var:branch} {state} $HOME ${1abc} ${EDITOR", lookup).unwrap(), "${var:branch} {state} $HOME ${1abc} ${EDITOR");
+
+        let err = expand_env("command: ${MISSING}", lookup).unwrap_err();
+        assert!(err.to_string().contains("'MISSING' is not set"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_args_expand_other_buttons_output() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Pick Branch"
+      command: "echo"
+      args: ["feature/login"]
+    - type: command
+      name: "Checkout"
+      command: "echo"
+      args: ["checkout {output:Pick Branch}"]
+      store_output_as: "checkout"
+"#,
+        )
+        .unwrap();
+        let context = context_for(config);
+
+        // Before the other button ran, its output expands to nothing
+        assert!(context.execute_button("Checkout").await);
+        assert_eq!(context.runtime_vars.get("checkout").as_deref(), Some("checkout"));
+
+        assert!(context.execute_button("Pick Branch").await);
+        assert_eq!(context.runtime_vars.output("Pick Branch").as_deref(), Some("feature/login"));
+        assert!(context.execute_button("Checkout").await);
+        assert_eq!(context.runtime_vars.get("checkout").as_deref(), Some("checkout feature/login"));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::toggle_state::ToggleState;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Expands a leading `~/` to the home directory
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// State of a file-backed toggle: On if the flag file exists, Off if it does not,
+/// Unknown if its existence cannot be checked (e.g. a permission error)
+pub fn probe_file(path: &Path) -> ToggleState {
+    let path = expand_home(path);
+    match std::fs::symlink_metadata(&path) {
+        Ok(_) => ToggleState::On,
+        Err(e) if e.kind() == ErrorKind::NotFound => ToggleState::Off,
+        Err(e) => {
+            warn!("Failed to check flag file {}: {}", path.display(), e);
+            ToggleState::Unknown
+        }
+    }
+}
+
+/// Creates the flag file, leaving an existing file's contents untouched
+pub fn create_file(path: &Path) -> std::io::Result<()> {
+    let path = expand_home(path);
+    debug!("Creating flag file {}", path.display());
+    OpenOptions::new().create(true).append(true).open(&path).map(|_| ())
+}
+
+/// Removes the flag file; a file that is already gone is not an error
+pub fn remove_file(path: &Path) -> std::io::Result<()> {
+    let path = expand_home(path);
+    debug!("Removing flag file {}", path.display());
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_flag_file_lifecycle() {
+        let dir = TempDir::new("flag");
+        let path = dir.join("flag");
+
+        assert_eq!(probe_file(&path), ToggleState::Off);
+        create_file(&path).unwrap();
+        assert_eq!(probe_file(&path), ToggleState::On);
+
+        // Creating again keeps the existing contents
+        std::fs::write(&path, "keep").unwrap();
+        create_file(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep");
+
+        remove_file(&path).unwrap();
+        assert_eq!(probe_file(&path), ToggleState::Off);
+        remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_flag_file_errors() {
+        // A path below a regular file can never exist
+        let dir = TempDir::new("flag-parent");
+        let file = dir.join("file");
+        std::fs::write(&file, "").unwrap();
+        let path = file.join("flag");
+
+        assert_eq!(probe_file(&path), ToggleState::Unknown);
+        assert!(create_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        assert_eq!(expand_home(Path::new("~/.flag")), home.join(".flag"));
+        assert_eq!(expand_home(Path::new("/tmp/~flag")), PathBuf::from("/tmp/~flag"));
+    }
+}
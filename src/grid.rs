@@ -0,0 +1,90 @@
+use streamdeck_oxide::generic_array::typenum::{Unsigned, U3, U5};
+
+/// Size of a deck's key grid. Keys are numbered row by row from the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridSize {
+    pub columns: usize,
+    pub rows: usize,
+}
+
+/// Grid the menus are drawn on, matching the `U5, U3` views of the plugins
+pub const DECK_GRID: GridSize = GridSize::of::<U5, U3>();
+
+impl GridSize {
+    /// The grid of a view with `W` columns and `H` rows
+    pub const fn of<W: Unsigned, H: Unsigned>() -> Self {
+        Self {
+            columns: W::USIZE,
+            rows: H::USIZE,
+        }
+    }
+
+    /// Number of keys on the grid
+    pub const fn key_count(self) -> usize {
+        self.columns * self.rows
+    }
+
+    /// Index of the last key, which submenus reserve for the back button
+    pub const fn back_button_index(self) -> usize {
+        self.key_count() - 1
+    }
+
+    /// (column, row) of the back button
+    pub const fn back_button_position(self) -> (usize, usize) {
+        self.position(self.back_button_index())
+    }
+
+    /// Number of keys left to menu buttons, every key before the back button
+    pub const fn menu_button_slots(self) -> usize {
+        self.back_button_index()
+    }
+
+    /// (column, row) of the key at `index`
+    pub const fn position(self, index: usize) -> (usize, usize) {
+        (index % self.columns, index / self.columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_back_button_index() {
+        let mini = GridSize { columns: 3, rows: 2 };
+        assert_eq!(mini.back_button_index(), 5);
+        assert_eq!(mini.back_button_position(), (2, 1));
+        assert_eq!(mini.menu_button_slots(), 5);
+
+        let mk2 = GridSize { columns: 5, rows: 3 };
+        assert_eq!(mk2.back_button_index(), 14);
+        assert_eq!(mk2.back_button_position(), (4, 2));
+        assert_eq!(mk2.menu_button_slots(), 14);
+
+        let xl = GridSize { columns: 8, rows: 4 };
+        assert_eq!(xl.back_button_index(), 31);
+        assert_eq!(xl.back_button_position(), (7, 3));
+        assert_eq!(xl.menu_button_slots(), 31);
+    }
+
+    #[test]
+    fn test_menu_layout_leaves_back_button_key_free() {
+        let buttons: String = (0..40)
+            .map(|index| format!("    - type: command\n      name: \"Key {}\"\n      command: \"true\"\n", index))
+            .collect();
+        let menu: crate::config::Menu = serde_yaml::from_str(&format!("name: \"Main\"\nbuttons:\n{}", buttons)).unwrap();
+
+        for grid in [GridSize { columns: 3, rows: 2 }, GridSize { columns: 5, rows: 3 }, GridSize { columns: 8, rows: 4 }] {
+            let layout = crate::button::menu_layout_in(&menu, grid);
+            assert_eq!(layout.len(), grid.menu_button_slots());
+            assert!(layout.iter().all(|(col, row, _)| (*col, *row) != grid.back_button_position()));
+            assert!(layout.iter().all(|(col, row, _)| *col < grid.columns && *row < grid.rows));
+        }
+    }
+
+    #[test]
+    fn test_deck_grid_matches_views() {
+        assert_eq!(DECK_GRID, GridSize { columns: 5, rows: 3 });
+        assert_eq!(DECK_GRID.position(7), (2, 1));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::last_result::CommandOutcome;
+use std::any::Any;
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error};
+
+/// What a key handler knows about its command when it returns, so it can update the
+/// key or show the result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionOutcome {
+    /// The command finished with this outcome
+    Completed(CommandOutcome),
+    /// The command was still running when the wait ran out and goes on in the background
+    TimedOut,
+    /// The command was left to run in the background without waiting for it
+    Spawned,
+}
+
+impl ActionOutcome {
+    /// The outcome of the command, if it finished
+    pub fn completed(self) -> Option<CommandOutcome> {
+        match self {
+            ActionOutcome::Completed(outcome) => Some(outcome),
+            ActionOutcome::TimedOut | ActionOutcome::Spawned => None,
+        }
+    }
+}
+
+/// Waits up to `timeout`, or until it finishes if `None`, for a command running in `task`.
+/// On a timeout the task keeps running and can still be awaited. A command task that
+/// panicked counts as a command that could not be run.
+pub async fn await_command(task: &mut JoinHandle<CommandOutcome>, timeout: Option<Duration>) -> ActionOutcome {
+    let result = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, &mut *task).await {
+            Ok(result) => result,
+            Err(_) => {
+                debug!("Command still running after {:?}", timeout);
+                return ActionOutcome::TimedOut;
+            }
+        },
+        None => (&mut *task).await,
+    };
+    match result {
+        Ok(outcome) => ActionOutcome::Completed(outcome),
+        Err(e) => {
+            error!("Command task failed: {}", e);
+            ActionOutcome::Completed(CommandOutcome::execution_error())
+        }
+    }
+}
+
+/// Runs a button handler in its own task. A panic inside the handler is contained to
+/// that task and logged with the button name; the returned handle resolves to the
+/// panic message in that case.
+pub fn spawn_handler<F>(button_name: impl Into<String>, handler: F) -> JoinHandle<Result<(), String>>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let button_name = button_name.into();
+    let task = tokio::spawn(handler);
+    tokio::spawn(async move {
+        match task.await {
+            Ok(()) => Ok(()),
+            Err(e) if e.is_panic() => {
+                let message = panic_message(e.into_panic());
+                error!("Handler for button '{}' panicked: {}", button_name, message);
+                Err(message)
+            }
+            Err(e) => {
+                error!("Handler for button '{}' was cancelled: {}", button_name, e);
+                Err(e.to_string())
+            }
+        }
+    })
+}
+
+/// Extracts the message passed to `panic!`, if it was a string
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "non-string panic payload".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_spawn_handler_contains_panics() {
+        let result = spawn_handler("Broken", async { panic!("no value configured") }).await.unwrap();
+        assert_eq!(result, Err("no value configured".to_string()));
+
+        let formatted = spawn_handler("Formatted", async { panic!("exit code {}", 3) }).await.unwrap();
+        assert_eq!(formatted, Err("exit code 3".to_string()));
+
+        // Later handlers keep running after a panic
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = ran.clone();
+        let result = spawn_handler("Working", async move { flag.store(true, Ordering::SeqCst) })
+            .await
+            .unwrap();
+        assert_eq!(result, Ok(()));
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_await_command() {
+        let mut task = tokio::spawn(async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            CommandOutcome::from_exit_code(3)
+        });
+        assert_eq!(await_command(&mut task, Some(Duration::from_secs(1))).await, ActionOutcome::TimedOut);
+        // The timed out command keeps running
+        let outcome = await_command(&mut task, None).await;
+        assert_eq!(outcome.completed().and_then(|outcome| outcome.exit_code), Some(3));
+
+        let mut panicked: JoinHandle<CommandOutcome> = tokio::spawn(async { panic!("no exit code") });
+        assert_eq!(await_command(&mut panicked, None).await, ActionOutcome::Completed(CommandOutcome::execution_error()));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::config::{Button, Config};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Number of distinct buttons [`ExecutionHistory`] remembers
+pub const HISTORY_CAPACITY: usize = 32;
+
+/// A command run by a button, as listed in a `dynamic: recent` menu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Execution {
+    /// Name of the button that ran the command
+    pub name: String,
+    pub command: String,
+    /// Args as configured, with placeholders not yet expanded so no secret is kept
+    pub args: Vec<String>,
+    pub no_prefix: bool,
+}
+
+/// The latest command run of each button, most recent first
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionHistory {
+    runs: Arc<Mutex<VecDeque<Execution>>>,
+}
+
+impl ExecutionHistory {
+    /// Creates an empty history
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a run, replacing the earlier run of the same button
+    pub fn record(&self, execution: Execution) {
+        let mut runs = self.runs.lock().unwrap_or_else(PoisonError::into_inner);
+        runs.retain(|run| run.name != execution.name);
+        runs.push_front(execution);
+        runs.truncate(HISTORY_CAPACITY);
+    }
+
+    /// Up to `limit` runs of distinct buttons, most recent first
+    pub fn recent(&self, limit: usize) -> Vec<Execution> {
+        let runs = self.runs.lock().unwrap_or_else(PoisonError::into_inner);
+        runs.iter().take(limit).cloned().collect()
+    }
+}
+
+/// Command buttons re-running the `limit` most recent runs of `history`. A run of a
+/// command button of `config` keeps that button's settings, like its icon and retries;
+/// runs of other buttons, like match buttons, become plain command buttons.
+pub fn recent_buttons(history: &ExecutionHistory, config: &Config, limit: usize) -> Vec<Button> {
+    history
+        .recent(limit)
+        .into_iter()
+        .map(|run| {
+            let mut configured = None;
+            config.walk_buttons(|button, _| {
+                if configured.is_none() && button.name() == run.name && matches!(button, Button::Command { .. }) {
+                    configured = Some(button.clone());
+                }
+            });
+            match configured {
+                Some(mut button) => {
+                    if let Button::Command { command, args, args_file, no_prefix, delay_ms, .. } = &mut button {
+                        *command = run.command;
+                        *args = run.args;
+                        // The recorded args already include those of the file
+                        *args_file = None;
+                        *no_prefix = run.no_prefix;
+                        *delay_ms = 0;
+                    }
+                    button
+                }
+                None => Button::plain_command(run.name, run.command, run.args, run.no_prefix),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(name: &str, command: &str, args: &[&str]) -> Execution {
+        Execution {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            no_prefix: false,
+        }
+    }
+
+    #[test]
+    fn test_recent_runs_are_distinct_and_newest_first() {
+        let history = ExecutionHistory::new();
+        history.record(run("Build", "make", &[]));
+        history.record(run("Deploy", "deploy.sh", &["staging"]));
+        history.record(run("Test", "cargo", &["test"]));
+        history.record(run("Deploy", "deploy.sh", &["production"]));
+
+        let recent = history.recent(10);
+        let names: Vec<_> = recent.iter().map(|run| run.name.as_str()).collect();
+        assert_eq!(names, ["Deploy", "Test", "Build"]);
+        assert_eq!(recent[0].args, ["production"]);
+        assert_eq!(history.recent(2).len(), 2);
+
+        for index in 0..HISTORY_CAPACITY + 5 {
+            history.record(run(&format!("Run {}", index), "true", &[]));
+        }
+        assert_eq!(history.recent(usize::MAX).len(), HISTORY_CAPACITY);
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use tracing::{debug, info};
+
+/// What releasing a key guarded by `confirm_hold_ms` did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldRelease {
+    /// Released before the hold passed, the action does not run
+    Cancelled,
+    /// Released after the action already fired
+    Confirmed,
+    /// The key was not being held for confirmation
+    NotHeld,
+}
+
+/// Keys held to confirm their action, keyed by key index. The action fires once
+/// when a key stays down for its whole hold; releasing it earlier cancels it.
+#[derive(Debug, Default)]
+pub struct HoldConfirm {
+    held: HashMap<u8, AbortHandle>,
+}
+
+impl HoldConfirm {
+    /// Creates a tracker with no key held
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the hold of a key: `run` fires once if the key is still down after `hold`
+    pub fn press<F>(&mut self, key: u8, hold: Duration, run: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.release(key);
+        info!("Hold key {} for {:?} to confirm", key, hold);
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(hold).await;
+            debug!("Key {} held for {:?}, confirmed", key, hold);
+            run.await;
+        });
+        self.held.insert(key, task.abort_handle());
+    }
+
+    /// Ends the hold of a key, cancelling its action if the hold has not passed yet
+    pub fn release(&mut self, key: u8) -> HoldRelease {
+        match self.held.remove(&key) {
+            Some(task) if task.is_finished() => HoldRelease::Confirmed,
+            Some(task) => {
+                task.abort();
+                info!("Key {} released early, cancelled", key);
+                HoldRelease::Cancelled
+            }
+            None => HoldRelease::NotHeld,
+        }
+    }
+}
+
+impl Drop for HoldConfirm {
+    fn drop(&mut self) {
+        for task in self.held.values() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn counting_run(runs: &Arc<AtomicUsize>) -> impl Future<Output = ()> + Send + 'static {
+        let runs = runs.clone();
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_early_release_cancels() {
+        let mut holds = HoldConfirm::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        holds.press(1, Duration::from_secs(2), counting_run(&runs));
+        tokio::time::sleep(Duration::from_millis(1900)).await;
+        assert_eq!(holds.release(1), HoldRelease::Cancelled);
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        assert_eq!(holds.release(1), HoldRelease::NotHeld);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_full_hold_fires_once() {
+        let mut holds = HoldConfirm::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        holds.press(1, Duration::from_secs(2), counting_run(&runs));
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Holding on does not fire again, and releasing afterwards is no cancellation
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(holds.release(1), HoldRelease::Confirmed);
+
+        // A new press needs a new full hold
+        holds.press(1, Duration::from_secs(2), counting_run(&runs));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(holds.release(1), HoldRelease::Cancelled);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+}
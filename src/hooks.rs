@@ -0,0 +1,126 @@
+use crate::config::HookCommand;
+use crate::spawn::SpawnConfig;
+use std::process::Stdio;
+use tracing::{debug, warn};
+
+/// Placeholder in hook args replaced with the pressed button's name
+pub const BUTTON_PLACEHOLDER: &str = "{button}";
+/// Placeholder in hook args replaced with the action's exit code, empty if it has none
+pub const EXIT_CODE_PLACEHOLDER: &str = "{exit_code}";
+
+/// The hook's args with `{button}` and `{exit_code}` substituted
+pub fn hook_args(hook: &HookCommand, button_name: &str, exit_code: Option<i32>) -> Vec<String> {
+    let exit_code = exit_code.map(|code| code.to_string()).unwrap_or_default();
+    hook.args
+        .iter()
+        .map(|arg| arg.replace(BUTTON_PLACEHOLDER, button_name).replace(EXIT_CODE_PLACEHOLDER, &exit_code))
+        .collect()
+}
+
+/// Runs a `pre_command` or `post_command` hook for the button and waits for it. A hook
+/// that fails is only logged, so it never blocks the action. Returns whether it succeeded.
+pub async fn run_hook(
+    kind: &str,
+    hook: &HookCommand,
+    button_name: &str,
+    exit_code: Option<i32>,
+    spawn: &SpawnConfig,
+) -> bool {
+    let args = hook_args(hook, button_name, exit_code);
+    debug!("Running {} hook for '{}': {:?}", kind, button_name, spawn.argv(&hook.command, &args));
+    let mut cmd = spawn.command(&hook.command, &args);
+    cmd.stdin(Stdio::null());
+    match cmd.output().await {
+        Ok(output) if output.status.success() => true,
+        Ok(output) => {
+            warn!(
+                "{} hook for '{}' exited with {:?}: {}",
+                kind,
+                button_name,
+                output.status.code(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            false
+        }
+        Err(e) => {
+            warn!("Failed to run the {} hook for '{}': {}", kind, button_name, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context_for, TempDir};
+
+    #[test]
+    fn test_hook_args() {
+        let hook = HookCommand {
+            command: "notify-send".to_string(),
+            args: vec!["{button} finished".to_string(), "exit {exit_code}".to_string()],
+        };
+        assert_eq!(hook_args(&hook, "Backup", Some(2)), ["Backup finished", "exit 2"]);
+        assert_eq!(hook_args(&hook, "Backup", None), ["Backup finished", "exit "]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_hook_is_reported() {
+        let spawn = SpawnConfig::default();
+        let hook = |command: &str| HookCommand { command: command.to_string(), args: vec![] };
+        assert!(run_hook("pre_command", &hook("true"), "Backup", None, &spawn).await);
+        assert!(!run_hook("pre_command", &hook("false"), "Backup", None, &spawn).await);
+        assert!(!run_hook("pre_command", &hook("nonexistent_command_xyz"), "Backup", None, &spawn).await);
+    }
+
+    #[tokio::test]
+    async fn test_hooks_run_around_command() {
+        let dir = TempDir::new("hooks");
+        let log = dir.join("log");
+        let yaml = format!(
+            r#"
+hooks:
+  pre_command:
+    command: "sh"
+    args: ["-c", "echo pre $1 >> {log}", "sh", "{{button}}"]
+  post_command:
+    command: "sh"
+    args: ["-c", "echo post $1 $2 >> {log}", "sh", "{{button}}", "{{exit_code}}"]
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Deploy"
+      command: "sh"
+      args: ["-c", "echo run >> {log}; exit 3"]
+"#,
+            log = log.display()
+        );
+        let config: Config = serde_yaml::from_str(&yaml).unwrap();
+        let context = context_for(config);
+
+        assert!(context.execute_button("Deploy").await);
+        let lines: Vec<String> = std::fs::read_to_string(&log).unwrap().lines().map(str::to_string).collect();
+        assert_eq!(lines, ["pre Deploy", "run", "post Deploy 3"]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_hook_does_not_block_command() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+hooks:
+  pre_command:
+    command: "nonexistent_command_xyz"
+menu:
+  name: "Main"
+  buttons: []
+"#,
+        )
+        .unwrap();
+        let context = context_for(config);
+
+        let outcome = context.run_command("Deploy", "true", &[], false, None, &[0], Default::default()).await;
+        assert!(outcome.success);
+    }
+}
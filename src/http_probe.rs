@@ -0,0 +1,165 @@
+use crate::config::HttpExpect;
+use crate::probe::ProbeResult;
+use crate::probe_backend::{ProbeBackend, ProbeRequest};
+use crate::spawn::next_run_id;
+use async_trait::async_trait;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Name of the built-in backend that checks a toggle's `probe_http` endpoint
+pub const HTTP_BACKEND: &str = "http";
+
+/// Largest response read from a probed endpoint; the rest is ignored
+const MAX_RESPONSE_BYTES: u64 = 64 * 1024;
+
+/// Checks toggles with a GET request to their `probe_http` URL
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpProbe;
+
+#[async_trait]
+impl ProbeBackend for HttpProbe {
+    async fn probe(&self, request: &ProbeRequest<'_>) -> ProbeResult {
+        match &request.context.http {
+            Some(check) => probe_http(&check.url, &check.expected, Duration::from_millis(check.timeout_ms)).await,
+            None => ProbeResult::execution_error(format!("Toggle '{}' has no probe_http", request.button_name)),
+        }
+    }
+}
+
+/// Fetches `url` and compares the response with `expected`. A matching response is a
+/// success (On), any other response a failure with the status as its exit code (Off),
+/// and a connection error or timeout an execution error (Unknown).
+#[tracing::instrument(name = "probe", skip_all, fields(run = %next_run_id(), url = url))]
+pub async fn probe_http(url: &str, expected: &HttpExpect, timeout: Duration) -> ProbeResult {
+    info!("Executing HTTP probe: GET {}", url);
+    let (status, body) = match tokio::time::timeout(timeout, http_get(url)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            warn!("HTTP probe of {} failed: {}", url, e);
+            return ProbeResult::execution_error(format!("HTTP probe of {} failed: {}", url, e));
+        }
+        Err(_) => {
+            warn!("HTTP probe of {} timed out after {:?}", url, timeout);
+            return ProbeResult::execution_error(format!("HTTP probe of {} timed out after {:?}", url, timeout));
+        }
+    };
+
+    let status_matches = match expected.status {
+        Some(expected) => status == expected,
+        None => (200..300).contains(&status),
+    };
+    let body_matches = expected.body_contains.as_deref().is_none_or(|text| body.contains(text));
+    debug!("HTTP probe of {} answered {} (body matches: {})", url, status, body_matches);
+    if status_matches && body_matches {
+        ProbeResult::success(0, body, String::new())
+    } else {
+        ProbeResult::failure(Some(status.into()), body, String::new())
+    }
+}
+
+/// Client shared by every HTTP probe, so connections are reused between probes
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .user_agent("streamdeck-commander")
+            .build()
+            .expect("an HTTP client with the default TLS settings")
+    })
+}
+
+/// Sends a GET to `url` and returns the response status and up to
+/// [`MAX_RESPONSE_BYTES`] of its body
+async fn http_get(url: &str) -> anyhow::Result<(u16, String)> {
+    let mut response = client().get(url).send().await?;
+    let status = response.status().as_u16();
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 >= MAX_RESPONSE_BYTES {
+            body.truncate(MAX_RESPONSE_BYTES as usize);
+            break;
+        }
+    }
+    Ok((status, String::from_utf8_lossy(&body).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toggle_state::ToggleState;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves one canned response per connection and returns the server's base URL
+    async fn serve(status_line: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}", status_line, body.len(), body);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    /// The toggle state a probe result stands for
+    fn state(result: &ProbeResult) -> ToggleState {
+        if result.is_success() {
+            ToggleState::On
+        } else if result.is_command_failure() {
+            ToggleState::Off
+        } else {
+            ToggleState::Unknown
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_http_maps_status_to_state() {
+        let timeout = Duration::from_secs(5);
+        let up = serve("200 OK", "service is running").await;
+        let down = serve("503 Service Unavailable", "maintenance").await;
+
+        let result = probe_http(&format!("{}/health", up), &HttpExpect::default(), timeout).await;
+        assert_eq!(state(&result), ToggleState::On);
+        assert_eq!(result.stdout, "service is running");
+        let result = probe_http(&down, &HttpExpect::default(), timeout).await;
+        assert_eq!(state(&result), ToggleState::Off);
+        assert_eq!(result.exit_code, Some(503));
+
+        // Expected status and body override the 2xx default
+        let maintenance = HttpExpect {
+            status: Some(503),
+            body_contains: Some("maintenance".to_string()),
+        };
+        assert_eq!(state(&probe_http(&down, &maintenance, timeout).await), ToggleState::On);
+        let running = HttpExpect {
+            body_contains: Some("stopped".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(state(&probe_http(&up, &running, timeout).await), ToggleState::Off);
+    }
+
+    #[tokio::test]
+    async fn test_probe_http_errors_are_unknown() {
+        let timeout = Duration::from_secs(5);
+        // Nothing listens on a port that was just released
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        assert_eq!(state(&probe_http(&closed, &HttpExpect::default(), timeout).await), ToggleState::Unknown);
+
+        // A server that never answers runs into the timeout
+        let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", silent.local_addr().unwrap());
+        let result = probe_http(&url, &HttpExpect::default(), Duration::from_millis(100)).await;
+        assert_eq!(state(&result), ToggleState::Unknown);
+
+        let result = probe_http("localhost:8080/health", &HttpExpect::default(), timeout).await;
+        assert_eq!(state(&result), ToggleState::Unknown);
+    }
+}
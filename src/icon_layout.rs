@@ -0,0 +1,288 @@
+use crate::config::Config;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use tracing::warn;
+
+/// Smallest accepted `icon_scale`
+pub const MIN_ICON_SCALE: f32 = 0.25;
+/// Largest accepted `icon_scale`
+pub const MAX_ICON_SCALE: f32 = 2.0;
+/// Largest accepted `icon_padding` (fraction of the key on each side)
+pub const MAX_ICON_PADDING: f32 = 0.4;
+
+/// Accepted values of a button's `icon_rotate`, in degrees clockwise
+pub const ICON_ROTATIONS: [u16; 4] = [0, 90, 180, 270];
+
+/// Transformed icons keyed by source icon address, layout bits and rotation
+type IconCache = Mutex<HashMap<(usize, u32, u32, u16), &'static str>>;
+
+/// Size and margin applied to icons when they are rendered into a key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IconLayout {
+    /// Icon size relative to its default size
+    pub scale: f32,
+    /// Blank margin on each side, as a fraction of the key size
+    pub padding: f32,
+}
+
+impl Default for IconLayout {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            padding: 0.0,
+        }
+    }
+}
+
+impl IconLayout {
+    /// Creates a layout, clamping both values into their accepted ranges
+    pub fn new(scale: f32, padding: f32) -> Self {
+        Self {
+            scale: clamp_or_default(scale, MIN_ICON_SCALE, MAX_ICON_SCALE, 1.0),
+            padding: clamp_or_default(padding, 0.0, MAX_ICON_PADDING, 0.0),
+        }
+    }
+
+    /// Builds the layout from the global configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.icon_scale, config.icon_padding)
+    }
+
+    /// Returns true if icons are rendered unchanged
+    pub fn is_identity(&self) -> bool {
+        self.scale == 1.0 && self.padding == 0.0
+    }
+
+    /// Fraction of the key's icon area the icon ends up covering
+    fn coverage(&self) -> f32 {
+        self.scale * (1.0 - 2.0 * self.padding)
+    }
+
+    /// Rewraps an SVG icon so it renders at this layout's size, centered in the key
+    pub fn transform_svg(&self, svg: &str) -> String {
+        self.transform_svg_rotated(svg, 0)
+    }
+
+    /// Like [`transform_svg`](Self::transform_svg), additionally turning the icon
+    /// clockwise by `rotate` degrees around the key's center
+    pub fn transform_svg_rotated(&self, svg: &str, rotate: u16) -> String {
+        let Some(start) = svg.find("<svg") else {
+            return svg.to_string();
+        };
+        let Some(tag_len) = svg[start..].find('>') else {
+            return svg.to_string();
+        };
+
+        // Drop any fixed size on the icon itself so it follows the wrapper
+        let root_tag = &svg[start + 4..start + tag_len];
+        let root_tag = remove_attribute(&remove_attribute(root_tag, "width"), "height");
+
+        let size = 100.0 * self.coverage();
+        let offset = (100.0 - size) / 2.0;
+        let icon = format!(
+            "<svg x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"{}{}",
+            offset,
+            offset,
+            size,
+            size,
+            root_tag,
+            &svg[start + tag_len..],
+        );
+        let icon = match rotate % 360 {
+            0 => icon,
+            degrees => format!("<g transform=\"rotate({} 50 50)\">{}</g>", degrees, icon),
+        };
+        format!("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 100 100\">{}</svg>", icon)
+    }
+
+    /// Applies the layout to a resolved icon. Transformed icons are cached so
+    /// re-rendering a view does not allocate them again.
+    pub fn apply(&self, icon: Option<&'static str>) -> Option<&'static str> {
+        self.apply_rotated(icon, 0)
+    }
+
+    /// Applies the layout to a resolved icon and turns it clockwise by `rotate` degrees
+    pub fn apply_rotated(&self, icon: Option<&'static str>, rotate: u16) -> Option<&'static str> {
+        let icon = icon?;
+        let rotate = rotate % 360;
+        if self.is_identity() && rotate == 0 {
+            return Some(icon);
+        }
+
+        static CACHE: OnceLock<IconCache> = OnceLock::new();
+        let key = (icon.as_ptr() as usize, self.scale.to_bits(), self.padding.to_bits(), rotate);
+        let mut cache = match CACHE.get_or_init(|| Mutex::new(HashMap::new())).lock() {
+            Ok(cache) => cache,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        Some(
+            *cache
+                .entry(key)
+                .or_insert_with(|| Box::leak(self.transform_svg_rotated(icon, rotate).into_boxed_str())),
+        )
+    }
+}
+
+/// Returns true for a `#rgb` or `#rrggbb` color
+pub fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Draws a rounded border of `color` around a resolved icon, or on its own for a
+/// text-only key. Highlighted icons are cached like transformed ones.
+pub fn highlight_icon(icon: Option<&'static str>, color: &str) -> &'static str {
+    static CACHE: OnceLock<Mutex<HashMap<(usize, String), &'static str>>> = OnceLock::new();
+    let key = (icon.map_or(0, |icon| icon.as_ptr() as usize), color.to_string());
+    let mut cache = match CACHE.get_or_init(|| Mutex::new(HashMap::new())).lock() {
+        Ok(cache) => cache,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    cache.entry(key).or_insert_with(|| {
+        let svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 100 100\"><rect x=\"3\" y=\"3\" width=\"94\" height=\"94\" rx=\"12\" fill=\"none\" stroke=\"{}\" stroke-width=\"6\"/>{}</svg>",
+            color,
+            icon.map(|icon| IconLayout::default().transform_svg(icon)).unwrap_or_default()
+        );
+        Box::leak(svg.into_boxed_str())
+    })
+}
+
+/// Clamps the configured icon layout into range, warning about values that were out of range
+pub fn normalize_icon_layout(config: &mut Config) {
+    let layout = IconLayout::from_config(config);
+    if layout.scale != config.icon_scale {
+        warn!(
+            "icon_scale {} is outside {}..={}, using {}",
+            config.icon_scale, MIN_ICON_SCALE, MAX_ICON_SCALE, layout.scale
+        );
+        config.icon_scale = layout.scale;
+    }
+    if layout.padding != config.icon_padding {
+        warn!(
+            "icon_padding {} is outside 0..={}, using {}",
+            config.icon_padding, MAX_ICON_PADDING, layout.padding
+        );
+        config.icon_padding = layout.padding;
+    }
+}
+
+fn clamp_or_default(value: f32, min: f32, max: f32, default: f32) -> f32 {
+    if value.is_nan() {
+        default
+    } else {
+        value.clamp(min, max)
+    }
+}
+
+/// Removes a `name="..."` attribute from the inside of an XML start tag
+fn remove_attribute(tag: &str, name: &str) -> String {
+    let pattern = format!(" {}=\"", name);
+    match tag.find(&pattern) {
+        Some(start) => {
+            let value_start = start + pattern.len();
+            match tag[value_start..].find('"') {
+                Some(value_len) => format!("{}{}", &tag[..start], &tag[value_start + value_len + 1..]),
+                None => tag.to_string(),
+            }
+        }
+        None => tag.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"24\" height=\"24\" viewBox=\"0 0 24 24\"><path d=\"M0 0h24v24H0z\"/></svg>";
+
+    #[test]
+    fn test_icon_layout_clamps_values() {
+        assert_eq!(IconLayout::new(1.5, 0.1), IconLayout { scale: 1.5, padding: 0.1 });
+        assert_eq!(IconLayout::new(0.01, -1.0), IconLayout { scale: MIN_ICON_SCALE, padding: 0.0 });
+        assert_eq!(IconLayout::new(10.0, 0.9), IconLayout { scale: MAX_ICON_SCALE, padding: MAX_ICON_PADDING });
+        assert_eq!(IconLayout::new(f32::NAN, f32::NAN), IconLayout::default());
+    }
+
+    #[test]
+    fn test_normalize_icon_layout() {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+icon_scale: 5.0
+icon_padding: 0.1
+menu:
+  name: "Main"
+  buttons: []
+"#,
+        )
+        .unwrap();
+
+        normalize_icon_layout(&mut config);
+        assert_eq!(config.icon_scale, MAX_ICON_SCALE);
+        assert_eq!(config.icon_padding, 0.1);
+    }
+
+    #[test]
+    fn test_transform_svg() {
+        let svg = IconLayout::new(0.5, 0.0).transform_svg(SVG);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 100 100\">"));
+        assert!(svg.contains("<svg x=\"25\" y=\"25\" width=\"50\" height=\"50\" xmlns="));
+        assert!(svg.contains("viewBox=\"0 0 24 24\"><path d=\"M0 0h24v24H0z\"/></svg></svg>"));
+        assert!(!svg.contains("width=\"24\""));
+
+        // Padding shrinks the icon further on top of the scale
+        let padded = IconLayout::new(1.0, 0.25).transform_svg(SVG);
+        assert!(padded.contains("x=\"25\" y=\"25\" width=\"50\" height=\"50\""));
+    }
+
+    #[test]
+    fn test_transform_svg_rotated() {
+        let unrotated = IconLayout::default().transform_svg_rotated(SVG, 0);
+        assert_eq!(unrotated, IconLayout::default().transform_svg(SVG));
+        assert!(!unrotated.contains("<g"));
+
+        // The rotation turns the whole icon around the key's center, keeping the square key size
+        let rotated = IconLayout::new(0.5, 0.0).transform_svg_rotated(SVG, 90);
+        assert!(rotated.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 100 100\"><g transform=\"rotate(90 50 50)\">"));
+        assert!(rotated.contains("<svg x=\"25\" y=\"25\" width=\"50\" height=\"50\" xmlns="));
+        assert!(rotated.ends_with("<path d=\"M0 0h24v24H0z\"/></svg></g></svg>"));
+
+        assert!(IconLayout::default().transform_svg_rotated(SVG, 270).contains("rotate(270 50 50)"));
+    }
+
+    #[test]
+    fn test_apply_rotated() {
+        static ICON: &str = SVG;
+        let rotated = IconLayout::default().apply_rotated(Some(ICON), 180).unwrap();
+        assert!(!std::ptr::eq(rotated, ICON));
+        assert!(rotated.contains("rotate(180 50 50)"));
+        assert!(std::ptr::eq(IconLayout::default().apply_rotated(Some(ICON), 180).unwrap(), rotated));
+        assert!(std::ptr::eq(IconLayout::default().apply_rotated(Some(ICON), 0).unwrap(), ICON));
+    }
+
+    #[test]
+    fn test_apply_caches_and_skips_identity() {
+        static ICON: &str = SVG;
+        assert!(std::ptr::eq(IconLayout::default().apply(Some(ICON)).unwrap(), ICON));
+        assert_eq!(IconLayout::new(0.5, 0.0).apply(None), None);
+
+        let first = IconLayout::new(0.5, 0.0).apply(Some(ICON)).unwrap();
+        let second = IconLayout::new(0.5, 0.0).apply(Some(ICON)).unwrap();
+        assert!(std::ptr::eq(first, second));
+        assert!(first.contains("width=\"50\""));
+    }
+
+    #[test]
+    fn test_highlight_icon() {
+        static ICON: &str = SVG;
+        let highlighted = highlight_icon(Some(ICON), "#0af");
+        assert!(highlighted.contains("stroke=\"#0af\""), "{}", highlighted);
+        assert!(highlighted.contains("M0 0h24v24H0z"), "{}", highlighted);
+        assert!(std::ptr::eq(highlighted, highlight_icon(Some(ICON), "#0af")));
+        assert!(!highlight_icon(None, "#0af").contains("<path"));
+
+        assert!(is_hex_color("#ff9800") && is_hex_color("#FFF"));
+        assert!(!is_hex_color("ff9800") && !is_hex_color("#ff98") && !is_hex_color("#gggggg"));
+    }
+}
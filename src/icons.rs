@@ -1,2 +1,199 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// Icon name that renders a key with its label only
+pub const NO_ICON: &str = "none";
+
+/// Returns true if the icon spec asks for a text-only key
+pub fn is_no_icon(icon: Option<&String>) -> bool {
+    icon.is_some_and(|icon| icon == NO_ICON)
+}
+
+/// Resolves an icon spec like [`resolve_icon`], except that with `strict` set an
+/// unknown icon or style yields `None` instead of the terminal icon
+pub fn resolve_icon_with(icon_name: Option<&String>, strict: bool) -> Option<&'static str> {
+    if strict {
+        icon_name.filter(|icon| !is_no_icon(Some(icon))).and_then(|icon| lookup_icon(icon))
+    } else {
+        resolve_icon(icon_name)
+    }
+}
+
+/// Prefix of an icon spec naming a Nerd Font glyph, e.g. `nf:nf-fa-wifi`
+pub const NERD_FONT_PREFIX: &str = "nf:";
+/// Prefix of an icon spec giving a glyph's codepoint in hex, e.g. `u:f1eb`
+pub const CODEPOINT_PREFIX: &str = "u:";
+/// Font family glyph icons are drawn with; it must be installed for them to show
+pub const GLYPH_FONT_FAMILY: &str = "Symbols Nerd Font";
+
+/// Nerd Font glyphs that can be referenced by name; others need their codepoint
+const NERD_FONT_GLYPHS: &[(&str, u32)] = &[
+    ("nf-dev-git", 0xe702),
+    ("nf-fa-battery_full", 0xf240),
+    ("nf-fa-bluetooth", 0xf293),
+    ("nf-fa-camera", 0xf030),
+    ("nf-fa-check", 0xf00c),
+    ("nf-fa-cog", 0xf013),
+    ("nf-fa-firefox", 0xf269),
+    ("nf-fa-github", 0xf09b),
+    ("nf-fa-home", 0xf015),
+    ("nf-fa-lock", 0xf023),
+    ("nf-fa-microphone", 0xf130),
+    ("nf-fa-microphone_slash", 0xf131),
+    ("nf-fa-moon_o", 0xf186),
+    ("nf-fa-pause", 0xf04c),
+    ("nf-fa-play", 0xf04b),
+    ("nf-fa-power_off", 0xf011),
+    ("nf-fa-refresh", 0xf021),
+    ("nf-fa-step_backward", 0xf048),
+    ("nf-fa-step_forward", 0xf051),
+    ("nf-fa-stop", 0xf04d),
+    ("nf-fa-sun_o", 0xf185),
+    ("nf-fa-terminal", 0xf120),
+    ("nf-fa-times", 0xf00d),
+    ("nf-fa-unlock", 0xf09c),
+    ("nf-fa-volume_down", 0xf027),
+    ("nf-fa-volume_off", 0xf026),
+    ("nf-fa-volume_up", 0xf028),
+    ("nf-fa-wifi", 0xf1eb),
+    ("nf-linux-nixos", 0xf313),
+];
+
+/// Where the image of an icon spec comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconSource<'a> {
+    /// A Material Design icon, as `style:name` or just `name`
+    Material(&'a str),
+    /// A font glyph, drawn with [`GLYPH_FONT_FAMILY`]
+    Glyph(char),
+}
+
+impl IconSource<'_> {
+    /// Parses an icon spec. Returns `None` for a glyph spec whose name is unknown or
+    /// whose codepoint is not a valid character.
+    pub fn parse(spec: &str) -> Option<IconSource<'_>> {
+        if let Some(name) = spec.strip_prefix(NERD_FONT_PREFIX) {
+            let (_, codepoint) = NERD_FONT_GLYPHS.iter().find(|(glyph, _)| *glyph == name)?;
+            char::from_u32(*codepoint).map(IconSource::Glyph)
+        } else if let Some(hex) = spec.strip_prefix(CODEPOINT_PREFIX) {
+            let hex = hex.trim_start_matches("U+").trim_start_matches("0x");
+            if hex.is_empty() || hex.len() > 6 {
+                return None;
+            }
+            u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(IconSource::Glyph)
+        } else {
+            Some(IconSource::Material(spec))
+        }
+    }
+}
+
+/// Returns true if the spec asks for a font glyph rather than a Material Design icon
+pub fn is_glyph_spec(spec: &str) -> bool {
+    spec.starts_with(NERD_FONT_PREFIX) || spec.starts_with(CODEPOINT_PREFIX)
+}
+
+/// Styles of the Material Design icons, usable as a spec's `style:` prefix
+pub const ICON_STYLES: &[&str] = &["filled", "outlined", "round", "sharp", "two_tone"];
+
+/// Prefixes a bare Material Design icon name with `style`. Specs that already name a
+/// style, glyph specs and [`NO_ICON`] are kept as they are.
+pub fn with_icon_style(spec: &str, style: &str) -> String {
+    if spec == NO_ICON || is_glyph_spec(spec) || spec.contains(':') {
+        spec.to_string()
+    } else {
+        format!("{}:{}", style, spec)
+    }
+}
+
+/// An SVG drawing `glyph` centered on the key. The SVGs are cached, so resolving the
+/// same glyph again does not allocate.
+pub fn glyph_svg(glyph: char) -> &'static str {
+    static CACHE: OnceLock<Mutex<HashMap<char, &'static str>>> = OnceLock::new();
+    let mut cache = match CACHE.get_or_init(|| Mutex::new(HashMap::new())).lock() {
+        Ok(cache) => cache,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    cache.entry(glyph).or_insert_with(|| {
+        let svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 24 24\"><text x=\"12\" y=\"12\" font-family=\"{}\" font-size=\"20\" text-anchor=\"middle\" dominant-baseline=\"central\">&#x{:x};</text></svg>",
+            GLYPH_FONT_FAMILY,
+            glyph as u32
+        );
+        Box::leak(svg.into_boxed_str())
+    })
+}
+
+/// Resolves a glyph spec for the generated lookups: `None` if `spec` is not a glyph
+/// spec, `Some(None)` if it names an unknown glyph or an invalid codepoint
+fn lookup_glyph(spec: &str) -> Option<Option<&'static str>> {
+    if !is_glyph_spec(spec) {
+        return None;
+    }
+    Some(match IconSource::parse(spec) {
+        Some(IconSource::Glyph(glyph)) => Some(glyph_svg(glyph)),
+        _ => None,
+    })
+}
+
 // Include the generated icon lookup code
-include!(concat!(env!("OUT_DIR"), "/icons_generated.rs"));
\ No newline at end of file
+include!(concat!(env!("OUT_DIR"), "/icons_generated.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strict_icons_do_not_fall_back() {
+        let typo = "termnial".to_string();
+        assert!(resolve_icon_with(Some(&typo), false).is_some());
+        assert_eq!(resolve_icon_with(Some(&typo), true), None);
+        assert_eq!(lookup_icon("bogus_style:terminal"), None);
+
+        let known = "terminal".to_string();
+        assert!(resolve_icon_with(Some(&known), true).is_some());
+        assert_eq!(resolve_icon_with(Some(&NO_ICON.to_string()), true), None);
+    }
+
+    #[test]
+    fn test_with_icon_style() {
+        assert_eq!(with_icon_style("home", "outlined"), "outlined:home");
+        assert_eq!(with_icon_style("sharp:home", "outlined"), "sharp:home");
+        assert_eq!(with_icon_style("nf:nf-fa-home", "outlined"), "nf:nf-fa-home");
+        assert_eq!(with_icon_style(NO_ICON, "outlined"), NO_ICON);
+    }
+
+    #[test]
+    fn test_parse_glyph_specs() {
+        assert_eq!(IconSource::parse("nf:nf-fa-wifi"), Some(IconSource::Glyph('\u{f1eb}')));
+        assert_eq!(IconSource::parse("u:f1eb"), Some(IconSource::Glyph('\u{f1eb}')));
+        assert_eq!(IconSource::parse("u:F1EB"), Some(IconSource::Glyph('\u{f1eb}')));
+        assert_eq!(IconSource::parse("u:U+e702"), Some(IconSource::Glyph('\u{e702}')));
+        assert_eq!(IconSource::parse("outlined:wifi"), Some(IconSource::Material("outlined:wifi")));
+        assert_eq!(IconSource::parse("wifi"), Some(IconSource::Material("wifi")));
+
+        // Unknown names and codepoints that are not characters
+        assert_eq!(IconSource::parse("nf:nf-fa-no_such_glyph"), None);
+        assert_eq!(IconSource::parse("u:zzzz"), None);
+        assert_eq!(IconSource::parse("u:"), None);
+        assert_eq!(IconSource::parse("u:d800"), None);
+        assert_eq!(IconSource::parse("u:110000"), None);
+        assert_eq!(IconSource::parse("u:0000f1eb"), None);
+    }
+
+    #[test]
+    fn test_glyph_icons_resolve() {
+        let wifi = resolve_icon(Some(&"nf:nf-fa-wifi".to_string())).unwrap();
+        assert!(wifi.contains("&#xf1eb;") && wifi.contains(GLYPH_FONT_FAMILY), "{}", wifi);
+        // The same glyph by codepoint shares the cached SVG
+        assert_eq!(resolve_icon(Some(&"u:f1eb".to_string())).unwrap().as_ptr(), wifi.as_ptr());
+        assert_eq!(lookup_icon("u:f1eb"), Some(wifi));
+    }
+
+    #[test]
+    fn test_invalid_glyph_falls_back() {
+        let invalid = "u:d800".to_string();
+        assert_eq!(resolve_icon(Some(&invalid)), resolve_icon(Some(&"terminal".to_string())));
+        assert_eq!(resolve_icon_with(Some(&invalid), true), None);
+        assert_eq!(lookup_icon("nf:nf-fa-no_such_glyph"), None);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::events::EventBus;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+/// Time of the last key press, shared by everything that reacts to inactivity
+#[derive(Debug, Clone)]
+pub struct ActivityTracker {
+    last: Arc<watch::Sender<Instant>>,
+}
+
+impl Default for ActivityTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActivityTracker {
+    /// Creates a tracker that counts from now
+    pub fn new() -> Self {
+        let (last, _) = watch::channel(Instant::now());
+        Self { last: Arc::new(last) }
+    }
+
+    /// Records activity happening now
+    pub fn touch(&self) {
+        self.last.send_replace(Instant::now());
+    }
+
+    /// How long ago the last activity was
+    pub fn idle_for(&self) -> Duration {
+        self.last.borrow().elapsed()
+    }
+
+    /// Counts every press published on `events` as activity
+    pub fn track_presses(&self, events: &EventBus) -> JoinHandle<()> {
+        let tracker = self.clone();
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => tracker.touch(),
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        })
+    }
+}
+
+/// Calls `go_home` once `timeout` passes without activity, then waits for the next
+/// activity before counting again, so an idle deck is sent home only once
+pub async fn run_idle_home<F, Fut>(activity: ActivityTracker, timeout: Duration, mut go_home: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    info!("Returning to the main menu after {:?} without presses", timeout);
+    let mut receiver = activity.last.subscribe();
+    loop {
+        let deadline = *receiver.borrow_and_update() + timeout;
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline.into()) => {
+                debug!("Idle for {:?}, returning to the main menu", timeout);
+                go_home().await;
+                if receiver.changed().await.is_err() {
+                    return;
+                }
+            }
+            changed = receiver.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::PressEvent;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_idle_home_fires_after_timeout_and_resets_on_activity() {
+        let activity = ActivityTracker::new();
+        let events = EventBus::new();
+        let presses = activity.track_presses(&events);
+        let homes = Arc::new(AtomicUsize::new(0));
+        let counter = homes.clone();
+        let timer = tokio::spawn(run_idle_home(activity.clone(), Duration::from_millis(200), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {}
+        }));
+
+        // Presses keep postponing the timeout
+        for _ in 0..4 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            events.publish(PressEvent::new("Terminal", "command", Some("success")));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(homes.load(Ordering::SeqCst), 0);
+        assert!(activity.idle_for() < Duration::from_millis(200));
+
+        // An idle deck goes home once, however long it stays idle
+        tokio::time::sleep(Duration::from_millis(550)).await;
+        assert_eq!(homes.load(Ordering::SeqCst), 1);
+
+        // The next activity starts a new countdown
+        activity.touch();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(homes.load(Ordering::SeqCst), 1);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(homes.load(Ordering::SeqCst), 2);
+
+        timer.abort();
+        presses.abort();
+    }
+}
@@ -0,0 +1,255 @@
+use crate::button::{CommanderContext, CommanderPlugin, BACK_BUTTON_POSITION};
+use crate::handler::spawn_handler;
+use crate::icons;
+use crate::last_result::DEFAULT_SUCCESS_EXIT_CODES;
+use crate::retry::RetryPolicy;
+use std::sync::{Arc, Mutex, PoisonError};
+use streamdeck_oxide::{
+    generic_array::typenum::{U3, U5},
+    plugins::{Plugin, PluginContext, PluginNavigation},
+    view::{
+        customizable::{ClickButton, CustomizableView},
+        View,
+    },
+    ExternalTrigger,
+};
+use tracing::{debug, error, info};
+
+/// Longest number the keypad accepts
+pub const MAX_INPUT_DIGITS: usize = 9;
+
+/// Digits in key order, filling the first two rows
+const DIGITS: [u8; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 0];
+
+/// A key of the numeric keypad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeypadKey {
+    Digit(u8),
+    Backspace,
+    Confirm,
+    Cancel,
+}
+
+/// What a key press on the keypad leads to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeypadAction {
+    /// The entry continues; the keypad is redrawn
+    Editing,
+    /// The entered number is submitted
+    Confirmed(String),
+    /// The entry is abandoned without running anything
+    Cancelled,
+}
+
+/// The number being entered on the keypad
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeypadState {
+    value: String,
+}
+
+impl KeypadState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The digits entered so far
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Applies one key press. Confirming or cancelling clears the entry; confirming
+    /// an empty entry does nothing.
+    pub fn press(&mut self, key: KeypadKey) -> KeypadAction {
+        match key {
+            KeypadKey::Digit(digit) => {
+                if digit <= 9 && self.value.len() < MAX_INPUT_DIGITS {
+                    self.value.push(char::from(b'0' + digit));
+                }
+                KeypadAction::Editing
+            }
+            KeypadKey::Backspace => {
+                self.value.pop();
+                KeypadAction::Editing
+            }
+            KeypadKey::Confirm if self.value.is_empty() => KeypadAction::Editing,
+            KeypadKey::Confirm => KeypadAction::Confirmed(std::mem::take(&mut self.value)),
+            KeypadKey::Cancel => {
+                self.value.clear();
+                KeypadAction::Cancelled
+            }
+        }
+    }
+}
+
+/// Numeric keypad shown by an input button. The confirmed number replaces `{input}` in
+/// the button's args and the deck returns to the menu the button was pressed in.
+#[derive(Clone)]
+pub struct KeypadPlugin {
+    name: String,
+    command: String,
+    args: Vec<String>,
+    no_prefix: bool,
+    state: Arc<Mutex<KeypadState>>,
+    return_to: CommanderPlugin,
+}
+
+impl KeypadPlugin {
+    pub fn new(name: &str, command: &str, args: &[String], no_prefix: bool, return_to: CommanderPlugin) -> Self {
+        Self {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: args.to_vec(),
+            no_prefix,
+            state: Arc::new(Mutex::new(KeypadState::new())),
+            return_to,
+        }
+    }
+
+    /// Label of the key showing the entry, the button's name while nothing is entered
+    pub fn display(&self) -> String {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match state.value() {
+            "" => self.name.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    /// Handles a key press: redraws the keypad while editing, otherwise returns to the
+    /// menu and runs the command with a confirmed number
+    async fn press(self, context: PluginContext, key: KeypadKey) {
+        let action = self.state.lock().unwrap_or_else(PoisonError::into_inner).press(key);
+        debug!("Keypad of '{}' pressed {:?}: {:?}", self.name, key, action);
+        let Some(commander_ctx) = context.get_context::<CommanderContext>().await else {
+            error!("Failed to get CommanderContext from plugin context");
+            return;
+        };
+        let Some(sender) = commander_ctx.navigation_sender.clone() else {
+            error!("No navigation sender available for the keypad of '{}'", self.name);
+            return;
+        };
+
+        let next = match &action {
+            KeypadAction::Editing => PluginNavigation::<U5, U3>::new(self.clone()),
+            KeypadAction::Confirmed(_) | KeypadAction::Cancelled => PluginNavigation::<U5, U3>::new(self.return_to.clone()),
+        };
+        if let Err(e) = sender.send(ExternalTrigger::new(next, false)).await {
+            error!("Failed to send keypad navigation: {}", e);
+        }
+
+        if let KeypadAction::Confirmed(input) = action {
+            info!("Running '{}' with input {}", self.name, input);
+            commander_ctx
+                .run_command_with_input(
+                    &self.name,
+                    &self.command,
+                    &self.args,
+                    Some(&input),
+                    self.no_prefix,
+                    None,
+                    &DEFAULT_SUCCESS_EXIT_CODES,
+                    RetryPolicy::default(),
+                )
+                .await;
+        }
+    }
+
+    fn key(&self, label: &str, icon: Option<&'static str>, key: KeypadKey) -> ClickButton<PluginContext> {
+        let plugin = self.clone();
+        ClickButton::new(label, icon, move |context: PluginContext| {
+            spawn_handler(plugin.name.clone(), plugin.clone().press(context, key));
+            async move { Ok(()) }
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin<U5, U3> for KeypadPlugin {
+    fn name(&self) -> &'static str {
+        "StreamDeck Commander Keypad"
+    }
+
+    async fn get_view(&self, context: PluginContext) -> Result<Box<dyn View<U5, U3, PluginContext, PluginNavigation<U5, U3>>>, Box<dyn std::error::Error>> {
+        let mut view = CustomizableView::new();
+        let icon_layout = context
+            .get_context::<CommanderContext>()
+            .await
+            .map(|ctx| ctx.icon_layout())
+            .unwrap_or_default();
+        let icon = |name: &str| icon_layout.apply(icons::resolve_icon(Some(&name.to_string())));
+
+        for (index, digit) in DIGITS.into_iter().enumerate() {
+            view.set_button(index % 5, index / 5, self.key(&digit.to_string(), None, KeypadKey::Digit(digit)))?;
+        }
+        view.set_button(0, 2, self.key("Delete", icon("backspace"), KeypadKey::Backspace))?;
+        view.set_button(2, 2, ClickButton::new(&self.display(), None, |_: PluginContext| async move { Ok(()) }))?;
+        view.set_button(3, 2, self.key("OK", icon("check"), KeypadKey::Confirm))?;
+        // Cancelling sits where the back button is in menus
+        let (cancel_col, cancel_row) = BACK_BUTTON_POSITION;
+        view.set_button(cancel_col, cancel_row, self.key("Cancel", icon("close"), KeypadKey::Cancel))?;
+
+        Ok(Box::new(view))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Menu;
+
+    fn enter(state: &mut KeypadState, digits: &[u8]) {
+        for digit in digits {
+            assert_eq!(state.press(KeypadKey::Digit(*digit)), KeypadAction::Editing);
+        }
+    }
+
+    #[test]
+    fn test_keypad_appends_and_deletes_digits() {
+        let mut state = KeypadState::new();
+        enter(&mut state, &[4, 2, 0]);
+        assert_eq!(state.value(), "420");
+
+        assert_eq!(state.press(KeypadKey::Backspace), KeypadAction::Editing);
+        assert_eq!(state.value(), "42");
+        state.press(KeypadKey::Backspace);
+        state.press(KeypadKey::Backspace);
+        // Deleting from an empty entry is harmless
+        assert_eq!(state.press(KeypadKey::Backspace), KeypadAction::Editing);
+        assert_eq!(state.value(), "");
+
+        // Digits past the limit are ignored
+        enter(&mut state, &[9; MAX_INPUT_DIGITS + 2]);
+        assert_eq!(state.value().len(), MAX_INPUT_DIGITS);
+    }
+
+    #[test]
+    fn test_keypad_confirm_and_cancel() {
+        let mut state = KeypadState::new();
+        // Nothing to confirm yet
+        assert_eq!(state.press(KeypadKey::Confirm), KeypadAction::Editing);
+
+        enter(&mut state, &[7, 5]);
+        assert_eq!(state.press(KeypadKey::Confirm), KeypadAction::Confirmed("75".to_string()));
+        assert_eq!(state.value(), "");
+
+        enter(&mut state, &[3]);
+        assert_eq!(state.press(KeypadKey::Cancel), KeypadAction::Cancelled);
+        assert_eq!(state.value(), "");
+        assert_eq!(state.press(KeypadKey::Confirm), KeypadAction::Editing);
+    }
+
+    #[test]
+    fn test_keypad_display() {
+        let menu = Menu {
+            name: "Main".to_string(),
+            buttons: vec![],
+            theme: None,
+            sort: Default::default(),
+            background_image: None,
+            dynamic: None,
+        };
+        let plugin = KeypadPlugin::new("Volume", "pamixer", &[], false, CommanderPlugin::new(menu));
+        assert_eq!(plugin.display(), "Volume");
+        plugin.state.lock().unwrap().press(KeypadKey::Digit(8));
+        assert_eq!(plugin.display(), "8");
+    }
+}
@@ -0,0 +1,180 @@
+use crate::config::Config;
+
+/// Edge length of a Stream Deck Mk2 key in pixels
+pub const DEFAULT_KEY_PX: u32 = 72;
+
+
+/// How a button label is laid out on its key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelLayout {
+    /// Label text, with a newline where it wraps or where the sublabel starts
+    pub text: String,
+    pub font_size: u32,
+    /// Font size of the sublabel on the last line, if there is one
+    pub sublabel_font_size: Option<u32>,
+}
+
+/// Font size bounds used for button labels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelConfig {
+    pub key_px: u32,
+    pub min_font_size: u32,
+    pub max_font_size: u32,
+}
+
+impl Default for LabelConfig {
+    fn default() -> Self {
+        Self {
+            key_px: DEFAULT_KEY_PX,
+            min_font_size: 10,
+            max_font_size: 18,
+        }
+    }
+}
+
+impl LabelConfig {
+    /// Builds the label settings from the global configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            min_font_size: config.label_font_min,
+            max_font_size: config.label_font_max,
+            ..Self::default()
+        }
+    }
+
+    /// Lays out a label for a key using these settings
+    pub fn layout(&self, text: &str) -> LabelLayout {
+        layout_label(text, self.key_px, self.min_font_size, self.max_font_size)
+    }
+
+    /// Lays out a label with an optional sublabel beneath it
+    pub fn layout_with_sublabel(&self, text: &str, sublabel: Option<&str>) -> LabelLayout {
+        match sublabel {
+            Some(sublabel) => layout_with_sublabel(text, sublabel, self.key_px, self.min_font_size, self.max_font_size),
+            None => self.layout(text),
+        }
+    }
+}
+
+/// Picks the largest font size in `min..=max` that fits `text_len` characters on one line of a key
+pub fn choose_font_size(text_len: usize, key_px: u32, min: u32, max: u32) -> u32 {
+    if text_len == 0 {
+        return max;
+    }
+    // Glyphs are roughly 0.6em wide and 90% of the key is usable, so a line of
+    // `text_len` characters fits at 0.9 / 0.6 = 1.5 key widths divided by its length
+    let fitting = (key_px as usize * 3 / (text_len * 2)).min(u32::MAX as usize) as u32;
+    fitting.clamp(min, max.max(min))
+}
+
+/// Chooses the font size for a label and wraps multi-word labels onto two lines when
+/// that allows a larger font than keeping them on one line
+pub fn layout_label(text: &str, key_px: u32, min: u32, max: u32) -> LabelLayout {
+    let single_line = choose_font_size(text.chars().count(), key_px, min, max);
+
+    if let Some((first, second)) = split_in_two(text) {
+        let longest = first.chars().count().max(second.chars().count());
+        let wrapped = choose_font_size(longest, key_px, min, max);
+        if wrapped > single_line {
+            return LabelLayout {
+                text: format!("{}\n{}", first, second),
+                font_size: wrapped,
+                sublabel_font_size: None,
+            };
+        }
+    }
+
+    LabelLayout {
+        text: text.to_string(),
+        font_size: single_line,
+        sublabel_font_size: None,
+    }
+}
+
+/// Lays out a label on one line with a sublabel on a second line below it. The
+/// sublabel is set at most three quarters the size of the label, never below `min`.
+pub fn layout_with_sublabel(text: &str, sublabel: &str, key_px: u32, min: u32, max: u32) -> LabelLayout {
+    let font_size = choose_font_size(text.chars().count(), key_px, min, max);
+    let sublabel_max = (font_size * 3 / 4).max(min);
+    let sublabel_font_size = choose_font_size(sublabel.chars().count(), key_px, min, sublabel_max);
+    LabelLayout {
+        text: format!("{}\n{}", text, sublabel),
+        font_size,
+        sublabel_font_size: Some(sublabel_font_size),
+    }
+}
+
+/// Splits a label at the space that best balances the two halves
+fn split_in_two(text: &str) -> Option<(&str, &str)> {
+    let half = text.chars().count() / 2;
+    text.char_indices()
+        .filter(|(_, c)| *c == ' ')
+        .map(|(index, _)| index)
+        .min_by_key(|index| text[..*index].chars().count().abs_diff(half))
+        .map(|index| (text[..index].trim_end(), text[index..].trim_start()))
+        .filter(|(first, second)| !first.is_empty() && !second.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_font_size() {
+        // Short labels are capped at the maximum
+        assert_eq!(choose_font_size(0, 72, 10, 18), 18);
+        assert_eq!(choose_font_size(4, 72, 10, 18), 18);
+        // Medium labels shrink to fit the key
+        assert_eq!(choose_font_size(8, 72, 10, 18), 13);
+        assert_eq!(choose_font_size(10, 72, 10, 18), 10);
+        // Long labels never go below the minimum
+        assert_eq!(choose_font_size(30, 72, 10, 18), 10);
+        // Larger keys allow larger text for the same label
+        assert_eq!(choose_font_size(8, 96, 10, 18), 18);
+    }
+
+    #[test]
+    fn test_layout_label_wrap_threshold() {
+        // Fits on one line at the maximum size, so it is not wrapped
+        assert_eq!(layout_label("Top 10", 72, 10, 18), LabelLayout { text: "Top 10".to_string(), font_size: 18, sublabel_font_size: None });
+
+        // Wrapping only pays off once the single line has to shrink
+        assert_eq!(layout_label("Mic Mute", 72, 10, 18), LabelLayout { text: "Mic\nMute".to_string(), font_size: 18, sublabel_font_size: None });
+        assert_eq!(
+            layout_label("System Monitor", 72, 10, 18),
+            LabelLayout { text: "System\nMonitor".to_string(), font_size: 15, sublabel_font_size: None }
+        );
+
+        // Single words cannot wrap
+        assert_eq!(
+            layout_label("Configuration", 72, 10, 18),
+            LabelLayout { text: "Configuration".to_string(), font_size: 10, sublabel_font_size: None }
+        );
+    }
+
+    #[test]
+    fn test_layout_with_sublabel() {
+        // The sublabel gets its own, smaller line and the label is never wrapped
+        assert_eq!(
+            layout_with_sublabel("Mic Mute", "active", 72, 10, 18),
+            LabelLayout { text: "Mic Mute\nactive".to_string(), font_size: 13, sublabel_font_size: Some(10) }
+        );
+        assert_eq!(
+            layout_with_sublabel("nginx", "on", 72, 10, 18),
+            LabelLayout { text: "nginx\non".to_string(), font_size: 18, sublabel_font_size: Some(13) }
+        );
+        // Long sublabels shrink down to the minimum like labels do
+        assert_eq!(layout_with_sublabel("DB", "inactive (dead)", 72, 10, 18).sublabel_font_size, Some(10));
+
+        // Without a sublabel the single label layout is kept
+        let config = LabelConfig::default();
+        assert_eq!(config.layout_with_sublabel("Mic Mute", None), config.layout("Mic Mute"));
+    }
+
+    #[test]
+    fn test_split_in_two_balances_halves() {
+        assert_eq!(split_in_two("Open Web Browser"), Some(("Open Web", "Browser")));
+        assert_eq!(split_in_two("Terminal"), None);
+        assert_eq!(split_in_two(" Padded"), None);
+    }
+}
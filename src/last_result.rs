@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Exit codes counted as success unless a button sets `success_exit_codes`
+pub const DEFAULT_SUCCESS_EXIT_CODES: [i32; 1] = [0];
+
+/// Outcome of the most recent run of a command button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandOutcome {
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    /// Time the command took, retries included
+    pub duration: Duration,
+}
+
+impl CommandOutcome {
+    /// Creates an outcome from a process exit code
+    pub fn from_exit_code(exit_code: i32) -> Self {
+        Self::from_exit_code_with(exit_code, &DEFAULT_SUCCESS_EXIT_CODES)
+    }
+
+    /// Creates an outcome from a process exit code, counting `success_exit_codes` as success
+    pub fn from_exit_code_with(exit_code: i32, success_exit_codes: &[i32]) -> Self {
+        Self {
+            success: success_exit_codes.contains(&exit_code),
+            exit_code: Some(exit_code),
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Creates an outcome for a command that could not be run at all
+    pub fn execution_error() -> Self {
+        Self {
+            success: false,
+            exit_code: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Records how long the command took
+    pub fn with_duration(self, duration: Duration) -> Self {
+        Self { duration, ..self }
+    }
+}
+
+/// Tracks the last outcome of every command button, keyed by button name
+#[derive(Debug)]
+pub struct LastResultManager {
+    results: Arc<RwLock<HashMap<String, CommandOutcome>>>,
+}
+
+impl Clone for LastResultManager {
+    fn clone(&self) -> Self {
+        Self {
+            results: Arc::clone(&self.results),
+        }
+    }
+}
+
+impl Default for LastResultManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LastResultManager {
+    /// Creates an empty result manager
+    pub fn new() -> Self {
+        Self {
+            results: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Records the outcome of a button's latest run
+    pub fn record(&self, button_name: &str, outcome: CommandOutcome) {
+        match self.results.write() {
+            Ok(mut results) => {
+                debug!("Recorded result for '{}': {:?}", button_name, outcome);
+                results.insert(button_name.to_string(), outcome);
+            }
+            Err(e) => {
+                warn!("Failed to record result for '{}': {}", button_name, e);
+            }
+        }
+    }
+
+    /// Gets the outcome of a button's latest run, if it has run
+    pub fn get(&self, button_name: &str) -> Option<CommandOutcome> {
+        match self.results.read() {
+            Ok(results) => results.get(button_name).copied(),
+            Err(e) => {
+                warn!("Failed to read result for '{}': {}", button_name, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::context_for;
+
+    #[test]
+    fn test_command_outcome_from_exit_code() {
+        assert!(CommandOutcome::from_exit_code(0).success);
+        assert!(!CommandOutcome::from_exit_code(2).success);
+        assert_eq!(CommandOutcome::from_exit_code(2).exit_code, Some(2));
+        assert!(CommandOutcome::from_exit_code_with(1, &[0, 1]).success);
+        assert!(!CommandOutcome::from_exit_code_with(0, &[1]).success);
+        assert_eq!(CommandOutcome::execution_error().exit_code, None);
+    }
+
+    #[test]
+    fn test_last_result_manager_record_and_get() {
+        let manager = LastResultManager::new();
+        assert_eq!(manager.get("backup"), None);
+
+        manager.record("backup", CommandOutcome::from_exit_code(1));
+        assert_eq!(manager.get("backup"), Some(CommandOutcome::from_exit_code(1)));
+
+        // Later runs replace the previous outcome, and clones share storage
+        let clone = manager.clone();
+        clone.record("backup", CommandOutcome::from_exit_code(0));
+        assert!(manager.get("backup").unwrap().success);
+    }
+
+    #[tokio::test]
+    async fn test_success_exit_codes() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Find"
+      command: "sh"
+      args: ["-c", "echo none; exit 1"]
+      store_output_as: "found"
+      success_exit_codes: [0, 1]
+"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        let context = context_for(config);
+
+        // Like `grep` finding nothing, the exit code 1 is recorded as success
+        assert!(context.execute_button("Find").await);
+        let outcome = context.last_result_manager.get("Find").unwrap();
+        assert!(outcome.success);
+        assert_eq!(outcome.exit_code, Some(1));
+        assert_eq!(context.runtime_vars.get("found").as_deref(), Some("none"));
+
+        // Without the setting only 0 is a success
+        let outcome = context.run_command("Find", "sh", &["-c".to_string(), "exit 1".to_string()], false, None, &[0], Default::default()).await;
+        assert!(!outcome.success);
+    }
+}
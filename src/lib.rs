@@ -1,17 +1,104 @@
+pub mod app;
+pub mod args_file;
+pub mod background;
 pub mod button;
+pub mod command_policy;
 pub mod config;
+pub mod cooldown;
+pub mod deck;
+pub mod delay;
+pub mod device;
+pub mod error;
+pub mod events;
+pub mod expand;
+pub mod file_toggle;
+pub mod grid;
+pub mod handler;
+pub mod history;
+pub mod hold_confirm;
+pub mod hooks;
+pub mod http_probe;
+pub mod idle;
+pub mod icon_layout;
 pub mod icons;
+pub mod keypad;
+pub mod label;
+pub mod list_buttons;
+pub mod locks;
+pub mod last_result;
+pub mod notification;
+pub mod pedal;
 pub mod probe;
+pub mod probe_backend;
+pub mod queue;
+pub mod render;
+pub mod render_dump;
+pub mod repeat;
+pub mod result_view;
+pub mod retry;
+pub mod runtime_vars;
+pub mod scene;
+pub mod services;
+pub mod spawn;
+pub mod state_file;
 pub mod toggle_command;
+pub mod toggle_group;
 pub mod toggle_icons;
 pub mod toggle_state;
+pub mod trigger;
+pub mod view_tree;
 
+#[cfg(test)]
+pub mod test_support;
 #[cfg(test)]
 pub mod toggle_integration_tests;
 
-pub use button::{CommanderContext, CommanderPlugin};
-pub use config::{Button, Config, Menu, ToggleMode, load_config};
-pub use probe::{ProbeConfig, ProbeResult, execute_probe_command, execute_probe_command_with_config};
-pub use toggle_command::{ToggleCommandResult, execute_toggle_command};
-pub use toggle_icons::{resolve_toggle_icon, get_toggle_display_name, get_simple_display_name, is_toggle_button, get_toggle_state_description};
-pub use toggle_state::{ToggleState, ToggleStateManager};
\ No newline at end of file
+pub use app::{run_commander, run_deck};
+pub use args_file::{command_args, parse_args_file};
+pub use background::{KeyCrop, KeyIconLayout, MenuBackground, MenuBackgrounds, key_crop};
+pub use button::{BACK_BUTTON_POSITION, BackButtonStyle, CommandRun, CommanderContext, CommanderPlugin, DISABLED_ICON, initialize_toggle_states, initialize_toggle_states_from, initialize_toggle_states_with, menu_layout, menu_layout_in, seed_initial_toggle_states, select_match_command};
+pub use command_policy::{CommandPolicy, resolve_executable};
+pub use config::{ActiveHours, BrightnessKind, Button, Config, ConfigWarning, DynamicMenu, GroupActionKind, HookCommand, HooksConfig, HttpExpect, HttpProbeConfig, IconMap, IconPriority, LogOutput, MatchCase, MatchCommand, Menu, MenuSort, PedalConfig, ProbeCombine, ProbeSpec, ProbeVote, RenderSettings, RepeatConfig, SingleBehavior, StateSource, Sublabel, ThemeName, ThemeRule, ToggleMode, TriggerOn, Weekday, load_config, load_config_from, parse_config, resolve_config_path, resolve_config_path_with, save_config};
+pub use cooldown::Cooldowns;
+pub use deck::{DeckDevice, HidDeck, Navigation, Renderer};
+pub use delay::{DelayedCommands, DelayedPress};
+pub use device::{BrightnessAction, BrightnessControl, DeviceInfo, MAX_BRIGHTNESS, brightness_action, brightness_after, prepare_device, read_device_info};
+pub use error::CommanderError;
+pub use events::{EventBus, PressEvent, serve_event_socket};
+pub use expand::{expand_all, expand_all_args, expand_env, ExpandContext};
+pub use file_toggle::{create_file, probe_file, remove_file};
+pub use grid::{DECK_GRID, GridSize};
+pub use handler::{ActionOutcome, await_command, spawn_handler};
+pub use history::{Execution, ExecutionHistory, HISTORY_CAPACITY, recent_buttons};
+pub use hold_confirm::{HoldConfirm, HoldRelease};
+pub use hooks::{BUTTON_PLACEHOLDER, EXIT_CODE_PLACEHOLDER, hook_args, run_hook};
+pub use icons::{CODEPOINT_PREFIX, GLYPH_FONT_FAMILY, IconSource, NERD_FONT_PREFIX, glyph_svg, is_glyph_spec};
+pub use icon_layout::{IconLayout, ICON_ROTATIONS, highlight_icon, is_hex_color, normalize_icon_layout};
+pub use keypad::{KeypadAction, KeypadKey, KeypadPlugin, KeypadState, MAX_INPUT_DIGITS};
+pub use label::{LabelConfig, LabelLayout, choose_font_size, layout_label, layout_with_sublabel};
+pub use last_result::{CommandOutcome, LastResultManager};
+pub use list_buttons::{ButtonEntry, LIST_BUTTONS_FLAG, list_buttons, write_button_list};
+pub use locks::ButtonLocks;
+pub use notification::{ActivitySummary, DesktopNotification, DesktopNotifier, NOTIFICATION_APP_NAME, Notifier, render_notification_text};
+pub use pedal::{PedalKey, dispatch_pedal_key, dispatch_pedal_repeat, pedal_confirm_hold, pedal_repeat, pedal_trigger, run_pedal};
+pub use idle::{ActivityTracker, run_idle_home};
+pub use http_probe::{HTTP_BACKEND, HttpProbe, probe_http};
+pub use probe::{TIMEOUT_EXIT_CODE, ProbeConfig, ProbeContext, ProbeResult, combine_probe_results, execute_combined_probe, execute_probe_command, execute_probe_command_in, execute_probe_command_with_config, execute_probe_command_with_spawn, vote_probe_results};
+pub use probe_backend::{PROCESS_BACKEND, ProbeBackend, ProbeBackendRegistry, ProbeRequest, ProcessProbe};
+pub use queue::{CommandQueues, QueueTurn};
+pub use render::{next_theme, render_config_from, select_theme, theme_from};
+pub use render_dump::{compose_deck, dump_active_view, DumpKey, KeyColors, DUMP_KEY_GAP, DUMP_KEY_SIZE};
+pub use repeat::HeldRepeats;
+pub use result_view::{ResultPlugin, last_output_line, show_transient_result, show_transient_result_for};
+pub use retry::RetryPolicy;
+pub use runtime_vars::RuntimeVars;
+pub use scene::restore_scene;
+pub use services::{ACTIVE_HOURS_CHECK_INTERVAL, Services, refresh_on_active_hours, start_after_first_view, start_services};
+pub use spawn::{SpawnConfig, log_command_output, shell_quote};
+pub use state_file::{STATE_SAVE_INTERVAL, load_toggle_states, persist_toggle_states, save_toggle_states};
+pub use toggle_command::{ToggleCommandConfig, ToggleCommandResult, execute_toggle_command, execute_toggle_command_with_config, execute_toggle_to_state};
+pub use toggle_group::{GroupActionConfig, GroupActionResult, ToggleGroupRegistry, execute_group_action, execute_group_action_with_config};
+pub use toggle_icons::{auto_style_icon, default_brightness_icon, default_button_icon, default_group_action_icon, resolve_button_icon, resolve_command_icon, resolve_toggle_icon, get_toggle_display_name, get_toggle_display_name_localized, get_toggle_label_localized, get_toggle_sublabel, get_simple_display_name, get_simple_display_name_localized, is_toggle_button, get_toggle_state_description};
+pub use toggle_state::{ParseToggleStateError, StateChange, ToggleState, ToggleStateManager};
+pub use trigger::{KeyEvent, KeyTrigger};
+pub use view_tree::{KeyKind, KeyNode, ViewNode, build_root_view};
@@ -0,0 +1,125 @@
+use crate::config::{Button, Config, ToggleMode};
+use serde::Serialize;
+use std::io::Write;
+
+/// Command-line flag that prints the configured buttons instead of starting the deck
+pub const LIST_BUTTONS_FLAG: &str = "--list-buttons";
+
+/// One configured button, printed by `--list-buttons` as a JSON line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ButtonEntry {
+    pub name: String,
+    /// Button type as written in the config (e.g. "command" or "toggle")
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Names of the menus leading to the button, starting with the root menu
+    pub menu: Vec<String>,
+    /// The command and args the button runs, if it runs one
+    pub command: Option<String>,
+    /// The first icon set on the button as written in the config
+    pub icon: Option<String>,
+}
+
+impl ButtonEntry {
+    fn new(button: &Button, path: &[&str]) -> Self {
+        Self {
+            name: button.name().to_string(),
+            kind: button.type_name().to_string(),
+            menu: path.iter().map(|menu| menu.to_string()).collect(),
+            command: command_summary(button),
+            icon: button.icon_specs().first().map(|icon| icon.to_string()),
+        }
+    }
+}
+
+/// Every button of the menu tree in walk order
+pub fn list_buttons(config: &Config) -> Vec<ButtonEntry> {
+    let mut entries = Vec::new();
+    config.walk_buttons(|button, path| entries.push(ButtonEntry::new(button, path)));
+    entries
+}
+
+/// Writes every button of the menu tree to `out`, one JSON object per line
+pub fn write_button_list(config: &Config, out: &mut impl Write) -> anyhow::Result<()> {
+    for entry in list_buttons(config) {
+        serde_json::to_writer(&mut *out, &entry)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// The command line a button runs, with a toggle's on and off commands separated by " / "
+fn command_summary(button: &Button) -> Option<String> {
+    let join = |command: &str, args: &[String]| {
+        std::iter::once(command).chain(args.iter().map(String::as_str)).collect::<Vec<_>>().join(" ")
+    };
+    match button {
+        Button::Command { command, args, .. } | Button::Input { command, args, .. } => Some(join(command, args)),
+        Button::Toggle { mode, .. } => Some(match mode {
+            ToggleMode::Single { command, args, .. } => join(command, args),
+            ToggleMode::Separate { on_command, on_args, off_command, off_args } => {
+                format!("{} / {}", join(on_command, on_args), join(off_command, off_args))
+            }
+            ToggleMode::Service { start, start_args, stop, stop_args, .. } => {
+                format!("{} / {}", join(start, start_args), join(stop, stop_args))
+            }
+            ToggleMode::File { path } => format!("file {}", path.display()),
+        }),
+        Button::Menu { .. }
+        | Button::Back { .. }
+        | Button::Reload { .. }
+        | Button::Brightness { .. }
+        | Button::Notification { .. }
+        | Button::GroupAction { .. }
+        | Button::Scene { .. }
+        | Button::Match { .. }
+        | Button::Branch { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_button_list() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Terminal"
+      command: "alacritty"
+      args: ["-e", "htop"]
+      icon: "terminal"
+    - type: menu
+      name: "System"
+      buttons:
+        - type: toggle
+          name: "WiFi"
+          mode: separate
+          on_command: "nmcli"
+          on_args: ["radio", "wifi", "on"]
+          off_command: "nmcli"
+          off_args: ["radio", "wifi", "off"]
+          on_icon: "wifi"
+        - type: back
+"#,
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        write_button_list(&config, &mut out).unwrap();
+        let lines: Vec<_> = String::from_utf8(out).unwrap().lines().map(str::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"name":"Terminal","type":"command","menu":["Main"],"command":"alacritty -e htop","icon":"terminal"}"#,
+                r#"{"name":"System","type":"menu","menu":["Main"],"command":null,"icon":null}"#,
+                r#"{"name":"WiFi","type":"toggle","menu":["Main","System"],"command":"nmcli radio wifi on / nmcli radio wifi off","icon":"wifi"}"#,
+                r#"{"name":"Back","type":"back","menu":["Main","System"],"command":null,"icon":null}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::OwnedMutexGuard;
+use tracing::debug;
+
+/// Named locks of buttons with a `lock`. Runs of buttons naming the same lock wait for
+/// each other, while buttons with other locks or none run in parallel.
+#[derive(Debug, Clone, Default)]
+pub struct ButtonLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl ButtonLocks {
+    /// Creates a registry with no lock taken yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits until the lock named `lock` is free and holds it for `button_name` until the
+    /// returned guard is dropped. Buttons without a lock get `None` right away.
+    pub async fn acquire(&self, button_name: &str, lock: Option<&str>) -> Option<OwnedMutexGuard<()>> {
+        let lock = lock?;
+        let mutex = self
+            .locks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(lock.to_string())
+            .or_default()
+            .clone();
+        match mutex.clone().try_lock_owned() {
+            Ok(guard) => Some(guard),
+            Err(_) => {
+                debug!("'{}' waits for lock '{}'", button_name, lock);
+                Some(mutex.lock_owned().await)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context_for, TempDir};
+    use std::path::Path;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    /// Holds `lock` for `duration` and returns when the hold started and ended
+    fn hold(locks: &ButtonLocks, button_name: &'static str, lock: Option<&'static str>, duration: Duration) -> tokio::task::JoinHandle<(Instant, Instant)> {
+        let locks = locks.clone();
+        tokio::spawn(async move {
+            let _guard = locks.acquire(button_name, lock).await;
+            let start = Instant::now();
+            tokio::time::sleep(duration).await;
+            (start, Instant::now())
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_same_lock_does_not_overlap() {
+        let locks = ButtonLocks::new();
+        let wifi = hold(&locks, "WiFi", Some("network"), Duration::from_secs(2));
+        let vpn = hold(&locks, "VPN", Some("network"), Duration::from_secs(2));
+        let (wifi, vpn) = (wifi.await.unwrap(), vpn.await.unwrap());
+        assert!(wifi.1 <= vpn.0 || vpn.1 <= wifi.0, "{:?} {:?}", wifi, vpn);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_other_locks_run_in_parallel() {
+        let locks = ButtonLocks::new();
+        let started = Instant::now();
+        let holds = [
+            hold(&locks, "WiFi", Some("network"), Duration::from_secs(2)),
+            hold(&locks, "Mute", Some("audio"), Duration::from_secs(2)),
+            hold(&locks, "Backup", None, Duration::from_secs(2)),
+            hold(&locks, "Sync", None, Duration::from_secs(2)),
+        ];
+        for hold in holds {
+            let (start, _) = hold.await.unwrap();
+            assert_eq!(start, started);
+        }
+    }
+
+    /// Command and toggle buttons whose runs append "<name> start" and "<name> end" to
+    /// `log`, with `lock`s: WiFi and Hotspot share "network", Mute holds "audio"
+    fn locked_buttons(log: &Path) -> Config {
+        let script = |name: &str| format!("[\"-c\", \"echo {0} start >> {1}; sleep 0.3; echo {0} end >> {1}\"]", name, log.display());
+        serde_yaml::from_str(&format!(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "WiFi"
+      command: "sh"
+      args: {}
+      lock: "network"
+    - type: toggle
+      name: "Hotspot"
+      mode: single
+      command: "sh"
+      args: {}
+      lock: "network"
+    - type: command
+      name: "Mute"
+      command: "sh"
+      args: {}
+      lock: "audio"
+"#,
+            script("WiFi"),
+            script("Hotspot"),
+            script("Mute")
+        ))
+        .unwrap()
+    }
+
+    fn read_log(log: &Path) -> Vec<String> {
+        std::fs::read_to_string(log).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn test_buttons_sharing_a_lock_do_not_overlap() {
+        let dir = TempDir::new("lock-shared");
+        let log = dir.join("log");
+        let context = context_for(locked_buttons(&log));
+        assert_eq!(context.current_config().lock_for("Hotspot").as_deref(), Some("network"));
+
+        let (wifi, hotspot) = tokio::join!(context.execute_button("WiFi"), context.execute_button("Hotspot"));
+        assert!(wifi && hotspot);
+        let lines = read_log(&log);
+        // Whichever got the lock first finished before the other started
+        let first = lines[0].strip_suffix(" start").unwrap().to_string();
+        let second = if first == "WiFi" { "Hotspot" } else { "WiFi" };
+        assert_eq!(
+            lines,
+            vec![format!("{} start", first), format!("{} end", first), format!("{} start", second), format!("{} end", second)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_buttons_with_other_locks_run_concurrently() {
+        let dir = TempDir::new("lock-other");
+        let log = dir.join("log");
+        let context = context_for(locked_buttons(&log));
+
+        let (wifi, mute) = tokio::join!(context.execute_button("WiFi"), context.execute_button("Mute"));
+        assert!(wifi && mute);
+        let lines = read_log(&log);
+        // Both started before either ended
+        assert_eq!(lines.len(), 4);
+        assert!(lines[..2].iter().all(|line| line.ends_with(" start")), "{:?}", lines);
+    }
+}
@@ -1,31 +1,75 @@
 use anyhow::Result;
-use std::{any::{Any, TypeId}, collections::BTreeMap, sync::Arc};
-use streamdeck_oxide::{
-    button::RenderConfig,
-    elgato_streamdeck,
-    generic_array::typenum::{U3, U5},
-    plugins::{PluginContext, PluginNavigation},
-    run_with_external_triggers,
-    theme::Theme,
-    ExternalTrigger,
-};
+use std::sync::Arc;
+use streamdeck_oxide::elgato_streamdeck;
 use tracing::{error, info};
 use tracing_subscriber::{self, EnvFilter};
 
+mod app;
+mod args_file;
+mod background;
 mod button;
+mod command_policy;
 mod config;
+mod cooldown;
+mod deck;
+mod delay;
+mod device;
+mod error;
+mod events;
+mod expand;
+mod file_toggle;
+mod grid;
+mod handler;
+mod history;
+mod hold_confirm;
+mod hooks;
+mod http_probe;
+mod idle;
+mod icon_layout;
 mod icons;
+mod keypad;
+mod label;
+mod list_buttons;
+mod locks;
+mod last_result;
+mod notification;
+mod pedal;
 mod probe;
+mod probe_backend;
+mod queue;
+mod render;
+mod render_dump;
+mod repeat;
+mod result_view;
+mod retry;
+mod runtime_vars;
+mod scene;
+mod services;
+mod spawn;
+mod state_file;
 mod toggle_command;
+mod toggle_group;
 mod toggle_icons;
 mod toggle_state;
+mod trigger;
+mod view_tree;
 
-use crate::button::{CommanderContext, CommanderPlugin};
-use crate::config::{Config, load_config};
-use crate::toggle_state::ToggleStateManager;
+#[cfg(test)]
+mod test_support;
+
+use crate::app::run_commander;
+use crate::config::{Config, load_config_from, resolve_config_path};
+use crate::deck::{DeckDevice, HidDeck};
+use crate::list_buttons::{write_button_list, LIST_BUTTONS_FLAG};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Print the configured buttons for tooling; logging stays off so stdout is pure JSON
+    if std::env::args().skip(1).any(|arg| arg == LIST_BUTTONS_FLAG) {
+        let config = load_config_from(resolve_config_path().as_deref())?;
+        return write_button_list(&config, &mut std::io::stdout().lock());
+    }
+
     // Configure logging
     // Default: info level for all crates, debug level for streamdeck_nix
     // Override with RUST_LOG environment variable, examples:
@@ -43,11 +87,16 @@ async fn main() -> Result<()> {
     
     info!("Starting StreamDeck Commander");
     
-    // Load embedded configuration
-    let config: Config = load_config()?;
+    // Load configuration from STREAMDECK_CONFIG, else the XDG config dir (or ./config.yaml),
+    // falling back to the embedded config
+    let config_path = resolve_config_path();
+    let config: Config = load_config_from(config_path.as_deref())?;
     let config = Arc::new(config);
     
-    info!("Configuration loaded from embedded config");
+    match &config_path {
+        Some(path) => info!("Configuration loaded from {}", path.display()),
+        None => info!("Configuration loaded from embedded config"),
+    }
     info!("Main menu: {}", config.menu.name);
     info!("Number of buttons: {}", config.menu.buttons.len());
     
@@ -62,14 +111,20 @@ async fn main() -> Result<()> {
     
     info!("Found {} Stream Deck device(s)", devices.len());
     
-    // Use the first available device (preferably Mk2, but fall back to others)
-    let (kind, serial) = devices
+    // A pedal has no keys to draw on; it only triggers buttons configured under `pedal:`
+    let (pedals, decks): (Vec<_>, Vec<_>) = devices
         .into_iter()
+        .partition(|(kind, _)| matches!(kind, elgato_streamdeck::info::Kind::Pedal));
+
+    // Use the first available device (preferably Mk2, but fall back to others)
+    let (kind, serial) = decks
+        .iter()
         .find(|(kind, _)| matches!(kind, elgato_streamdeck::info::Kind::Mk2))
         .or_else(|| {
             // Fall back to any device if Mk2 not found
-            elgato_streamdeck::list_devices(&hid).into_iter().next()
+            decks.first()
         })
+        .cloned()
         .ok_or_else(|| anyhow::anyhow!("No Stream Deck found"))?;
     
     info!("Using Stream Deck: {:?} (Serial: {})", kind, serial);
@@ -79,45 +134,23 @@ async fn main() -> Result<()> {
     )?);
     
     info!("Connected to Stream Deck successfully!");
-    
-    // Create configuration
-    let render_config = RenderConfig::default();
-    let theme = Theme::light();
-    
-    // Create external trigger channel
-    let (sender, receiver) = tokio::sync::mpsc::channel::<ExternalTrigger<PluginNavigation<U5, U3>, U5, U3, PluginContext>>(1);
-    
-    // Create plugin context
-    let toggle_state_manager = ToggleStateManager::new();
-    let commander_context = CommanderContext {
-        config: config.clone(),
-        toggle_state_manager: toggle_state_manager.clone(),
-        navigation_sender: Some(sender.clone()),
+    let deck: Arc<dyn DeckDevice> = Arc::new(HidDeck::new(deck));
+
+    // Connect the pedal now; its presses are dispatched once the services start
+    let pedal: Option<Arc<dyn DeckDevice>> = match (&config.pedal, pedals.first()) {
+        (Some(_), Some((pedal_kind, pedal_serial))) => match elgato_streamdeck::AsyncStreamDeck::connect(&hid, *pedal_kind, pedal_serial) {
+            Ok(pedal) => {
+                info!("Connected to Stream Deck Pedal (Serial: {})", pedal_serial);
+                Some(Arc::new(HidDeck::new(Arc::new(pedal))))
+            }
+            Err(e) => {
+                error!("Failed to connect to Stream Deck Pedal: {}", e);
+                None
+            }
+        },
+        _ => None,
     };
-    
-    let context = PluginContext::new(BTreeMap::from([
-        (TypeId::of::<CommanderContext>(), Box::new(Arc::new(commander_context)) as Box<dyn Any + Send + Sync>)
-    ]));
-    
-    // Send initial navigation to main menu
-    sender.send(ExternalTrigger::new(
-        PluginNavigation::<U5, U3>::new(CommanderPlugin::new_with_state_manager(config.menu.clone(), toggle_state_manager)),
-        true
-    )).await?;
-    
-    info!("Starting Stream Deck application...");
-    info!("Press Ctrl+C to exit");
-    
+
     // Run the application
-    run_with_external_triggers::<PluginNavigation<U5, U3>, U5, U3, PluginContext>(
-        theme,
-        render_config,
-        deck,
-        context,
-        receiver,
-    )
-    .await
-    .map_err(|e| anyhow::anyhow!("StreamDeck application error: {}", e))?;
-    
-    Ok(())
+    run_commander(deck, kind, &serial, pedal, config, config_path).await
 }
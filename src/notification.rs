@@ -0,0 +1,288 @@
+use crate::config::{Button, Config};
+use crate::error::CommanderError;
+use crate::expand::{expand_all, ExpandContext};
+use crate::last_result::LastResultManager;
+use crate::runtime_vars::RuntimeVars;
+use crate::toggle_state::{ToggleState, ToggleStateManager};
+use async_trait::async_trait;
+
+/// Application name the notifications are sent under
+pub const NOTIFICATION_APP_NAME: &str = "StreamDeck Commander";
+
+/// A notification ready to be shown, with its placeholders filled in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopNotification {
+    pub title: String,
+    pub body: String,
+    /// Icon name or image path, as understood by the notification daemon
+    pub icon: Option<String>,
+}
+
+/// Shows desktop notifications. Having no notification daemon is an error, not a panic.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &DesktopNotification) -> Result<(), CommanderError>;
+}
+
+/// Shows notifications through the desktop's notification daemon, whichever one runs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DesktopNotifier;
+
+#[async_trait]
+impl Notifier for DesktopNotifier {
+    async fn notify(&self, notification: &DesktopNotification) -> Result<(), CommanderError> {
+        let notification = notification.clone();
+        // Talking to the daemon blocks until it answers
+        let shown = tokio::task::spawn_blocking(move || {
+            let mut desktop = notify_rust::Notification::new();
+            desktop
+                .appname(NOTIFICATION_APP_NAME)
+                .summary(&notification.title)
+                .body(&notification.body);
+            if let Some(icon) = &notification.icon {
+                desktop.icon(icon);
+            }
+            desktop.show().map(|_| ())
+        })
+        .await
+        .map_err(|e| CommanderError::Notification(e.to_string()))?;
+        shown.map_err(|e| CommanderError::Notification(e.to_string()))
+    }
+}
+
+/// What the deck's buttons are up to, for the placeholders of a notification
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActivitySummary {
+    /// Toggles that are on, in config order
+    pub toggles_on: Vec<String>,
+    /// Toggles that are off, in config order
+    pub toggles_off: Vec<String>,
+    /// Buttons whose last command failed, in config order
+    pub failed: Vec<String>,
+}
+
+impl ActivitySummary {
+    /// Collects the state of every toggle and the last result of every command in the config
+    pub fn collect(config: &Config, toggles: &ToggleStateManager, results: &LastResultManager) -> Self {
+        let mut summary = Self::default();
+        config.walk_buttons(|button, _| {
+            let name = button.name().to_string();
+            let list = match button {
+                Button::Toggle { .. } => match toggles.get_state(&name) {
+                    ToggleState::On => &mut summary.toggles_on,
+                    ToggleState::Off => &mut summary.toggles_off,
+                    ToggleState::Unknown => return,
+                },
+                _ if button.is_stateful() && results.get(&name).is_some_and(|outcome| !outcome.success) => {
+                    &mut summary.failed
+                }
+                _ => return,
+            };
+            // A button placed in several menus is listed once
+            if !list.contains(&name) {
+                list.push(name);
+            }
+        });
+        summary
+    }
+}
+
+/// Fills in the placeholders of a notification's title or body: `{toggles_on}`,
+/// `{toggles_off}` and `{failed}` become comma-separated button names ("none" if
+/// there are none), and `${var:NAME}` the runtime variable.
+pub fn render_notification_text(template: &str, summary: &ActivitySummary, vars: &RuntimeVars) -> String {
+    let names = |names: &[String]| match names {
+        [] => "none".to_string(),
+        names => names.join(", "),
+    };
+    let text = template
+        .replace("{toggles_on}", &names(&summary.toggles_on))
+        .replace("{toggles_off}", &names(&summary.toggles_off))
+        .replace("{failed}", &names(&summary.failed));
+    let context = ExpandContext { vars: Some(vars), ..Default::default() };
+    // Only secrets can fail to expand, and they are not read here
+    expand_all(&text, &context).unwrap_or(text)
+}
+
+/// A notifier for tests that records what it shows, or fails like a desktop without a
+/// notification daemon
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockNotifier {
+    shown: std::sync::Mutex<Vec<DesktopNotification>>,
+    no_daemon: bool,
+}
+
+#[cfg(test)]
+impl MockNotifier {
+    /// A notifier whose every notification fails
+    pub fn without_daemon() -> Self {
+        Self {
+            no_daemon: true,
+            ..Default::default()
+        }
+    }
+
+    /// Every notification shown, oldest first
+    pub fn shown(&self) -> Vec<DesktopNotification> {
+        self.shown.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Notifier for MockNotifier {
+    async fn notify(&self, notification: &DesktopNotification) -> Result<(), CommanderError> {
+        if self.no_daemon {
+            return Err(CommanderError::Notification("no notification daemon".to_string()));
+        }
+        self.shown.lock().unwrap().push(notification.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::button::CommanderContext;
+    use crate::last_result::CommandOutcome;
+
+    fn summary() -> ActivitySummary {
+        ActivitySummary {
+            toggles_on: vec!["VPN".to_string(), "Fan".to_string()],
+            toggles_off: vec![],
+            failed: vec!["Backup".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_render_notification_text() {
+        let vars = RuntimeVars::new();
+        vars.set("host", "nas");
+        assert_eq!(
+            render_notification_text("On: {toggles_on}; off: {toggles_off}", &summary(), &vars),
+            "On: VPN, Fan; off: none"
+        );
+        assert_eq!(
+            render_notification_text("{failed} failed on ${var:host}", &summary(), &vars),
+            "Backup failed on nas"
+        );
+        // Unknown placeholders are kept as written
+        assert_eq!(render_notification_text("{input} {state}", &summary(), &vars), "{input} {state}");
+    }
+
+    #[test]
+    fn test_collect_activity_summary() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "VPN"
+      mode: single
+      command: "vpnctl"
+    - type: toggle
+      name: "Fan"
+      mode: single
+      command: "fanctl"
+    - type: toggle
+      name: "Lamp"
+      mode: single
+      command: "lampctl"
+    - type: command
+      name: "Backup"
+      command: "backup"
+    - type: menu
+      name: "More"
+      buttons:
+        - type: command
+          name: "Sync"
+          command: "sync"
+        - type: toggle
+          name: "VPN"
+          mode: single
+          command: "vpnctl"
+"#,
+        )
+        .unwrap();
+        let toggles = ToggleStateManager::new();
+        toggles.set_state("VPN", ToggleState::On);
+        toggles.set_state("Fan", ToggleState::Off);
+        let results = LastResultManager::new();
+        results.record("Backup", CommandOutcome::from_exit_code(1));
+        results.record("Sync", CommandOutcome::from_exit_code(0));
+
+        let summary = ActivitySummary::collect(&config, &toggles, &results);
+        assert_eq!(summary.toggles_on, vec!["VPN"]);
+        assert_eq!(summary.toggles_off, vec!["Fan"]);
+        assert_eq!(summary.failed, vec!["Backup"]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_notifier_without_daemon() {
+        let notification = DesktopNotification {
+            title: "Deck".to_string(),
+            body: String::new(),
+            icon: None,
+        };
+        let notifier = MockNotifier::default();
+        notifier.notify(&notification).await.unwrap();
+        assert_eq!(notifier.shown(), vec![notification.clone()]);
+
+        let missing = MockNotifier::without_daemon();
+        assert!(matches!(missing.notify(&notification).await, Err(CommanderError::Notification(_))));
+        assert!(missing.shown().is_empty());
+    }
+
+    fn notification_context(notifier: std::sync::Arc<MockNotifier>) -> CommanderContext {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "VPN"
+      mode: single
+      command: "true"
+    - type: notification
+      name: "Status"
+      title: "Deck status"
+      body: "On: {toggles_on}"
+      notification_icon: "dialog-information"
+"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        CommanderContext {
+            notifier,
+            ..CommanderContext::new(std::sync::Arc::new(config), None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notification_button_fills_in_activity() {
+        let notifier = std::sync::Arc::new(MockNotifier::default());
+        let context = notification_context(notifier.clone());
+        context.toggle_state_manager.set_state("VPN", ToggleState::On);
+        let mut events = context.events.subscribe();
+
+        assert!(context.send_notification("Status", "Deck status", "On: {toggles_on}", Some("dialog-information")).await);
+        let shown = notifier.shown();
+        assert_eq!(shown.len(), 1);
+        assert_eq!((shown[0].title.as_str(), shown[0].body.as_str()), ("Deck status", "On: VPN"));
+        assert_eq!(shown[0].icon.as_deref(), Some("dialog-information"));
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.button.as_str(), event.result.as_deref()), ("Status", Some("success")));
+    }
+
+    #[tokio::test]
+    async fn test_notification_button_without_daemon() {
+        let context = notification_context(std::sync::Arc::new(MockNotifier::without_daemon()));
+        let mut events = context.events.subscribe();
+
+        // A missing daemon is reported as a failed press, not a crash
+        assert!(!context.send_notification("Status", "Deck status", "", None).await);
+        assert_eq!(events.try_recv().unwrap().result.as_deref(), Some("failure"));
+    }
+}
@@ -0,0 +1,329 @@
+use crate::button::CommanderContext;
+use crate::config::{Config, PedalConfig, TriggerOn};
+use crate::deck::DeckDevice;
+use crate::hold_confirm::HoldConfirm;
+use crate::repeat::HeldRepeats;
+use crate::trigger::{KeyEvent, KeyTrigger};
+use std::sync::Arc;
+use std::time::Duration;
+use streamdeck_oxide::elgato_streamdeck::DeviceStateUpdate;
+use tracing::{debug, error, info};
+
+/// How long a pedal read waits for key events before polling again
+const PEDAL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// One of the three keys of a Stream Deck Pedal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PedalKey {
+    Left,
+    Middle,
+    Right,
+}
+
+impl PedalKey {
+    /// Maps the key index reported by the device to a pedal key
+    pub fn from_index(index: u8) -> Option<Self> {
+        match index {
+            0 => Some(PedalKey::Left),
+            1 => Some(PedalKey::Middle),
+            2 => Some(PedalKey::Right),
+            _ => None,
+        }
+    }
+}
+
+impl PedalConfig {
+    /// Name of the button pressed by `key`, if one is configured
+    pub fn button_for(&self, key: PedalKey) -> Option<&str> {
+        match key {
+            PedalKey::Left => self.left.as_deref(),
+            PedalKey::Middle => self.middle.as_deref(),
+            PedalKey::Right => self.right.as_deref(),
+        }
+        .filter(|name| !name.is_empty())
+    }
+}
+
+/// Presses the button mapped to the pedal key at `index`. Returns false if the key
+/// has no button configured or the button could not be pressed.
+pub async fn dispatch_pedal_key(context: &CommanderContext, index: u8) -> bool {
+    let config = context.current_config();
+    let Some(key) = PedalKey::from_index(index) else {
+        debug!("Ignoring unknown pedal key {}", index);
+        return false;
+    };
+    let Some(name) = config.pedal.as_ref().and_then(|pedal| pedal.button_for(key)) else {
+        debug!("No button configured for the {:?} pedal", key);
+        return false;
+    };
+    info!("{:?} pedal pressed, running '{}'", key, name);
+    context.execute_button(name).await
+}
+
+/// Runs the `repeat` command of the button mapped to the pedal key at `index`, once
+/// per interval the key stays held
+pub async fn dispatch_pedal_repeat(context: &CommanderContext, index: u8) -> bool {
+    let config = context.current_config();
+    let Some(name) = PedalKey::from_index(index).and_then(|key| config.pedal.as_ref()?.button_for(key)) else {
+        return false;
+    };
+    debug!("Pedal key {} held, repeating '{}'", index, name);
+    context.repeat_button(name).await
+}
+
+/// How often the pedal key at `index` repeats while held, if the button it is mapped
+/// to has a `repeat`
+pub fn pedal_repeat(config: &Config, index: u8) -> Option<Duration> {
+    let key = PedalKey::from_index(index)?;
+    let name = config.pedal.as_ref()?.button_for(key)?;
+    config.repeat_for(name).map(|repeat| Duration::from_millis(repeat.interval_ms))
+}
+
+/// How long the pedal key at `index` must be held to fire, if the button it is
+/// mapped to sets `confirm_hold_ms`
+pub fn pedal_confirm_hold(config: &Config, index: u8) -> Option<Duration> {
+    let key = PedalKey::from_index(index)?;
+    let name = config.pedal.as_ref()?.button_for(key)?;
+    config.confirm_hold_for(name).map(Duration::from_millis)
+}
+
+/// Whether the pedal key at `index` fires on press or on release, following the
+/// `trigger_on` of the button it is mapped to
+pub fn pedal_trigger(config: &Config, index: u8) -> TriggerOn {
+    PedalKey::from_index(index)
+        .and_then(|key| config.pedal.as_ref()?.button_for(key))
+        .map_or(config.trigger_on, |name| config.trigger_for(name))
+}
+
+/// Reads key presses from a connected pedal and dispatches them until the pedal
+/// disconnects. Keys whose button has a `repeat` re-run it until they are released,
+/// and keys whose button sets `confirm_hold_ms` only fire once held that long.
+pub async fn run_pedal(pedal: Arc<dyn DeckDevice>, context: Arc<CommanderContext>) {
+    let mut trigger = KeyTrigger::new();
+    let mut repeats = HeldRepeats::new();
+    let mut holds = HoldConfirm::new();
+    loop {
+        let updates = match pedal.read_events(Some(PEDAL_POLL_INTERVAL)).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                error!("Stopped reading the pedal: {}", e);
+                return;
+            }
+        };
+        for update in updates {
+            let event = match update {
+                DeviceStateUpdate::ButtonDown(index) => KeyEvent::Down(index),
+                DeviceStateUpdate::ButtonUp(index) => KeyEvent::Up(index),
+                _ => continue,
+            };
+            let config = context.current_config();
+            match event {
+                KeyEvent::Down(index) => {
+                    if let Some(hold) = pedal_confirm_hold(&config, index) {
+                        let context = context.clone();
+                        holds.press(index, hold, async move {
+                            dispatch_pedal_key(&context, index).await;
+                        });
+                    }
+                    if let Some(interval) = pedal_repeat(&config, index) {
+                        let context = context.clone();
+                        repeats.hold(index, interval, move || {
+                            let context = context.clone();
+                            async move {
+                                dispatch_pedal_repeat(&context, index).await;
+                            }
+                        });
+                    }
+                }
+                KeyEvent::Up(index) => {
+                    repeats.release(index);
+                    holds.release(index);
+                }
+            }
+            // Keys held to confirm fire from their hold instead
+            let fired = trigger.handle(event, |index| pedal_trigger(&config, index));
+            if let Some(index) = fired.filter(|index| pedal_confirm_hold(&config, *index).is_none()) {
+                dispatch_pedal_key(&context, index).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Button, Config, RepeatConfig};
+
+    #[test]
+    fn test_parse_pedal_config() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+pedal:
+  left: "Previous"
+  middle: "Play"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Previous"
+      command: "playerctl"
+      args: ["previous"]
+    - type: toggle
+      name: "Play"
+      mode: separate
+      on_command: "playerctl"
+      on_args: ["play"]
+      off_command: "playerctl"
+      off_args: ["pause"]
+"#,
+        )
+        .unwrap();
+        let pedal = config.pedal.clone().unwrap();
+        assert_eq!(pedal.button_for(PedalKey::Left), Some("Previous"));
+        assert_eq!(pedal.button_for(PedalKey::Middle), Some("Play"));
+        assert_eq!(pedal.button_for(PedalKey::Right), None);
+        assert!(config.validate().is_ok());
+
+        let no_pedal: Config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        assert_eq!(no_pedal.pedal, None);
+    }
+
+    #[test]
+    fn test_pedal_must_reference_command_or_toggle() {
+        let mut config: Config = serde_yaml::from_str(
+            "pedal:\n  right: \"Missing\"\nmenu:\n  name: \"Main\"\n  buttons:\n    - type: reload\n",
+        )
+        .unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("pedal references 'Missing'"), "{}", err);
+
+        config.pedal = Some(PedalConfig { right: Some("Reload".to_string()), ..Default::default() });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_pedal_trigger_follows_mapped_button() {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+pedal:
+  left: "Record"
+  middle: "Mute"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Record"
+      command: "obs-cmd"
+      trigger_on: release
+    - type: toggle
+      name: "Mute"
+      mode: single
+      command: "pactl"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.trigger_on, TriggerOn::Press);
+        assert_eq!(pedal_trigger(&config, 0), TriggerOn::Release);
+        assert_eq!(pedal_trigger(&config, 1), TriggerOn::Press);
+        assert_eq!(pedal_trigger(&config, 2), TriggerOn::Press);
+
+        config.trigger_on = TriggerOn::Release;
+        assert_eq!(pedal_trigger(&config, 1), TriggerOn::Release);
+        assert_eq!(pedal_trigger(&config, 2), TriggerOn::Release);
+    }
+
+    #[test]
+    fn test_pedal_repeat() {
+        let yaml = |interval_ms: u64| {
+            format!(
+                r#"
+pedal:
+  left: "Volume Down"
+  right: "Mute"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Volume Down"
+      command: "pactl"
+      args: ["set-sink-volume", "@DEFAULT_SINK@", "-5%"]
+      repeat:
+        interval_ms: {}
+        command: "pactl"
+        args: ["set-sink-volume", "@DEFAULT_SINK@", "-2%"]
+    - type: command
+      name: "Mute"
+      command: "pactl"
+"#,
+                interval_ms
+            )
+        };
+        let config: Config = serde_yaml::from_str(&yaml(150)).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(pedal_repeat(&config, 0), Some(Duration::from_millis(150)));
+        assert_eq!(config.repeat_for("Volume Down").unwrap().args[2], "-2%");
+        // Buttons without a repeat and unmapped keys fire once
+        assert_eq!(pedal_repeat(&config, 1), None);
+        assert_eq!(pedal_repeat(&config, 2), None);
+
+        let zero: Config = serde_yaml::from_str(&yaml(0)).unwrap();
+        let err = zero.validate().unwrap_err();
+        assert_eq!(err.to_string(), "repeat interval_ms must be greater than 0: 'Volume Down'");
+    }
+
+    #[test]
+    fn test_pedal_confirm_hold() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+pedal:
+  left: "Reboot"
+  middle: "VPN"
+  right: "Mute"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Reboot"
+      command: "systemctl"
+      args: ["reboot"]
+      confirm_hold_ms: 2000
+    - type: toggle
+      name: "VPN"
+      mode: single
+      command: "vpn"
+      confirm_hold_ms: 500
+    - type: command
+      name: "Mute"
+      command: "pactl"
+"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(pedal_confirm_hold(&config, 0), Some(Duration::from_secs(2)));
+        assert_eq!(pedal_confirm_hold(&config, 1), Some(Duration::from_millis(500)));
+        assert_eq!(pedal_confirm_hold(&config, 2), None);
+
+        let mut zero = config.clone();
+        if let Button::Command { confirm_hold_ms, .. } = &mut zero.menu.buttons[0] {
+            *confirm_hold_ms = Some(0);
+        }
+        assert_eq!(zero.validate().unwrap_err().to_string(), "confirm_hold_ms must be greater than 0: 'Reboot'");
+
+        let mut repeated = config.clone();
+        if let Button::Command { repeat, .. } = &mut repeated.menu.buttons[0] {
+            *repeat = Some(RepeatConfig { interval_ms: 100, command: "true".to_string(), args: vec![] });
+        }
+        assert_eq!(
+            repeated.validate().unwrap_err().to_string(),
+            "confirm_hold_ms cannot be combined with repeat: 'Reboot'"
+        );
+    }
+
+    #[test]
+    fn test_pedal_key_from_index() {
+        assert_eq!(PedalKey::from_index(0), Some(PedalKey::Left));
+        assert_eq!(PedalKey::from_index(1), Some(PedalKey::Middle));
+        assert_eq!(PedalKey::from_index(2), Some(PedalKey::Right));
+        assert_eq!(PedalKey::from_index(3), None);
+    }
+}
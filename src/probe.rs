@@ -1,6 +1,14 @@
+use crate::config::{Button, HttpProbeConfig, ProbeCombine, ProbeSpec, ProbeVote};
+use crate::http_probe::HTTP_BACKEND;
+use crate::spawn::{next_run_id, SpawnConfig};
+use crate::toggle_state::ToggleState;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::process::Stdio;
+use std::time::{Duration, Instant};
 use tokio::process::Command;
-use tracing::{debug, error, info, warn};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn, Instrument};
 
 /// Result of a probe command execution
 #[derive(Debug, Clone)]
@@ -58,27 +66,228 @@ impl ProbeResult {
     }
 }
 
+/// Working directory, environment and shell a probe is spawned with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeContext {
+    pub cwd: Option<PathBuf>,
+    pub env: BTreeMap<String, String>,
+    /// Run the probe command as an `sh -c` script, with its args as positional parameters
+    pub shell: bool,
+    /// Name of the probe backend that checks the toggle, `None` to run its probe command
+    pub backend: Option<String>,
+    /// Endpoint checked by the `http` probe backend
+    pub http: Option<HttpProbeConfig>,
+    /// Probes run together instead of the probe command
+    pub all: Vec<ProbeSpec>,
+    /// How the results of `all` are combined
+    pub combine: ProbeCombine,
+    /// Times the probe runs per check; 0 and 1 both run it once
+    pub samples: u32,
+    /// How the results of the samples are combined
+    pub vote: ProbeVote,
+    /// Probes running longer are stopped and count as `timeout_state`
+    pub timeout_ms: Option<u64>,
+    /// State a probe that times out stands for
+    pub timeout_state: ToggleState,
+    /// Output text meaning On, whatever the exit code
+    pub success_indicators: Vec<String>,
+    /// Output text meaning Off, winning over the success indicators
+    pub failure_indicators: Vec<String>,
+    /// Also look for the indicators in stderr, scanned after stdout
+    pub check_stderr: bool,
+}
+
+impl Default for ProbeContext {
+    fn default() -> Self {
+        Self {
+            cwd: None,
+            env: BTreeMap::new(),
+            shell: false,
+            backend: None,
+            http: None,
+            all: Vec::new(),
+            combine: ProbeCombine::default(),
+            samples: 1,
+            vote: ProbeVote::default(),
+            timeout_ms: None,
+            timeout_state: ToggleState::Unknown,
+            success_indicators: Vec::new(),
+            failure_indicators: Vec::new(),
+            check_stderr: false,
+        }
+    }
+}
+
+impl ProbeContext {
+    /// Builds the probe context from a toggle's `probe_*` settings
+    pub fn from_button(button: &Button) -> Self {
+        match button {
+            Button::Toggle {
+                probe_cwd,
+                probe_env,
+                probe_shell,
+                probe_backend,
+                probe_http,
+                probe_all,
+                combine,
+                probe_samples,
+                probe_vote,
+                probe_timeout_ms,
+                timeout_state,
+                success_indicators,
+                failure_indicators,
+                check_stderr,
+                ..
+            } => Self {
+                cwd: probe_cwd.clone(),
+                env: probe_env.clone(),
+                shell: *probe_shell,
+                // A `probe_http` block selects the http backend unless another is named
+                backend: probe_backend
+                    .clone()
+                    .or_else(|| probe_http.as_ref().map(|_| HTTP_BACKEND.to_string())),
+                http: probe_http.clone(),
+                all: probe_all.clone(),
+                combine: *combine,
+                samples: *probe_samples,
+                vote: *probe_vote,
+                timeout_ms: *probe_timeout_ms,
+                timeout_state: timeout_state.unwrap_or(ToggleState::Unknown),
+                success_indicators: success_indicators.clone(),
+                failure_indicators: failure_indicators.clone(),
+                check_stderr: *check_stderr,
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Returns the program and arguments to spawn for a probe
+    pub fn argv(&self, command: &str, args: &[String]) -> (String, Vec<String>) {
+        if self.shell {
+            let script = ["-c", command, "sh"].into_iter().map(String::from);
+            ("sh".to_string(), script.chain(args.iter().cloned()).collect())
+        } else {
+            (command.to_string(), args.to_vec())
+        }
+    }
+
+    /// Creates the probe `Command` with the spawn settings and this context applied
+    pub fn command(&self, spawn: &SpawnConfig, command: &str, args: &[String]) -> Command {
+        let (program, args) = self.argv(command, args);
+        let mut cmd = spawn.command(&program, &args);
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+        cmd.envs(&self.env);
+        // A probe that times out is dropped, which must not leave it running
+        cmd.kill_on_drop(true);
+        cmd
+    }
+
+    /// Looks for the indicators in a probe's output: `Some(false)` if a failure
+    /// indicator is found, else `Some(true)` if a success indicator is, `None` if
+    /// neither. With `check_stderr` stderr is scanned after stdout, and a failure
+    /// indicator in either stream still wins over a success indicator in the other.
+    pub fn match_indicators(&self, stdout: &str, stderr: &str) -> Option<bool> {
+        let streams: &[&str] = if self.check_stderr { &[stdout, stderr] } else { &[stdout] };
+        let found = |indicators: &[String]| {
+            streams
+                .iter()
+                .any(|stream| indicators.iter().any(|indicator| stream.contains(indicator.as_str())))
+        };
+        if found(&self.failure_indicators) {
+            Some(false)
+        } else if found(&self.success_indicators) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// Result of a probe stopped after `timeout_ms`, standing for `timeout_state`
+    fn timed_out(&self, button_name: &str, command: &str, timeout_ms: u64) -> ProbeResult {
+        warn!("Probe command for '{}' timed out after {}ms: {} (counts as {:?})", button_name, timeout_ms, command, self.timeout_state);
+        let message = format!("Command timed out after {}ms", timeout_ms);
+        match self.timeout_state {
+            ToggleState::On => ProbeResult::success(0, String::new(), message),
+            ToggleState::Off => ProbeResult::failure(Some(TIMEOUT_EXIT_CODE), String::new(), message),
+            ToggleState::Unknown => ProbeResult::execution_error(message),
+        }
+    }
+}
+
+/// Warns that the probe of `button_name` took `elapsed` if that is longer than the
+/// spawn settings' `slow_probe_warn_ms`
+pub fn warn_if_slow_probe(button_name: &str, command: &str, elapsed: Duration, spawn: &SpawnConfig) {
+    let Some(limit) = spawn.slow_probe_warn_ms else {
+        return;
+    };
+    if elapsed > Duration::from_millis(limit) {
+        warn!(
+            "Slow probe for '{}': {} took {}ms (slow_probe_warn_ms: {})",
+            button_name,
+            command,
+            elapsed.as_millis(),
+            limit
+        );
+    }
+}
+
 /// Executes a probe command to determine the current state of a toggle
 pub async fn execute_probe_command(
     command: &str,
     args: &[String],
     button_name: &str,
 ) -> ProbeResult {
-    info!("Executing probe command for '{}': {} {:?}", button_name, command, args);
+    execute_probe_command_with_spawn(command, args, button_name, &SpawnConfig::default()).await
+}
+
+/// Executes a probe command with the given process settings applied
+pub async fn execute_probe_command_with_spawn(
+    command: &str,
+    args: &[String],
+    button_name: &str,
+    spawn: &SpawnConfig,
+) -> ProbeResult {
+    execute_probe_command_in(command, args, button_name, spawn, &ProbeContext::default()).await
+}
+
+/// Executes a probe command with the given process settings and probe context applied
+#[tracing::instrument(name = "probe", skip_all, fields(run = %next_run_id(), button = button_name))]
+pub async fn execute_probe_command_in(
+    command: &str,
+    args: &[String],
+    button_name: &str,
+    spawn: &SpawnConfig,
+    context: &ProbeContext,
+) -> ProbeResult {
+    let (program, program_args) = context.argv(command, args);
+    info!("Executing probe command for '{}': {:?}", button_name, spawn.argv(&program, &program_args));
 
-    let mut cmd = Command::new(command);
-    cmd.args(args)
-        .stdout(Stdio::piped())
+    let mut cmd = context.command(spawn, command, args);
+    cmd.stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .stdin(Stdio::null()); // Ensure no interactive input
 
-    match cmd.output().await {
+    let started = Instant::now();
+    let output = match context.timeout_ms {
+        Some(timeout_ms) => match tokio::time::timeout(Duration::from_millis(timeout_ms), cmd.output()).await {
+            Ok(output) => output,
+            Err(_) => return context.timed_out(button_name, command, timeout_ms),
+        },
+        None => cmd.output().await,
+    };
+    warn_if_slow_probe(button_name, command, started.elapsed(), spawn);
+    match output {
         Ok(output) => {
             let exit_code = output.status.code();
             let stdout = String::from_utf8_lossy(&output.stdout).to_string();
             let stderr = String::from_utf8_lossy(&output.stderr).to_string();
 
-            let success = output.status.success();
+            // Indicators found in the output decide over the exit code
+            let success = context
+                .match_indicators(&stdout, &stderr)
+                .unwrap_or(output.status.success());
             
             debug!(
                 "Probe command for '{}' completed: exit_code={:?}, success={}, stdout_len={}, stderr_len={}",
@@ -94,7 +303,8 @@ pub async fn execute_probe_command(
             }
 
             if success {
-                ProbeResult::success(exit_code.unwrap_or(0), stdout, stderr)
+                // On by an indicator counts as a clean exit, whatever the exit code was
+                ProbeResult::success(0, stdout, stderr)
             } else {
                 ProbeResult::failure(exit_code, stdout, stderr)
             }
@@ -107,6 +317,79 @@ pub async fn execute_probe_command(
     }
 }
 
+/// Runs the context's `all` probes concurrently, each with the context's working
+/// directory, environment and shell, and combines their results
+pub async fn execute_combined_probe(button_name: &str, spawn: &SpawnConfig, context: &ProbeContext) -> ProbeResult {
+    let mut probes = JoinSet::new();
+    for (index, probe) in context.all.iter().enumerate() {
+        let (probe, button_name, spawn, context) = (probe.clone(), button_name.to_string(), spawn.clone(), context.clone());
+        probes.spawn(
+            async move { (index, execute_probe_command_in(&probe.command, &probe.args, &button_name, &spawn, &context).await) }
+                .in_current_span(),
+        );
+    }
+
+    let mut results = vec![None; context.all.len()];
+    while let Some(joined) = probes.join_next().await {
+        match joined {
+            Ok((index, result)) => results[index] = Some(result),
+            Err(e) => return ProbeResult::execution_error(format!("Probe of '{}' panicked: {}", button_name, e)),
+        }
+    }
+    combine_probe_results(results.into_iter().flatten().collect(), context.combine)
+}
+
+/// Combines the results of several probes into one: successful when all (or any) of
+/// them succeeded. A probe that could not be executed makes the whole result an
+/// execution error, so the toggle's state is Unknown. Outputs are joined in order.
+pub fn combine_probe_results(results: Vec<ProbeResult>, combine: ProbeCombine) -> ProbeResult {
+    if let Some(error) = results.iter().find(|result| result.is_execution_error()) {
+        return error.clone();
+    }
+    let on = match combine {
+        ProbeCombine::All => results.iter().all(ProbeResult::is_success),
+        ProbeCombine::Any => results.iter().any(ProbeResult::is_success),
+    };
+    let stdout = results.iter().map(|result| result.stdout.as_str()).collect();
+    let stderr = results.iter().map(|result| result.stderr.as_str()).collect();
+    if on {
+        ProbeResult::success(0, stdout, stderr)
+    } else {
+        let exit_code = results.iter().find(|result| !result.is_success()).and_then(|result| result.exit_code);
+        ProbeResult::failure(exit_code, stdout, stderr)
+    }
+}
+
+/// Combines the results of the samples of one check of a flaky probe by `vote`.
+/// Samples that could not be executed do not vote; if none could, the check is an
+/// execution error. Outputs are those of the last sample that voted.
+pub fn vote_probe_results(results: Vec<ProbeResult>, vote: ProbeVote) -> ProbeResult {
+    let votes: Vec<&ProbeResult> = results.iter().filter(|result| !result.is_execution_error()).collect();
+    let Some(last) = votes.last() else {
+        return results
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| ProbeResult::execution_error("Probe was not run".to_string()));
+    };
+    let successes = votes.iter().filter(|result| result.is_success()).count();
+    let on = match vote {
+        ProbeVote::Majority => successes * 2 > votes.len(),
+        ProbeVote::All => successes == votes.len(),
+        ProbeVote::Any => successes > 0,
+    };
+    debug!("{} of {} probe samples succeeded, {:?} vote is {}", successes, votes.len(), vote, if on { "On" } else { "Off" });
+    let (stdout, stderr) = (last.stdout.clone(), last.stderr.clone());
+    if on {
+        ProbeResult::success(0, stdout, stderr)
+    } else {
+        let exit_code = votes.iter().rev().find(|result| !result.is_success()).and_then(|result| result.exit_code);
+        ProbeResult::failure(exit_code, stdout, stderr)
+    }
+}
+
+/// Exit code reported for a timed-out probe that counts as Off, as `timeout(1)` uses
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
 /// Configuration for probe behavior
 #[derive(Debug, Clone)]
 pub struct ProbeConfig {
@@ -114,10 +397,11 @@ pub struct ProbeConfig {
     pub timeout_ms: u64,
     /// Whether to consider empty stdout as success or failure
     pub empty_stdout_is_success: bool,
-    /// Custom success indicators in stdout (if any of these are found, consider success)
-    pub success_indicators: Vec<String>,
-    /// Custom failure indicators in stdout (if any of these are found, consider failure)  
-    pub failure_indicators: Vec<String>,
+    /// Process settings applied when spawning the probe
+    pub spawn: SpawnConfig,
+    /// Working directory, environment, shell, indicators and timeout state of the probe;
+    /// its `timeout_ms` is replaced by the one above
+    pub context: ProbeContext,
 }
 
 impl Default for ProbeConfig {
@@ -125,8 +409,8 @@ impl Default for ProbeConfig {
         Self {
             timeout_ms: 5000, // 5 seconds default timeout
             empty_stdout_is_success: true,
-            success_indicators: Vec::new(),
-            failure_indicators: Vec::new(),
+            spawn: SpawnConfig::default(),
+            context: ProbeContext::default(),
         }
     }
 }
@@ -139,20 +423,25 @@ pub async fn execute_probe_command_with_config(
     config: &ProbeConfig,
 ) -> ProbeResult {
     info!(
-        "Executing probe command with config for '{}': {} {:?} (timeout: {}ms)",
-        button_name, command, args, config.timeout_ms
+        "Executing probe command with config for '{}': {:?} (timeout: {}ms)",
+        button_name, config.spawn.argv(command, args), config.timeout_ms
     );
 
-    let mut cmd = Command::new(command);
-    cmd.args(args)
-        .stdout(Stdio::piped())
+    let mut cmd = config.context.command(&config.spawn, command, args);
+    cmd.stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .stdin(Stdio::null());
 
     // Use tokio timeout for command execution
     let timeout_duration = std::time::Duration::from_millis(config.timeout_ms);
     
-    match tokio::time::timeout(timeout_duration, cmd.output()).await {
+    let started = Instant::now();
+    let output = tokio::time::timeout(timeout_duration, cmd.output()).await;
+    // A timed-out probe gets its own warning below
+    if output.is_ok() {
+        warn_if_slow_probe(button_name, command, started.elapsed(), &config.spawn);
+    }
+    match output {
         Ok(Ok(output)) => {
             let exit_code = output.status.code();
             let stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -161,7 +450,7 @@ pub async fn execute_probe_command_with_config(
             let exit_success = output.status.success();
             
             // Apply custom success/failure logic
-            let custom_success = evaluate_custom_indicators(&stdout, config);
+            let custom_success = evaluate_custom_indicators(&stdout, &stderr, config);
             let final_success = match custom_success {
                 Some(success) => success,
                 None => exit_success,
@@ -173,7 +462,7 @@ pub async fn execute_probe_command_with_config(
             );
 
             if final_success {
-                ProbeResult::success(exit_code.unwrap_or(0), stdout, stderr)
+                ProbeResult::success(0, stdout, stderr)
             } else {
                 ProbeResult::failure(exit_code, stdout, stderr)
             }
@@ -183,32 +472,20 @@ pub async fn execute_probe_command_with_config(
                    button_name, command, args, e);
             ProbeResult::execution_error(format!("Command execution failed: {}", e))
         }
-        Err(_) => {
-            warn!("Probe command for '{}' timed out after {}ms: {} {:?}", 
-                  button_name, config.timeout_ms, command, args);
-            ProbeResult::execution_error(format!("Command timed out after {}ms", config.timeout_ms))
-        }
+        Err(_) => config.context.timed_out(button_name, command, config.timeout_ms),
     }
 }
 
-/// Evaluates custom success/failure indicators in command output
-fn evaluate_custom_indicators(stdout: &str, config: &ProbeConfig) -> Option<bool> {
-    // Check failure indicators first (they take precedence)
-    for indicator in &config.failure_indicators {
-        if stdout.contains(indicator) {
-            return Some(false);
-        }
-    }
-
-    // Check success indicators
-    for indicator in &config.success_indicators {
-        if stdout.contains(indicator) {
-            return Some(true);
-        }
+/// Evaluates the context's success/failure indicators in command output, falling
+/// back to `empty_stdout_is_success` when the scanned output is empty
+fn evaluate_custom_indicators(stdout: &str, stderr: &str, config: &ProbeConfig) -> Option<bool> {
+    if let Some(success) = config.context.match_indicators(stdout, stderr) {
+        return Some(success);
     }
 
-    // Handle empty stdout case
-    if stdout.trim().is_empty() {
+    // Handle empty output case
+    let streams: &[&str] = if config.context.check_stderr { &[stdout, stderr] } else { &[stdout] };
+    if streams.iter().all(|stream| stream.trim().is_empty()) {
         return Some(config.empty_stdout_is_success);
     }
 
@@ -219,6 +496,7 @@ fn evaluate_custom_indicators(stdout: &str, config: &ProbeConfig) -> Option<bool
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::TempDir;
 
     #[test]
     fn test_probe_result_creation() {
@@ -241,30 +519,77 @@ mod tests {
     #[test]
     fn test_evaluate_custom_indicators() {
         let mut config = ProbeConfig::default();
-        config.success_indicators = vec!["enabled".to_string(), "active".to_string()];
-        config.failure_indicators = vec!["disabled".to_string(), "inactive".to_string()];
+        config.context.success_indicators = vec!["enabled".to_string(), "active".to_string()];
+        config.context.failure_indicators = vec!["disabled".to_string(), "inactive".to_string()];
 
         // Test success indicators
-        assert_eq!(evaluate_custom_indicators("Service is enabled", &config), Some(true));
-        assert_eq!(evaluate_custom_indicators("Status: active", &config), Some(true));
+        assert_eq!(evaluate_custom_indicators("Service is enabled", "", &config), Some(true));
+        assert_eq!(evaluate_custom_indicators("Status: active", "", &config), Some(true));
 
         // Test failure indicators (should take precedence)
-        assert_eq!(evaluate_custom_indicators("Service is disabled", &config), Some(false));
-        assert_eq!(evaluate_custom_indicators("Status: inactive", &config), Some(false));
+        assert_eq!(evaluate_custom_indicators("Service is disabled", "", &config), Some(false));
+        assert_eq!(evaluate_custom_indicators("Status: inactive", "", &config), Some(false));
 
         // Test mixed (failure takes precedence)
-        assert_eq!(evaluate_custom_indicators("Service enabled but disabled", &config), Some(false));
+        assert_eq!(evaluate_custom_indicators("Service enabled but disabled", "", &config), Some(false));
 
         // Test no indicators
-        assert_eq!(evaluate_custom_indicators("unknown status", &config), None);
+        assert_eq!(evaluate_custom_indicators("unknown status", "", &config), None);
 
         // Test empty stdout
         config.empty_stdout_is_success = true;
-        assert_eq!(evaluate_custom_indicators("", &config), Some(true));
-        assert_eq!(evaluate_custom_indicators("   ", &config), Some(true));
+        assert_eq!(evaluate_custom_indicators("", "", &config), Some(true));
+        assert_eq!(evaluate_custom_indicators("   ", "", &config), Some(true));
 
         config.empty_stdout_is_success = false;
-        assert_eq!(evaluate_custom_indicators("", &config), Some(false));
+        assert_eq!(evaluate_custom_indicators("", "", &config), Some(false));
+    }
+
+    #[test]
+    fn test_evaluate_indicators_in_stderr() {
+        let mut config = ProbeConfig {
+            context: ProbeContext {
+                success_indicators: vec!["active (running)".to_string()],
+                failure_indicators: vec!["inactive".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Without check_stderr only stdout counts
+        assert_eq!(evaluate_custom_indicators("status", "active (running)", &config), None);
+        assert_eq!(evaluate_custom_indicators("status", "inactive", &config), None);
+
+        config.context.check_stderr = true;
+        assert_eq!(evaluate_custom_indicators("status", "active (running)", &config), Some(true));
+        assert_eq!(evaluate_custom_indicators("status", "inactive", &config), Some(false));
+        // A failure in stderr still beats a success in stdout
+        assert_eq!(evaluate_custom_indicators("active (running)", "inactive", &config), Some(false));
+        // Output on stderr alone is not empty
+        config.empty_stdout_is_success = false;
+        assert_eq!(evaluate_custom_indicators("", "warning: unit changed", &config), None);
+        assert_eq!(evaluate_custom_indicators("", " ", &config), Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_probe_checks_stderr() {
+        let probe = |check_stderr| {
+            let config = ProbeConfig {
+                context: ProbeContext {
+                    failure_indicators: vec!["inactive".to_string()],
+                    check_stderr,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            async move {
+                let args = ["-c".to_string(), "echo checking; echo inactive >&2".to_string()];
+                execute_probe_command_with_config("sh", &args, "test-button", &config).await
+            }
+        };
+
+        assert!(probe(false).await.is_success());
+        assert!(probe(true).await.is_command_failure());
     }
 
     #[tokio::test]
@@ -316,4 +641,293 @@ mod tests {
         assert!(result.is_execution_error());
         assert!(result.stderr.contains("timed out"));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_probe_timeout_state() {
+        async fn probe(timeout_state: ToggleState) -> ProbeResult {
+            let config = ProbeConfig {
+                timeout_ms: 50,
+                context: ProbeContext { timeout_state, ..Default::default() },
+                ..Default::default()
+            };
+            execute_probe_command_with_config("sleep", &["1".to_string()], "test-button", &config).await
+        }
+
+        let off = probe(ToggleState::Off).await;
+        assert!(off.is_command_failure());
+        assert_eq!(off.exit_code, Some(TIMEOUT_EXIT_CODE));
+        assert!(off.stderr.contains("timed out"));
+
+        assert!(probe(ToggleState::On).await.is_success());
+        assert!(probe(ToggleState::Unknown).await.is_execution_error());
+    }
+
+    #[tokio::test]
+    async fn test_probe_context_timeout() {
+        let spawn = SpawnConfig::default();
+        let context = ProbeContext { timeout_ms: Some(50), timeout_state: ToggleState::Off, ..Default::default() };
+        let result = execute_probe_command_in("sleep", &["1".to_string()], "test-button", &spawn, &context).await;
+        assert!(result.is_command_failure());
+        assert_eq!(result.exit_code, Some(TIMEOUT_EXIT_CODE));
+
+        let context = ProbeContext { timeout_ms: Some(5000), timeout_state: ToggleState::Off, ..Default::default() };
+        assert!(execute_probe_command_in("true", &[], "test-button", &spawn, &context).await.is_success());
+    }
+
+    #[tokio::test]
+    async fn test_probe_context_indicators() {
+        let spawn = SpawnConfig::default();
+        let args = ["-c".to_string(), "echo checking; echo inactive >&2".to_string()];
+        let mut context = ProbeContext { failure_indicators: vec!["inactive".to_string()], ..Default::default() };
+        assert!(execute_probe_command_in("sh", &args, "test-button", &spawn, &context).await.is_success());
+        context.check_stderr = true;
+        assert!(execute_probe_command_in("sh", &args, "test-button", &spawn, &context).await.is_command_failure());
+
+        // A success indicator overrides a failing exit code
+        let args = ["-c".to_string(), "echo degraded; exit 3".to_string()];
+        let context = ProbeContext { success_indicators: vec!["degraded".to_string()], ..Default::default() };
+        assert!(execute_probe_command_in("sh", &args, "test-button", &spawn, &context).await.is_success());
+    }
+
+    #[tokio::test]
+    async fn test_probe_context_cwd_and_env() {
+        let dir = TempDir::new("probe-cwd");
+        std::fs::write(dir.join("marker"), "").unwrap();
+
+        let context = ProbeContext {
+            cwd: Some(dir.path().to_path_buf()),
+            env: BTreeMap::from([("PROBE_MODE".to_string(), "on".to_string())]),
+            shell: false,
+            ..Default::default()
+        };
+        let spawn = SpawnConfig::default();
+
+        // Relative paths resolve against the probe's working directory
+        let args = ["-f".to_string(), "marker".to_string()];
+        assert!(execute_probe_command_in("test", &args, "test-button", &spawn, &context).await.is_success());
+        assert!(execute_probe_command_in("test", &args, "test-button", &spawn, &ProbeContext::default())
+            .await
+            .is_command_failure());
+
+        let result = execute_probe_command_in("printenv", &["PROBE_MODE".to_string()], "test-button", &spawn, &context).await;
+        assert_eq!(result.stdout.trim(), "on");
+    }
+
+    #[tokio::test]
+    async fn test_combined_probe() {
+        let button: Button = serde_yaml::from_str(
+            r#"
+type: toggle
+name: "Services"
+mode: single
+command: "true"
+probe_all:
+  - command: "true"
+  - command: "false"
+"#,
+        )
+        .unwrap();
+        let mut context = ProbeContext::from_button(&button);
+        assert_eq!(context.combine, ProbeCombine::All);
+        let spawn = SpawnConfig::default();
+
+        let result = execute_combined_probe("Services", &spawn, &context).await;
+        assert!(result.is_command_failure());
+        assert_eq!(result.exit_code, Some(1));
+
+        context.combine = ProbeCombine::Any;
+        let result = execute_combined_probe("Services", &spawn, &context).await;
+        assert!(result.is_success());
+
+        // A probe that cannot run leaves the whole state unknown
+        context.all.push(ProbeSpec { command: "nonexistent_command_xyz".to_string(), args: vec![] });
+        let result = execute_combined_probe("Services", &spawn, &context).await;
+        assert!(result.is_execution_error());
+    }
+
+    #[tokio::test]
+    async fn test_probe_context_shell_expansion() {
+        let dir = TempDir::new("probe-home");
+        std::fs::write(dir.join("file"), "").unwrap();
+
+        let mut context = ProbeContext {
+            env: BTreeMap::from([("HOME".to_string(), dir.path().to_string_lossy().into_owned())]),
+            shell: true,
+            ..Default::default()
+        };
+        let spawn = SpawnConfig::default();
+
+        // `~` is expanded by the shell, args become positional parameters
+        assert!(execute_probe_command_in("test -f ~/file", &[], "test-button", &spawn, &context).await.is_success());
+        assert!(execute_probe_command_in("test -f ~/missing", &[], "test-button", &spawn, &context)
+            .await
+            .is_command_failure());
+        let result = execute_probe_command_in("echo \"$1-$2\"", &["a".to_string(), "b".to_string()], "test-button", &spawn, &context).await;
+        assert_eq!(result.stdout.trim(), "a-b");
+
+        // Without the shell the script is treated as a program name
+        context.shell = false;
+        assert!(execute_probe_command_in("test -f ~/file", &[], "test-button", &spawn, &context)
+            .await
+            .is_execution_error());
+    }
+
+    #[test]
+    fn test_probe_context_from_button() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Backup"
+      mode: single
+      command: "true"
+      probe_command: "test -f ~/.backup-enabled"
+      probe_shell: true
+      probe_cwd: "/tmp"
+      probe_env: { LANG: "C" }
+    - type: toggle
+      name: "Plain"
+      mode: single
+      command: "true"
+"#,
+        )
+        .unwrap();
+
+        let context = ProbeContext::from_button(&config.menu.buttons[0]);
+        assert!(context.shell);
+        assert_eq!(context.cwd, Some(PathBuf::from("/tmp")));
+        assert_eq!(context.env.get("LANG").map(String::as_str), Some("C"));
+        assert_eq!(ProbeContext::from_button(&config.menu.buttons[1]), ProbeContext::default());
+    }
+
+    /// Records the `run` field of the innermost span of every event
+    #[derive(Clone, Default)]
+    struct RunIds(std::sync::Arc<std::sync::Mutex<Vec<Option<String>>>>);
+
+    struct RunId(String);
+
+    struct RunVisitor(Option<String>);
+
+    impl tracing::field::Visit for RunVisitor {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "run" {
+                self.0 = Some(format!("{:?}", value));
+            }
+        }
+    }
+
+    impl<S> tracing_subscriber::Layer<S> for RunIds
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+            let mut visitor = RunVisitor(None);
+            attrs.record(&mut visitor);
+            if let (Some(run), Some(span)) = (visitor.0, ctx.span(id)) {
+                span.extensions_mut().insert(RunId(run));
+            }
+        }
+
+        fn on_event(&self, event: &tracing::Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+            let run = ctx
+                .event_scope(event)
+                .and_then(|mut scope| scope.find_map(|span| span.extensions().get::<RunId>().map(|run| run.0.clone())));
+            self.0.lock().unwrap().push(run);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_logs_share_run_id() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let runs = RunIds::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(runs.clone()));
+
+        let args = vec!["-c".to_string(), "echo out; echo err >&2".to_string()];
+        execute_probe_command_with_spawn("sh", &args, "first", &SpawnConfig::default()).await;
+        let first = std::mem::take(&mut *runs.0.lock().unwrap());
+        execute_probe_command_with_spawn("sh", &args, "second", &SpawnConfig::default()).await;
+        let second = std::mem::take(&mut *runs.0.lock().unwrap());
+
+        // Spawn, completion, stdout and stderr are all logged inside the run's span
+        assert!(first.len() >= 4, "{:?}", first);
+        assert!(first.iter().all(|run| run.is_some() && *run == first[0]), "{:?}", first);
+        assert!(second.iter().all(|run| *run == second[0]), "{:?}", second);
+        assert_ne!(first[0], second[0]);
+    }
+
+    /// Records the message of every warning
+    #[derive(Clone, Default)]
+    struct Warnings(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    struct MessageVisitor(String);
+
+    impl tracing::field::Visit for MessageVisitor {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.0 = format!("{:?}", value);
+            }
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Warnings {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+            if *event.metadata().level() == tracing::Level::WARN {
+                let mut visitor = MessageVisitor(String::new());
+                event.record(&mut visitor);
+                self.0.lock().unwrap().push(visitor.0);
+            }
+        }
+    }
+
+    /// Runs `run` and returns the warnings it logged
+    async fn logged_warnings(run: impl std::future::Future) -> Vec<String> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let warnings = Warnings::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(warnings.clone()));
+        run.await;
+        let logged = warnings.0.lock().unwrap().clone();
+        logged
+    }
+
+    /// Probes `sleep` for `seconds` with a 50ms slow-probe threshold
+    async fn sleep_probe(seconds: &str, with_config: bool) {
+        let spawn = SpawnConfig {
+            slow_probe_warn_ms: Some(50),
+            ..Default::default()
+        };
+        let args = vec![seconds.to_string()];
+        let result = if with_config {
+            let config = ProbeConfig { spawn, ..Default::default() };
+            execute_probe_command_with_config("sleep", &args, "Backup", &config).await
+        } else {
+            execute_probe_command_with_spawn("sleep", &args, "Backup", &spawn).await
+        };
+        assert!(result.is_success());
+    }
+
+    #[tokio::test]
+    async fn test_slow_probe_is_logged() {
+        let prefix = "Slow probe for 'Backup': sleep took ";
+        for with_config in [false, true] {
+            let warnings = logged_warnings(sleep_probe("0.2", with_config)).await;
+            assert_eq!(warnings.len(), 1, "{:?}", warnings);
+            let took = warnings[0].strip_prefix(prefix).and_then(|rest| rest.split_once("ms")).unwrap().0;
+            assert!(took.parse::<u64>().unwrap() >= 200, "{}", warnings[0]);
+            assert!(warnings[0].ends_with("(slow_probe_warn_ms: 50)"), "{}", warnings[0]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fast_probe_is_not_logged() {
+        for with_config in [false, true] {
+            assert_eq!(logged_warnings(sleep_probe("0", with_config)).await, Vec::<String>::new());
+        }
+        // Without a threshold even a slow probe is not reported
+        let unset = async { warn_if_slow_probe("Backup", "sleep", Duration::from_secs(60), &SpawnConfig::default()) };
+        assert!(logged_warnings(unset).await.is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::http_probe::{HttpProbe, HTTP_BACKEND};
+use crate::probe::{execute_combined_probe, execute_probe_command_in, vote_probe_results, ProbeContext, ProbeResult};
+use crate::spawn::SpawnConfig;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, warn};
+
+/// Name of the built-in backend that runs the toggle's `probe_command`
+pub const PROCESS_BACKEND: &str = "process";
+
+/// Everything a probe backend gets to check a toggle's state
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeRequest<'a> {
+    pub button_name: &'a str,
+    /// The toggle's `probe_command`, if it has one
+    pub command: Option<&'a str>,
+    pub args: &'a [String],
+    pub spawn: &'a SpawnConfig,
+    pub context: &'a ProbeContext,
+}
+
+/// Detects a toggle's state. A successful result means On, a result with an exit code
+/// means Off and an execution error means Unknown, as for probe commands.
+#[async_trait]
+pub trait ProbeBackend: Send + Sync {
+    async fn probe(&self, request: &ProbeRequest<'_>) -> ProbeResult;
+}
+
+/// Runs the toggle's `probe_command` as a process
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessProbe;
+
+#[async_trait]
+impl ProbeBackend for ProcessProbe {
+    async fn probe(&self, request: &ProbeRequest<'_>) -> ProbeResult {
+        match request.command {
+            Some(command) => {
+                execute_probe_command_in(command, request.args, request.button_name, request.spawn, request.context).await
+            }
+            None => ProbeResult::execution_error(format!("Toggle '{}' has no probe_command", request.button_name)),
+        }
+    }
+}
+
+/// Probe backends toggles can select with `probe_backend`, keyed by name
+pub struct ProbeBackendRegistry {
+    backends: Arc<RwLock<HashMap<String, Arc<dyn ProbeBackend>>>>,
+}
+
+impl Clone for ProbeBackendRegistry {
+    fn clone(&self) -> Self {
+        Self {
+            backends: Arc::clone(&self.backends),
+        }
+    }
+}
+
+impl Default for ProbeBackendRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ProbeBackendRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProbeBackendRegistry").field("backends", &self.names()).finish()
+    }
+}
+
+impl ProbeBackendRegistry {
+    /// Creates a registry holding the built-in backends
+    pub fn new() -> Self {
+        let registry = Self {
+            backends: Arc::new(RwLock::new(HashMap::new())),
+        };
+        registry.register(PROCESS_BACKEND, ProcessProbe);
+        registry.register(HTTP_BACKEND, HttpProbe);
+        registry
+    }
+
+    /// Registers `backend` under `name`, replacing any backend registered before
+    pub fn register(&self, name: &str, backend: impl ProbeBackend + 'static) {
+        match self.backends.write() {
+            Ok(mut backends) => {
+                debug!("Registered probe backend '{}'", name);
+                backends.insert(name.to_string(), Arc::new(backend));
+            }
+            Err(e) => warn!("Failed to register probe backend '{}': {}", name, e),
+        }
+    }
+
+    /// Returns the backend registered under `name`
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ProbeBackend>> {
+        match self.backends.read() {
+            Ok(backends) => backends.get(name).cloned(),
+            Err(e) => {
+                warn!("Failed to read probe backends: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Names of the registered backends, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .backends
+            .read()
+            .map(|backends| backends.keys().cloned().collect())
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    /// Probes a toggle with the backend its context selects, or runs its probe command
+    /// (or its `probe_all` probes) when it selects none. With `samples` above 1 the probe
+    /// runs that many times one after another and the results are combined by `vote`.
+    /// Returns `None` for toggles with nothing to probe.
+    pub async fn probe(&self, request: &ProbeRequest<'_>) -> Option<ProbeResult> {
+        let samples = request.context.samples.max(1);
+        if samples == 1 {
+            return self.probe_once(request).await;
+        }
+        let mut results = Vec::new();
+        for _ in 0..samples {
+            results.push(self.probe_once(request).await?);
+        }
+        debug!("Sampled the probe of '{}' {} times", request.button_name, samples);
+        Some(vote_probe_results(results, request.context.vote))
+    }
+
+    async fn probe_once(&self, request: &ProbeRequest<'_>) -> Option<ProbeResult> {
+        if request.context.backend.is_none() && !request.context.all.is_empty() {
+            return Some(execute_combined_probe(request.button_name, request.spawn, request.context).await);
+        }
+        let name = match (&request.context.backend, request.command) {
+            (Some(name), _) => name.as_str(),
+            (None, Some(_)) => PROCESS_BACKEND,
+            (None, None) => return None,
+        };
+        match self.get(name) {
+            Some(backend) => Some(backend.probe(request).await),
+            None => {
+                warn!("Toggle '{}' uses unknown probe backend '{}'", request.button_name, name);
+                Some(ProbeResult::execution_error(format!("Unknown probe backend '{}'", name)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeVote;
+
+    struct FixedProbe(bool);
+
+    #[async_trait]
+    impl ProbeBackend for FixedProbe {
+        async fn probe(&self, request: &ProbeRequest<'_>) -> ProbeResult {
+            if self.0 {
+                ProbeResult::success(0, request.button_name.to_string(), String::new())
+            } else {
+                ProbeResult::failure(Some(1), String::new(), String::new())
+            }
+        }
+    }
+
+    /// Answers with the next of `results` on every probe, true meaning success
+    struct SequenceProbe(std::sync::Mutex<std::collections::VecDeque<bool>>);
+
+    impl SequenceProbe {
+        fn new(results: &[bool]) -> Self {
+            Self(std::sync::Mutex::new(results.iter().copied().collect()))
+        }
+    }
+
+    #[async_trait]
+    impl ProbeBackend for SequenceProbe {
+        async fn probe(&self, _request: &ProbeRequest<'_>) -> ProbeResult {
+            match self.0.lock().unwrap().pop_front() {
+                Some(true) => ProbeResult::success(0, String::new(), String::new()),
+                Some(false) => ProbeResult::failure(Some(1), String::new(), String::new()),
+                None => ProbeResult::execution_error("no more results".to_string()),
+            }
+        }
+    }
+
+    fn request<'a>(command: Option<&'a str>, context: &'a ProbeContext, spawn: &'a SpawnConfig) -> ProbeRequest<'a> {
+        ProbeRequest {
+            button_name: "Service",
+            command,
+            args: &[],
+            spawn,
+            context,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_selects_backend() {
+        let registry = ProbeBackendRegistry::new();
+        registry.register("always_on", FixedProbe(true));
+        assert_eq!(registry.names(), vec!["always_on", "http", "process"]);
+        let spawn = SpawnConfig::default();
+
+        // Without a backend, the probe command decides and no command means no probe
+        let process = ProbeContext::default();
+        assert!(registry.probe(&request(Some("true"), &process, &spawn)).await.unwrap().is_success());
+        assert!(registry.probe(&request(Some("false"), &process, &spawn)).await.unwrap().is_command_failure());
+        assert!(registry.probe(&request(None, &process, &spawn)).await.is_none());
+
+        let custom = ProbeContext { backend: Some("always_on".to_string()), ..Default::default() };
+        let result = registry.probe(&request(Some("false"), &custom, &spawn)).await.unwrap();
+        assert!(result.is_success());
+        assert_eq!(result.stdout, "Service");
+
+        let unknown = ProbeContext { backend: Some("carrier_pigeon".to_string()), ..Default::default() };
+        assert!(registry.probe(&request(None, &unknown, &spawn)).await.unwrap().is_execution_error());
+    }
+
+    /// Checks a toggle whose probe answers `results` in turn, `samples` times combined by `vote`
+    async fn sample(results: &[bool], samples: u32, vote: ProbeVote) -> ProbeResult {
+        let registry = ProbeBackendRegistry::new();
+        registry.register("flaky", SequenceProbe::new(results));
+        let context = ProbeContext { backend: Some("flaky".to_string()), samples, vote, ..Default::default() };
+        registry.probe(&request(None, &context, &SpawnConfig::default())).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sampled_probe_votes() {
+        let flaky = [true, false, true];
+        let outcome = |vote| sample(&flaky, 3, vote);
+
+        assert!(outcome(ProbeVote::Majority).await.is_success());
+        let all = outcome(ProbeVote::All).await;
+        assert!(all.is_command_failure());
+        assert_eq!(all.exit_code, Some(1));
+        assert!(outcome(ProbeVote::Any).await.is_success());
+
+        // A single sample, the default, runs the probe once
+        assert!(sample(&[false, true, true], 1, ProbeVote::Majority).await.is_command_failure());
+    }
+
+    #[test]
+    fn test_vote_ignores_samples_that_did_not_run() {
+        let error = || ProbeResult::execution_error("spawn failed".to_string());
+        let on = || ProbeResult::success(0, "up".to_string(), String::new());
+        let off = || ProbeResult::failure(Some(3), String::new(), String::new());
+
+        assert!(vote_probe_results(vec![on(), error(), error()], ProbeVote::Majority).is_success());
+        assert!(vote_probe_results(vec![on(), off(), error()], ProbeVote::Majority).is_command_failure());
+        assert_eq!(vote_probe_results(vec![on(), off()], ProbeVote::All).exit_code, Some(3));
+        assert!(vote_probe_results(vec![error(), error()], ProbeVote::Any).is_execution_error());
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+
+/// A queued press: the worker signals `start` when it is the press's turn, then waits
+/// for `done` to close before starting the next one
+type QueuedPress = (oneshot::Sender<()>, oneshot::Receiver<()>);
+
+/// Per-button FIFO queues for buttons with `queue: true`. Presses of one button run
+/// one after another in the order they came in, while different buttons run in
+/// parallel. Each button gets its worker task on its first press.
+#[derive(Debug, Clone, Default)]
+pub struct CommandQueues {
+    workers: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<QueuedPress>>>>,
+}
+
+/// The running press of a queued button; the next press starts once this is dropped
+#[derive(Debug)]
+pub struct QueueTurn {
+    _done: oneshot::Sender<()>,
+}
+
+impl CommandQueues {
+    /// Creates queues with no worker running
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a press of the button right away and resolves once every earlier press
+    /// of it has finished. The press holds its turn until the returned guard is dropped.
+    pub fn turn(&self, button_name: &str) -> impl Future<Output = QueueTurn> + Send + 'static {
+        let (start, started) = oneshot::channel();
+        let (done, finished) = oneshot::channel();
+        let mut workers = self.workers.lock().unwrap_or_else(PoisonError::into_inner);
+        let unsent = match workers.get(button_name) {
+            Some(worker) => match worker.send((start, finished)) {
+                Ok(()) => None,
+                Err(mpsc::error::SendError(press)) => {
+                    warn!("Queue worker of '{}' stopped, starting a new one", button_name);
+                    Some(press)
+                }
+            },
+            None => Some((start, finished)),
+        };
+        if let Some(press) = unsent {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            tokio::spawn(run_worker(button_name.to_string(), receiver));
+            // The worker was just spawned with its receiver, so it cannot be gone yet
+            let _ = sender.send(press);
+            workers.insert(button_name.to_string(), sender);
+        }
+        let name = button_name.to_string();
+        async move {
+            if started.await.is_err() {
+                warn!("Queue of '{}' dropped a press, running it anyway", name);
+            }
+            QueueTurn { _done: done }
+        }
+    }
+}
+
+/// Lets queued presses of one button run one at a time, in order
+async fn run_worker(button_name: String, mut presses: mpsc::UnboundedReceiver<QueuedPress>) {
+    debug!("Started the queue of '{}'", button_name);
+    while let Some((start, done)) = presses.recv().await {
+        // A press whose caller went away is skipped
+        if start.send(()).is_ok() {
+            let _ = done.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    /// Start and end of each press: button, press number, event and time
+    type PressLog = Arc<Mutex<Vec<(&'static str, usize, &'static str, Instant)>>>;
+
+    /// Queues a press that records when it starts and ends, taking `duration`
+    fn recorded_press(
+        queues: &CommandQueues,
+        button_name: &'static str,
+        press: usize,
+        duration: Duration,
+        log: &PressLog,
+    ) -> tokio::task::JoinHandle<()> {
+        let turn = queues.turn(button_name);
+        let log = log.clone();
+        tokio::spawn(async move {
+            let _turn = turn.await;
+            log.lock().unwrap().push((button_name, press, "start", Instant::now()));
+            tokio::time::sleep(duration).await;
+            log.lock().unwrap().push((button_name, press, "end", Instant::now()));
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_presses_of_one_button_run_in_order() {
+        let queues = CommandQueues::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        // Later presses are shorter, so they would finish first if they overlapped
+        let presses: Vec<_> = (1..=3)
+            .map(|press| recorded_press(&queues, "Deploy", press, Duration::from_secs(4 - press as u64), &log))
+            .collect();
+        for press in presses {
+            press.await.unwrap();
+        }
+
+        let events: Vec<_> = log.lock().unwrap().iter().map(|(_, press, event, _)| (*press, *event)).collect();
+        assert_eq!(
+            events,
+            vec![(1, "start"), (1, "end"), (2, "start"), (2, "end"), (3, "start"), (3, "end")]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_different_buttons_run_in_parallel() {
+        let queues = CommandQueues::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let started = Instant::now();
+        let deploy = recorded_press(&queues, "Deploy", 1, Duration::from_secs(10), &log);
+        let backup = recorded_press(&queues, "Backup", 1, Duration::from_secs(10), &log);
+        deploy.await.unwrap();
+        backup.await.unwrap();
+
+        let log = log.lock().unwrap();
+        let ends: Vec<_> = log.iter().filter(|(_, _, event, _)| *event == "end").collect();
+        assert_eq!(ends.len(), 2);
+        // Neither waited for the other
+        assert!(ends.iter().all(|(_, _, _, at)| at.duration_since(started) < Duration::from_secs(11)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dropped_press_does_not_block_the_queue() {
+        let queues = CommandQueues::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let first = queues.turn("Deploy").await;
+        drop(queues.turn("Deploy"));
+        let next = recorded_press(&queues, "Deploy", 3, Duration::from_secs(1), &log);
+        drop(first);
+        next.await.unwrap();
+        assert_eq!(log.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::button::CommanderContext;
+use crate::config::{RenderSettings, ThemeName, ThemeRule};
+use crate::toggle_state::{StateChange, ToggleStateManager};
+use streamdeck_oxide::button::RenderConfig;
+use streamdeck_oxide::theme::Theme;
+use tokio::sync::broadcast;
+
+/// Smallest accepted `render.font_size`
+pub const MIN_RENDER_FONT_SIZE: f32 = 6.0;
+/// Largest accepted `render.font_size`
+pub const MAX_RENDER_FONT_SIZE: f32 = 48.0;
+
+/// Maps the `render:` block onto the renderer's settings, keeping the renderer's
+/// defaults for everything that is not set
+pub fn render_config_from(settings: &RenderSettings) -> RenderConfig {
+    let mut config = RenderConfig::default();
+    if let Some(font_size) = settings.font_size {
+        config.font_size = font_size;
+    }
+    config
+}
+
+/// Builds the color theme selected in the `render:` block
+pub fn theme_from(settings: &RenderSettings) -> Theme {
+    match settings.theme {
+        ThemeName::Light => Theme::light(),
+        ThemeName::Dark => Theme::dark(),
+    }
+}
+
+/// The theme of the first `theme_when` rule whose toggle is in its state, or `default`
+/// if none matches. Evaluated on every render, so the theme follows the toggles.
+pub fn select_theme(rules: &[ThemeRule], states: &ToggleStateManager, default: ThemeName) -> ThemeName {
+    rules
+        .iter()
+        .find(|rule| states.get_state(&rule.toggle) == rule.state)
+        .map_or(default, |rule| rule.theme)
+}
+
+/// Waits for toggle `changes` until the deck theme of `ctx` is no longer `current`
+/// and returns the new one; `None` once the toggle states are gone
+pub async fn next_theme(ctx: &CommanderContext, changes: &mut broadcast::Receiver<StateChange>, current: ThemeName) -> Option<ThemeName> {
+    loop {
+        match changes.recv().await {
+            // Missed changes still leave the states to look at
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                let theme = ctx.deck_theme();
+                if theme != current {
+                    return Some(theme);
+                }
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toggle_state::ToggleState;
+
+    #[test]
+    fn test_render_config_defaults_preserved() {
+        let config = render_config_from(&RenderSettings::default());
+        assert_eq!(config.font_size, RenderConfig::default().font_size);
+    }
+
+    #[test]
+    fn test_render_config_font_size() {
+        let settings = RenderSettings {
+            font_size: Some(12.0),
+            ..Default::default()
+        };
+        assert_eq!(render_config_from(&settings).font_size, 12.0);
+    }
+
+    fn rule(toggle: &str, state: ToggleState, theme: ThemeName) -> ThemeRule {
+        ThemeRule {
+            toggle: toggle.to_string(),
+            state,
+            theme,
+        }
+    }
+
+    #[test]
+    fn test_select_theme() {
+        let states = ToggleStateManager::new();
+        let rules = vec![
+            rule("VPN", ToggleState::On, ThemeName::Dark),
+            rule("Night Light", ToggleState::On, ThemeName::Light),
+            rule("Wi-Fi", ToggleState::Off, ThemeName::Dark),
+        ];
+
+        // No rule matches toggles that were never probed
+        assert_eq!(select_theme(&rules, &states, ThemeName::Light), ThemeName::Light);
+        assert_eq!(select_theme(&[], &states, ThemeName::Dark), ThemeName::Dark);
+
+        states.set_state("Wi-Fi", ToggleState::Off);
+        assert_eq!(select_theme(&rules, &states, ThemeName::Light), ThemeName::Dark);
+        states.set_state("Wi-Fi", ToggleState::On);
+        assert_eq!(select_theme(&rules, &states, ThemeName::Light), ThemeName::Light);
+
+        // With several rules matching, the first one listed wins
+        states.set_state("Night Light", ToggleState::On);
+        states.set_state("Wi-Fi", ToggleState::Off);
+        assert_eq!(select_theme(&rules, &states, ThemeName::Dark), ThemeName::Light);
+        states.set_state("VPN", ToggleState::On);
+        assert_eq!(select_theme(&rules, &states, ThemeName::Light), ThemeName::Dark);
+    }
+
+    #[tokio::test]
+    async fn test_next_theme_follows_toggle_changes() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+theme_when:
+  - { toggle: "VPN", state: on, theme: dark }
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "VPN"
+      mode: single
+      command: "true"
+    - type: toggle
+      name: "Wifi"
+      mode: single
+      command: "true"
+"#,
+        )
+        .unwrap();
+        let ctx = CommanderContext::new(std::sync::Arc::new(config), None);
+        let mut changes = ctx.toggle_state_manager.subscribe();
+        assert_eq!(ctx.deck_theme(), ThemeName::Light);
+
+        // Changes that keep the theme are skipped
+        ctx.toggle_state_manager.set_state("Wifi", ToggleState::On);
+        ctx.toggle_state_manager.set_state("VPN", ToggleState::On);
+        assert_eq!(next_theme(&ctx, &mut changes, ThemeName::Light).await, Some(ThemeName::Dark));
+
+        ctx.toggle_state_manager.set_state("VPN", ToggleState::Off);
+        assert_eq!(next_theme(&ctx, &mut changes, ThemeName::Dark).await, Some(ThemeName::Light));
+    }
+}
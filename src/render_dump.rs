@@ -0,0 +1,250 @@
+use crate::button::{menu_layout, BackButtonStyle, CommanderContext, DISABLED_ICON};
+use crate::config::{Button, RenderSettings};
+use crate::error::CommanderError;
+use crate::grid::{GridSize, DECK_GRID};
+use crate::icon_layout::IconLayout;
+use crate::icons;
+use crate::render::theme_from;
+use crate::toggle_icons::{get_toggle_label_localized, resolve_button_icon, resolve_command_icon};
+use crate::toggle_state::ToggleState;
+use chrono::Local;
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use streamdeck_oxide::theme::{Color, Theme};
+use tracing::debug;
+
+/// Side of a key in a render dump, in pixels, as on a Stream Deck MK.2
+pub const DUMP_KEY_SIZE: u32 = 72;
+/// Space between the keys of a render dump, in pixels
+pub const DUMP_KEY_GAP: u32 = 8;
+/// Color of the deck's frame around and between the keys
+const FRAME_COLOR: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// Background and foreground colors a key is drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyColors {
+    pub background: (u8, u8, u8),
+    pub foreground: (u8, u8, u8),
+}
+
+impl KeyColors {
+    /// Picks the theme colors used for a key in the given toggle state
+    pub fn from_theme(theme: &Theme, state: Option<ToggleState>) -> Self {
+        let (background, foreground) = match state {
+            Some(ToggleState::On) => (theme.active_background, theme.active_foreground_color),
+            Some(ToggleState::Off) => (theme.inactive_background, theme.foreground_color),
+            _ => (theme.background, theme.foreground_color),
+        };
+        Self {
+            background: rgb(background),
+            foreground: rgb(foreground),
+        }
+    }
+}
+
+fn rgb(color: Color) -> (u8, u8, u8) {
+    (color.0, color.1, color.2)
+}
+
+/// One key of the view to mirror: its label, colors and the icon drawn on it
+#[derive(Debug, Clone)]
+pub struct DumpKey {
+    pub col: usize,
+    pub row: usize,
+    pub label: String,
+    pub colors: KeyColors,
+    pub icon: Option<&'static str>,
+}
+
+/// Lays out key images on `grid`, `gap` pixels apart on the deck's frame color.
+/// Images of another size are scaled to `key_size`; keys outside the grid are cut off.
+pub fn compose_deck(grid: GridSize, key_size: u32, gap: u32, keys: &[(usize, usize, RgbaImage)]) -> RgbaImage {
+    let span = |count: usize| count as u32 * key_size + (count as u32).saturating_sub(1) * gap;
+    let mut deck = RgbaImage::from_pixel(span(grid.columns), span(grid.rows), FRAME_COLOR);
+    for (col, row, image) in keys {
+        let origin = |index: usize| i64::from(index as u32 * (key_size + gap));
+        if image.dimensions() == (key_size, key_size) {
+            imageops::replace(&mut deck, image, origin(*col), origin(*row));
+        } else {
+            let scaled = imageops::resize(image, key_size, key_size, FilterType::Triangle);
+            imageops::replace(&mut deck, &scaled, origin(*col), origin(*row));
+        }
+    }
+    deck
+}
+
+/// System fonts for the labels, loaded once
+fn fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut fonts = usvg::fontdb::Database::new();
+            fonts.load_system_fonts();
+            debug!("Loaded {} fonts for render dumps", fonts.len());
+            Arc::new(fonts)
+        })
+        .clone()
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Draws a key: its background color, the icon in the foreground color above the
+/// label, or the label alone in the middle of a text-only key
+pub fn render_key(key: &DumpKey, size: u32) -> Result<RgbaImage, CommanderError> {
+    let lines: Vec<&str> = key.label.lines().collect();
+    let line_height = 13.0;
+    let first_line = match key.icon {
+        Some(_) => 92.0 - line_height * lines.len().saturating_sub(1) as f32,
+        None => 55.0 - line_height * lines.len().saturating_sub(1) as f32 / 2.0,
+    };
+    let label: String = lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| format!("<tspan x=\"50\" y=\"{}\">{}</tspan>", first_line + line_height * index as f32, escape(line)))
+        .collect();
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" viewBox=\"0 0 100 100\">\
+         <rect width=\"100\" height=\"100\" fill=\"{background}\"/>\
+         <g fill=\"{foreground}\" transform=\"translate(20 4) scale(0.6)\">{icon}</g>\
+         <text font-family=\"sans-serif\" font-size=\"12\" text-anchor=\"middle\" fill=\"{foreground}\">{label}</text></svg>",
+        background = hex(key.colors.background),
+        foreground = hex(key.colors.foreground),
+        icon = key.icon.map(|icon| IconLayout::default().transform_svg(icon)).unwrap_or_default(),
+    );
+    let options = usvg::Options {
+        fontdb: fonts(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_str(&svg, &options).map_err(|e| CommanderError::Render(format!("key '{}': {}", key.label, e)))?;
+    let mut pixmap = tiny_skia::Pixmap::new(size, size).ok_or_else(|| CommanderError::Render(format!("key size {}", size)))?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    // The background is opaque, so the premultiplied pixels are plain RGBA
+    RgbaImage::from_raw(size, size, pixmap.take()).ok_or_else(|| CommanderError::Render(format!("key '{}'", key.label)))
+}
+
+/// The keys of the menu shown on the deck, with the labels, icons and theme colors it
+/// shows them with, including the back button of a submenu
+pub fn active_view_keys(ctx: &CommanderContext) -> Vec<DumpKey> {
+    let config = ctx.current_config();
+    let plugin = ctx.active_plugin();
+    let theme = theme_from(&RenderSettings {
+        theme: ctx.theme_for(&plugin),
+        ..config.render.clone()
+    });
+    let locale = config.locale.as_deref();
+    let menu = plugin.shown_menu(Some(ctx));
+    let now = Local::now().naive_local();
+    let mut keys: Vec<DumpKey> = menu_layout(&menu)
+        .into_iter()
+        .map(|(col, row, button)| {
+            let state = matches!(button, Button::Toggle { .. }).then(|| ctx.toggle_state_manager.get_state(button.name()));
+            let icon = match button {
+                _ if !button.is_active_at(now) => icons::resolve_icon(Some(&DISABLED_ICON.to_string())),
+                Button::Command { .. } => resolve_command_icon(button, &ctx.last_result_manager),
+                _ => resolve_button_icon(button, &ctx.toggle_state_manager),
+            };
+            DumpKey {
+                col,
+                row,
+                label: get_toggle_label_localized(button, &ctx.toggle_state_manager, locale),
+                colors: KeyColors::from_theme(&theme, state),
+                icon,
+            }
+        })
+        .collect();
+    if plugin.parent().is_some() {
+        let (col, row) = DECK_GRID.back_button_position();
+        let back = BackButtonStyle::from_config(&config);
+        let icon = icons::resolve_icon(Some(&back.icon));
+        keys.push(DumpKey {
+            col,
+            row,
+            label: back.label,
+            colors: KeyColors::from_theme(&theme, None),
+            icon,
+        });
+    }
+    keys
+}
+
+/// Writes the menu shown on the deck to `path` as a PNG of the whole key grid
+pub fn dump_active_view(ctx: &CommanderContext, path: &Path) -> Result<(), CommanderError> {
+    let keys = active_view_keys(ctx)
+        .iter()
+        .map(|key| Ok((key.col, key.row, render_key(key, DUMP_KEY_SIZE)?)))
+        .collect::<Result<Vec<_>, CommanderError>>()?;
+    compose_deck(DECK_GRID, DUMP_KEY_SIZE, DUMP_KEY_GAP, &keys)
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| CommanderError::RenderDump(format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(color: [u8; 4], size: u32) -> RgbaImage {
+        RgbaImage::from_pixel(size, size, Rgba(color))
+    }
+
+    fn dump_key(label: &str, background: (u8, u8, u8)) -> DumpKey {
+        DumpKey {
+            col: 0,
+            row: 0,
+            label: label.to_string(),
+            colors: KeyColors {
+                background,
+                foreground: (255, 255, 255),
+            },
+            icon: None,
+        }
+    }
+
+    #[test]
+    fn test_compose_deck_places_keys_on_grid() {
+        let grid = GridSize { columns: 5, rows: 3 };
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let keys = vec![(0, 0, solid(red, 10)), (4, 2, solid(blue, 10)), (2, 1, solid(blue, 20))];
+        let deck = compose_deck(grid, 10, 2, &keys);
+        assert_eq!(deck.dimensions(), (5 * 10 + 4 * 2, 3 * 10 + 2 * 2));
+
+        assert_eq!(deck.get_pixel(0, 0).0, red);
+        assert_eq!(deck.get_pixel(9, 9).0, red);
+        // The gaps and missing keys show the frame
+        assert_eq!(deck.get_pixel(10, 0).0, FRAME_COLOR.0);
+        assert_eq!(deck.get_pixel(12, 0).0, FRAME_COLOR.0);
+        // The last key fills the bottom right corner, and larger keys are scaled down
+        assert_eq!(deck.get_pixel(57, 33).0, blue);
+        assert_eq!(deck.get_pixel(48, 24).0, blue);
+        assert_eq!(deck.get_pixel(24, 12).0, blue);
+        assert_eq!(deck.get_pixel(33, 21).0, blue);
+        assert_eq!(deck.get_pixel(34, 22).0, FRAME_COLOR.0);
+
+        assert_eq!(compose_deck(GridSize { columns: 1, rows: 1 }, DUMP_KEY_SIZE, DUMP_KEY_GAP, &[]).dimensions(), (72, 72));
+    }
+
+    #[test]
+    fn test_render_key() {
+        let key = dump_key("", (0, 128, 0));
+        let image = render_key(&key, 16).unwrap();
+        assert_eq!(image.dimensions(), (16, 16));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 128, 0, 255]);
+        assert_eq!(image.get_pixel(15, 15).0, [0, 128, 0, 255]);
+
+        // The icon is drawn in the foreground color over the background
+        let icon = "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 24 24\"><path d=\"M0 0h24v24H0z\"/></svg>";
+        let with_icon = DumpKey { icon: Some(icon), ..key };
+        let image = render_key(&with_icon, 100).unwrap();
+        assert_eq!(image.get_pixel(50, 30).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(2, 2).0, [0, 128, 0, 255]);
+    }
+}
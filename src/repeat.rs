@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::debug;
+
+/// Repeating runs of the keys currently held down, keyed by key index
+#[derive(Debug, Default)]
+pub struct HeldRepeats {
+    held: HashMap<u8, AbortHandle>,
+}
+
+impl HeldRepeats {
+    /// Creates a tracker with no key held
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts running `run` every `interval` until the key is released. The press itself
+    /// runs the button's action, so the first repeat comes one interval after it.
+    /// A run that takes longer than the interval delays the next one instead of piling up.
+    pub fn hold<F, Fut>(&mut self, key: u8, interval: Duration, run: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        self.release(key);
+        let task = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                run().await;
+            }
+        });
+        debug!("Key {} held, repeating every {:?}", key, interval);
+        self.held.insert(key, task.abort_handle());
+    }
+
+    /// Stops repeating the key. Returns false if it was not repeating.
+    pub fn release(&mut self, key: u8) -> bool {
+        match self.held.remove(&key) {
+            Some(task) => {
+                task.abort();
+                debug!("Key {} released, stopped repeating", key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true while the key repeats
+    pub fn is_held(&self, key: u8) -> bool {
+        self.held.contains_key(&key)
+    }
+}
+
+impl Drop for HeldRepeats {
+    fn drop(&mut self) {
+        for task in self.held.values() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn counting_run(runs: &Arc<AtomicUsize>) -> impl Fn() -> std::future::Ready<()> + Send + 'static {
+        let runs = runs.clone();
+        move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_repeats_while_held() {
+        let mut repeats = HeldRepeats::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        repeats.hold(0, Duration::from_millis(200), counting_run(&runs));
+        assert!(repeats.is_held(0));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+        // Held for just over a second: runs at 200, 400, 600, 800 and 1000 ms
+        tokio::time::sleep(Duration::from_millis(950)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_release_stops_promptly() {
+        let mut repeats = HeldRepeats::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        repeats.hold(2, Duration::from_millis(200), counting_run(&runs));
+        tokio::time::sleep(Duration::from_millis(450)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        // Released just before the next run is due: nothing runs after the release
+        assert!(repeats.release(2));
+        assert!(!repeats.is_held(2));
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert!(!repeats.release(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keys_repeat_independently() {
+        let mut repeats = HeldRepeats::new();
+        let left = Arc::new(AtomicUsize::new(0));
+        let right = Arc::new(AtomicUsize::new(0));
+
+        repeats.hold(0, Duration::from_millis(100), counting_run(&left));
+        repeats.hold(2, Duration::from_millis(300), counting_run(&right));
+        tokio::time::sleep(Duration::from_millis(650)).await;
+        repeats.release(0);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(left.load(Ordering::SeqCst), 6);
+        assert_eq!(right.load(Ordering::SeqCst), 3);
+
+        drop(repeats);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(right.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::button::{CommanderContext, CommanderPlugin};
+use crate::icons;
+use std::time::Duration;
+use streamdeck_oxide::{
+    generic_array::typenum::{U3, U5},
+    plugins::{Plugin, PluginContext, PluginNavigation},
+    view::{customizable::CustomizableView, View},
+    ExternalTrigger,
+};
+use tokio::sync::mpsc::Sender;
+use tracing::{error, info};
+
+/// How long a transient result stays on the deck before returning
+pub const RESULT_DISPLAY_DURATION: Duration = Duration::from_millis(1500);
+
+/// Sender used to push navigation triggers to the running deck
+pub type NavigationSender = Sender<ExternalTrigger<PluginNavigation<U5, U3>, U5, U3, PluginContext>>;
+
+/// A single-key view that reports the outcome of an action
+#[derive(Clone)]
+pub struct ResultPlugin {
+    message: String,
+    success: bool,
+    next: CommanderPlugin,
+}
+
+impl ResultPlugin {
+    pub fn new(message: impl Into<String>, success: bool, next: CommanderPlugin) -> Self {
+        Self {
+            message: message.into(),
+            success,
+            next,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.success
+    }
+
+    /// Icon shown next to the message
+    pub fn icon_name(&self) -> &'static str {
+        if self.success {
+            "check"
+        } else {
+            "error"
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin<U5, U3> for ResultPlugin {
+    fn name(&self) -> &'static str {
+        "StreamDeck Commander Result"
+    }
+
+    async fn get_view(&self, context: PluginContext) -> Result<Box<dyn View<U5, U3, PluginContext, PluginNavigation<U5, U3>>>, Box<dyn std::error::Error>> {
+        let mut view = CustomizableView::new();
+        let icon_layout = context
+            .get_context::<CommanderContext>()
+            .await
+            .map(|ctx| ctx.icon_layout())
+            .unwrap_or_default();
+
+        // Pressing the message returns immediately instead of waiting for the timeout
+        view.set_navigation(
+            2, // center column
+            1, // center row
+            PluginNavigation::<U5, U3>::new(self.next.clone()),
+            &self.message,
+            icon_layout.apply(icons::resolve_icon(Some(&self.icon_name().to_string()))),
+        )?;
+
+        Ok(Box::new(view))
+    }
+}
+
+/// The last line of a command's output with text on it, trimmed, for `toast_last_line`
+pub fn last_output_line(stdout: &str) -> Option<&str> {
+    stdout.lines().map(str::trim).rfind(|line| !line.is_empty())
+}
+
+/// Shows a result message for `RESULT_DISPLAY_DURATION`, then navigates to `next`
+pub async fn show_transient_result(
+    sender: &NavigationSender,
+    message: impl Into<String>,
+    success: bool,
+    next: CommanderPlugin,
+) {
+    show_transient_result_for(sender, message, success, next, RESULT_DISPLAY_DURATION).await
+}
+
+/// Shows a result message for `duration`, then navigates to `next`
+pub async fn show_transient_result_for(
+    sender: &NavigationSender,
+    message: impl Into<String>,
+    success: bool,
+    next: CommanderPlugin,
+    duration: Duration,
+) {
+    let message = message.into();
+    info!("Showing transient result: {} (success: {})", message, success);
+
+    let result_trigger = ExternalTrigger::new(
+        PluginNavigation::<U5, U3>::new(ResultPlugin::new(message, success, next.clone())),
+        false,
+    );
+    if let Err(e) = sender.send(result_trigger).await {
+        error!("Failed to send result view trigger: {}", e);
+        return;
+    }
+
+    tokio::time::sleep(duration).await;
+
+    let next_trigger = ExternalTrigger::new(PluginNavigation::<U5, U3>::new(next), false);
+    if let Err(e) = sender.send(next_trigger).await {
+        error!("Failed to send navigation trigger after result view: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Menu;
+
+    fn next_plugin() -> CommanderPlugin {
+        CommanderPlugin::new(Menu {
+            name: "Main".to_string(),
+            buttons: vec![],
+            theme: None,
+            sort: Default::default(),
+            background_image: None,
+            dynamic: None,
+        })
+    }
+
+    #[test]
+    fn test_result_plugin_icon() {
+        let ok = ResultPlugin::new("Reloaded", true, next_plugin());
+        assert!(ok.is_success());
+        assert_eq!(ok.message(), "Reloaded");
+        assert_eq!(ok.icon_name(), "check");
+
+        let failed = ResultPlugin::new("Reload failed", false, next_plugin());
+        assert!(!failed.is_success());
+        assert_eq!(failed.icon_name(), "error");
+    }
+
+    #[test]
+    fn test_last_output_line() {
+        assert_eq!(last_output_line("Syncing...\n  12 files synced  \n\n   \n"), Some("12 files synced"));
+        assert_eq!(last_output_line("done"), Some("done"));
+        assert_eq!(last_output_line("first\r\nlast\r\n"), Some("last"));
+        assert_eq!(last_output_line(""), None);
+        assert_eq!(last_output_line("\n \n\t\n"), None);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::config::Button;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often a button's command is run again after exiting with a non-zero status
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Extra attempts after the first one
+    pub retries: u32,
+    /// Pause before each extra attempt
+    pub delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Builds the policy from a command or toggle button's `retries` and `retry_delay_ms`
+    pub fn for_button(button: &Button) -> Self {
+        match button {
+            Button::Command { retries, retry_delay_ms, .. } | Button::Toggle { retries, retry_delay_ms, .. } => Self {
+                retries: *retries,
+                delay: Duration::from_millis(*retry_delay_ms),
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Runs `attempt` until `failed` rejects its output or the retries run out and
+    /// returns the last output. Outputs that are not failures, such as a command that
+    /// could not be spawned at all, are returned right away.
+    pub async fn run<T, F, Fut>(&self, button_name: &str, mut attempt: F, failed: impl Fn(&T) -> bool) -> T
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let attempts = self.retries + 1;
+        let mut output = attempt().await;
+        for number in 2..=attempts {
+            if !failed(&output) {
+                break;
+            }
+            warn!(
+                "Command for '{}' failed, retrying in {:?} (attempt {}/{})",
+                button_name, self.delay, number, attempts
+            );
+            tokio::time::sleep(self.delay).await;
+            output = attempt().await;
+            if !failed(&output) {
+                info!("Command for '{}' succeeded on attempt {}/{}", button_name, number, attempts);
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{context_for, TempDir};
+    use crate::toggle_state::ToggleState;
+    use std::cell::Cell;
+
+    /// Runs `policy` against a mock executor that exits with the given codes in turn
+    async fn run_mock(policy: RetryPolicy, exit_codes: &[i32]) -> (i32, usize) {
+        let calls = Cell::new(0);
+        let code = policy
+            .run(
+                "Service",
+                || {
+                    let code = exit_codes[calls.get()];
+                    calls.set(calls.get() + 1);
+                    async move { code }
+                },
+                |code| *code != 0,
+            )
+            .await;
+        (code, calls.get())
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let policy = RetryPolicy { retries: 2, delay: Duration::from_millis(1) };
+        assert_eq!(run_mock(policy, &[1, 0]).await, (0, 2));
+        assert_eq!(run_mock(policy, &[0]).await, (0, 1));
+
+        // Retries run out on the last failure
+        assert_eq!(run_mock(policy, &[1, 2, 3]).await, (3, 3));
+        assert_eq!(run_mock(RetryPolicy::default(), &[1]).await, (1, 1));
+    }
+
+    #[test]
+    fn test_policy_for_button() {
+        let button: Button = serde_yaml::from_str(
+            r#"
+type: command
+name: "Start"
+command: "systemctl"
+retries: 3
+retry_delay_ms: 250
+"#,
+        )
+        .unwrap();
+        assert_eq!(RetryPolicy::for_button(&button), RetryPolicy { retries: 3, delay: Duration::from_millis(250) });
+
+        let button: Button = serde_yaml::from_str("type: back").unwrap();
+        assert_eq!(RetryPolicy::for_button(&button), RetryPolicy::default());
+    }
+
+    #[tokio::test]
+    async fn test_flaky_commands_are_retried() {
+        let dir = TempDir::new("flaky");
+        let marker = dir.join("marker");
+        // Fails the first time it runs, succeeds from then on
+        let flaky = format!("test -f {0} || {{ touch {0}; exit 1; }}", marker.display());
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Service"
+      mode: single
+      command: "sh"
+      args: ["-c", "{flaky}"]
+      retries: 2
+      retry_delay_ms: 10
+    - type: toggle
+      name: "No Retry"
+      mode: single
+      command: "sh"
+      args: ["-c", "{flaky}"]
+    - type: command
+      name: "Start"
+      command: "sh"
+      args: ["-c", "{flaky}"]
+      retries: 1
+"#
+        ))
+        .unwrap();
+        let context = context_for(config);
+        context.toggle_state_manager.set_state("Service", ToggleState::Off);
+        context.toggle_state_manager.set_state("No Retry", ToggleState::Off);
+
+        // The second attempt succeeds, so the toggle turns on
+        assert!(context.execute_button("Service").await);
+        assert_eq!(context.toggle_state_manager.get_state("Service"), ToggleState::On);
+
+        // Without retries the failed first attempt leaves the state alone
+        std::fs::remove_file(&marker).unwrap();
+        assert!(context.execute_button("No Retry").await);
+        assert_eq!(context.toggle_state_manager.get_state("No Retry"), ToggleState::Off);
+
+        std::fs::remove_file(&marker).unwrap();
+        assert!(context.execute_button("Start").await);
+        assert!(context.last_result_manager.get("Start").unwrap().success);
+    }
+}
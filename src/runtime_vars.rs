@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, warn};
+
+/// Named values captured at runtime (e.g. a command's stdout) for use in later buttons' args
+#[derive(Debug)]
+pub struct RuntimeVars {
+    vars: Arc<RwLock<HashMap<String, String>>>,
+    outputs: Arc<RwLock<HashMap<String, String>>>, // Last stdout of each command button
+}
+
+impl Clone for RuntimeVars {
+    fn clone(&self) -> Self {
+        Self {
+            vars: Arc::clone(&self.vars),
+            outputs: Arc::clone(&self.outputs),
+        }
+    }
+}
+
+impl Default for RuntimeVars {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RuntimeVars {
+    /// Creates an empty variable map
+    pub fn new() -> Self {
+        Self {
+            vars: Arc::new(RwLock::new(HashMap::new())),
+            outputs: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Sets a variable, replacing any previous value
+    pub fn set(&self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.vars.write() {
+            Ok(mut vars) => {
+                debug!("Set runtime variable '{}' = '{}'", name, value);
+                vars.insert(name.to_string(), value);
+            }
+            Err(e) => {
+                warn!("Failed to set runtime variable '{}': {}", name, e);
+            }
+        }
+    }
+
+    /// Gets a variable's value, if it has been set
+    pub fn get(&self, name: &str) -> Option<String> {
+        match self.vars.read() {
+            Ok(vars) => vars.get(name).cloned(),
+            Err(e) => {
+                warn!("Failed to read runtime variable '{}': {}", name, e);
+                None
+            }
+        }
+    }
+
+    /// Records the stdout of the latest run of a button, for `{output:NAME}`
+    pub fn set_output(&self, button_name: &str, output: impl Into<String>) {
+        match self.outputs.write() {
+            Ok(mut outputs) => {
+                outputs.insert(button_name.to_string(), output.into());
+            }
+            Err(e) => {
+                warn!("Failed to record the output of '{}': {}", button_name, e);
+            }
+        }
+    }
+
+    /// Gets the stdout of the latest run of a button, if it has run
+    pub fn output(&self, button_name: &str) -> Option<String> {
+        match self.outputs.read() {
+            Ok(outputs) => outputs.get(button_name).cloned(),
+            Err(e) => {
+                warn!("Failed to read the output of '{}': {}", button_name, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Button, Config};
+    use crate::test_support::context_for;
+
+    #[test]
+    fn test_runtime_vars_set_and_get() {
+        let vars = RuntimeVars::new();
+        assert_eq!(vars.get("branch"), None);
+
+        vars.set("branch", "main");
+        assert_eq!(vars.get("branch"), Some("main".to_string()));
+
+        // Clones share storage
+        vars.clone().set("branch", "dev");
+        assert_eq!(vars.get("branch"), Some("dev".to_string()));
+
+        // Button outputs are kept apart from variables of the same name
+        assert_eq!(vars.output("branch"), None);
+        vars.clone().set_output("branch", "feature");
+        assert_eq!(vars.output("branch"), Some("feature".to_string()));
+        assert_eq!(vars.get("branch"), Some("dev".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_stored_output_expands_in_later_command_args() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Current Branch"
+      command: "printf"
+      args: ["  main\n"]
+      store_output_as: "last_branch"
+"#,
+        )
+        .unwrap();
+        match &config.menu.buttons[0] {
+            Button::Command { store_output_as, .. } => assert_eq!(store_output_as.as_deref(), Some("last_branch")),
+            _ => panic!("Expected command button"),
+        }
+        let context = context_for(config);
+
+        let outcome = context
+            .run_command("Current Branch", "printf", &["  main\n".to_string()], false, Some("last_branch"), &[0], Default::default())
+            .await;
+        assert!(outcome.success);
+        assert_eq!(context.runtime_vars.get("last_branch"), Some("main".to_string()));
+
+        let outcome = context
+            .run_command("Checkout", "echo", &["checkout ${var:last_branch}".to_string()], false, Some("echoed"), &[0], Default::default())
+            .await;
+        assert!(outcome.success);
+        assert_eq!(context.runtime_vars.get("echoed"), Some("checkout main".to_string()));
+        assert!(context.last_result_manager.get("Checkout").unwrap().success);
+
+        // Failed runs do not overwrite the stored value
+        context.run_command("Broken", "false", &[], false, Some("last_branch"), &[0], Default::default()).await;
+        assert_eq!(context.runtime_vars.get("last_branch"), Some("main".to_string()));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config::{Button, GroupActionKind, Menu};
+use crate::toggle_group::{run_toggle_actions, GroupActionConfig, GroupActionResult};
+use crate::toggle_state::{ToggleState, ToggleStateManager};
+use std::collections::HashMap;
+use tracing::{error, info};
+
+/// Restores the toggle states of `scene`, captured with [`ToggleStateManager::snapshot`]
+/// or configured under `scenes`. With `run_commands` every listed toggle of `menu` is
+/// driven to its state by its on/off command, like a group action; otherwise the states
+/// are only recorded. Unknown states in the scene are skipped.
+pub async fn restore_scene(
+    menu: &Menu,
+    scene: &HashMap<String, ToggleState>,
+    state_manager: &ToggleStateManager,
+    run_commands: bool,
+    config: &GroupActionConfig,
+) -> GroupActionResult {
+    info!("Restoring a scene of {} toggles (running commands: {})", scene.len(), run_commands);
+    if !run_commands {
+        state_manager.restore(scene);
+        return GroupActionResult::default();
+    }
+
+    let mut actions = Vec::new();
+    menu.walk_buttons(|button, _| {
+        let Button::Toggle { name, .. } = button else {
+            return;
+        };
+        match scene.get(name) {
+            Some(ToggleState::On) => actions.push((button, GroupActionKind::On)),
+            Some(ToggleState::Off) => actions.push((button, GroupActionKind::Off)),
+            Some(ToggleState::Unknown) | None => {}
+        }
+    });
+    let result = run_toggle_actions(actions, state_manager, config).await;
+    if !result.success() {
+        error!("Restoring the scene failed for: {:?}", result.failed());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::TempDir;
+
+    /// Two separate-mode toggles whose commands append to `log`
+    fn scene_config(log: &std::path::Path) -> Config {
+        let toggle = |name: &str| {
+            format!(
+                "    - type: toggle\n      name: \"{0}\"\n      mode: separate\n      on_command: \"sh\"\n      on_args: [\"-c\", \"echo {0} on >> {1}\"]\n      off_command: \"sh\"\n      off_args: [\"-c\", \"echo {0} off >> {1}\"]\n",
+                name,
+                log.display()
+            )
+        };
+        let yaml = format!(
+            "menu:\n  name: \"Main\"\n  buttons:\n{}{}    - type: scene\n      name: \"Movie\"\n      scene: \"movie\"\nscenes:\n  movie:\n    Lights: off\n    Projector: on\n",
+            toggle("Lights"),
+            toggle("Projector")
+        );
+        let config: Config = serde_yaml::from_str(&yaml).unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    fn log_lines(log: &std::path::Path) -> Vec<String> {
+        std::fs::read_to_string(log).unwrap_or_default().lines().map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn test_restore_snapshot_without_commands() {
+        let dir = TempDir::new("scene-states");
+        let log = dir.join("log");
+        let config = scene_config(&log);
+        let states = ToggleStateManager::new();
+        states.set_state("Lights", ToggleState::On);
+        states.set_state("Projector", ToggleState::Off);
+        states.set_state("Fan", ToggleState::Unknown);
+
+        let snapshot = states.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        states.set_state("Lights", ToggleState::Off);
+        states.set_state("Projector", ToggleState::On);
+
+        let result = restore_scene(&config.menu, &snapshot, &states, false, &GroupActionConfig::default()).await;
+        assert!(result.success());
+        assert_eq!(states.get_state("Lights"), ToggleState::On);
+        assert_eq!(states.get_state("Projector"), ToggleState::Off);
+        // Only the states were restored
+        assert!(log_lines(&log).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore_configured_scene_runs_commands() {
+        let dir = TempDir::new("scene-commands");
+        let log = dir.join("log");
+        let config = scene_config(&log);
+        let states = ToggleStateManager::new();
+        states.set_state("Lights", ToggleState::On);
+        states.set_state("Projector", ToggleState::On);
+
+        let scene = &config.scenes["movie"];
+        let result = restore_scene(&config.menu, scene, &states, true, &GroupActionConfig::default()).await;
+        assert!(result.success());
+        assert_eq!(states.get_state("Lights"), ToggleState::Off);
+        assert_eq!(states.get_state("Projector"), ToggleState::On);
+        // The projector was already on, so only the lights' command ran
+        assert_eq!(log_lines(&log), ["Lights off"]);
+    }
+}
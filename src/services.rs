@@ -0,0 +1,213 @@
+use crate::button::CommanderContext;
+use crate::config::Config;
+use crate::deck::DeckDevice;
+use crate::events::serve_event_socket;
+use crate::file_toggle::expand_home;
+use crate::idle::run_idle_home;
+use crate::pedal::run_pedal;
+use crate::render_dump::dump_active_view;
+use crate::state_file::{persist_toggle_states, STATE_SAVE_INTERVAL};
+use chrono::Local;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// How often buttons with `active_hours` are checked for entering or leaving them
+pub const ACTIVE_HOURS_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Background tasks running next to the deck, kept so they can be stopped on shutdown
+#[derive(Debug, Default)]
+pub struct Services {
+    handles: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl Services {
+    /// Creates a set with no service running
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the handle of the running service `name`
+    pub fn push(&mut self, name: &'static str, handle: JoinHandle<()>) {
+        self.handles.push((name, handle));
+    }
+
+    /// Names of the services started, in the order they were started
+    pub fn names(&self) -> Vec<&'static str> {
+        self.handles.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Stops every service still running
+    pub fn shutdown(self) {
+        for (name, handle) in self.handles {
+            if !handle.is_finished() {
+                debug!("Stopping {}", name);
+                handle.abort();
+            }
+        }
+    }
+}
+
+/// Queues `first_view` for the renderer and only then starts the background services,
+/// so none of them races the first render of the main menu with its own redraws
+pub async fn start_after_first_view<T, F, Fut>(navigation: &mpsc::Sender<T>, first_view: T, start: F) -> anyhow::Result<Services>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = anyhow::Result<Services>>,
+{
+    navigation
+        .send(first_view)
+        .await
+        .map_err(|_| anyhow::anyhow!("the deck renderer stopped before the first view"))?;
+    start().await
+}
+
+/// Re-renders the shown menu whenever a button enters or leaves its `active_hours`,
+/// checking every `interval`
+pub async fn refresh_on_active_hours(ctx: Arc<CommanderContext>, interval: Duration) {
+    let mut inactive = ctx.current_config().inactive_buttons(Local::now().naive_local());
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let now_inactive = ctx.current_config().inactive_buttons(Local::now().naive_local());
+        if now_inactive != inactive {
+            debug!("Buttons outside their active_hours changed to {:?}", now_inactive);
+            ctx.refresh_active_view().await;
+            inactive = now_inactive;
+        }
+    }
+}
+
+/// Starts the services `config` asks for: saving toggle states, the signal handlers,
+/// the pedal, the event socket, returning home when idle and redrawing buttons with
+/// `active_hours`
+pub async fn start_services(config: &Config, ctx: &Arc<CommanderContext>, pedal: Option<Arc<dyn DeckDevice>>) -> anyhow::Result<Services> {
+    let mut services = Services::new();
+
+    if let Some(path) = config.state_file.as_deref() {
+        let path = expand_home(Path::new(path));
+        let state_manager = ctx.toggle_state_manager.clone();
+        services.push("state file", tokio::spawn(async move { persist_toggle_states(state_manager, &path, STATE_SAVE_INTERVAL).await }));
+    }
+
+    // Re-probe all toggles on SIGHUP so external scripts can resync the deck
+    let mut hangup = signal(SignalKind::hangup())?;
+    let hangup_context = ctx.clone();
+    services.push(
+        "SIGHUP handler",
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                info!("Received SIGHUP, re-probing toggle states");
+                hangup_context.refresh_toggle_states().await;
+            }
+        }),
+    );
+
+    // Mirror the current view to a PNG on SIGUSR1, for looking at the deck remotely
+    if let Some(path) = &config.dump_render_path {
+        let path = expand_home(Path::new(path));
+        let mut user1 = signal(SignalKind::user_defined1())?;
+        let dump_context = ctx.clone();
+        services.push(
+            "render dump",
+            tokio::spawn(async move {
+                while user1.recv().await.is_some() {
+                    match dump_active_view(&dump_context, &path) {
+                        Ok(()) => info!("Received SIGUSR1, wrote the current view to {}", path.display()),
+                        Err(e) => warn!("Received SIGUSR1, but {}", e),
+                    }
+                }
+            }),
+        );
+    }
+
+    // Dispatch pedal presses to their configured buttons
+    if let Some(pedal) = pedal {
+        services.push("pedal", tokio::spawn(run_pedal(pedal, ctx.clone())));
+    }
+
+    // Publish button presses to local listeners if an event socket is configured
+    if let Some(socket) = &config.event_socket {
+        let socket = expand_home(Path::new(socket));
+        match serve_event_socket(&socket, ctx.events.clone()) {
+            Ok(handle) => services.push("event socket", handle),
+            Err(e) => error!("Failed to open event socket {}: {}", socket.display(), e),
+        }
+    }
+
+    // Return to the main menu once the deck has been left alone for a while
+    if let Some(secs) = config.idle_home_secs {
+        ctx.activity.track_presses(&ctx.events);
+        let home_context = ctx.clone();
+        services.push(
+            "idle home",
+            tokio::spawn(run_idle_home(ctx.activity.clone(), Duration::from_secs(secs), move || {
+                let home_context = home_context.clone();
+                async move {
+                    home_context.navigate_home().await;
+                }
+            })),
+        );
+    }
+
+    // Redraw buttons as they enter or leave their active_hours
+    let mut scheduled = false;
+    config.walk_buttons(|button, _| scheduled |= button.active_hours().is_some());
+    if scheduled {
+        services.push("active hours", tokio::spawn(refresh_on_active_hours(ctx.clone(), ACTIVE_HOURS_CHECK_INTERVAL)));
+    }
+
+    info!("Started {} background services", services.names().len());
+    Ok(services)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_services_start_after_first_view() {
+        let (sender, mut receiver) = mpsc::channel::<&str>(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let started = order.clone();
+        let services = start_after_first_view(&sender, "main menu", || async move {
+            // The first view is already waiting for the renderer when services start
+            let queued = receiver.try_recv().ok();
+            started.lock().unwrap().push(format!("services after {:?}", queued));
+            let mut services = Services::new();
+            services.push("poller", tokio::spawn(std::future::pending()));
+            Ok(services)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(*order.lock().unwrap(), ["services after Some(\"main menu\")"]);
+        assert_eq!(services.names(), ["poller"]);
+        services.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_services_do_not_start_without_renderer() {
+        let (sender, receiver) = mpsc::channel::<&str>(1);
+        drop(receiver);
+        let result = start_after_first_view(&sender, "main menu", || async { panic!("services started without a renderer") }).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_services() {
+        let mut services = Services::new();
+        let poller = tokio::spawn(std::future::pending::<()>());
+        let abort = poller.abort_handle();
+        services.push("poller", poller);
+        services.shutdown();
+        tokio::task::yield_now().await;
+        assert!(abort.is_finished());
+    }
+}
@@ -0,0 +1,348 @@
+use crate::command_policy::CommandPolicy;
+use crate::config::{Config, LogOutput};
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::process::Command;
+use tracing::{debug, info};
+
+static NEXT_RUN_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Returns a short id for one spawned execution. Spawning functions record it as the
+/// `run` field of their span, so every log line of one execution can be grepped by it.
+pub fn next_run_id() -> String {
+    format!("{:06x}", NEXT_RUN_ID.fetch_add(1, Ordering::Relaxed) & 0xff_ffff)
+}
+
+/// Process settings shared by every command, toggle and probe the commander spawns
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpawnConfig {
+    /// Program and arguments prepended to every spawned argv (e.g. `["nice", "-n", "10"]`)
+    pub command_prefix: Vec<String>,
+    /// Start from an empty environment instead of inheriting the daemon's
+    pub clean_env: bool,
+    /// Variables of the daemon's environment kept when `clean_env` is set
+    pub clean_env_keep: Vec<String>,
+    /// Commands buttons may run
+    pub policy: CommandPolicy,
+    /// Level the command's stdout and stderr are logged at
+    pub log_output: LogOutput,
+    /// Probes running longer than this many milliseconds are logged as slow
+    pub slow_probe_warn_ms: Option<u64>,
+}
+
+impl SpawnConfig {
+    /// Builds the spawn settings from the global configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            command_prefix: config.command_prefix.clone(),
+            clean_env: config.clean_env,
+            clean_env_keep: config.clean_env_keep.clone(),
+            policy: CommandPolicy::from_config(config),
+            log_output: config.log_output,
+            slow_probe_warn_ms: config.slow_probe_warn_ms,
+        }
+    }
+
+    /// Returns the global settings, without the command prefix for buttons that opted out of it
+    pub fn for_button(config: &Config, no_prefix: bool) -> Self {
+        let spawn = Self::from_config(config);
+        if no_prefix {
+            Self {
+                command_prefix: Vec::new(),
+                ..spawn
+            }
+        } else {
+            spawn
+        }
+    }
+
+    /// Returns the full argv that will be executed, program first
+    pub fn argv(&self, command: &str, args: &[String]) -> Vec<String> {
+        self.command_prefix
+            .iter()
+            .cloned()
+            .chain(std::iter::once(command.to_string()))
+            .chain(args.iter().cloned())
+            .collect()
+    }
+
+    /// Returns the full argv as a command line that can be pasted into a shell
+    pub fn command_line(&self, command: &str, args: &[String]) -> String {
+        let argv = self.argv(command, args);
+        shell_quote(&argv[0], &argv[1..])
+    }
+
+    /// Creates a `Command` for the given program and arguments with these settings applied.
+    /// Variables set on the returned command afterwards (e.g. a probe's `probe_env`)
+    /// are kept even with `clean_env`.
+    pub fn command(&self, command: &str, args: &[String]) -> Command {
+        let argv = self.argv(command, args);
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]);
+        if self.clean_env {
+            cmd.env_clear();
+            for name in &self.clean_env_keep {
+                if let Some(value) = std::env::var_os(name) {
+                    cmd.env(name, value);
+                }
+            }
+        }
+        cmd
+    }
+}
+
+/// Joins `command` and its args into a command line that can be pasted into a shell:
+/// words with spaces, quotes or other shell syntax are single-quoted and empty args
+/// become `''`
+pub fn shell_quote(command: &str, args: &[String]) -> String {
+    std::iter::once(command)
+        .chain(args.iter().map(String::as_str))
+        .map(quote_word)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn quote_word(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        // A single quote cannot appear inside single quotes, so it is closed, escaped and reopened
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Logs a line of command output at the `log_output` level
+pub fn log_command_output(level: LogOutput, message: std::fmt::Arguments<'_>) {
+    match level {
+        LogOutput::Off => {}
+        LogOutput::Debug => debug!("{}", message),
+        LogOutput::Info => info!("{}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_argv_without_prefix() {
+        let spawn = SpawnConfig::default();
+        assert_eq!(spawn.argv("echo", &["hi".to_string()]), vec!["echo", "hi"]);
+    }
+
+    #[test]
+    fn test_argv_with_prefix() {
+        let spawn = SpawnConfig {
+            command_prefix: vec!["nice".to_string(), "-n".to_string(), "10".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            spawn.argv("echo", &["hi".to_string()]),
+            vec!["nice", "-n", "10", "echo", "hi"]
+        );
+    }
+
+    #[test]
+    fn test_for_button_opt_out() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+command_prefix: ["nice", "-n", "10"]
+menu:
+  name: "Main"
+  buttons: []
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(SpawnConfig::for_button(&config, false).command_prefix.len(), 3);
+        assert!(SpawnConfig::for_button(&config, true).command_prefix.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clean_env() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+clean_env: true
+menu:
+  name: "Main"
+  buttons: []
+"#,
+        )
+        .unwrap();
+        let spawn = SpawnConfig::for_button(&config, true);
+        assert_eq!(spawn.clean_env_keep, ["PATH", "HOME"]);
+        // The variable is inherited from the test process unless the environment is cleaned
+        std::env::set_var("STREAMDECK_CLEAN_ENV_TEST", "leaked");
+
+        let mut cmd = spawn.command("env", &[]);
+        cmd.env("BUTTON_VAR", "set");
+        let output = cmd.output().await.unwrap();
+        let mut names: Vec<_> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once('=').map(|(name, _)| name.to_string()))
+            .collect();
+        names.sort();
+        let mut expected = vec!["BUTTON_VAR".to_string(), "PATH".to_string()];
+        if std::env::var_os("HOME").is_some() {
+            expected.push("HOME".to_string());
+        }
+        expected.sort();
+        assert_eq!(names, expected);
+
+        let inherited = SpawnConfig::default().command("env", &[]).output().await.unwrap();
+        assert!(String::from_utf8_lossy(&inherited.stdout).contains("STREAMDECK_CLEAN_ENV_TEST=leaked"));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(shell_quote("ls", &args(&["-la", "/tmp"])), "ls -la /tmp");
+        assert_eq!(shell_quote("notify-send", &args(&["arg with spaces"])), "notify-send 'arg with spaces'");
+        assert_eq!(shell_quote("echo", &args(&["it's", "say \"hi\""])), r#"echo 'it'\''s' 'say "hi"'"#);
+        assert_eq!(shell_quote("printf", &args(&["", "x"])), "printf '' x");
+        assert_eq!(shell_quote("sh", &args(&["-c", "echo $HOME; ls *"])), "sh -c 'echo $HOME; ls *'");
+    }
+
+    #[test]
+    fn test_command_line_includes_prefix() {
+        let spawn = SpawnConfig {
+            command_prefix: vec!["systemd-run".to_string(), "--user".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(spawn.command_line("echo", &["hello world".to_string()]), "systemd-run --user echo 'hello world'");
+    }
+
+    #[tokio::test]
+    async fn test_command_runs_through_prefix() {
+        let spawn = SpawnConfig {
+            command_prefix: vec!["env".to_string()],
+            ..Default::default()
+        };
+        let output = spawn.command("echo", &["hi".to_string()]).output().await.unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hi");
+    }
+
+    /// Writes log lines into a shared buffer
+    struct CaptureWriter(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CaptureWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs `run` with every level logged and returns the log lines mentioning `stream`
+    async fn logged_lines(stream: &str, run: impl std::future::Future) -> Vec<String> {
+        let buffer = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || CaptureWriter(writer.clone()))
+            .finish();
+        {
+            // The test runtime is single-threaded, so spawned output readers log here too
+            let _guard = tracing::subscriber::set_default(subscriber);
+            run.await;
+        }
+        let logs = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        logs.lines().filter(|line| line.contains(stream)).map(str::to_string).collect()
+    }
+
+    fn at_level(level: LogOutput) -> SpawnConfig {
+        SpawnConfig {
+            log_output: level,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_output_logged_at_configured_level() {
+        use crate::button::CommanderPlugin;
+        use crate::expand::ExpandContext;
+
+        let run = |spawn: SpawnConfig| async move {
+            let args = ["streamdeck-log-check".to_string()];
+            CommanderPlugin::execute_command("echo", &args, &ExpandContext::default(), &spawn, &[0]).await.unwrap();
+        };
+
+        let lines = logged_lines("STDOUT", run(at_level(LogOutput::Info))).await;
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert!(lines[0].contains(" INFO ") && lines[0].contains("streamdeck-log-check"), "{}", lines[0]);
+
+        let lines = logged_lines("STDOUT", run(at_level(LogOutput::Debug))).await;
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert!(lines[0].contains("DEBUG "), "{}", lines[0]);
+
+        assert!(logged_lines("STDOUT", run(at_level(LogOutput::Off))).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_command_output_kept_as_printed() {
+        use crate::button::CommanderPlugin;
+        use crate::expand::ExpandContext;
+
+        let args = ["-c".to_string(), r"printf 'one\r\ntwo\n\n\377end'".to_string()];
+        let (code, stdout) = CommanderPlugin::execute_command("sh", &args, &ExpandContext::default(), &SpawnConfig::default(), &[0])
+            .await
+            .unwrap();
+        assert_eq!(code, 0);
+        // Line endings and the blank line are kept, invalid UTF-8 does not cut the output short
+        assert_eq!(stdout, "one\r\ntwo\n\n\u{FFFD}end");
+    }
+
+    #[tokio::test]
+    async fn test_toggle_output_logged_at_configured_level() {
+        use crate::config::{SingleBehavior, ToggleMode};
+        use crate::toggle_command::{execute_toggle_command_with_config, ToggleCommandConfig};
+        use crate::toggle_state::ToggleStateManager;
+
+        let run = |spawn: SpawnConfig| async move {
+            let mode = ToggleMode::Single {
+                command: "sh".to_string(),
+                args: vec!["-c".to_string(), "echo toggled; echo warned >&2".to_string()],
+                single_behavior: SingleBehavior::Flip,
+            };
+            let config = ToggleCommandConfig { spawn, ..Default::default() };
+            execute_toggle_command_with_config("Lamp", &mode, None, &[], &ToggleStateManager::new(), &config).await;
+        };
+
+        let lines = logged_lines("Command STD", run(at_level(LogOutput::Info))).await;
+        assert_eq!(lines.len(), 2, "{:?}", lines);
+        assert!(lines.iter().all(|line| line.contains(" INFO ")), "{:?}", lines);
+
+        assert!(logged_lines("Command STD", run(at_level(LogOutput::Off))).await.is_empty());
+    }
+
+    #[test]
+    fn test_log_output_per_button() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+log_output: "off"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Quiet"
+      command: "true"
+    - type: command
+      name: "Loud"
+      command: "true"
+      log_output: info
+"#,
+        )
+        .unwrap();
+        assert_eq!(SpawnConfig::from_config(&config).log_output, LogOutput::Off);
+        assert_eq!(config.log_output_for("Quiet"), LogOutput::Off);
+        assert_eq!(config.log_output_for("Loud"), LogOutput::Info);
+
+        let default: Config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        assert_eq!(default.log_output, LogOutput::Debug);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::file_toggle::expand_home;
+use crate::toggle_state::{ToggleState, ToggleStateManager};
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How often the toggle states are checked for changes to save
+pub const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reads the toggle states saved in `path`. A missing file just means nothing was
+/// saved yet; a file that cannot be read or parsed is logged and treated as empty.
+pub fn load_toggle_states(path: &Path) -> HashMap<String, ToggleState> {
+    let path = expand_home(path);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            debug!("No saved toggle states in {}", path.display());
+            return HashMap::new();
+        }
+        Err(e) => {
+            warn!("Failed to read saved toggle states from {}: {}", path.display(), e);
+            return HashMap::new();
+        }
+    };
+    match serde_yaml::from_str::<Option<HashMap<String, ToggleState>>>(&contents) {
+        Ok(states) => {
+            let mut states = states.unwrap_or_default();
+            states.retain(|_, state| state.is_known());
+            states
+        }
+        Err(e) => {
+            warn!("Ignoring saved toggle states in {}: {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Writes `states` to `path` through a temporary file, so a crash never leaves half of it
+pub fn save_toggle_states(path: &Path, states: &HashMap<String, ToggleState>) -> std::io::Result<()> {
+    let path = expand_home(path);
+    let sorted: BTreeMap<_, _> = states.iter().collect();
+    let contents = serde_yaml::to_string(&sorted).map_err(std::io::Error::other)?;
+    let mut temporary = path.clone().into_os_string();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, &path)
+}
+
+/// Saves the known toggle states to `path` whenever they change, checking every `interval`
+pub async fn persist_toggle_states(state_manager: ToggleStateManager, path: &Path, interval: Duration) {
+    info!("Saving toggle states to {}", path.display());
+    let mut saved = load_toggle_states(path);
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let states = state_manager.snapshot();
+        if states == saved {
+            continue;
+        }
+        match save_toggle_states(path, &states) {
+            Ok(()) => {
+                debug!("Saved {} toggle states", states.len());
+                saved = states;
+            }
+            Err(e) => warn!("Failed to save toggle states to {}: {}", path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_save_and_load_toggle_states() {
+        let dir = TempDir::new("states-round-trip");
+        let path = dir.join("states.yaml");
+        assert!(load_toggle_states(&path).is_empty());
+
+        let states = HashMap::from([
+            ("VPN".to_string(), ToggleState::On),
+            ("Mute".to_string(), ToggleState::Off),
+        ]);
+        save_toggle_states(&path, &states).unwrap();
+        assert_eq!(load_toggle_states(&path), states);
+
+        // Unknown states are not restored and a broken file is ignored
+        std::fs::write(&path, "VPN: unknown\nMute: off\n").unwrap();
+        assert_eq!(load_toggle_states(&path), HashMap::from([("Mute".to_string(), ToggleState::Off)]));
+        std::fs::write(&path, "[not, a, map").unwrap();
+        assert!(load_toggle_states(&path).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_persist_saves_changed_states() {
+        let dir = TempDir::new("states-persist");
+        let path = dir.join("states.yaml");
+        let state_manager = ToggleStateManager::new();
+        let task = tokio::spawn({
+            let state_manager = state_manager.clone();
+            let path = path.clone();
+            async move { persist_toggle_states(state_manager, &path, Duration::from_secs(1)).await }
+        });
+
+        state_manager.set_state("VPN", ToggleState::On);
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(load_toggle_states(&path), HashMap::from([("VPN".to_string(), ToggleState::On)]));
+
+        state_manager.set_state("VPN", ToggleState::Off);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(load_toggle_states(&path), HashMap::from([("VPN".to_string(), ToggleState::Off)]));
+        task.abort();
+    }
+}
@@ -0,0 +1,48 @@
+//! Fixtures shared by the tests of several modules
+
+use crate::button::CommanderContext;
+use crate::config::Config;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Context for `config` with no deck attached and no navigation sender
+pub fn context_for(config: Config) -> CommanderContext {
+    CommanderContext::new(Arc::new(config), None)
+}
+
+/// Empty directory under the system temp dir, removed with its contents when dropped
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a directory named after `name`, unique across tests and test runs
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "streamdeck-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// The directory itself
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Path of `name` inside the directory, which is not created
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,11 +1,23 @@
-use crate::config::ToggleMode;
-use crate::probe::execute_probe_command;
+use crate::config::{SingleBehavior, ToggleMode};
+use crate::error::CommanderError;
+use crate::expand::{expand_all_args, ExpandContext};
+use crate::file_toggle::{create_file, probe_file, remove_file};
+use crate::locks::ButtonLocks;
+use crate::probe::{ProbeContext, ProbeResult};
+use crate::probe_backend::{ProbeBackendRegistry, ProbeRequest};
+use crate::retry::RetryPolicy;
+use crate::spawn::{log_command_output, next_run_id, SpawnConfig};
 use crate::toggle_state::{ToggleState, ToggleStateManager};
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
 use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tracing::{debug, error, info, warn};
 
+/// Pause between the probes of a toggle with `concurrent_verify` while its command runs
+pub const VERIFY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Result of executing a toggle command
 #[derive(Debug, Clone)]
 pub struct ToggleCommandResult {
@@ -15,6 +27,10 @@ pub struct ToggleCommandResult {
     pub stdout: String,
     pub stderr: String,
     pub error_message: Option<String>,
+    /// The command was refused by `allowed_commands` or `denied_commands`
+    pub not_allowed: bool,
+    /// Time the toggle's command took, retries included; zero if no command ran
+    pub duration: Duration,
 }
 
 impl ToggleCommandResult {
@@ -27,6 +43,8 @@ impl ToggleCommandResult {
             stdout,
             stderr,
             error_message: None,
+            not_allowed: false,
+            duration: Duration::ZERO,
         }
     }
 
@@ -45,8 +63,61 @@ impl ToggleCommandResult {
             stdout,
             stderr,
             error_message: Some(error_message),
+            not_allowed: false,
+            duration: Duration::ZERO,
         }
     }
+
+    /// Records how long the command took
+    pub fn with_duration(self, duration: Duration) -> Self {
+        Self { duration, ..self }
+    }
+}
+
+/// Configuration for toggle command execution
+#[derive(Debug, Clone, Default)]
+pub struct ToggleCommandConfig {
+    /// Process settings applied to the toggle command and its probes
+    pub spawn: SpawnConfig,
+    /// Working directory, environment and shell of the toggle's probes
+    pub probe: ProbeContext,
+    /// Probe backends the toggle's `probe_backend` is looked up in
+    pub backends: ProbeBackendRegistry,
+    /// How often a failing on/off command is run again
+    pub retry: RetryPolicy,
+    /// Poll the probe while the command runs instead of probing once it exits
+    pub concurrent_verify: bool,
+    /// Only re-probe the toggle; its command never runs
+    pub readonly: bool,
+    /// Registry the toggle's `lock` is taken from
+    pub locks: ButtonLocks,
+    /// Lock held while the toggle is probed and switched, shared with other buttons naming it
+    pub lock: Option<String>,
+}
+
+impl ToggleCommandConfig {
+    /// Probes the toggle with its backend; `None` if it has neither a backend nor a probe command
+    async fn probe(&self, button_name: &str, probe_command: Option<&str>, probe_args: &[String]) -> Option<ProbeResult> {
+        let request = ProbeRequest {
+            button_name,
+            command: probe_command,
+            args: probe_args,
+            spawn: &self.spawn,
+            context: &self.probe,
+        };
+        self.backends.probe(&request).await
+    }
+}
+
+/// The toggle state a probe result stands for
+fn probed_state(probe: &ProbeResult) -> ToggleState {
+    if probe.is_success() {
+        ToggleState::On
+    } else if probe.is_command_failure() {
+        ToggleState::Off
+    } else {
+        ToggleState::Unknown
+    }
 }
 
 /// Executes a toggle command and updates state accordingly
@@ -57,44 +128,47 @@ pub async fn execute_toggle_command(
     probe_args: &[String],
     state_manager: &ToggleStateManager,
 ) -> ToggleCommandResult {
-    info!("Executing toggle command for '{}'", button_name);
+    execute_toggle_command_with_config(
+        button_name,
+        mode,
+        probe_command,
+        probe_args,
+        state_manager,
+        &ToggleCommandConfig::default(),
+    )
+    .await
+}
 
-    // Get current state - either from probe or from state manager
-    let current_state = if let Some(probe_cmd) = probe_command {
-        // Execute probe to get current state
-        let probe_result = execute_probe_command(probe_cmd, probe_args, button_name).await;
-        let probed_state = if probe_result.is_success() {
-            ToggleState::On
-        } else if probe_result.is_command_failure() {
-            ToggleState::Off
-        } else {
-            ToggleState::Unknown
-        };
-        
-        // Update state manager with probed state
-        state_manager.set_state(button_name, probed_state);
-        probed_state
-    } else {
-        // Use state from state manager
-        state_manager.get_state(button_name)
-    };
+/// Executes a toggle command with custom configuration and updates state accordingly
+pub async fn execute_toggle_command_with_config(
+    button_name: &str,
+    mode: &ToggleMode,
+    probe_command: Option<&str>,
+    probe_args: &[String],
+    state_manager: &ToggleStateManager,
+    config: &ToggleCommandConfig,
+) -> ToggleCommandResult {
+    info!("Executing toggle command for '{}'", button_name);
+    let _pending = state_manager.begin_pending(button_name);
+    let _lock = config.locks.acquire(button_name, config.lock.as_deref()).await;
+    let (probe_command, probe_args) = mode.probe(probe_command, probe_args);
 
+    let current_state = current_toggle_state(button_name, mode, probe_command, probe_args, state_manager, config).await;
     debug!("Current state for '{}': {:?}", button_name, current_state);
+    if config.readonly {
+        info!("Toggle '{}' is readonly, re-probed as {:?}", button_name, current_state);
+        return ToggleCommandResult::success(current_state, 0, String::new(), String::new());
+    }
 
     // Determine what command to execute based on mode and current state
     let (command, args, expected_new_state) = match (mode, current_state) {
-        (ToggleMode::Single { command, args }, state) => {
+        (ToggleMode::Single { command, args, single_behavior }, state) => {
             // For single command mode, always execute the same command
-            let new_state = match state {
-                ToggleState::On => ToggleState::Off,
-                ToggleState::Off => ToggleState::On,
-                ToggleState::Unknown => {
-                    // If state is unknown, we assume we're turning it on
-                    debug!("State unknown for '{}', assuming we're turning it on", button_name);
-                    ToggleState::On
-                }
-            };
-            (command.clone(), args.clone(), new_state)
+            if state == ToggleState::Unknown && *single_behavior == SingleBehavior::Flip {
+                // If state is unknown, we assume we're turning it on
+                debug!("State unknown for '{}', assuming we're turning it on", button_name);
+            }
+            (command.clone(), args.clone(), single_behavior.target(state))
         }
         (ToggleMode::Separate { on_command, on_args, off_command, off_args }, state) => {
             // For separate command mode, choose command based on desired state
@@ -114,13 +188,193 @@ pub async fn execute_toggle_command(
                 }
             }
         }
+        (ToggleMode::Service { start, start_args, stop, stop_args, .. }, state) => match state {
+            ToggleState::On => (stop.clone(), stop_args.clone(), ToggleState::Off),
+            ToggleState::Off => (start.clone(), start_args.clone(), ToggleState::On),
+            ToggleState::Unknown => {
+                debug!("State unknown for '{}', starting the service", button_name);
+                (start.clone(), start_args.clone(), ToggleState::On)
+            }
+        },
+        (ToggleMode::File { path }, state) => {
+            let new_state = if state == ToggleState::On { ToggleState::Off } else { ToggleState::On };
+            return run_file_transition(button_name, path, new_state, state_manager);
+        }
     };
 
+    run_toggle_transition(
+        button_name,
+        mode,
+        &command,
+        &args,
+        current_state,
+        expected_new_state,
+        probe_command,
+        probe_args,
+        state_manager,
+        config,
+    )
+    .await
+}
+
+/// Drives a toggle to `target`, running its command only if it is not already there.
+/// Separate-mode toggles run the on or off command and services start or stop;
+/// single-mode toggles run their one command, which flips the state unless it only
+/// turns the toggle on or off. Such a command cannot reach the other state and fails.
+pub async fn execute_toggle_to_state(
+    button_name: &str,
+    mode: &ToggleMode,
+    probe_command: Option<&str>,
+    probe_args: &[String],
+    state_manager: &ToggleStateManager,
+    target: ToggleState,
+    config: &ToggleCommandConfig,
+) -> ToggleCommandResult {
+    info!("Setting toggle '{}' to {:?}", button_name, target);
+    let _pending = state_manager.begin_pending(button_name);
+    let _lock = config.locks.acquire(button_name, config.lock.as_deref()).await;
+    let (probe_command, probe_args) = mode.probe(probe_command, probe_args);
+
+    let current_state = current_toggle_state(button_name, mode, probe_command, probe_args, state_manager, config).await;
+    if current_state == target {
+        debug!("Toggle '{}' is already {:?}, nothing to run", button_name, target);
+        return ToggleCommandResult::success(current_state, 0, String::new(), String::new());
+    }
+    if config.readonly {
+        warn!("Cannot set toggle '{}' to {:?}: it is readonly", button_name, target);
+        return ToggleCommandResult::failure(current_state, None, String::new(), String::new(), "it is readonly".to_string());
+    }
+
+    let (command, args) = match (mode, target) {
+        (ToggleMode::File { path }, _) => {
+            return run_file_transition(button_name, path, target, state_manager);
+        }
+        (ToggleMode::Single { command, args, single_behavior }, _) => {
+            if *single_behavior != SingleBehavior::Flip && single_behavior.target(current_state) != target {
+                let error_msg = format!("its command only turns it {}", single_behavior.target(current_state));
+                warn!("Cannot set toggle '{}': {}", button_name, error_msg);
+                return ToggleCommandResult::failure(current_state, None, String::new(), String::new(), error_msg);
+            }
+            (command, args)
+        }
+        (ToggleMode::Separate { off_command, off_args, .. }, ToggleState::Off) => (off_command, off_args),
+        (ToggleMode::Separate { on_command, on_args, .. }, _) => (on_command, on_args),
+        (ToggleMode::Service { stop, stop_args, .. }, ToggleState::Off) => (stop, stop_args),
+        (ToggleMode::Service { start, start_args, .. }, _) => (start, start_args),
+    };
+
+    run_toggle_transition(
+        button_name,
+        mode,
+        command,
+        args,
+        current_state,
+        target,
+        probe_command,
+        probe_args,
+        state_manager,
+        config,
+    )
+    .await
+}
+
+/// Gets the current state of a toggle - from its flag file, its probe or the state manager
+async fn current_toggle_state(
+    button_name: &str,
+    mode: &ToggleMode,
+    probe_command: Option<&str>,
+    probe_args: &[String],
+    state_manager: &ToggleStateManager,
+    config: &ToggleCommandConfig,
+) -> ToggleState {
+    if let ToggleMode::File { path } = mode {
+        let state = probe_file(path);
+        state_manager.set_state(button_name, state);
+        return state;
+    }
+
+    if let Some(probe_result) = config.probe(button_name, probe_command, probe_args).await {
+        // Probe to get current state
+        let probed_state = probed_state(&probe_result);
+
+        // Update state manager with probed state
+        state_manager.set_state(button_name, probed_state);
+        state_manager.set_probe_output(button_name, &probe_result.stdout);
+        probed_state
+    } else {
+        // Use state from state manager
+        state_manager.get_state(button_name)
+    }
+}
+
+/// Moves a file-backed toggle to `target` by creating or removing its flag file,
+/// then records the state the file is actually in
+fn run_file_transition(
+    button_name: &str,
+    path: &Path,
+    target: ToggleState,
+    state_manager: &ToggleStateManager,
+) -> ToggleCommandResult {
+    info!("Setting flag file for '{}' to {:?}: {}", button_name, target, path.display());
+
+    let result = if target == ToggleState::On { create_file(path) } else { remove_file(path) };
+    let new_state = probe_file(path);
+    state_manager.set_state(button_name, new_state);
+    match result {
+        Ok(()) => {
+            info!("Toggle '{}' updated, new state: {:?}", button_name, new_state);
+            ToggleCommandResult::success(new_state, 0, String::new(), String::new())
+        }
+        Err(e) => {
+            let error_msg = format!("Failed to update flag file {}: {}", path.display(), e);
+            error!("Toggle command execution error for '{}': {}", button_name, error_msg);
+            ToggleCommandResult::failure(new_state, None, String::new(), String::new(), error_msg)
+        }
+    }
+}
+
+/// Token substituted for `{state}` in single-mode arguments: the target state, or
+/// "toggle" when the current state is unknown and the command can only flip it
+fn state_token(current_state: ToggleState, target: ToggleState) -> &'static str {
+    match (current_state, target) {
+        (ToggleState::Unknown, _) | (_, ToggleState::Unknown) => "toggle",
+        (_, ToggleState::On) => "on",
+        (_, ToggleState::Off) => "off",
+    }
+}
+
+/// Runs the command moving a toggle from `current_state` to `expected_new_state`
+/// and records the resulting state, verified by the probe when there is one
+#[allow(clippy::too_many_arguments)]
+async fn run_toggle_transition(
+    button_name: &str,
+    mode: &ToggleMode,
+    command: &str,
+    args: &[String],
+    current_state: ToggleState,
+    expected_new_state: ToggleState,
+    probe_command: Option<&str>,
+    probe_args: &[String],
+    state_manager: &ToggleStateManager,
+    config: &ToggleCommandConfig,
+) -> ToggleCommandResult {
+    let expand = ExpandContext {
+        state: match mode {
+            ToggleMode::Single { single_behavior: SingleBehavior::Flip, .. } => Some(state_token(current_state, expected_new_state)),
+            // A command that only turns the toggle on or off always knows its target
+            ToggleMode::Single { .. } => Some(expected_new_state.as_str()),
+            ToggleMode::Separate { .. } | ToggleMode::Service { .. } | ToggleMode::File { .. } => None,
+        },
+        secrets: true,
+        ..Default::default()
+    };
     info!(
         "Executing {} command for '{}': {} {:?} (expecting state: {:?})",
         match mode {
             ToggleMode::Single { .. } => "single",
             ToggleMode::Separate { .. } => "separate",
+            ToggleMode::Service { .. } => "service",
+            ToggleMode::File { .. } => "file",
         },
         button_name,
         command,
@@ -128,17 +382,31 @@ pub async fn execute_toggle_command(
         expected_new_state
     );
 
-    // Execute the command
-    match execute_command_with_output(&command, &args, button_name).await {
+    // Execute the command, retrying non-zero exits; the state only changes once it succeeds
+    let started = Instant::now();
+    let run = config.retry.run(
+        button_name,
+        || execute_command_with_output(command, args, &expand, button_name, &config.spawn),
+        |output| matches!(output, Ok((exit_code, _, _)) if *exit_code != 0),
+    );
+    let (output, verified_early) = if config.concurrent_verify {
+        run_with_concurrent_verify(run, button_name, expected_new_state, probe_command, probe_args, state_manager, config).await
+    } else {
+        (run.await, None)
+    };
+    let duration = started.elapsed();
+    debug!("Toggle command for '{}' took {:?}", button_name, duration);
+    let result = match output {
         Ok((exit_code, stdout, stderr)) => {
             if exit_code == 0 {
                 // Command succeeded, update state
                 state_manager.set_state(button_name, expected_new_state);
                 
-                // Optionally verify the new state with a probe
-                let final_state = if let Some(probe_cmd) = probe_command {
-                    debug!("Verifying new state for '{}' with probe", button_name);
-                    let verify_probe = execute_probe_command(probe_cmd, probe_args, button_name).await;
+                // Optionally verify the new state with a probe, unless one already matched while it ran
+                let final_state = if verified_early.is_some() {
+                    expected_new_state
+                } else if let Some(verify_probe) = config.probe(button_name, probe_command, probe_args).await {
+                    debug!("Verified new state for '{}' with probe", button_name);
                     let verified_state = if verify_probe.is_success() {
                         ToggleState::On
                     } else if verify_probe.is_command_failure() {
@@ -157,6 +425,7 @@ pub async fn execute_toggle_command(
                     }
                     
                     state_manager.set_state(button_name, verified_state);
+                    state_manager.set_probe_output(button_name, &verify_probe.stdout);
                     verified_state
                 } else {
                     expected_new_state
@@ -168,28 +437,81 @@ pub async fn execute_toggle_command(
                 // Command failed
                 let error_msg = format!("Toggle command failed with exit code {}", exit_code);
                 warn!("Toggle command for '{}' failed: {}", button_name, error_msg);
-                ToggleCommandResult::failure(current_state, Some(exit_code), stdout, stderr, error_msg)
+                // A probe that matched while the command ran already moved the state
+                let state = if verified_early.is_some() { expected_new_state } else { current_state };
+                ToggleCommandResult::failure(state, Some(exit_code), stdout, stderr, error_msg)
             }
         }
         Err(e) => {
             let error_msg = format!("Failed to execute toggle command: {}", e);
             error!("Toggle command execution error for '{}': {}", button_name, error_msg);
-            ToggleCommandResult::failure(current_state, None, String::new(), String::new(), error_msg)
+            ToggleCommandResult {
+                not_allowed: matches!(e, CommanderError::NotAllowed { .. }),
+                ..ToggleCommandResult::failure(current_state, None, String::new(), String::new(), error_msg)
+            }
+        }
+    };
+    result.with_duration(duration)
+}
+
+/// Runs the toggle's command while polling its probe every [`VERIFY_POLL_INTERVAL`].
+/// The first probe reporting `expected` sets the state right away, without waiting
+/// for a slow command to exit. Returns the command's output and that probe, if any.
+async fn run_with_concurrent_verify<T>(
+    run: impl Future<Output = T>,
+    button_name: &str,
+    expected: ToggleState,
+    probe_command: Option<&str>,
+    probe_args: &[String],
+    state_manager: &ToggleStateManager,
+    config: &ToggleCommandConfig,
+) -> (T, Option<ProbeResult>) {
+    let poll = async {
+        loop {
+            match config.probe(button_name, probe_command, probe_args).await {
+                Some(probe) if probed_state(&probe) == expected => return Some(probe),
+                Some(_) => tokio::time::sleep(VERIFY_POLL_INTERVAL).await,
+                None => return None,
+            }
+        }
+    };
+    tokio::pin!(run);
+    tokio::select! {
+        biased;
+        output = &mut run => (output, None),
+        verified = poll => {
+            if let Some(probe) = &verified {
+                debug!("Probe of '{}' reported {:?} while its command runs", button_name, expected);
+                state_manager.set_state(button_name, expected);
+                state_manager.set_probe_output(button_name, &probe.stdout);
+            }
+            (run.await, verified)
         }
     }
 }
 
 /// Executes a command and captures all output
+#[tracing::instrument(name = "exec", skip_all, fields(run = %next_run_id(), button = button_name))]
 async fn execute_command_with_output(
     command: &str,
     args: &[String],
+    expand: &ExpandContext<'_>,
     button_name: &str,
-) -> Result<(i32, String, String), Box<dyn std::error::Error + Send + Sync>> {
-    debug!("Executing command for '{}': {} {:?}", button_name, command, args);
+    spawn: &SpawnConfig,
+) -> Result<(i32, String, String), CommanderError> {
+    debug!("Executing command for '{}': {}", button_name, spawn.command_line(command, args));
+    if !spawn.policy.allows(command) {
+        error!("Refusing to run '{}' for '{}': not allowed by allowed_commands or denied_commands", command, button_name);
+        return Err(CommanderError::NotAllowed { command: command.to_string() });
+    }
 
-    let mut cmd = Command::new(command);
-    cmd.args(args)
-        .stdout(Stdio::piped())
+    // Placeholders are expanded after logging so secrets never reach the logs
+    let spawn_args = expand_all_args(args, expand).map_err(|e| CommanderError::InvalidArgs {
+        command: command.to_string(),
+        message: format!("{:#}", e),
+    })?;
+    let mut cmd = spawn.command(command, &spawn_args);
+    cmd.stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
     match cmd.spawn() {
@@ -198,74 +520,58 @@ async fn execute_command_with_output(
             let stdout = child.stdout.take().expect("Failed to capture stdout");
             let stderr = child.stderr.take().expect("Failed to capture stderr");
 
-            // Create async readers
-            let stdout_reader = BufReader::new(stdout);
-            let stderr_reader = BufReader::new(stderr);
-
-            // Read all output
-            let stdout_task = {
-                tokio::spawn(async move {
-                    let mut lines = stdout_reader.lines();
-                    let mut output = String::new();
-                    while let Ok(Some(line)) = lines.next_line().await {
-                        if !output.is_empty() {
-                            output.push('\n');
-                        }
-                        output.push_str(&line);
-                    }
-                    output
-                })
-            };
-
-            let stderr_task = {
-                tokio::spawn(async move {
-                    let mut lines = stderr_reader.lines();
-                    let mut output = String::new();
-                    while let Ok(Some(line)) = lines.next_line().await {
-                        if !output.is_empty() {
-                            output.push('\n');
-                        }
-                        output.push_str(&line);
-                    }
-                    output
-                })
-            };
+            // Read raw bytes to completion and decode once, so partial lines and
+            // multi-byte characters split across reads are preserved. Both pipes are
+            // drained alongside the wait, so output is complete even when the child
+            // exits before the readers catch up.
+            let (status, stdout, stderr) = tokio::join!(child.wait(), read_to_end(stdout), read_to_end(stderr));
 
-            // Wait for the process to complete
-            match child.wait().await {
+            match status {
                 Ok(status) => {
-                    // Wait for output reading tasks to complete
-                    let (stdout_result, stderr_result) = tokio::join!(stdout_task, stderr_task);
-                    let stdout = stdout_result.unwrap_or_default();
-                    let stderr = stderr_result.unwrap_or_default();
+                    let stdout = String::from_utf8_lossy(&stdout).into_owned();
+                    let stderr = String::from_utf8_lossy(&stderr).into_owned();
 
                     let exit_code = status.code().unwrap_or(-1);
                     
                     if !stdout.is_empty() {
-                        debug!("Command STDOUT for '{}': {}", button_name, stdout);
+                        log_command_output(spawn.log_output, format_args!("Command STDOUT for '{}': {}", button_name, stdout));
                     }
                     if !stderr.is_empty() {
-                        debug!("Command STDERR for '{}': {}", button_name, stderr);
+                        log_command_output(spawn.log_output, format_args!("Command STDERR for '{}': {}", button_name, stderr));
                     }
 
                     Ok((exit_code, stdout, stderr))
                 }
                 Err(e) => {
                     error!("Failed to wait for command for '{}': {}", button_name, e);
-                    Err(Box::new(e))
+                    Err(CommanderError::Spawn { command: command.to_string(), source: e })
                 }
             }
         }
         Err(e) => {
             error!("Failed to spawn command for '{}': {} {:?} - {}", button_name, command, args, e);
-            Err(Box::new(e))
+            Err(CommanderError::Spawn { command: command.to_string(), source: e })
         }
     }
 }
 
+/// Reads a child output pipe until EOF, keeping whatever was read if the pipe errors
+pub(crate) async fn read_to_end<R: AsyncRead + Unpin>(mut reader: R) -> Vec<u8> {
+    let mut output = Vec::new();
+    if let Err(e) = reader.read_to_end(&mut output).await {
+        warn!("Failed to read command output: {}", e);
+    }
+    output
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::probe_backend::ProbeBackend;
+    use crate::test_support::TempDir;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     #[test]
     fn test_toggle_command_result_creation() {
@@ -291,11 +597,35 @@ mod tests {
         assert_eq!(failure.new_state, ToggleState::Off);
         assert_eq!(failure.exit_code, Some(1));
         assert!(failure.error_message.is_some());
+        assert_eq!(failure.duration, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_toggle_command_duration() {
+        let state_manager = ToggleStateManager::new();
+        let mode = ToggleMode::Single {
+            command: "sleep".to_string(),
+            args: vec!["0.1".to_string()],
+            single_behavior: SingleBehavior::Flip,
+        };
+        let result = execute_toggle_command("test", &mode, None, &[], &state_manager).await;
+        assert!(result.success);
+        assert!(result.duration >= Duration::from_millis(100), "{:?}", result.duration);
+
+        // Failed commands are timed too
+        let mode = ToggleMode::Single {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), "sleep 0.1; exit 1".to_string()],
+            single_behavior: SingleBehavior::Flip,
+        };
+        let result = execute_toggle_command("test", &mode, None, &[], &state_manager).await;
+        assert!(!result.success);
+        assert!(result.duration >= Duration::from_millis(100), "{:?}", result.duration);
     }
 
     #[tokio::test]
     async fn test_execute_command_with_output_success() {
-        let result = execute_command_with_output("echo", &["test".to_string()], "test-button").await;
+        let result = execute_command_with_output("echo", &["test".to_string()], &ExpandContext::default(), "test-button", &SpawnConfig::default()).await;
         
         assert!(result.is_ok());
         let (exit_code, stdout, stderr) = result.unwrap();
@@ -304,9 +634,58 @@ mod tests {
         assert!(stderr.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_execute_command_with_output_without_trailing_newline() {
+        let result = execute_command_with_output(
+            "printf",
+            &["first\\nlast".to_string()],
+            &ExpandContext::default(),
+            "test-button",
+            &SpawnConfig::default(),
+        ).await;
+
+        let (exit_code, stdout, _stderr) = result.unwrap();
+        assert_eq!(exit_code, 0);
+        assert_eq!(stdout, "first\nlast");
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_with_output_split_multibyte() {
+        // "ł" is 0xC5 0x82; emit the two bytes in separate writes
+        let result = execute_command_with_output(
+            "sh",
+            &["-c".to_string(), "printf '\\305'; sleep 0.1; printf '\\202'".to_string()],
+            &ExpandContext::default(),
+            "test-button",
+            &SpawnConfig::default(),
+        ).await;
+
+        let (exit_code, stdout, _stderr) = result.unwrap();
+        assert_eq!(exit_code, 0);
+        assert_eq!(stdout, "ł");
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_with_output_captures_burst_before_exit() {
+        // Write far more than a pipe buffer to stderr and exit right away
+        let result = execute_command_with_output(
+            "sh",
+            &["-c".to_string(), "head -c 300000 /dev/zero | tr '\\0' e >&2; echo done; exit 3".to_string()],
+            &ExpandContext::default(),
+            "test-button",
+            &SpawnConfig::default(),
+        ).await;
+
+        let (exit_code, stdout, stderr) = result.unwrap();
+        assert_eq!(exit_code, 3);
+        assert_eq!(stdout, "done\n");
+        assert_eq!(stderr.len(), 300000);
+        assert!(stderr.bytes().all(|b| b == b'e'));
+    }
+
     #[tokio::test]
     async fn test_execute_command_with_output_failure() {
-        let result = execute_command_with_output("false", &[], "test-button").await;
+        let result = execute_command_with_output("false", &[], &ExpandContext::default(), "test-button", &SpawnConfig::default()).await;
         
         assert!(result.is_ok());
         let (exit_code, _stdout, _stderr) = result.unwrap();
@@ -319,6 +698,7 @@ mod tests {
         let mode = ToggleMode::Single {
             command: "echo".to_string(),
             args: vec!["toggle".to_string()],
+            single_behavior: SingleBehavior::Flip,
         };
 
         // Set initial state to Off
@@ -331,6 +711,96 @@ mod tests {
         assert_eq!(state_manager.get_state("test"), ToggleState::On);
     }
 
+    #[tokio::test]
+    async fn test_single_mode_state_placeholder() {
+        let state_manager = ToggleStateManager::new();
+        let mode = ToggleMode::Single {
+            command: "echo".to_string(),
+            args: vec!["--set".to_string(), "{state}".to_string()],
+            single_behavior: SingleBehavior::Flip,
+        };
+
+        // Unknown state: the command can only be asked to flip
+        let result = execute_toggle_command("placeholder", &mode, None, &[], &state_manager).await;
+        assert_eq!(result.stdout.trim(), "--set toggle");
+        assert_eq!(result.new_state, ToggleState::On);
+
+        // On -> Off
+        let result = execute_toggle_command("placeholder", &mode, None, &[], &state_manager).await;
+        assert_eq!(result.stdout.trim(), "--set off");
+
+        // Off -> On
+        let result = execute_toggle_command("placeholder", &mode, None, &[], &state_manager).await;
+        assert_eq!(result.stdout.trim(), "--set on");
+
+        // Driving to an explicit state uses the target
+        let result = execute_toggle_to_state(
+            "placeholder",
+            &mode,
+            None,
+            &[],
+            &state_manager,
+            ToggleState::Off,
+            &ToggleCommandConfig::default(),
+        )
+        .await;
+        assert_eq!(result.stdout.trim(), "--set off");
+    }
+
+    #[tokio::test]
+    async fn test_single_behavior_ensure_on() {
+        let state_manager = ToggleStateManager::new();
+        let mode = ToggleMode::Single {
+            command: "echo".to_string(),
+            args: vec!["--set".to_string(), "{state}".to_string()],
+            single_behavior: SingleBehavior::EnsureOn,
+        };
+
+        // Every press runs the command and drives toward On, whatever the state was
+        for state in [ToggleState::Unknown, ToggleState::On, ToggleState::Off] {
+            state_manager.set_state("ensure", state);
+            let result = execute_toggle_command("ensure", &mode, None, &[], &state_manager).await;
+            assert!(result.success, "{:?}", state);
+            assert_eq!(result.stdout.trim(), "--set on", "{:?}", state);
+            assert_eq!(result.new_state, ToggleState::On, "{:?}", state);
+            assert_eq!(state_manager.get_state("ensure"), ToggleState::On);
+        }
+
+        // Driving it off cannot work, so nothing runs
+        let result = execute_toggle_to_state("ensure", &mode, None, &[], &state_manager, ToggleState::Off, &ToggleCommandConfig::default()).await;
+        assert!(!result.success);
+        assert!(result.stdout.is_empty());
+        assert_eq!(result.error_message.as_deref(), Some("its command only turns it on"));
+        assert_eq!(state_manager.get_state("ensure"), ToggleState::On);
+
+        state_manager.set_state("ensure", ToggleState::Off);
+        let result = execute_toggle_to_state("ensure", &mode, None, &[], &state_manager, ToggleState::On, &ToggleCommandConfig::default()).await;
+        assert!(result.success);
+        assert_eq!(result.stdout.trim(), "--set on");
+    }
+
+    #[tokio::test]
+    async fn test_single_behavior_ensure_off_with_probe() {
+        let state_manager = ToggleStateManager::new();
+        let mode = ToggleMode::Single {
+            command: "echo".to_string(),
+            args: vec!["{state}".to_string()],
+            single_behavior: SingleBehavior::EnsureOff,
+        };
+
+        // The probe says off, and the command still runs to make sure
+        let result = execute_toggle_command("ensure-off", &mode, Some("false"), &[], &state_manager).await;
+        assert!(result.success);
+        assert_eq!(result.stdout.trim(), "off");
+        assert_eq!(result.new_state, ToggleState::Off);
+
+        // A probe disagreeing after the run wins, as for flipping toggles
+        let result = execute_toggle_command("ensure-off", &mode, Some("true"), &[], &state_manager).await;
+        assert!(result.success);
+        assert_eq!(result.stdout.trim(), "off");
+        assert_eq!(result.new_state, ToggleState::On);
+    }
+
     #[tokio::test]
     async fn test_execute_toggle_command_separate_mode() {
         let state_manager = ToggleStateManager::new();
@@ -351,12 +821,68 @@ mod tests {
         assert!(result.stdout.contains("turn_on"));
     }
 
+    /// A service that runs while `flag` exists
+    fn flag_service(flag: &Path) -> ToggleMode {
+        let flag = flag.display().to_string();
+        ToggleMode::Service {
+            start: "touch".to_string(),
+            start_args: vec![flag.clone()],
+            stop: "rm".to_string(),
+            stop_args: vec![flag.clone()],
+            status: "test".to_string(),
+            status_args: vec!["-e".to_string(), flag],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_toggle_command_service_mode() {
+        let dir = TempDir::new("service");
+        let flag = dir.join("running");
+        let state_manager = ToggleStateManager::new();
+        let mode = flag_service(&flag);
+
+        // The status command is the probe: the service is stopped, so pressing starts it
+        let result = execute_toggle_command("Service", &mode, None, &[], &state_manager).await;
+        assert!(result.success);
+        assert_eq!(result.new_state, ToggleState::On);
+        assert!(flag.exists());
+
+        // The state comes from `status`, even when the state manager says otherwise
+        state_manager.set_state("Service", ToggleState::Off);
+        let result = execute_toggle_command("Service", &mode, None, &[], &state_manager).await;
+        assert_eq!(result.new_state, ToggleState::Off);
+        assert!(!flag.exists());
+
+        let config = ToggleCommandConfig::default();
+        let result = execute_toggle_to_state("Service", &mode, None, &[], &state_manager, ToggleState::On, &config).await;
+        assert_eq!(result.new_state, ToggleState::On);
+        assert!(flag.exists());
+    }
+
+    #[test]
+    fn test_service_mode_probe_command_overrides_status() {
+        let mode = ToggleMode::Service {
+            start: "true".to_string(),
+            start_args: vec![],
+            stop: "true".to_string(),
+            stop_args: vec![],
+            status: "false".to_string(),
+            status_args: vec![],
+        };
+        assert_eq!(mode.probe(None, &[]), (Some("false"), &[] as &[String]));
+        let args = vec!["-q".to_string()];
+        assert_eq!(mode.probe(Some("systemctl"), &args), (Some("systemctl"), args.as_slice()));
+        let single = ToggleMode::Single { command: "true".to_string(), args: vec![], single_behavior: SingleBehavior::Flip };
+        assert_eq!(single.probe(None, &[]).0, None);
+    }
+
     #[tokio::test]
     async fn test_execute_toggle_command_with_probe() {
         let state_manager = ToggleStateManager::new();
         let mode = ToggleMode::Single {
             command: "echo".to_string(),
             args: vec!["toggle".to_string()],
+            single_behavior: SingleBehavior::Flip,
         };
 
         // Use a probe that should succeed
@@ -373,4 +899,133 @@ mod tests {
         // This is expected behavior - the probe determines the final state
         assert_eq!(result.new_state, ToggleState::On);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_execute_toggle_command_with_prefix() {
+        let state_manager = ToggleStateManager::new();
+        let mode = ToggleMode::Single {
+            command: "hi".to_string(),
+            args: vec![],
+            single_behavior: SingleBehavior::Flip,
+        };
+        let config = ToggleCommandConfig {
+            spawn: SpawnConfig {
+                command_prefix: vec!["echo".to_string(), "prefixed".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // With the prefix the real program is `echo`, so the unknown `hi` becomes an argument
+        let result = execute_toggle_command_with_config("test", &mode, None, &[], &state_manager, &config).await;
+        assert!(result.success);
+        assert_eq!(result.stdout.trim_end(), "prefixed hi");
+    }
+
+    /// Reports Off until it has been asked `off_polls` times, On afterwards
+    struct EventuallyOn {
+        off_polls: usize,
+        polls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ProbeBackend for EventuallyOn {
+        async fn probe(&self, _request: &ProbeRequest<'_>) -> ProbeResult {
+            if self.polls.fetch_add(1, Ordering::SeqCst) < self.off_polls {
+                ProbeResult::failure(Some(1), String::new(), String::new())
+            } else {
+                ProbeResult::success(0, "on".to_string(), String::new())
+            }
+        }
+    }
+
+    fn eventually_on_config(off_polls: usize, concurrent_verify: bool) -> (ToggleCommandConfig, Arc<AtomicUsize>) {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let backends = ProbeBackendRegistry::new();
+        backends.register("eventually_on", EventuallyOn { off_polls, polls: polls.clone() });
+        let config = ToggleCommandConfig {
+            probe: ProbeContext { backend: Some("eventually_on".to_string()), ..Default::default() },
+            backends,
+            concurrent_verify,
+            ..Default::default()
+        };
+        (config, polls)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_verify_settles_before_command_exits() {
+        let state_manager = ToggleStateManager::new();
+        let mode = ToggleMode::Single {
+            command: "sleep".to_string(),
+            args: vec!["1".to_string()],
+            single_behavior: SingleBehavior::Flip,
+        };
+        // The first probe reads the current state, the next two poll while the command runs
+        let (config, polls) = eventually_on_config(2, true);
+
+        let toggle = {
+            let state_manager = state_manager.clone();
+            tokio::spawn(async move {
+                execute_toggle_command_with_config("Service", &mode, None, &[], &state_manager, &config).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!toggle.is_finished());
+        assert_eq!(state_manager.get_state("Service"), ToggleState::On);
+        assert_eq!(polls.load(Ordering::SeqCst), 3);
+
+        let result = toggle.await.unwrap();
+        assert!(result.success);
+        assert_eq!(result.new_state, ToggleState::On);
+        // Polling stopped at the first match and no probe ran after the command
+        assert_eq!(polls.load(Ordering::SeqCst), 3);
+        assert_eq!(state_manager.get_probe_output("Service").as_deref(), Some("on"));
+    }
+
+    #[tokio::test]
+    async fn test_sequential_verify_probes_after_command() {
+        let state_manager = ToggleStateManager::new();
+        let mode = ToggleMode::Single {
+            command: "true".to_string(),
+            args: vec![],
+            single_behavior: SingleBehavior::Flip,
+        };
+        let (config, polls) = eventually_on_config(1, false);
+
+        let result = execute_toggle_command_with_config("Service", &mode, None, &[], &state_manager, &config).await;
+        assert!(result.success);
+        assert_eq!(result.new_state, ToggleState::On);
+        // One probe for the current state and one verifying the new state
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_readonly_toggle_only_probes() {
+        let state_manager = ToggleStateManager::new();
+        let dir = TempDir::new("readonly");
+        let marker = dir.join("marker");
+        let mode = ToggleMode::Single {
+            command: "touch".to_string(),
+            args: vec![marker.to_string_lossy().into_owned()],
+            single_behavior: SingleBehavior::Flip,
+        };
+        let (config, polls) = eventually_on_config(1, false);
+        let config = ToggleCommandConfig { readonly: true, ..config };
+
+        let result = execute_toggle_command_with_config("Sensor", &mode, None, &[], &state_manager, &config).await;
+        assert!(result.success);
+        assert_eq!(result.new_state, ToggleState::Off);
+        assert_eq!(state_manager.get_state("Sensor"), ToggleState::Off);
+
+        // The next press picks up the changed state, still without running the command
+        let result = execute_toggle_command_with_config("Sensor", &mode, None, &[], &state_manager, &config).await;
+        assert_eq!(result.new_state, ToggleState::On);
+        assert_eq!(state_manager.get_state("Sensor"), ToggleState::On);
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+
+        let result = execute_toggle_to_state("Sensor", &mode, None, &[], &state_manager, ToggleState::Off, &config).await;
+        assert!(!result.success);
+        assert_eq!(result.error_message.as_deref(), Some("it is readonly"));
+        assert!(!marker.exists());
+    }
+}
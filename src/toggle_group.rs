@@ -0,0 +1,304 @@
+use crate::config::{Button, GroupActionKind, Menu};
+use crate::locks::ButtonLocks;
+use crate::probe::ProbeContext;
+use crate::probe_backend::ProbeBackendRegistry;
+use crate::retry::RetryPolicy;
+use crate::spawn::SpawnConfig;
+use crate::toggle_command::{
+    execute_toggle_command_with_config, execute_toggle_to_state, ToggleCommandConfig, ToggleCommandResult,
+};
+use crate::toggle_state::{ToggleState, ToggleStateManager};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tracing::{error, info};
+
+/// Toggle buttons of the menu tree indexed by their `group`
+#[derive(Debug, Clone, Default)]
+pub struct ToggleGroupRegistry {
+    groups: HashMap<String, Vec<Button>>,
+}
+
+impl ToggleGroupRegistry {
+    /// Collects every grouped toggle in the menu tree
+    pub fn from_menu(menu: &Menu) -> Self {
+        let mut registry = Self::default();
+        menu.walk_buttons(|button, _| {
+            if let Button::Toggle { group: Some(group), .. } = button {
+                registry.groups.entry(group.clone()).or_default().push(button.clone());
+            }
+        });
+        registry
+    }
+
+    /// Toggles belonging to `group`, in menu order
+    pub fn members(&self, group: &str) -> &[Button] {
+        self.groups.get(group).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// Configuration for running a group action
+#[derive(Debug, Clone)]
+pub struct GroupActionConfig {
+    /// Maximum number of member commands running at once
+    pub max_concurrency: usize,
+    /// Process settings applied to members that do not opt out of them
+    pub spawn: SpawnConfig,
+    /// Probe backends the members' `probe_backend` is looked up in
+    pub backends: ProbeBackendRegistry,
+    /// Registry the members' `lock` is taken from
+    pub locks: ButtonLocks,
+}
+
+impl Default for GroupActionConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 4,
+            spawn: SpawnConfig::default(),
+            backends: ProbeBackendRegistry::default(),
+            locks: ButtonLocks::default(),
+        }
+    }
+}
+
+/// Per-member results of a group action
+#[derive(Debug, Clone, Default)]
+pub struct GroupActionResult {
+    pub results: Vec<(String, ToggleCommandResult)>,
+}
+
+impl GroupActionResult {
+    /// Returns true if every member's command succeeded
+    pub fn success(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.success)
+    }
+
+    /// Names of the members whose command failed
+    pub fn failed(&self) -> Vec<&str> {
+        self.results
+            .iter()
+            .filter(|(_, result)| !result.success)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+/// Applies `action` to every toggle in `group`
+pub async fn execute_group_action(
+    registry: &ToggleGroupRegistry,
+    group: &str,
+    action: GroupActionKind,
+    state_manager: &ToggleStateManager,
+) -> GroupActionResult {
+    execute_group_action_with_config(registry, group, action, state_manager, &GroupActionConfig::default()).await
+}
+
+/// Applies `action` to every toggle in `group`, running at most `max_concurrency` members at once
+pub async fn execute_group_action_with_config(
+    registry: &ToggleGroupRegistry,
+    group: &str,
+    action: GroupActionKind,
+    state_manager: &ToggleStateManager,
+    config: &GroupActionConfig,
+) -> GroupActionResult {
+    let members = registry.members(group);
+    info!("Running group action {:?} on '{}' ({} members)", action, group, members.len());
+
+    let result = run_toggle_actions(members.iter().map(|member| (member, action)), state_manager, config).await;
+    if !result.success() {
+        error!("Group action on '{}' failed for: {:?}", group, result.failed());
+    }
+    result
+}
+
+/// Applies an action to each toggle, running at most `max_concurrency` of them at once.
+/// Results are in the order the toggles were given; buttons that are not toggles are skipped.
+pub(crate) async fn run_toggle_actions<'a>(
+    actions: impl IntoIterator<Item = (&'a Button, GroupActionKind)>,
+    state_manager: &ToggleStateManager,
+    config: &GroupActionConfig,
+) -> GroupActionResult {
+    let permits = Arc::new(Semaphore::new(config.max_concurrency.max(1)));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (index, (member, action)) in actions.into_iter().enumerate() {
+        let Button::Toggle { name, mode, probe_command, probe_args, no_prefix, concurrent_verify, readonly, log_output, .. } = member else {
+            continue;
+        };
+        let spawn = SpawnConfig {
+            log_output: log_output.unwrap_or(config.spawn.log_output),
+            ..config.spawn.clone()
+        };
+        let toggle_config = ToggleCommandConfig {
+            spawn: if *no_prefix {
+                SpawnConfig { command_prefix: Vec::new(), ..spawn }
+            } else {
+                spawn
+            },
+            probe: ProbeContext::from_button(member),
+            backends: config.backends.clone(),
+            retry: RetryPolicy::for_button(member),
+            concurrent_verify: *concurrent_verify,
+            readonly: *readonly,
+            locks: config.locks.clone(),
+            lock: member.lock().map(str::to_string),
+        };
+        let (name, mode, probe_command, probe_args) =
+            (name.clone(), mode.clone(), probe_command.clone(), probe_args.clone());
+        let state_manager = state_manager.clone();
+        let permits = permits.clone();
+
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let result = match action {
+                GroupActionKind::Toggle => {
+                    execute_toggle_command_with_config(
+                        &name,
+                        &mode,
+                        probe_command.as_deref(),
+                        &probe_args,
+                        &state_manager,
+                        &toggle_config,
+                    )
+                    .await
+                }
+                GroupActionKind::On | GroupActionKind::Off => {
+                    let target = if action == GroupActionKind::On { ToggleState::On } else { ToggleState::Off };
+                    execute_toggle_to_state(
+                        &name,
+                        &mode,
+                        probe_command.as_deref(),
+                        &probe_args,
+                        &state_manager,
+                        target,
+                        &toggle_config,
+                    )
+                    .await
+                }
+            };
+            (index, name, result)
+        });
+    }
+
+    let mut results = Vec::with_capacity(tasks.len());
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(result) => results.push(result),
+            Err(e) => error!("Group action task failed: {}", e),
+        }
+    }
+    results.sort_by_key(|(index, _, _)| *index);
+
+    GroupActionResult {
+        results: results.into_iter().map(|(_, name, result)| (name, result)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn lights_config() -> Config {
+        serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Desk Lamp"
+      mode: separate
+      on_command: "true"
+      off_command: "true"
+      group: "lights"
+    - type: menu
+      name: "Bedroom"
+      buttons:
+        - type: toggle
+          name: "Ceiling"
+          mode: single
+          command: "true"
+          group: "lights"
+    - type: toggle
+      name: "Fan"
+      mode: single
+      command: "true"
+    - type: group_action
+      name: "Lights Off"
+      group: "lights"
+      action: "off"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_registry_collects_nested_members() {
+        let registry = ToggleGroupRegistry::from_menu(&lights_config().menu);
+        let names: Vec<_> = registry
+            .members("lights")
+            .iter()
+            .map(crate::toggle_icons::get_simple_display_name)
+            .collect();
+        assert_eq!(names, vec!["Desk Lamp", "Ceiling"]);
+        assert!(registry.members("fans").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_group_action_off_turns_all_members_off() {
+        let registry = ToggleGroupRegistry::from_menu(&lights_config().menu);
+        let state_manager = ToggleStateManager::new();
+        state_manager.set_state("Desk Lamp", ToggleState::On);
+        state_manager.set_state("Ceiling", ToggleState::On);
+        state_manager.set_state("Fan", ToggleState::On);
+
+        let result = execute_group_action(&registry, "lights", GroupActionKind::Off, &state_manager).await;
+        assert!(result.success());
+        assert_eq!(result.results.len(), 2);
+        assert_eq!(state_manager.get_state("Desk Lamp"), ToggleState::Off);
+        assert_eq!(state_manager.get_state("Ceiling"), ToggleState::Off);
+        // Toggles outside the group are left alone
+        assert_eq!(state_manager.get_state("Fan"), ToggleState::On);
+
+        // Members already in the target state are not flipped back
+        let result = execute_group_action(&registry, "lights", GroupActionKind::Off, &state_manager).await;
+        assert!(result.success());
+        assert_eq!(state_manager.get_state("Ceiling"), ToggleState::Off);
+    }
+
+    #[tokio::test]
+    async fn test_group_action_toggle_reports_failures() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Works"
+      mode: single
+      command: "true"
+      group: "mixed"
+    - type: toggle
+      name: "Breaks"
+      mode: single
+      command: "false"
+      group: "mixed"
+"#,
+        )
+        .unwrap();
+        let registry = ToggleGroupRegistry::from_menu(&config.menu);
+        let state_manager = ToggleStateManager::new();
+        state_manager.set_state("Works", ToggleState::Off);
+        state_manager.set_state("Breaks", ToggleState::Off);
+
+        let settings = GroupActionConfig {
+            max_concurrency: 1,
+            ..Default::default()
+        };
+        let result =
+            execute_group_action_with_config(&registry, "mixed", GroupActionKind::Toggle, &state_manager, &settings).await;
+        assert!(!result.success());
+        assert_eq!(result.failed(), vec!["Breaks"]);
+        assert_eq!(state_manager.get_state("Works"), ToggleState::On);
+        assert_eq!(state_manager.get_state("Breaks"), ToggleState::Off);
+    }
+}
@@ -1,94 +1,209 @@
-use crate::config::Button;
-use crate::icons::resolve_icon;
+use crate::config::{BrightnessKind, Button, GroupActionKind, IconPriority, Sublabel};
+use crate::icons::{is_no_icon, lookup_icon, resolve_icon};
+use crate::last_result::LastResultManager;
 use crate::toggle_state::{ToggleState, ToggleStateManager};
 use tracing::debug;
 
-/// Resolves the appropriate icon for a toggle button based on its current state
+/// Icon shown while a toggle's command runs, unless the toggle sets `pending_icon`
+pub const PENDING_ICON: &str = "sync";
+
+/// Resolves the appropriate icon for a toggle button based on its current state;
+/// `None` for other buttons, see [`resolve_button_icon`]
 pub fn resolve_toggle_icon(
     button: &Button,
     state_manager: &ToggleStateManager,
 ) -> Option<&'static str> {
     match button {
-        Button::Toggle { name, on_icon, off_icon, icon, .. } => {
+        Button::Toggle { name, on_icon, off_icon, icon, pending_icon, icon_priority, auto_style_by_state, .. } => {
+            if state_manager.is_pending(name) {
+                debug!("Toggle '{}' is pending", name);
+                return resolve_state_icon(name, pending_icon.as_ref(), None, IconPriority::StateFirst, PENDING_ICON);
+            }
             let current_state = state_manager.get_state(name);
             
             debug!("Resolving icon for toggle '{}' in state {:?}", name, current_state);
-            
-            match current_state {
-                ToggleState::On => {
-                    // Try on_icon first, then fallback to general icon, then default
-                    if let Some(resolved) = on_icon.as_ref().and_then(|i| resolve_icon(Some(i))) {
-                        debug!("Using on_icon for '{}': resolved", name);
-                        Some(resolved)
-                    } else if let Some(resolved) = icon.as_ref().and_then(|i| resolve_icon(Some(i))) {
-                        debug!("Using fallback icon for '{}' (on state): resolved", name);
-                        Some(resolved)
-                    } else {
-                        debug!("No icon specified for '{}' (on state), using default", name);
-                        resolve_icon(Some(&"toggle_on".to_string()))
-                    }
-                }
-                ToggleState::Off => {
-                    // Try off_icon first, then fallback to general icon, then default
-                    if let Some(resolved) = off_icon.as_ref().and_then(|i| resolve_icon(Some(i))) {
-                        debug!("Using off_icon for '{}': resolved", name);
-                        Some(resolved)
-                    } else if let Some(resolved) = icon.as_ref().and_then(|i| resolve_icon(Some(i))) {
-                        debug!("Using fallback icon for '{}' (off state): resolved", name);
-                        Some(resolved)
-                    } else {
-                        debug!("No icon specified for '{}' (off state), using default", name);
-                        resolve_icon(Some(&"toggle_off".to_string()))
-                    }
-                }
-                ToggleState::Unknown => {
-                    // For unknown state, prefer fallback icon, then a question mark or similar
-                    if let Some(resolved) = icon.as_ref().and_then(|i| resolve_icon(Some(i))) {
-                        debug!("Using fallback icon for '{}' (unknown state): resolved", name);
-                        Some(resolved)
+            let styled;
+            let icon = match icon {
+                Some(spec) if *auto_style_by_state && !is_no_icon(Some(spec)) => {
+                    styled = auto_style_icon(spec, current_state);
+                    if lookup_icon(&styled).is_some() {
+                        Some(&styled)
                     } else {
-                        debug!("No icon specified for '{}' (unknown state), using default", name);
-                        resolve_icon(Some(&"help".to_string()))
+                        debug!("No '{}' icon for '{}', using '{}' as written", styled, name, spec);
+                        Some(spec)
                     }
                 }
+                _ => icon.as_ref(),
+            };
+
+            match current_state {
+                ToggleState::On => resolve_state_icon(name, on_icon.as_ref(), icon, *icon_priority, "toggle_on"),
+                ToggleState::Off => resolve_state_icon(name, off_icon.as_ref(), icon, *icon_priority, "toggle_off"),
+                // For unknown state, prefer fallback icon, then a question mark
+                ToggleState::Unknown => resolve_state_icon(name, None, icon, *icon_priority, "help"),
             }
         }
-        // For non-toggle buttons, use the standard icon resolution
-        Button::Command { icon, .. }
-        | Button::Menu { icon, .. }
-        | Button::Back { icon, .. } => {
-            resolve_icon(icon.as_ref())
+        _ => None,
+    }
+}
+
+/// Resolves the icon of any button's key: a toggle's follows its state, other buttons
+/// show their `icon`, else the default of their kind from [`default_button_icon`]
+pub fn resolve_button_icon(button: &Button, state_manager: &ToggleStateManager) -> Option<&'static str> {
+    match (button, button.icon()) {
+        (Button::Toggle { .. }, _) => resolve_toggle_icon(button, state_manager),
+        (_, Some(icon)) => resolve_icon(Some(icon)),
+        (_, None) => resolve_icon(Some(&default_button_icon(button)?.to_string())),
+    }
+}
+
+/// Icon shown on a button without an explicit `icon`; `None` for the kinds that are
+/// text-only by default. Toggles pick theirs by state in [`resolve_toggle_icon`].
+pub fn default_button_icon(button: &Button) -> Option<&'static str> {
+    match button {
+        Button::Input { .. } => Some("dialpad"),
+        Button::Reload { .. } => Some("refresh"),
+        Button::Scene { .. } => Some("movie"),
+        Button::Match { .. } => Some("alt_route"),
+        Button::Branch { .. } => Some("call_split"),
+        Button::Notification { .. } => Some("notifications"),
+        Button::Brightness { action, value, .. } => Some(default_brightness_icon(*action, *value)),
+        Button::GroupAction { action, .. } => Some(default_group_action_icon(*action)),
+        Button::Command { .. } | Button::Menu { .. } | Button::Back { .. } | Button::Toggle { .. } => None,
+    }
+}
+
+/// The general icon of a toggle with `auto_style_by_state` in `state`: filled when On,
+/// outlined when Off and as written while Unknown. A style prefix on `icon` is replaced.
+pub fn auto_style_icon(icon: &str, state: ToggleState) -> String {
+    let name = icon.split_once(':').map_or(icon, |(_, name)| name);
+    match state {
+        ToggleState::On => format!("filled:{}", name),
+        ToggleState::Off => format!("outlined:{}", name),
+        ToggleState::Unknown => icon.to_string(),
+    }
+}
+
+/// Tries the state-specific icon and the general `icon` in the order `priority` gives,
+/// then `default`. An icon set to "none" stops the fallback and leaves the key text-only.
+fn resolve_state_icon(
+    name: &str,
+    state_icon: Option<&String>,
+    icon: Option<&String>,
+    priority: IconPriority,
+    default: &str,
+) -> Option<&'static str> {
+    let order = match priority {
+        IconPriority::StateFirst => [state_icon, icon],
+        IconPriority::GeneralFirst => [icon, state_icon],
+    };
+    for spec in order.into_iter().flatten() {
+        if is_no_icon(Some(spec)) {
+            debug!("Toggle '{}' is text-only", name);
+            return None;
+        }
+        if let Some(resolved) = resolve_icon(Some(spec)) {
+            debug!("Using icon '{}' for '{}'", spec, name);
+            return Some(resolved);
+        }
+    }
+    debug!("No icon specified for '{}', using default '{}'", name, default);
+    resolve_icon(Some(&default.to_string()))
+}
+
+/// Icon shown on a group action button without an explicit `icon`
+pub fn default_group_action_icon(action: GroupActionKind) -> &'static str {
+    match action {
+        GroupActionKind::On => "toggle_on",
+        GroupActionKind::Off => "toggle_off",
+        GroupActionKind::Toggle => "sync",
+    }
+}
+
+/// Icon shown on a brightness button without an explicit `icon`
+pub fn default_brightness_icon(action: BrightnessKind, value: i32) -> &'static str {
+    match action {
+        BrightnessKind::Set => "brightness_medium",
+        BrightnessKind::Step if value < 0 => "brightness_low",
+        BrightnessKind::Step => "brightness_high",
+    }
+}
+
+/// Resolves the icon for a command button based on the outcome of its last run
+pub fn resolve_command_icon(
+    button: &Button,
+    last_results: &LastResultManager,
+) -> Option<&'static str> {
+    match button {
+        Button::Command { name, icon, icon_map: Some(icon_map), .. } => {
+            let outcome = last_results.get(name);
+            debug!("Resolving icon for command '{}' with last outcome {:?}", name, outcome);
+            resolve_icon(icon_map.select(outcome, icon.as_ref()))
         }
+        Button::Command { icon, .. } => resolve_icon(icon.as_ref()),
+        _ => None,
     }
 }
 
 /// Gets the display name for a toggle button, potentially with state indicators
 pub fn get_toggle_display_name(button: &Button, state_manager: &ToggleStateManager) -> String {
+    get_toggle_display_name_localized(button, state_manager, None)
+}
+
+/// Gets the display name for `locale`, potentially with state indicators
+pub fn get_toggle_display_name_localized(
+    button: &Button,
+    state_manager: &ToggleStateManager,
+    locale: Option<&str>,
+) -> String {
+    let display_name = get_toggle_label_localized(button, state_manager, locale);
     match button {
         Button::Toggle { name, .. } => {
             let current_state = state_manager.get_state(name);
             match current_state {
-                ToggleState::On => format!("{} ●", name),      // Green dot indicator
-                ToggleState::Off => format!("{} ○", name),     // Empty circle indicator
-                ToggleState::Unknown => format!("{} ?", name), // Question mark for unknown
+                ToggleState::On => format!("{} ●", display_name),      // Green dot indicator
+                ToggleState::Off => format!("{} ○", display_name),     // Empty circle indicator
+                ToggleState::Unknown => format!("{} ?", display_name), // Question mark for unknown
             }
         }
-        Button::Command { name, .. }
-        | Button::Menu { name, .. }
-        | Button::Back { name, .. } => name.clone(),
+        _ => display_name,
     }
 }
 
-/// Gets a simple display name without state indicators
-pub fn get_simple_display_name(button: &Button) -> &str {
+/// Gets the key label for `locale`: the button's name, followed by the last probe output
+/// for toggles with `label_from_probe` set
+pub fn get_toggle_label_localized(button: &Button, state_manager: &ToggleStateManager, locale: Option<&str>) -> String {
+    let display_name = button.localized_name(locale);
     match button {
-        Button::Command { name, .. }
-        | Button::Menu { name, .. }
-        | Button::Back { name, .. }
-        | Button::Toggle { name, .. } => name,
+        Button::Toggle { name, label_from_probe: true, .. } => match state_manager.get_probe_output(name) {
+            Some(output) => format!("{}: {}", display_name, output),
+            None => display_name.to_string(),
+        },
+        _ => display_name.to_string(),
     }
 }
 
+/// Gets the sublabel shown beneath a toggle's label, if it has one
+pub fn get_toggle_sublabel(button: &Button, state_manager: &ToggleStateManager) -> Option<String> {
+    match button {
+        Button::Toggle { sublabel: Some(Sublabel::Text(text)), .. } => Some(text.clone()),
+        Button::Toggle { name, sublabel: Some(Sublabel::FromProbe { from_probe: true }), .. } => {
+            state_manager.get_probe_output(name).filter(|output| !output.is_empty())
+        }
+        _ => None,
+    }
+}
+
+/// Gets a simple display name without state indicators
+pub fn get_simple_display_name(button: &Button) -> &str {
+    get_simple_display_name_localized(button, None)
+}
+
+/// Gets a simple display name for `locale` without state indicators
+pub fn get_simple_display_name_localized<'a>(button: &'a Button, locale: Option<&str>) -> &'a str {
+    button.localized_name(locale)
+}
+
 /// Checks if a button is a toggle button
 pub fn is_toggle_button(button: &Button) -> bool {
     matches!(button, Button::Toggle { .. })
@@ -112,7 +227,8 @@ pub fn get_toggle_state_description(button: &Button, state_manager: &ToggleState
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::ToggleMode;
+    use crate::config::{IconMap, SingleBehavior, ToggleMode};
+    use crate::last_result::CommandOutcome;
 
     fn create_test_toggle_button() -> Button {
         Button::Toggle {
@@ -120,12 +236,47 @@ mod tests {
             mode: ToggleMode::Single {
                 command: "test".to_string(),
                 args: vec![],
+                single_behavior: SingleBehavior::Flip,
             },
             probe_command: None,
             probe_args: vec![],
             on_icon: Some("wifi".to_string()),
             off_icon: Some("wifi_off".to_string()),
             icon: Some("settings".to_string()),
+            icon_rotate: 0,
+            active_hours: None,
+            pending_icon: None,
+            icon_priority: Default::default(),
+            auto_style_by_state: false,
+            initial_state: None,
+            no_prefix: false,
+            group: None,
+            names: Default::default(),
+            sort_key: None,
+            label_from_probe: false,
+            sublabel: None,
+            probe_cwd: None,
+            probe_env: Default::default(),
+            probe_shell: false,
+            probe_backend: None,
+            probe_http: None,
+            probe_all: vec![],
+            combine: Default::default(),
+            probe_samples: 1,
+            probe_vote: Default::default(),
+            probe_timeout_ms: None,
+            timeout_state: None,
+            success_indicators: Vec::new(),
+            failure_indicators: Vec::new(),
+            check_stderr: false,
+            retries: 0,
+            retry_delay_ms: 0,
+            concurrent_verify: false,
+            readonly: false,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            lock: None,
         }
     }
 
@@ -135,6 +286,28 @@ mod tests {
             command: "echo".to_string(),
             args: vec![],
             icon: Some("terminal".to_string()),
+            icon_rotate: 0,
+            active_hours: None,
+            icon_map: None,
+            no_prefix: false,
+            store_output_as: None,
+            names: Default::default(),
+            sort_key: None,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            retries: 0,
+            retry_delay_ms: 0,
+            delay_ms: 0,
+            queue: false,
+            args_file: None,
+            lock: None,
+            toast_last_line: false,
+            toast_ms: None,
+            wait_ms: None,
+            cooldown_ms: None,
+            success_exit_codes: vec![0],
+            repeat: None,
         }
     }
 
@@ -223,12 +396,47 @@ mod tests {
             mode: ToggleMode::Single {
                 command: "test".to_string(),
                 args: vec![],
+                single_behavior: SingleBehavior::Flip,
             },
             probe_command: None,
             probe_args: vec![],
             on_icon: None,
             off_icon: None,
             icon: None,
+            icon_rotate: 0,
+            active_hours: None,
+            pending_icon: None,
+            icon_priority: Default::default(),
+            auto_style_by_state: false,
+            initial_state: None,
+            no_prefix: false,
+            group: None,
+            names: Default::default(),
+            sort_key: None,
+            label_from_probe: false,
+            sublabel: None,
+            probe_cwd: None,
+            probe_env: Default::default(),
+            probe_shell: false,
+            probe_backend: None,
+            probe_http: None,
+            probe_all: vec![],
+            combine: Default::default(),
+            probe_samples: 1,
+            probe_vote: Default::default(),
+            probe_timeout_ms: None,
+            timeout_state: None,
+            success_indicators: Vec::new(),
+            failure_indicators: Vec::new(),
+            check_stderr: false,
+            retries: 0,
+            retry_delay_ms: 0,
+            concurrent_verify: false,
+            readonly: false,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            lock: None,
         };
         
         state_manager.set_state("Minimal Toggle", ToggleState::Unknown);
@@ -236,6 +444,398 @@ mod tests {
         
         // Test with command button (should use standard resolution)
         let command = create_test_command_button();
-        let _result = resolve_toggle_icon(&command, &state_manager);
+        assert_eq!(resolve_toggle_icon(&command, &state_manager), None);
+        let _result = resolve_button_icon(&command, &state_manager);
+    }
+
+    #[test]
+    fn test_command_icon_follows_last_outcome() {
+        let icon_map = IconMap {
+            success: Some("check".to_string()),
+            failure: Some("error".to_string()),
+        };
+        let button = Button::Command {
+            name: "Backup".to_string(),
+            command: "backup".to_string(),
+            args: vec![],
+            icon: Some("storage".to_string()),
+            icon_rotate: 0,
+            active_hours: None,
+            icon_map: Some(icon_map.clone()),
+            no_prefix: false,
+            store_output_as: None,
+            names: Default::default(),
+            sort_key: None,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            retries: 0,
+            retry_delay_ms: 0,
+            delay_ms: 0,
+            queue: false,
+            args_file: None,
+            lock: None,
+            toast_last_line: false,
+            toast_ms: None,
+            wait_ms: None,
+            cooldown_ms: None,
+            success_exit_codes: vec![0],
+            repeat: None,
+        };
+        let last_results = LastResultManager::new();
+        let selected = |results: &LastResultManager| {
+            icon_map
+                .select(results.get("Backup"), Some(&"storage".to_string()))
+                .cloned()
+        };
+
+        // No run recorded yet: the static icon is used
+        assert_eq!(selected(&last_results).as_deref(), Some("storage"));
+        let _result = resolve_command_icon(&button, &last_results);
+
+        last_results.record("Backup", CommandOutcome::from_exit_code(0));
+        assert_eq!(selected(&last_results).as_deref(), Some("check"));
+        let _result = resolve_command_icon(&button, &last_results);
+
+        last_results.record("Backup", CommandOutcome::from_exit_code(3));
+        assert_eq!(selected(&last_results).as_deref(), Some("error"));
+
+        last_results.record("Backup", CommandOutcome::execution_error());
+        assert_eq!(selected(&last_results).as_deref(), Some("error"));
+    }
+
+    #[test]
+    fn test_icon_map_partial_falls_back_to_icon() {
+        let icon_map = IconMap {
+            success: None,
+            failure: Some("error".to_string()),
+        };
+        let icon = "storage".to_string();
+        assert_eq!(
+            icon_map.select(Some(CommandOutcome::from_exit_code(0)), Some(&icon)),
+            Some(&icon)
+        );
+        assert_eq!(icon_map.select(None, None), None);
+    }
+
+    #[test]
+    fn test_pending_icon_overrides_state_icon() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Default"
+      mode: single
+      command: "true"
+      icon: "none"
+      on_icon: "none"
+    - type: toggle
+      name: "Custom"
+      mode: single
+      command: "true"
+      pending_icon: "none"
+"#,
+        )
+        .unwrap();
+        let default = &config.menu.buttons[0];
+        let custom = &config.menu.buttons[1];
+        let state_manager = ToggleStateManager::new();
+        state_manager.set_state("Default", ToggleState::On);
+        assert_eq!(resolve_toggle_icon(default, &state_manager), None);
+        assert!(resolve_toggle_icon(custom, &state_manager).is_some());
+
+        // While pending, the pending icon replaces whatever the state would show
+        let _default_pending = state_manager.begin_pending("Default");
+        let _custom_pending = state_manager.begin_pending("Custom");
+        assert_eq!(
+            resolve_toggle_icon(default, &state_manager),
+            resolve_icon(Some(&PENDING_ICON.to_string()))
+        );
+        assert!(resolve_toggle_icon(default, &state_manager).is_some());
+        assert_eq!(resolve_toggle_icon(custom, &state_manager), None);
+    }
+
+    #[test]
+    fn test_icon_priority() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "State First"
+      mode: single
+      command: "true"
+      on_icon: "wifi"
+      icon: "settings"
+    - type: toggle
+      name: "General First"
+      mode: single
+      command: "true"
+      on_icon: "wifi"
+      icon: "settings"
+      icon_priority: general_first
+"#,
+        )
+        .unwrap();
+        let state_first = &config.menu.buttons[0];
+        let general_first = &config.menu.buttons[1];
+        let icon = |name: &str| resolve_icon(Some(&name.to_string()));
+        let state_manager = ToggleStateManager::new();
+
+        // By default the state icon wins and the general icon fills in for the other states
+        state_manager.set_state("State First", ToggleState::On);
+        assert_eq!(resolve_toggle_icon(state_first, &state_manager), icon("wifi"));
+        state_manager.set_state("State First", ToggleState::Off);
+        assert_eq!(resolve_toggle_icon(state_first, &state_manager), icon("settings"));
+
+        // general_first shows the general icon in every state
+        for state in [ToggleState::On, ToggleState::Off, ToggleState::Unknown] {
+            state_manager.set_state("General First", state);
+            assert_eq!(resolve_toggle_icon(general_first, &state_manager), icon("settings"));
+        }
+        assert!(icon("settings").is_some());
+    }
+
+    #[test]
+    fn test_auto_style_by_state() {
+        assert_eq!(auto_style_icon("wifi", ToggleState::On), "filled:wifi");
+        assert_eq!(auto_style_icon("sharp:wifi", ToggleState::Off), "outlined:wifi");
+        assert_eq!(auto_style_icon("sharp:wifi", ToggleState::Unknown), "sharp:wifi");
+
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Wifi"
+      mode: single
+      command: "true"
+      icon: "wifi"
+      auto_style_by_state: true
+"#,
+        )
+        .unwrap();
+        let button = &config.menu.buttons[0];
+        let state_manager = ToggleStateManager::new();
+
+        state_manager.set_state("Wifi", ToggleState::On);
+        assert!(lookup_icon("filled:wifi").is_some());
+        assert_eq!(resolve_toggle_icon(button, &state_manager), lookup_icon("filled:wifi"));
+        state_manager.set_state("Wifi", ToggleState::Off);
+        assert!(lookup_icon("outlined:wifi").is_some());
+        assert_eq!(resolve_toggle_icon(button, &state_manager), lookup_icon("outlined:wifi"));
+        state_manager.set_state("Wifi", ToggleState::Unknown);
+        assert_eq!(resolve_toggle_icon(button, &state_manager), resolve_icon(Some(&"wifi".to_string())));
+    }
+
+    #[test]
+    fn test_button_icon_defaults() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: input
+      name: "Volume"
+      command: "pamixer"
+    - type: reload
+    - type: scene
+      name: "Movie"
+      scene: "movie"
+    - type: scene
+      name: "Party"
+      scene: "party"
+      icon: "celebration"
+    - type: menu
+      name: "More"
+      buttons: []
+"#,
+        )
+        .unwrap();
+        let buttons = &config.menu.buttons;
+        let state_manager = ToggleStateManager::new();
+        let icon = |name: &str| resolve_icon(Some(&name.to_string()));
+
+        assert_eq!(default_button_icon(&buttons[0]), Some("dialpad"));
+        assert_eq!(resolve_button_icon(&buttons[0], &state_manager), icon("dialpad"));
+        assert_eq!(resolve_button_icon(&buttons[1], &state_manager), icon("refresh"));
+        assert_eq!(resolve_button_icon(&buttons[2], &state_manager), icon("movie"));
+        // An explicit icon wins over the default of its kind
+        assert_eq!(resolve_button_icon(&buttons[3], &state_manager), icon("celebration"));
+        // Menus are text-only unless they name an icon
+        assert_eq!(default_button_icon(&buttons[4]), None);
+        assert_eq!(resolve_button_icon(&buttons[4], &state_manager), None);
+    }
+
+    #[test]
+    fn test_no_icon_renders_text_only() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Plain"
+      mode: single
+      command: "true"
+      icon: "none"
+    - type: toggle
+      name: "Off Only"
+      mode: single
+      command: "true"
+      on_icon: "wifi"
+      off_icon: "none"
+      icon: "help"
+    - type: command
+      name: "Text"
+      command: "true"
+      icon: "none"
+      icon_map: { failure: "none" }
+"#,
+        )
+        .unwrap();
+        let plain = &config.menu.buttons[0];
+        let off_only = &config.menu.buttons[1];
+        let command = &config.menu.buttons[2];
+        let state_manager = ToggleStateManager::new();
+        let last_results = LastResultManager::new();
+
+        // No fallback to the toggle_on/toggle_off/help defaults in any state
+        for state in [ToggleState::Unknown, ToggleState::On, ToggleState::Off] {
+            state_manager.set_state("Plain", state);
+            assert_eq!(resolve_toggle_icon(plain, &state_manager), None);
+        }
+
+        // "none" on a state icon stops the fallback to `icon` for that state only
+        state_manager.set_state("Off Only", ToggleState::Off);
+        assert_eq!(resolve_toggle_icon(off_only, &state_manager), None);
+        state_manager.set_state("Off Only", ToggleState::On);
+        assert!(resolve_toggle_icon(off_only, &state_manager).is_some());
+
+        assert_eq!(resolve_command_icon(command, &last_results), None);
+        last_results.record("Text", CommandOutcome::from_exit_code(1));
+        assert_eq!(resolve_command_icon(command, &last_results), None);
+        assert_eq!(crate::icon_layout::IconLayout::new(0.5, 0.1).apply(None), None);
+    }
+
+    #[test]
+    fn test_localized_display_names() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+locale: "pl_PL"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Light"
+      names: { en: "Light", pl: "Światło" }
+      mode: single
+      command: "true"
+    - type: command
+      name: "Terminal"
+      names: { de: "Konsole" }
+      command: "true"
+"#,
+        )
+        .unwrap();
+        let locale = config.locale.as_deref();
+        let toggle = &config.menu.buttons[0];
+        let command = &config.menu.buttons[1];
+        let state_manager = ToggleStateManager::new();
+        state_manager.set_state("Light", ToggleState::On);
+
+        // "pl_PL" falls back to the "pl" translation
+        assert_eq!(get_simple_display_name_localized(toggle, locale), "Światło");
+        assert_eq!(get_toggle_display_name_localized(toggle, &state_manager, locale), "Światło ●");
+        assert_eq!(get_simple_display_name_localized(toggle, Some("en")), "Light");
+
+        // Missing translations and no locale fall back to the plain name
+        assert_eq!(get_simple_display_name_localized(command, locale), "Terminal");
+        assert_eq!(get_toggle_display_name_localized(command, &state_manager, locale), "Terminal");
+        assert_eq!(get_simple_display_name(toggle), "Light");
+        assert_eq!(get_toggle_display_name(toggle, &state_manager), "Light ●");
+    }
+
+    #[tokio::test]
+    async fn test_label_from_probe_output() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Power"
+      mode: single
+      command: "true"
+      probe_command: "echo"
+      probe_args: ["performance"]
+      label_from_probe: true
+    - type: toggle
+      name: "Wifi"
+      mode: single
+      command: "true"
+      probe_command: "echo"
+      probe_args: ["connected"]
+"#,
+        )
+        .unwrap();
+        let power = &config.menu.buttons[0];
+        let wifi = &config.menu.buttons[1];
+        let state_manager = ToggleStateManager::new();
+
+        // Nothing probed yet, so only the name is shown
+        assert_eq!(get_toggle_label_localized(power, &state_manager, None), "Power");
+
+        crate::button::initialize_toggle_states(&config, &state_manager).await;
+        assert_eq!(state_manager.get_probe_output("Power"), Some("performance".to_string()));
+        assert_eq!(get_toggle_label_localized(power, &state_manager, None), "Power: performance");
+        assert_eq!(get_toggle_display_name(power, &state_manager), "Power: performance ●");
+
+        // Toggles without `label_from_probe` keep their plain name
+        assert_eq!(get_toggle_label_localized(wifi, &state_manager, None), "Wifi");
+        assert_eq!(get_toggle_display_name(wifi, &state_manager), "Wifi ●");
+    }
+
+    #[tokio::test]
+    async fn test_toggle_sublabel() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "nginx"
+      mode: single
+      command: "true"
+      probe_command: "echo"
+      probe_args: ["active"]
+      sublabel: { from_probe: true }
+    - type: toggle
+      name: "VPN"
+      mode: single
+      command: "true"
+      sublabel: "office"
+    - type: toggle
+      name: "Wifi"
+      mode: single
+      command: "true"
+"#,
+        )
+        .unwrap();
+        let [nginx, vpn, wifi] = &config.menu.buttons[..] else { panic!("expected three toggles") };
+        let state_manager = ToggleStateManager::new();
+
+        // A probe sublabel only shows up once there is output
+        assert_eq!(get_toggle_sublabel(nginx, &state_manager), None);
+        crate::button::initialize_toggle_states(&config, &state_manager).await;
+        assert_eq!(get_toggle_sublabel(nginx, &state_manager), Some("active".to_string()));
+        assert_eq!(get_toggle_label_localized(nginx, &state_manager, None), "nginx");
+
+        assert_eq!(get_toggle_sublabel(vpn, &state_manager), Some("office".to_string()));
+        assert_eq!(get_toggle_sublabel(wifi, &state_manager), None);
     }
-}
\ No newline at end of file
+}
@@ -3,8 +3,10 @@
 //! This module contains comprehensive tests that validate the entire toggle button
 //! implementation including state management, command execution, probing, and UI integration.
 
-use crate::config::{Button, Menu, ToggleMode};
-use crate::probe::{execute_probe_command, ProbeConfig, execute_probe_command_with_config};
+use crate::button::{initialize_toggle_states, seed_initial_toggle_states, CommanderContext};
+use crate::config::{Button, Menu, MenuSort, SingleBehavior, ToggleMode};
+use crate::test_support::{context_for, TempDir};
+use crate::probe::{execute_probe_command, ProbeConfig, ProbeContext, execute_probe_command_with_config};
 use crate::toggle_command::execute_toggle_command;
 use crate::toggle_icons::{resolve_toggle_icon, get_toggle_display_name, is_toggle_button};
 use crate::toggle_state::{ToggleState, ToggleStateManager};
@@ -19,12 +21,47 @@ mod tests {
             mode: ToggleMode::Single {
                 command: "nmcli".to_string(),
                 args: vec!["radio".to_string(), "wifi".to_string()],
+                single_behavior: SingleBehavior::Flip,
             },
             probe_command: Some("nmcli".to_string()),
             probe_args: vec!["radio".to_string(), "wifi".to_string()],
             on_icon: Some("wifi".to_string()),
             off_icon: Some("wifi_off".to_string()),
             icon: Some("settings".to_string()),
+            icon_rotate: 0,
+            active_hours: None,
+            pending_icon: None,
+            icon_priority: Default::default(),
+            auto_style_by_state: false,
+            initial_state: None,
+            no_prefix: false,
+            group: None,
+            names: Default::default(),
+            sort_key: None,
+            label_from_probe: false,
+            sublabel: None,
+            probe_cwd: None,
+            probe_env: Default::default(),
+            probe_shell: false,
+            probe_backend: None,
+            probe_http: None,
+            probe_all: vec![],
+            combine: Default::default(),
+            probe_samples: 1,
+            probe_vote: Default::default(),
+            probe_timeout_ms: None,
+            timeout_state: None,
+            success_indicators: Vec::new(),
+            failure_indicators: Vec::new(),
+            check_stderr: false,
+            retries: 0,
+            retry_delay_ms: 0,
+            concurrent_verify: false,
+            readonly: false,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            lock: None,
         }
     }
 
@@ -42,6 +79,40 @@ mod tests {
             on_icon: Some("vpn_key".to_string()),
             off_icon: Some("vpn_key_off".to_string()),
             icon: None,
+            icon_rotate: 0,
+            active_hours: None,
+            pending_icon: None,
+            icon_priority: Default::default(),
+            auto_style_by_state: false,
+            initial_state: None,
+            no_prefix: false,
+            group: None,
+            names: Default::default(),
+            sort_key: None,
+            label_from_probe: false,
+            sublabel: None,
+            probe_cwd: None,
+            probe_env: Default::default(),
+            probe_shell: false,
+            probe_backend: None,
+            probe_http: None,
+            probe_all: vec![],
+            combine: Default::default(),
+            probe_samples: 1,
+            probe_vote: Default::default(),
+            probe_timeout_ms: None,
+            timeout_state: None,
+            success_indicators: Vec::new(),
+            failure_indicators: Vec::new(),
+            check_stderr: false,
+            retries: 0,
+            retry_delay_ms: 0,
+            concurrent_verify: false,
+            readonly: false,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            lock: None,
         }
     }
 
@@ -54,6 +125,28 @@ mod tests {
                     command: "echo".to_string(),
                     args: vec!["hello".to_string()],
                     icon: Some("terminal".to_string()),
+                    icon_rotate: 0,
+                    active_hours: None,
+                    icon_map: None,
+                    no_prefix: false,
+                    store_output_as: None,
+                    names: Default::default(),
+                    sort_key: None,
+                    trigger_on: None,
+                    confirm_hold_ms: None,
+                    log_output: None,
+                    retries: 0,
+                    retry_delay_ms: 0,
+                    delay_ms: 0,
+                    queue: false,
+                    args_file: None,
+                    lock: None,
+                    toast_last_line: false,
+                    toast_ms: None,
+                    wait_ms: None,
+                    cooldown_ms: None,
+                    success_exit_codes: vec![0],
+                    repeat: None,
                 },
                 create_single_mode_toggle(),
                 create_separate_mode_toggle(),
@@ -61,8 +154,20 @@ mod tests {
                     name: "Submenu".to_string(),
                     buttons: vec![create_single_mode_toggle()],
                     icon: Some("folder".to_string()),
+                    icon_rotate: 0,
+                    active_hours: None,
+                    names: Default::default(),
+                    sort_key: None,
+                    theme: None,
+                    sort: MenuSort::None,
+                    background_image: None,
+                    dynamic: None,
                 },
             ],
+            theme: None,
+            sort: MenuSort::None,
+            background_image: None,
+            dynamic: None,
         }
     }
 
@@ -75,6 +180,28 @@ mod tests {
             command: "echo".to_string(),
             args: vec![],
             icon: None,
+            icon_rotate: 0,
+            active_hours: None,
+            icon_map: None,
+            no_prefix: false,
+            store_output_as: None,
+            names: Default::default(),
+            sort_key: None,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            retries: 0,
+            retry_delay_ms: 0,
+            delay_ms: 0,
+            queue: false,
+            args_file: None,
+            lock: None,
+            toast_last_line: false,
+            toast_ms: None,
+            wait_ms: None,
+            cooldown_ms: None,
+            success_exit_codes: vec![0],
+            repeat: None,
         };
 
         assert!(is_toggle_button(&single_toggle));
@@ -143,12 +270,47 @@ mod tests {
             mode: ToggleMode::Single {
                 command: "test".to_string(),
                 args: vec![],
+                single_behavior: SingleBehavior::Flip,
             },
             probe_command: None,
             probe_args: vec![],
             on_icon: None,
             off_icon: None,
             icon: None,
+            icon_rotate: 0,
+            active_hours: None,
+            pending_icon: None,
+            icon_priority: Default::default(),
+            auto_style_by_state: false,
+            initial_state: None,
+            no_prefix: false,
+            group: None,
+            names: Default::default(),
+            sort_key: None,
+            label_from_probe: false,
+            sublabel: None,
+            probe_cwd: None,
+            probe_env: Default::default(),
+            probe_shell: false,
+            probe_backend: None,
+            probe_http: None,
+            probe_all: vec![],
+            combine: Default::default(),
+            probe_samples: 1,
+            probe_vote: Default::default(),
+            probe_timeout_ms: None,
+            timeout_state: None,
+            success_indicators: Vec::new(),
+            failure_indicators: Vec::new(),
+            check_stderr: false,
+            retries: 0,
+            retry_delay_ms: 0,
+            concurrent_verify: false,
+            readonly: false,
+            trigger_on: None,
+            confirm_hold_ms: None,
+            log_output: None,
+            lock: None,
         };
 
         state_manager.set_state("Minimal", ToggleState::On);
@@ -179,8 +341,12 @@ mod tests {
         let config = ProbeConfig {
             timeout_ms: 1000,
             empty_stdout_is_success: true,
-            success_indicators: vec!["active".to_string()],
-            failure_indicators: vec!["inactive".to_string()],
+            context: ProbeContext {
+                success_indicators: vec!["active".to_string()],
+                failure_indicators: vec!["inactive".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
         };
 
         // Test with custom success indicator
@@ -208,6 +374,7 @@ mod tests {
         let mode = ToggleMode::Single {
             command: "echo".to_string(),
             args: vec!["toggling".to_string()],
+            single_behavior: SingleBehavior::Flip,
         };
 
         // Test toggle from unknown state
@@ -248,12 +415,110 @@ mod tests {
         assert!(result.stdout.contains("turning_off"));
     }
 
+    #[tokio::test]
+    async fn test_toggle_pending_while_slow_command_runs() {
+        let state_manager = ToggleStateManager::new();
+        state_manager.set_state("Slow", ToggleState::Off);
+        let mode = ToggleMode::Separate {
+            on_command: "sleep".to_string(),
+            on_args: vec!["0.3".to_string()],
+            off_command: "false".to_string(),
+            off_args: vec![],
+        };
+
+        let task = {
+            let (state_manager, mode) = (state_manager.clone(), mode.clone());
+            tokio::spawn(async move { execute_toggle_command("Slow", &mode, None, &[], &state_manager).await })
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(2), async {
+            while !state_manager.is_pending("Slow") {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("toggle never became pending");
+        // The old state is kept until the command resolves
+        assert_eq!(state_manager.get_state("Slow"), ToggleState::Off);
+
+        let result = task.await.unwrap();
+        assert!(result.success);
+        assert!(!state_manager.is_pending("Slow"));
+        assert_eq!(state_manager.get_state("Slow"), ToggleState::On);
+
+        // A failing command clears the pending flag too
+        let result = execute_toggle_command("Slow", &mode, None, &[], &state_manager).await;
+        assert!(!result.success);
+        assert!(!state_manager.is_pending("Slow"));
+        assert_eq!(state_manager.get_state("Slow"), ToggleState::On);
+    }
+
+    #[tokio::test]
+    async fn test_back_after_pending_refresh_in_submenu() {
+        use crate::button::CommanderPlugin;
+        use std::collections::BTreeMap;
+        use std::sync::Arc;
+        use streamdeck_oxide::plugins::PluginContext;
+
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: menu
+      name: "Lights"
+      buttons:
+        - type: toggle
+          name: "Lamp"
+          mode: single
+          command: "sleep"
+          args: ["0.2"]
+          initial_state: "off"
+"#,
+        )
+        .unwrap();
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(8);
+        let mut context = CommanderContext::new(Arc::new(config.clone()), None);
+        context.navigation_sender = Some(sender);
+        let context = Arc::new(context);
+        let plugin_context = PluginContext::new(BTreeMap::from([(
+            std::any::TypeId::of::<CommanderContext>(),
+            Box::new(context.clone()) as Box<dyn std::any::Any + Send + Sync>,
+        )]));
+
+        let root = CommanderPlugin::new_with_state_manager(config.menu.clone(), context.toggle_state_manager.clone());
+        let Button::Menu { name, buttons, .. } = &config.menu.buttons[0] else {
+            panic!("expected a menu button");
+        };
+        let submenu = Menu { name: name.clone(), buttons: buttons.clone(), theme: None, sort: MenuSort::None, background_image: None, dynamic: None };
+        let lights = CommanderPlugin::new_with_parent(submenu, root);
+        let press = tokio::spawn({
+            let (lights, lamp) = (lights.clone(), buttons[0].clone());
+            async move { lights.press_toggle(&plugin_context, &lamp).await }
+        });
+
+        // The submenu is re-rendered with the pending icon and keeps its Back key
+        tokio::time::timeout(std::time::Duration::from_secs(2), receiver.recv()).await.unwrap().unwrap();
+        assert!(context.toggle_state_manager.is_pending("Lamp"));
+        assert!(context.active_plugin().parent().is_some());
+
+        // Once the command resolves it is re-rendered again, and Back still leads to the main menu
+        press.await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(2), receiver.recv()).await.unwrap().unwrap();
+        assert_eq!(context.toggle_state_manager.get_state("Lamp"), ToggleState::On);
+        let shown = context.active_plugin();
+        assert_eq!(shown.menu().name, "Lights");
+        let back = shown.parent().expect("the refreshed submenu lost its Back key");
+        assert_eq!(back.menu().name, "Main");
+        assert!(back.parent().is_none());
+    }
+
     #[tokio::test]
     async fn test_toggle_with_probe_verification() {
         let state_manager = ToggleStateManager::new();
         let mode = ToggleMode::Single {
             command: "echo".to_string(),
             args: vec!["toggle".to_string()],
+            single_behavior: SingleBehavior::Flip,
         };
 
         // Test with probe that succeeds (indicating "on" state)
@@ -287,6 +552,7 @@ mod tests {
         let mode = ToggleMode::Single {
             command: "false".to_string(), // Command that always fails
             args: vec![],
+            single_behavior: SingleBehavior::Flip,
         };
 
         state_manager.set_state("test", ToggleState::Off);
@@ -404,4 +670,719 @@ menu:
         // Verify all buttons were created
         assert_eq!(state_manager.button_count(), 10);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_probe_less_toggle_starts_in_declared_state() {
+        let mut fan = create_single_mode_toggle();
+        if let Button::Toggle { name, probe_command, initial_state, .. } = &mut fan {
+            *name = "Fan".to_string();
+            *probe_command = None;
+            *initial_state = Some(ToggleState::On);
+        }
+        let mut probed = create_separate_mode_toggle();
+        if let Button::Toggle { initial_state, .. } = &mut probed {
+            *initial_state = Some(ToggleState::On);
+        }
+        let menu = Menu {
+            name: "Seed Menu".to_string(),
+            buttons: vec![
+                probed,
+                Button::Menu {
+                    name: "Nested".to_string(),
+                    buttons: vec![fan],
+                    icon: None,
+                    icon_rotate: 0,
+                    active_hours: None,
+                    names: Default::default(),
+                    sort_key: None,
+                    theme: None,
+                    sort: MenuSort::None,
+                    background_image: None,
+                    dynamic: None,
+                },
+            ],
+            theme: None,
+            sort: MenuSort::None,
+            background_image: None,
+            dynamic: None,
+        };
+
+        let state_manager = ToggleStateManager::new();
+        seed_initial_toggle_states(&menu, &state_manager);
+
+        assert_eq!(state_manager.get_state("Fan"), ToggleState::On);
+        // Toggles with a probe are left for the probe to determine
+        assert_eq!(state_manager.get_state("VPN"), ToggleState::Unknown);
+    }
+
+    #[test]
+    fn test_reload_reparses_changed_config() {
+        use std::sync::{Arc, RwLock};
+
+        let dir = TempDir::new("reload");
+        let path = dir.join("config.yaml");
+        let write_config = |menu_name: &str| {
+            std::fs::write(
+                &path,
+                format!(
+                    "menu:\n  name: \"{}\"\n  buttons:\n    - type: reload\n    - type: command\n      name: \"Echo\"\n      command: \"echo\"\n",
+                    menu_name
+                ),
+            )
+            .unwrap();
+        };
+
+        write_config("Before");
+        let initial = crate::config::load_config_from(Some(&path)).unwrap();
+        let context = CommanderContext {
+            config: RwLock::new(Arc::new(initial)),
+            config_path: Some(path.clone()),
+            toggle_state_manager: ToggleStateManager::new(),
+            last_result_manager: crate::last_result::LastResultManager::new(),
+            navigation_sender: None,
+            active_plugin: RwLock::new(None),
+            runtime_vars: crate::runtime_vars::RuntimeVars::new(),
+            events: crate::events::EventBus::new(),
+            probe_backends: crate::probe_backend::ProbeBackendRegistry::new(),
+            activity: crate::idle::ActivityTracker::new(),
+            delayed_commands: crate::delay::DelayedCommands::new(),
+            command_queues: crate::queue::CommandQueues::new(),
+            button_locks: crate::locks::ButtonLocks::new(),
+            execution_history: crate::history::ExecutionHistory::new(),
+            cooldowns: crate::cooldown::Cooldowns::new(),
+            notifier: std::sync::Arc::new(crate::notification::DesktopNotifier),
+            brightness: crate::device::BrightnessControl::new(None, crate::device::MAX_BRIGHTNESS),
+            backgrounds: crate::background::MenuBackgrounds::default(),
+        };
+        assert_eq!(context.current_config().menu.name, "Before");
+
+        write_config("After");
+        let root = context.reload().unwrap();
+        assert_eq!(root.menu().name, "After");
+        assert_eq!(root.menu().buttons.len(), 2);
+        assert!(matches!(&root.menu().buttons[0], Button::Reload { name, .. } if name == "Reload"));
+        assert_eq!(context.current_config().menu.name, "After");
+
+        // A broken file keeps the previously loaded config active
+        std::fs::write(&path, "menu: [").unwrap();
+        assert!(context.reload().is_err());
+        assert_eq!(context.current_config().menu.name, "After");
+    }
+
+    #[tokio::test]
+    async fn test_initialize_toggle_states_reprobes_menu() {
+        use std::sync::{Arc, RwLock};
+
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Up"
+      mode: single
+      command: "true"
+      probe_command: "true"
+    - type: menu
+      name: "Nested"
+      buttons:
+        - type: toggle
+          name: "Down"
+          mode: single
+          command: "true"
+          probe_command: "false"
+    - type: toggle
+      name: "Fan"
+      mode: single
+      command: "true"
+      initial_state: "on"
+"#,
+        )
+        .unwrap();
+
+        let state_manager = ToggleStateManager::new();
+        assert_eq!(initialize_toggle_states(&config, &state_manager).await, 2);
+        assert_eq!(state_manager.get_state("Up"), ToggleState::On);
+        assert_eq!(state_manager.get_state("Down"), ToggleState::Off);
+        assert_eq!(state_manager.get_state("Fan"), ToggleState::On);
+
+        // A re-probe overrides states that drifted since the last probe
+        state_manager.set_state("Up", ToggleState::Off);
+        state_manager.set_state("Down", ToggleState::On);
+        assert_eq!(initialize_toggle_states(&config, &state_manager).await, 2);
+        assert_eq!(state_manager.get_state("Up"), ToggleState::On);
+        assert_eq!(state_manager.get_state("Down"), ToggleState::Off);
+
+        // The SIGHUP handler re-probes through the context without touching the config
+        state_manager.set_state("Down", ToggleState::On);
+        let context = CommanderContext {
+            config: RwLock::new(Arc::new(config)),
+            config_path: None,
+            toggle_state_manager: state_manager.clone(),
+            last_result_manager: crate::last_result::LastResultManager::new(),
+            navigation_sender: None,
+            active_plugin: RwLock::new(None),
+            runtime_vars: crate::runtime_vars::RuntimeVars::new(),
+            events: crate::events::EventBus::new(),
+            probe_backends: crate::probe_backend::ProbeBackendRegistry::new(),
+            activity: crate::idle::ActivityTracker::new(),
+            delayed_commands: crate::delay::DelayedCommands::new(),
+            command_queues: crate::queue::CommandQueues::new(),
+            button_locks: crate::locks::ButtonLocks::new(),
+            execution_history: crate::history::ExecutionHistory::new(),
+            cooldowns: crate::cooldown::Cooldowns::new(),
+            notifier: std::sync::Arc::new(crate::notification::DesktopNotifier),
+            brightness: crate::device::BrightnessControl::new(None, crate::device::MAX_BRIGHTNESS),
+            backgrounds: crate::background::MenuBackgrounds::default(),
+        };
+        assert_eq!(context.refresh_toggle_states().await, 1);
+        assert_eq!(state_manager.get_state("Down"), ToggleState::Off);
+        assert_eq!(context.active_plugin().menu().name, "Main");
+    }
+
+    #[test]
+    fn test_configured_icon_layout_reaches_rendering() {
+        use std::sync::{Arc, RwLock};
+
+        let dir = TempDir::new("icon-layout");
+        let path = dir.join("config.yaml");
+        std::fs::write(
+            &path,
+            "icon_scale: 0.5\nicon_padding: 0.9\nmenu:\n  name: \"Main\"\n  buttons: []\n",
+        )
+        .unwrap();
+        let config = crate::config::load_config_from(Some(&path)).unwrap();
+
+        // Out-of-range values are clamped when the config is loaded
+        assert_eq!(config.icon_scale, 0.5);
+        assert_eq!(config.icon_padding, crate::icon_layout::MAX_ICON_PADDING);
+
+        let context = CommanderContext {
+            config: RwLock::new(Arc::new(config)),
+            config_path: None,
+            toggle_state_manager: ToggleStateManager::new(),
+            last_result_manager: crate::last_result::LastResultManager::new(),
+            navigation_sender: None,
+            active_plugin: RwLock::new(None),
+            runtime_vars: crate::runtime_vars::RuntimeVars::new(),
+            events: crate::events::EventBus::new(),
+            probe_backends: crate::probe_backend::ProbeBackendRegistry::new(),
+            activity: crate::idle::ActivityTracker::new(),
+            delayed_commands: crate::delay::DelayedCommands::new(),
+            command_queues: crate::queue::CommandQueues::new(),
+            button_locks: crate::locks::ButtonLocks::new(),
+            execution_history: crate::history::ExecutionHistory::new(),
+            cooldowns: crate::cooldown::Cooldowns::new(),
+            notifier: std::sync::Arc::new(crate::notification::DesktopNotifier),
+            brightness: crate::device::BrightnessControl::new(None, crate::device::MAX_BRIGHTNESS),
+            backgrounds: crate::background::MenuBackgrounds::default(),
+        };
+        let layout = context.icon_layout();
+        assert_eq!(layout.scale, 0.5);
+        assert_eq!(layout.padding, crate::icon_layout::MAX_ICON_PADDING);
+
+        let icon = layout.apply(Some("<svg viewBox=\"0 0 24 24\"><path/></svg>")).unwrap();
+        assert!(icon.contains("viewBox=\"0 0 100 100\""));
+    }
+
+    #[tokio::test]
+    async fn test_initialize_toggle_states_follows_probe_order() {
+        let dir = TempDir::new("probe-order");
+        let log = dir.join("probes.log");
+
+        // "Slow" would finish last if every probe ran at once
+        let yaml = format!(
+            r#"
+probe_order:
+  - ["Slow"]
+  - ["Fast"]
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Unlisted"
+      mode: single
+      command: "true"
+      probe_command: "sh"
+      probe_args: ["-c", "echo unlisted >> {log}"]
+    - type: toggle
+      name: "Fast"
+      mode: single
+      command: "true"
+      probe_command: "sh"
+      probe_args: ["-c", "echo fast >> {log}"]
+    - type: toggle
+      name: "Slow"
+      mode: single
+      command: "true"
+      probe_command: "sh"
+      probe_args: ["-c", "sleep 0.3; echo slow >> {log}"]
+"#,
+            log = log.display()
+        );
+        let config: crate::config::Config = serde_yaml::from_str(&yaml).unwrap();
+        config.validate().unwrap();
+
+        let state_manager = ToggleStateManager::new();
+        assert_eq!(initialize_toggle_states(&config, &state_manager).await, 3);
+
+        let order = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(order.lines().collect::<Vec<_>>(), vec!["slow", "fast", "unlisted"]);
+    }
+
+    /// Initializes toggles whose probe succeeds, fails and cannot run, each saved with the
+    /// opposite state, and returns their states after startup
+    async fn initialize_with_state_source(state_source: &str) -> [ToggleState; 3] {
+        let config: crate::config::Config = serde_yaml::from_str(&format!(
+            r#"
+state_source: {}
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Succeeds"
+      mode: single
+      command: "true"
+      probe_command: "true"
+    - type: toggle
+      name: "Fails"
+      mode: single
+      command: "true"
+      probe_command: "false"
+    - type: toggle
+      name: "Errors"
+      mode: single
+      command: "true"
+      probe_command: "streamdeck-no-such-probe"
+"#,
+            state_source
+        ))
+        .unwrap();
+        let persisted = std::collections::HashMap::from([
+            ("Succeeds".to_string(), ToggleState::Off),
+            ("Fails".to_string(), ToggleState::On),
+            ("Errors".to_string(), ToggleState::On),
+        ]);
+        let state_manager = ToggleStateManager::new();
+        crate::button::initialize_toggle_states_from(
+            &config,
+            &state_manager,
+            &crate::probe_backend::ProbeBackendRegistry::new(),
+            &persisted,
+        )
+        .await;
+        ["Succeeds", "Fails", "Errors"].map(|name| state_manager.get_state(name))
+    }
+
+    #[tokio::test]
+    async fn test_state_source_precedence() {
+        use ToggleState::{Off, On, Unknown};
+
+        // Probes win; saved states are not read
+        assert_eq!(initialize_with_state_source("probe").await, [On, Off, Unknown]);
+        // Saved states win, without running any probe
+        assert_eq!(initialize_with_state_source("persisted").await, [Off, On, On]);
+        // Probes win, and a probe that cannot run falls back to the saved state
+        assert_eq!(initialize_with_state_source("probe_then_persisted").await, [On, Off, On]);
+    }
+
+    #[tokio::test]
+    async fn test_persisted_state_source_probes_unsaved_toggles() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            "state_source: persisted\nmenu:\n  name: \"Main\"\n  buttons:\n    - type: toggle\n      name: \"New\"\n      mode: single\n      command: \"true\"\n      probe_command: \"true\"\n",
+        )
+        .unwrap();
+        let state_manager = ToggleStateManager::new();
+        let persisted = std::collections::HashMap::from([("Removed".to_string(), ToggleState::On)]);
+        crate::button::initialize_toggle_states_from(
+            &config,
+            &state_manager,
+            &crate::probe_backend::ProbeBackendRegistry::new(),
+            &persisted,
+        )
+        .await;
+        assert_eq!(state_manager.get_state("New"), ToggleState::On);
+        // Saved states of toggles no longer in the config are not restored
+        assert_eq!(state_manager.get_state("Removed"), ToggleState::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_file_toggle_follows_flag_file() {
+        let dir = TempDir::new("file-toggle");
+        let flag = dir.join("flag");
+        let config: crate::config::Config = serde_yaml::from_str(&format!(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Do Not Disturb"
+      mode: file
+      path: "{}"
+"#,
+            flag.display()
+        ))
+        .unwrap();
+        let Button::Toggle { mode, .. } = &config.menu.buttons[0] else {
+            panic!("Expected toggle button");
+        };
+        let state_manager = ToggleStateManager::new();
+
+        // Probing at startup reads the file without running any command
+        assert_eq!(initialize_toggle_states(&config, &state_manager).await, 1);
+        assert_eq!(state_manager.get_state("Do Not Disturb"), ToggleState::Off);
+
+        let result = execute_toggle_command("Do Not Disturb", mode, None, &[], &state_manager).await;
+        assert!(result.success);
+        assert_eq!(result.new_state, ToggleState::On);
+        assert!(flag.exists());
+
+        // The file is the source of truth, even if it changes behind the deck's back
+        std::fs::remove_file(&flag).unwrap();
+        let result = execute_toggle_command("Do Not Disturb", mode, None, &[], &state_manager).await;
+        assert_eq!(result.new_state, ToggleState::On);
+        assert!(flag.exists());
+
+        let result = execute_toggle_command("Do Not Disturb", mode, None, &[], &state_manager).await;
+        assert!(result.success);
+        assert_eq!(state_manager.get_state("Do Not Disturb"), ToggleState::Off);
+        assert!(!flag.exists());
+    }
+
+    #[tokio::test]
+    async fn test_file_toggle_unreachable_path_fails() {
+        let dir = TempDir::new("file-toggle-parent");
+        let parent = dir.join("file");
+        std::fs::write(&parent, "").unwrap();
+        let mode = ToggleMode::File { path: parent.join("flag") };
+        let state_manager = ToggleStateManager::new();
+
+        let result = execute_toggle_command("Broken Flag", &mode, None, &[], &state_manager).await;
+        assert!(!result.success);
+        assert!(result.error_message.unwrap().contains("Failed to update flag file"));
+        assert_eq!(state_manager.get_state("Broken Flag"), ToggleState::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_command_press_reaches_event_socket_client() {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        let context = context_for(config);
+        let dir = TempDir::new("press");
+        let path = dir.join("events.sock");
+        let server = crate::events::serve_event_socket(&path, context.events.clone()).unwrap();
+        let client = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let mut lines = BufReader::new(client).lines();
+
+        // Publish until the accept task has subscribed the client
+        let line = loop {
+            context.run_command("Deploy", "false", &[], false, None, &[0], Default::default()).await;
+            if let Ok(line) = tokio::time::timeout(std::time::Duration::from_millis(50), lines.next_line()).await {
+                break line.unwrap().unwrap();
+            }
+        };
+        assert!(line.starts_with(r#"{"button":"Deploy","type":"command","timestamp":"#), "{}", line);
+        assert!(line.ends_with(r#","result":"failure"}"#), "{}", line);
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_command_args_file_is_read_when_pressed() {
+        let dir = TempDir::new("argsfile");
+        let args_path = dir.join("args");
+        let config: crate::config::Config = serde_yaml::from_str(&format!(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Sync"
+      command: "printf"
+      args: ["%s|"]
+      args_file: "{}"
+      store_output_as: "argv"
+"#,
+            args_path.display()
+        ))
+        .unwrap();
+        let context = context_for(config);
+
+        std::fs::write(&args_path, "# hosts
+alpha.lan
+
+beta lan
+").unwrap();
+        assert!(context.execute_button("Sync").await);
+        assert_eq!(context.runtime_vars.get("argv").as_deref(), Some("alpha.lan|beta lan|"));
+
+        // Edits apply to the next press
+        std::fs::write(&args_path, "gamma.lan
+").unwrap();
+        assert!(context.execute_button("Sync").await);
+        assert_eq!(context.runtime_vars.get("argv").as_deref(), Some("gamma.lan|"));
+
+        // Without the file the command is skipped
+        std::fs::remove_file(&args_path).unwrap();
+        let mut events = context.events.subscribe();
+        assert!(context.execute_button("Sync").await);
+        assert_eq!(context.runtime_vars.get("argv").as_deref(), Some("gamma.lan|"));
+        assert_eq!(events.try_recv().unwrap().result.as_deref(), Some("skipped"));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_toast_shows_last_output_line() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Sync"
+      command: "printf"
+      args: ["syncing\n3 files synced\n\n"]
+      toast_last_line: true
+      toast_ms: 4000
+    - type: command
+      name: "Quiet"
+      command: "true"
+      toast_last_line: true
+    - type: command
+      name: "Plain"
+      command: "echo"
+      args: ["hi"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.toast_for("Sync"), Some(std::time::Duration::from_secs(4)));
+        assert_eq!(config.toast_for("Quiet"), Some(crate::result_view::RESULT_DISPLAY_DURATION));
+        assert_eq!(config.toast_for("Plain"), None);
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(4);
+        let mut context = context_for(config);
+        context.navigation_sender = Some(sender);
+
+        // The toast is shown, then the menu comes back once its time is up
+        assert!(context.execute_button("Sync").await);
+        tokio::task::yield_now().await;
+        assert!(receiver.try_recv().is_ok());
+        // execute_button refreshes the view as well
+        while receiver.try_recv().is_ok() {}
+        tokio::time::sleep(std::time::Duration::from_millis(3900)).await;
+        assert!(receiver.try_recv().is_err());
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(receiver.try_recv().is_ok());
+
+        // Empty output and buttons without the option show nothing but the refresh
+        for name in ["Quiet", "Plain"] {
+            assert!(context.execute_button(name).await);
+            tokio::task::yield_now().await;
+            assert!(receiver.try_recv().is_ok(), "{}", name);
+            assert!(receiver.try_recv().is_err(), "{}", name);
+        }
+        assert!(!context.show_output_toast("Quiet", "\n  \n", true, crate::result_view::RESULT_DISPLAY_DURATION));
+    }
+
+    #[tokio::test]
+    async fn test_middle_pedal_runs_its_command() {
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+pedal:
+  middle: "Deploy"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Build"
+      command: "false"
+    - type: menu
+      name: "Release"
+      buttons:
+        - type: command
+          name: "Deploy"
+          command: "true"
+"#,
+        )
+        .unwrap();
+        let context = context_for(config);
+        let mut events = context.events.subscribe();
+
+        assert!(crate::pedal::dispatch_pedal_key(&context, 1).await);
+        assert!(context.last_result_manager.get("Deploy").unwrap().success);
+        assert_eq!(context.last_result_manager.get("Build"), None);
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.button.as_str(), event.result.as_deref()), ("Deploy", Some("success")));
+
+        // Unmapped keys press nothing
+        assert!(!crate::pedal::dispatch_pedal_key(&context, 0).await);
+        assert!(!crate::pedal::dispatch_pedal_key(&context, 7).await);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mock_devices_drive_menu_presses_and_brightness() {
+        use crate::deck::MockDeck;
+        use crate::view_tree::{build_root_view, KeyKind};
+        use std::collections::BTreeMap;
+        use std::sync::Arc;
+        use streamdeck_oxide::elgato_streamdeck::DeviceStateUpdate;
+        use streamdeck_oxide::plugins::PluginContext;
+
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+brightness: 60
+pedal:
+  middle: "Deploy"
+menu:
+  name: "Main"
+  buttons:
+    - type: menu
+      name: "Release"
+      buttons:
+        - type: command
+          name: "Deploy"
+          command: "true"
+        - type: brightness
+          name: "Dimmer"
+          action: step
+          value: -25
+"#,
+        )
+        .unwrap();
+        let deck = Arc::new(MockDeck::default());
+        let context = Arc::new(CommanderContext::new(Arc::new(config.clone()), Some(deck.clone())));
+        let plugin_context = PluginContext::new(BTreeMap::from([(
+            std::any::TypeId::of::<CommanderContext>(),
+            Box::new(context.clone()) as Box<dyn std::any::Any + Send + Sync>,
+        )]));
+
+        // Navigate the views the deck would show down to the pressed keys
+        let root = build_root_view(&config, plugin_context).await.unwrap();
+        assert_eq!(root.key_at(0, 0).unwrap().kind, KeyKind::Menu);
+        let release = root.submenu("Release").unwrap();
+        assert_eq!(release.key_at(0, 0).unwrap().kind, KeyKind::Command);
+        assert_eq!(release.key_at(1, 0).unwrap().kind, KeyKind::Brightness);
+
+        // The pedal presses and releases its middle key, then disconnects
+        let pedal = Arc::new(MockDeck::with_events(vec![
+            vec![DeviceStateUpdate::ButtonDown(1)],
+            vec![DeviceStateUpdate::ButtonUp(1)],
+        ]));
+        let mut events = context.events.subscribe();
+        crate::pedal::run_pedal(pedal, context.clone()).await;
+        assert!(context.last_result_manager.get("Deploy").unwrap().success);
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.button.as_str(), event.result.as_deref()), ("Deploy", Some("success")));
+        assert!(events.try_recv().is_err());
+
+        // Brightness steps start from the configured brightness and reach the deck
+        assert_eq!(context.brightness.apply(crate::config::BrightnessKind::Step, -25).await, 35);
+        assert_eq!(context.brightness.apply(crate::config::BrightnessKind::Step, -25).await, 10);
+        assert_eq!(deck.brightness_history(), vec![35, 10]);
+    }
+
+    #[tokio::test]
+    async fn test_toggle_uses_registered_probe_backend() {
+        use crate::probe::ProbeResult;
+        use crate::probe_backend::{ProbeBackend, ProbeRequest};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        /// Reports the service as running and counts how often it was asked
+        struct MockBackend(Arc<AtomicUsize>);
+
+        #[async_trait::async_trait]
+        impl ProbeBackend for MockBackend {
+            async fn probe(&self, request: &ProbeRequest<'_>) -> ProbeResult {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                ProbeResult::success(0, format!("{} is up", request.button_name), String::new())
+            }
+        }
+
+        let config: crate::config::Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: toggle
+      name: "Service"
+      mode: single
+      command: "true"
+      probe_command: "false"
+      probe_backend: "mock"
+"#,
+        )
+        .unwrap();
+        let context = context_for(config);
+        let calls = Arc::new(AtomicUsize::new(0));
+        context.probe_backends.register("mock", MockBackend(calls.clone()));
+
+        // The backend replaces the probe command, which would report Off
+        context.refresh_toggle_states().await;
+        assert_eq!(context.toggle_state_manager.get_state("Service"), ToggleState::On);
+        assert_eq!(context.toggle_state_manager.get_probe_output("Service").as_deref(), Some("Service is up"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Pressing the toggle asks the backend before and after running the command
+        assert!(context.execute_button("Service").await);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(context.toggle_state_manager.get_state("Service"), ToggleState::On);
+    }
+
+    #[tokio::test]
+    async fn test_command_outcome_records_duration() {
+        let config: crate::config::Config = serde_yaml::from_str("menu:\n  name: \"Main\"\n  buttons: []\n").unwrap();
+        let context = context_for(config);
+
+        let outcome = context.run_command("Nap", "sleep", &["0.1".to_string()], false, None, &[0], Default::default()).await;
+        assert!(outcome.success);
+        assert!(outcome.duration >= std::time::Duration::from_millis(100), "{:?}", outcome.duration);
+        assert_eq!(context.last_result_manager.get("Nap"), Some(outcome));
+    }
+
+    #[tokio::test]
+    async fn test_idle_home_navigates_to_root() {
+        use crate::button::CommanderPlugin;
+        use std::time::Duration;
+
+        let config: crate::config::Config = serde_yaml::from_str(
+            "idle_home_secs: 30\nmenu:\n  name: \"Main\"\n  buttons:\n    - type: menu\n      name: \"System\"\n      buttons: []\n",
+        )
+        .unwrap();
+        assert_eq!(config.idle_home_secs, Some(30));
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let mut context = context_for(config.clone());
+        context.navigation_sender = Some(sender);
+        let context = std::sync::Arc::new(context);
+
+        let root = CommanderPlugin::new_with_state_manager(config.menu.clone(), context.toggle_state_manager.clone());
+        let Button::Menu { name, buttons, .. } = &config.menu.buttons[0] else {
+            panic!("expected a menu button");
+        };
+        let submenu = Menu { name: name.clone(), buttons: buttons.clone(), theme: None, sort: MenuSort::None, background_image: None, dynamic: None };
+        context.set_active_plugin(CommanderPlugin::new_with_parent(submenu, root.clone()));
+
+        let home_context = context.clone();
+        let timer = tokio::spawn(crate::idle::run_idle_home(context.activity.clone(), Duration::from_millis(100), move || {
+            let home_context = home_context.clone();
+            async move {
+                home_context.navigate_home().await;
+            }
+        }));
+
+        // Presses in the submenu keep it open
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        context.activity.touch();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(receiver.try_recv().is_err());
+
+        // Once idle, the root menu is sent through the trigger channel
+        tokio::time::timeout(Duration::from_secs(2), receiver.recv()).await.unwrap().unwrap();
+
+        // The root menu is not sent again
+        context.set_active_plugin(root);
+        assert!(!context.navigate_home().await);
+        timer.abort();
+    }
+}
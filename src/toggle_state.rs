@@ -1,7 +1,14 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::sync::broadcast;
 use tracing::{debug, warn};
 
+/// Number of state changes kept for a listener that has not read them yet
+const CHANGE_BUFFER: usize = 64;
+
 /// Represents the state of a toggle button
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ToggleState {
@@ -24,22 +31,140 @@ impl ToggleState {
     pub fn is_known(self) -> bool {
         matches!(self, ToggleState::On | ToggleState::Off)
     }
+
+    /// Returns the lowercase name used in configuration files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ToggleState::On => "on",
+            ToggleState::Off => "off",
+            ToggleState::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for ToggleState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error returned when a string does not name a toggle state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseToggleStateError(String);
+
+impl fmt::Display for ParseToggleStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid toggle state '{}' (expected 'on', 'off' or 'unknown')",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseToggleStateError {}
+
+impl FromStr for ToggleState {
+    type Err = ParseToggleStateError;
+
+    /// Parses `on`, `off` or `unknown`, ignoring case and surrounding whitespace
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "on" => Ok(ToggleState::On),
+            "off" => Ok(ToggleState::Off),
+            "unknown" => Ok(ToggleState::Unknown),
+            _ => Err(ParseToggleStateError(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<&str> for ToggleState {
+    type Error = ParseToggleStateError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Serialize for ToggleState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ToggleState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A toggle moving to another state, sent to the listeners of [`ToggleStateManager::subscribe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+    pub button_name: String,
+    pub state: ToggleState,
 }
 
 /// Manages the state of all toggle buttons in the application
 #[derive(Debug)]
 pub struct ToggleStateManager {
     states: Arc<RwLock<HashMap<String, ToggleState>>>,
+    probe_outputs: Arc<RwLock<HashMap<String, String>>>,
+    pending: Arc<RwLock<HashMap<String, usize>>>, // Running transitions per toggle
+    changes: broadcast::Sender<StateChange>, // Shared by every clone
 }
 
 impl Clone for ToggleStateManager {
     fn clone(&self) -> Self {
         Self {
             states: Arc::clone(&self.states),
+            probe_outputs: Arc::clone(&self.probe_outputs),
+            pending: Arc::clone(&self.pending),
+            changes: self.changes.clone(),
         }
     }
 }
 
+/// Keeps a toggle marked as pending until dropped, see [`ToggleStateManager::begin_pending`]
+#[derive(Debug)]
+pub struct PendingGuard {
+    manager: ToggleStateManager,
+    button_name: String,
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        let mut pending = write_recovered(&self.manager.pending, "pending toggles");
+        if let Some(count) = pending.get_mut(&self.button_name) {
+            *count -= 1;
+            if *count == 0 {
+                pending.remove(&self.button_name);
+                debug!("Toggle '{}' is no longer pending", self.button_name);
+            }
+        }
+    }
+}
+
+/// Reads `lock` even if a thread panicked while holding it. The maps only hold plain
+/// values, so they stay usable; the poison is cleared so each panic warns only once.
+fn read_recovered<'a, T>(lock: &'a RwLock<T>, what: &str) -> RwLockReadGuard<'a, T> {
+    lock.read().unwrap_or_else(|poisoned| {
+        warn!("Recovering {} after a panic while they were held", what);
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Writes `lock` even if a thread panicked while holding it, see [`read_recovered`]
+fn write_recovered<'a, T>(lock: &'a RwLock<T>, what: &str) -> RwLockWriteGuard<'a, T> {
+    lock.write().unwrap_or_else(|poisoned| {
+        warn!("Recovering {} after a panic while they were held", what);
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
 impl Default for ToggleStateManager {
     fn default() -> Self {
         Self::new()
@@ -51,40 +176,79 @@ impl ToggleStateManager {
     pub fn new() -> Self {
         Self {
             states: Arc::new(RwLock::new(HashMap::new())),
+            probe_outputs: Arc::new(RwLock::new(HashMap::new())),
+            pending: Arc::new(RwLock::new(HashMap::new())),
+            changes: broadcast::channel(CHANGE_BUFFER).0,
         }
     }
 
+    /// Starts listening for toggles changing state from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<StateChange> {
+        self.changes.subscribe()
+    }
+
+    /// Tells the listeners that `button_name` moved to `state`; without listeners it is dropped
+    fn publish_change(&self, button_name: &str, state: ToggleState) {
+        let _ = self.changes.send(StateChange {
+            button_name: button_name.to_string(),
+            state,
+        });
+    }
+
     /// Gets the current state of a toggle button
     pub fn get_state(&self, button_name: &str) -> ToggleState {
-        match self.states.read() {
-            Ok(states) => {
-                let state = states.get(button_name).copied().unwrap_or(ToggleState::Unknown);
-                debug!("Retrieved state for '{}': {:?}", button_name, state);
-                state
-            }
-            Err(e) => {
-                warn!("Failed to read toggle state for '{}': {}", button_name, e);
-                ToggleState::Unknown
-            }
-        }
+        let state = read_recovered(&self.states, "toggle states")
+            .get(button_name)
+            .copied()
+            .unwrap_or(ToggleState::Unknown);
+        debug!("Retrieved state for '{}': {:?}", button_name, state);
+        state
     }
 
     /// Sets the state of a toggle button
     pub fn set_state(&self, button_name: &str, state: ToggleState) {
-        match self.states.write() {
-            Ok(mut states) => {
-                let previous = states.insert(button_name.to_string(), state);
-                debug!(
-                    "Set state for '{}': {:?} -> {:?}",
-                    button_name, previous.unwrap_or(ToggleState::Unknown), state
-                );
-            }
-            Err(e) => {
-                warn!("Failed to set toggle state for '{}': {}", button_name, e);
-            }
+        let previous = write_recovered(&self.states, "toggle states").insert(button_name.to_string(), state);
+        let previous = previous.unwrap_or(ToggleState::Unknown);
+        debug!("Set state for '{}': {:?} -> {:?}", button_name, previous, state);
+        if previous != state {
+            self.publish_change(button_name, state);
         }
     }
 
+    /// Gets the trimmed stdout of the button's last probe, if it printed anything
+    pub fn get_probe_output(&self, button_name: &str) -> Option<String> {
+        read_recovered(&self.probe_outputs, "probe outputs").get(button_name).cloned()
+    }
+
+    /// Records the stdout of the button's latest probe and returns true if it differs
+    /// from the previous one
+    pub fn set_probe_output(&self, button_name: &str, stdout: &str) -> bool {
+        let output = stdout.trim();
+        let mut outputs = write_recovered(&self.probe_outputs, "probe outputs");
+        let previous = if output.is_empty() {
+            outputs.remove(button_name)
+        } else {
+            outputs.insert(button_name.to_string(), output.to_string())
+        };
+        previous.as_deref().unwrap_or("") != output
+    }
+
+    /// Marks the toggle as pending while a transition runs. The toggle stays pending
+    /// until every returned guard is dropped; its state is left untouched.
+    pub fn begin_pending(&self, button_name: &str) -> PendingGuard {
+        *write_recovered(&self.pending, "pending toggles").entry(button_name.to_string()).or_default() += 1;
+        debug!("Toggle '{}' is pending", button_name);
+        PendingGuard {
+            manager: self.clone(),
+            button_name: button_name.to_string(),
+        }
+    }
+
+    /// Returns true while a transition of the toggle is running
+    pub fn is_pending(&self, button_name: &str) -> bool {
+        read_recovered(&self.pending, "pending toggles").contains_key(button_name)
+    }
+
     /// Toggles the state of a button and returns the new state
     pub fn toggle_state(&self, button_name: &str) -> ToggleState {
         let current_state = self.get_state(button_name);
@@ -105,35 +269,40 @@ impl ToggleStateManager {
 
     /// Clears all states (useful for resetting)
     pub fn clear_all(&self) {
-        match self.states.write() {
-            Ok(mut states) => {
-                let count = states.len();
-                states.clear();
-                debug!("Cleared {} toggle states", count);
-            }
-            Err(e) => {
-                warn!("Failed to clear toggle states: {}", e);
+        let cleared: Vec<_> = write_recovered(&self.states, "toggle states").drain().collect();
+        debug!("Cleared {} toggle states", cleared.len());
+        write_recovered(&self.probe_outputs, "probe outputs").clear();
+        for (button_name, state) in cleared {
+            if state.is_known() {
+                self.publish_change(&button_name, ToggleState::Unknown);
             }
         }
     }
 
     /// Gets all current states (for debugging/monitoring)
     pub fn get_all_states(&self) -> HashMap<String, ToggleState> {
-        match self.states.read() {
-            Ok(states) => states.clone(),
-            Err(e) => {
-                warn!("Failed to read all toggle states: {}", e);
-                HashMap::new()
-            }
+        read_recovered(&self.states, "toggle states").clone()
+    }
+
+    /// Captures the known states of all toggles, e.g. to restore them later as a scene.
+    /// Toggles in the Unknown state are left out.
+    pub fn snapshot(&self) -> HashMap<String, ToggleState> {
+        let mut states = self.get_all_states();
+        states.retain(|_, state| state.is_known());
+        states
+    }
+
+    /// Sets every state in `snapshot` without running any command; toggles missing from
+    /// it keep their current state
+    pub fn restore(&self, snapshot: &HashMap<String, ToggleState>) {
+        for (button_name, state) in snapshot {
+            self.set_state(button_name, *state);
         }
     }
 
     /// Returns the number of buttons being tracked
     pub fn button_count(&self) -> usize {
-        match self.states.read() {
-            Ok(states) => states.len(),
-            Err(_) => 0,
-        }
+        read_recovered(&self.states, "toggle states").len()
     }
 }
 
@@ -155,6 +324,58 @@ mod tests {
         assert!(!ToggleState::Unknown.is_known());
     }
 
+    #[test]
+    fn test_toggle_state_from_str() {
+        assert_eq!("on".parse::<ToggleState>(), Ok(ToggleState::On));
+        assert_eq!("OFF".parse::<ToggleState>(), Ok(ToggleState::Off));
+        assert_eq!(" Unknown ".parse::<ToggleState>(), Ok(ToggleState::Unknown));
+        assert_eq!(ToggleState::try_from("On"), Ok(ToggleState::On));
+
+        assert!("enabled".parse::<ToggleState>().is_err());
+        assert!(ToggleState::try_from("").is_err());
+    }
+
+    #[test]
+    fn test_toggle_state_display_round_trip() {
+        for state in [ToggleState::On, ToggleState::Off, ToggleState::Unknown] {
+            assert_eq!(state.to_string().parse::<ToggleState>(), Ok(state));
+        }
+    }
+
+    #[test]
+    fn test_pending_guard_lifecycle() {
+        let manager = ToggleStateManager::new();
+        manager.set_state("vpn", ToggleState::Off);
+        assert!(!manager.is_pending("vpn"));
+
+        let outer = manager.begin_pending("vpn");
+        let inner = manager.clone().begin_pending("vpn");
+        assert!(manager.is_pending("vpn"));
+        assert!(!manager.is_pending("wifi"));
+        // Pending is shown on top of the state, which keeps its value
+        assert_eq!(manager.get_state("vpn"), ToggleState::Off);
+
+        drop(inner);
+        assert!(manager.is_pending("vpn"));
+        drop(outer);
+        assert!(!manager.is_pending("vpn"));
+    }
+
+    #[test]
+    fn test_toggle_state_manager_probe_output() {
+        let manager = ToggleStateManager::new();
+        assert_eq!(manager.get_probe_output("power"), None);
+
+        assert!(manager.set_probe_output("power", "performance\n"));
+        assert_eq!(manager.get_probe_output("power"), Some("performance".to_string()));
+        assert!(!manager.clone().set_probe_output("power", "  performance "));
+
+        // Empty output clears the stored value
+        assert!(manager.set_probe_output("power", "\n"));
+        assert_eq!(manager.get_probe_output("power"), None);
+        assert!(!manager.set_probe_output("power", ""));
+    }
+
     #[test]
     fn test_toggle_state_manager_basic() {
         let manager = ToggleStateManager::new();
@@ -231,4 +452,48 @@ mod tests {
         manager2.set_state("test", ToggleState::Off);
         assert_eq!(manager1.get_state("test"), ToggleState::Off);
     }
+
+    #[test]
+    fn test_toggle_state_manager_broadcasts_changes() {
+        let manager = ToggleStateManager::new();
+        let mut changes = manager.clone().subscribe();
+        manager.set_state("Wifi", ToggleState::On);
+        // Setting the state it is already in is not a change
+        manager.set_state("Wifi", ToggleState::On);
+        manager.toggle_state("Wifi");
+        manager.clear_all();
+
+        let received: Vec<_> = std::iter::from_fn(|| changes.try_recv().ok()).map(|change| change.state).collect();
+        assert_eq!(received, [ToggleState::On, ToggleState::Off, ToggleState::Unknown]);
+    }
+
+    #[test]
+    fn test_toggle_state_manager_recovers_from_poisoned_lock() {
+        let manager = ToggleStateManager::new();
+        manager.set_state("Lights", ToggleState::On);
+        manager.set_probe_output("Lights", "42%");
+
+        let poisoner = manager.clone();
+        let panicked = std::thread::spawn(move || {
+            let _states = poisoner.states.write().unwrap();
+            let _outputs = poisoner.probe_outputs.write().unwrap();
+            panic!("panic while holding the toggle states");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(manager.states.is_poisoned());
+
+        // The states written before the panic are still there and can be changed
+        assert_eq!(manager.get_state("Lights"), ToggleState::On);
+        assert!(!manager.states.is_poisoned());
+        manager.set_state("Lights", ToggleState::Off);
+        manager.set_state("Fan", ToggleState::On);
+        assert_eq!(manager.get_state("Lights"), ToggleState::Off);
+        assert_eq!(manager.button_count(), 2);
+        assert_eq!(manager.get_probe_output("Lights").as_deref(), Some("42%"));
+        let pending = manager.begin_pending("Fan");
+        assert!(manager.is_pending("Fan"));
+        drop(pending);
+        assert!(!manager.is_pending("Fan"));
+    }
 }
\ No newline at end of file
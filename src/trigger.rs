@@ -0,0 +1,104 @@
+use crate::config::TriggerOn;
+use tracing::debug;
+
+/// A raw key transition reported by a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Down(u8),
+    Up(u8),
+}
+
+/// Decides which key presses fire their action, given whether each key triggers on
+/// press or on release. A release-triggered key only fires if it is released without
+/// any other key going down or up in between, so sliding off the key aborts it.
+#[derive(Debug, Default)]
+pub struct KeyTrigger {
+    pressed: Option<u8>,
+}
+
+impl KeyTrigger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds one key event and returns the key whose action fires now, if any
+    pub fn handle(&mut self, event: KeyEvent, trigger_on: impl Fn(u8) -> TriggerOn) -> Option<u8> {
+        match event {
+            KeyEvent::Down(key) => {
+                if let Some(previous) = self.pressed.replace(key) {
+                    debug!("Key {} went down while key {} was held, aborting it", key, previous);
+                }
+                match trigger_on(key) {
+                    TriggerOn::Press => Some(key),
+                    TriggerOn::Release => None,
+                }
+            }
+            KeyEvent::Up(key) => match self.pressed.take() {
+                Some(pressed) if pressed == key => match trigger_on(key) {
+                    TriggerOn::Press => None,
+                    TriggerOn::Release => Some(key),
+                },
+                Some(pressed) => {
+                    debug!("Key {} released while key {} was held, aborting it", key, pressed);
+                    None
+                }
+                None => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fired(trigger_on: TriggerOn, events: &[KeyEvent]) -> Vec<u8> {
+        let mut trigger = KeyTrigger::new();
+        events
+            .iter()
+            .filter_map(|event| trigger.handle(*event, |_| trigger_on))
+            .collect()
+    }
+
+    #[test]
+    fn test_press_fires_on_key_down() {
+        let mut trigger = KeyTrigger::new();
+        assert_eq!(trigger.handle(KeyEvent::Down(3), |_| TriggerOn::Press), Some(3));
+        assert_eq!(trigger.handle(KeyEvent::Up(3), |_| TriggerOn::Press), None);
+
+        // Pressing is never aborted
+        let events = [KeyEvent::Down(1), KeyEvent::Up(2), KeyEvent::Up(1)];
+        assert_eq!(fired(TriggerOn::Press, &events), vec![1]);
+    }
+
+    #[test]
+    fn test_release_fires_on_key_up() {
+        let mut trigger = KeyTrigger::new();
+        assert_eq!(trigger.handle(KeyEvent::Down(3), |_| TriggerOn::Release), None);
+        assert_eq!(trigger.handle(KeyEvent::Up(3), |_| TriggerOn::Release), Some(3));
+
+        let events = [KeyEvent::Down(1), KeyEvent::Up(1), KeyEvent::Down(2), KeyEvent::Up(2)];
+        assert_eq!(fired(TriggerOn::Release, &events), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_release_aborted_by_release_elsewhere() {
+        // Down on one key, up on another: nothing fires, not even the late release
+        let events = [KeyEvent::Down(1), KeyEvent::Up(2), KeyEvent::Up(1)];
+        assert!(fired(TriggerOn::Release, &events).is_empty());
+
+        // A second key going down takes over from the first
+        let events = [KeyEvent::Down(1), KeyEvent::Down(2), KeyEvent::Up(2), KeyEvent::Up(1)];
+        assert_eq!(fired(TriggerOn::Release, &events), vec![2]);
+    }
+
+    #[test]
+    fn test_mixed_triggers_per_key() {
+        let trigger_on = |key: u8| if key == 0 { TriggerOn::Release } else { TriggerOn::Press };
+        let mut trigger = KeyTrigger::new();
+        assert_eq!(trigger.handle(KeyEvent::Down(1), trigger_on), Some(1));
+        assert_eq!(trigger.handle(KeyEvent::Up(1), trigger_on), None);
+        assert_eq!(trigger.handle(KeyEvent::Down(0), trigger_on), None);
+        assert_eq!(trigger.handle(KeyEvent::Up(0), trigger_on), Some(0));
+    }
+}
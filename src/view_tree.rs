@@ -0,0 +1,444 @@
+use crate::button::{menu_layout, BackButtonStyle, CommanderContext, CommanderPlugin, BACK_BUTTON_POSITION};
+use crate::config::{Button, Config, ThemeName};
+use crate::error::CommanderError;
+use crate::toggle_icons::get_simple_display_name_localized;
+use streamdeck_oxide::plugins::PluginContext;
+
+/// What a key on the deck does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Command,
+    Menu,
+    Toggle,
+    Reload,
+    Brightness,
+    Notification,
+    GroupAction,
+    Scene,
+    Match,
+    Branch,
+    Input,
+    Back,
+}
+
+/// A key placed on a menu's view
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyNode {
+    pub col: usize,
+    pub row: usize,
+    pub label: String,
+    pub kind: KeyKind,
+}
+
+/// Layout of one menu's view and of every submenu reachable from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViewNode {
+    pub menu: String,
+    pub theme: ThemeName,
+    pub keys: Vec<KeyNode>,
+    pub submenus: Vec<ViewNode>,
+}
+
+impl ViewNode {
+    /// The key at the given position, if one is placed there
+    pub fn key_at(&self, col: usize, row: usize) -> Option<&KeyNode> {
+        self.keys.iter().find(|key| key.col == col && key.row == row)
+    }
+
+    /// The view of the named submenu
+    pub fn submenu(&self, name: &str) -> Option<&ViewNode> {
+        self.submenus.iter().find(|submenu| submenu.menu == name)
+    }
+}
+
+/// Builds the views of the root menu and all submenus without a device, returning the
+/// layout of each level. Every level goes through the same view builder the deck uses.
+pub async fn build_root_view(config: &Config, context: PluginContext) -> Result<ViewNode, CommanderError> {
+    let commander_ctx = context.get_context::<CommanderContext>().await;
+    let state_manager = commander_ctx
+        .as_ref()
+        .map(|ctx| ctx.toggle_state_manager.clone())
+        .unwrap_or_default();
+
+    let root = CommanderPlugin::new_with_state_manager(config.menu.clone(), state_manager);
+    build_view_node(&root, commander_ctx.as_deref())
+}
+
+fn build_view_node(
+    plugin: &CommanderPlugin,
+    commander_ctx: Option<&CommanderContext>,
+) -> Result<ViewNode, CommanderError> {
+    plugin.create_view_from_menu(commander_ctx)?;
+    let locale = commander_ctx.and_then(|ctx| ctx.current_config().locale.clone());
+
+    let mut keys = Vec::new();
+    let mut submenus = Vec::new();
+    let menu = plugin.shown_menu(commander_ctx);
+    for (col, row, button) in menu_layout(&menu) {
+        let kind = match button {
+            Button::Command { .. } => KeyKind::Command,
+            Button::Menu { .. } => KeyKind::Menu,
+            Button::Toggle { .. } => KeyKind::Toggle,
+            Button::Reload { .. } => KeyKind::Reload,
+            Button::Brightness { .. } => KeyKind::Brightness,
+            Button::Notification { .. } => KeyKind::Notification,
+            Button::GroupAction { .. } => KeyKind::GroupAction,
+            Button::Scene { .. } => KeyKind::Scene,
+            Button::Match { .. } => KeyKind::Match,
+            Button::Branch { .. } => KeyKind::Branch,
+            Button::Input { .. } => KeyKind::Input,
+            // User-defined back buttons are replaced by the automatic one
+            Button::Back { .. } => continue,
+        };
+        keys.push(KeyNode {
+            col,
+            row,
+            label: get_simple_display_name_localized(button, locale.as_deref()).to_string(),
+            kind,
+        });
+
+        if let Some(submenu) = button.submenu() {
+            let child = CommanderPlugin::new_with_parent(submenu, plugin.clone());
+            submenus.push(build_view_node(&child, commander_ctx)?);
+        }
+        if let Button::Branch { on_success, on_failure, .. } = button {
+            for menu in [on_success, on_failure] {
+                let child = CommanderPlugin::new_with_parent(menu.clone(), plugin.clone());
+                submenus.push(build_view_node(&child, commander_ctx)?);
+            }
+        }
+    }
+
+    if plugin.parent().is_some() {
+        let (col, row) = BACK_BUTTON_POSITION;
+        let back_style = commander_ctx
+            .map(|ctx| BackButtonStyle::from_config(&ctx.current_config()))
+            .unwrap_or_default();
+        keys.push(KeyNode {
+            col,
+            row,
+            label: back_style.label,
+            kind: KeyKind::Back,
+        });
+    }
+
+    let theme = match commander_ctx {
+        Some(ctx) => ctx.theme_for(plugin),
+        None => plugin.theme(ThemeName::default()),
+    };
+    Ok(ViewNode {
+        menu: plugin.menu().name.clone(),
+        theme,
+        keys,
+        submenus,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::DECK_GRID;
+    use crate::history::recent_buttons;
+    use crate::last_result::LastResultManager;
+    use crate::runtime_vars::RuntimeVars;
+    use crate::toggle_state::ToggleStateManager;
+    use std::any::{Any, TypeId};
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, RwLock};
+
+    fn multi_level_config() -> Config {
+        serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Terminal"
+      command: "true"
+    - type: toggle
+      name: "WiFi"
+      mode: single
+      command: "true"
+    - type: menu
+      name: "System"
+      buttons:
+        - type: command
+          name: "Top"
+          command: "true"
+        - type: back
+        - type: menu
+          name: "Power"
+          buttons:
+            - type: command
+              name: "Suspend"
+              command: "true"
+    - type: reload
+"#,
+        )
+        .unwrap()
+    }
+
+    fn context_for(config: &Config) -> PluginContext {
+        let commander_ctx = CommanderContext {
+            config: RwLock::new(Arc::new(config.clone())),
+            config_path: None,
+            toggle_state_manager: ToggleStateManager::new(),
+            last_result_manager: LastResultManager::new(),
+            navigation_sender: None,
+            active_plugin: RwLock::new(None),
+            runtime_vars: RuntimeVars::new(),
+            events: crate::events::EventBus::new(),
+            probe_backends: crate::probe_backend::ProbeBackendRegistry::new(),
+            activity: crate::idle::ActivityTracker::new(),
+            delayed_commands: crate::delay::DelayedCommands::new(),
+            command_queues: crate::queue::CommandQueues::new(),
+            button_locks: crate::locks::ButtonLocks::new(),
+            execution_history: crate::history::ExecutionHistory::new(),
+            cooldowns: crate::cooldown::Cooldowns::new(),
+            notifier: std::sync::Arc::new(crate::notification::DesktopNotifier),
+            brightness: crate::device::BrightnessControl::new(None, crate::device::MAX_BRIGHTNESS),
+            backgrounds: crate::background::MenuBackgrounds::default(),
+        };
+        PluginContext::new(BTreeMap::from([(
+            TypeId::of::<CommanderContext>(),
+            Box::new(Arc::new(commander_ctx)) as Box<dyn Any + Send + Sync>,
+        )]))
+    }
+
+    #[tokio::test]
+    async fn test_build_root_view_recurses_submenus() {
+        let config = multi_level_config();
+        let root = build_root_view(&config, context_for(&config)).await.unwrap();
+
+        assert_eq!(root.menu, "Main");
+        assert_eq!(root.keys.len(), 4);
+        assert_eq!(root.key_at(1, 0).unwrap().kind, KeyKind::Toggle);
+        assert_eq!(root.key_at(3, 0).unwrap().label, "Reload");
+        // The root menu has no back button
+        assert_eq!(root.key_at(4, 2), None);
+
+        let system = root.submenu("System").unwrap();
+        assert_eq!(system.keys.len(), 3);
+        assert_eq!(system.key_at(0, 0).unwrap().label, "Top");
+        // The user-defined back button keeps its slot but is not rendered
+        assert_eq!(system.key_at(1, 0), None);
+        assert_eq!(system.key_at(2, 0).unwrap().kind, KeyKind::Menu);
+        assert_eq!(system.key_at(4, 2).unwrap().kind, KeyKind::Back);
+
+        let power = system.submenu("Power").unwrap();
+        assert_eq!(power.keys.len(), 2);
+        assert_eq!(power.key_at(0, 0).unwrap().label, "Suspend");
+        assert!(power.submenus.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_back_button_uses_configured_label_and_icon() {
+        let mut config = multi_level_config();
+        assert_eq!(BackButtonStyle::from_config(&config), BackButtonStyle::default());
+        let root = build_root_view(&config, context_for(&config)).await.unwrap();
+        assert_eq!(root.submenu("System").unwrap().key_at(4, 2).unwrap().label, "Back");
+
+        config.back_label = "Wstecz".to_string();
+        config.back_icon = "home".to_string();
+        let style = BackButtonStyle::from_config(&config);
+        assert_eq!(style.icon, "home");
+        assert!(crate::icons::resolve_icon(Some(&style.icon)).is_some());
+
+        let root = build_root_view(&config, context_for(&config)).await.unwrap();
+        let system = root.submenu("System").unwrap();
+        assert_eq!(system.key_at(4, 2).unwrap().label, "Wstecz");
+        assert_eq!(system.submenu("Power").unwrap().key_at(4, 2).unwrap().label, "Wstecz");
+    }
+
+    #[test]
+    fn test_back_button_highlight() {
+        let mut config = multi_level_config();
+        let layout = crate::icon_layout::IconLayout::default();
+        let plain = BackButtonStyle::from_config(&config);
+        assert_eq!(plain.highlight, None);
+        assert!(!plain.render_icon(&layout).unwrap_or_default().contains("stroke="));
+
+        config.back_highlight = Some("#ff9800".to_string());
+        assert!(config.validate().is_ok());
+        let style = BackButtonStyle::from_config(&config);
+        let icon = style.render_icon(&layout).unwrap();
+        assert!(icon.contains("stroke=\"#ff9800\""), "{}", icon);
+
+        // A text-only back button still gets its border
+        config.back_icon = crate::icons::NO_ICON.to_string();
+        let icon = BackButtonStyle::from_config(&config).render_icon(&layout).unwrap();
+        assert!(icon.contains("stroke=\"#ff9800\""), "{}", icon);
+
+        config.back_highlight = Some("orange\" onload=\"x".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().starts_with("back_highlight must be a color"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_submenu_theme_override() {
+        let mut config = multi_level_config();
+        config.render.theme = ThemeName::Light;
+        if let Button::Menu { theme, .. } = &mut config.menu.buttons[2] {
+            *theme = Some(ThemeName::Dark);
+        }
+
+        let root = build_root_view(&config, context_for(&config)).await.unwrap();
+        assert_eq!(root.theme, ThemeName::Light);
+        let system = root.submenu("System").unwrap();
+        assert_eq!(system.theme, ThemeName::Dark);
+        // Nested submenus inherit the override
+        assert_eq!(system.submenu("Power").unwrap().theme, ThemeName::Dark);
+
+        let parsed: Config = serde_yaml::from_str(
+            "menu:\n  name: \"Main\"\n  theme: dark\n  buttons:\n    - type: menu\n      name: \"Danger Zone\"\n      theme: light\n      buttons: []\n",
+        )
+        .unwrap();
+        let root = build_root_view(&parsed, context_for(&parsed)).await.unwrap();
+        assert_eq!(root.theme, ThemeName::Dark);
+        assert_eq!(root.submenu("Danger Zone").unwrap().theme, ThemeName::Light);
+    }
+
+    #[tokio::test]
+    async fn test_theme_when_toggle_state() {
+        let mut config = multi_level_config();
+        if let Button::Menu { theme, .. } = &mut config.menu.buttons[2] {
+            *theme = Some(ThemeName::Light);
+        }
+        config.theme_when = vec![crate::config::ThemeRule {
+            toggle: "WiFi".to_string(),
+            state: crate::toggle_state::ToggleState::On,
+            theme: ThemeName::Dark,
+        }];
+        config.validate().unwrap();
+        let context = context_for(&config);
+        let root = build_root_view(&config, context.clone()).await.unwrap();
+        assert_eq!(root.theme, ThemeName::Light);
+
+        // The whole deck, submenus with their own theme included, follows the toggle
+        let commander_ctx = context.get_context::<CommanderContext>().await.unwrap();
+        commander_ctx.toggle_state_manager.set_state("WiFi", crate::toggle_state::ToggleState::On);
+        let root = build_root_view(&config, context).await.unwrap();
+        assert_eq!(root.theme, ThemeName::Dark);
+        assert_eq!(root.submenu("System").unwrap().theme, ThemeName::Dark);
+
+        config.theme_when[0].toggle = "VPN".to_string();
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.to_string(), "theme_when references unknown toggle 'VPN'");
+    }
+
+    #[tokio::test]
+    async fn test_recent_menu_lists_latest_distinct_runs() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  buttons:
+    - type: command
+      name: "Build"
+      command: "true"
+      icon: "build"
+      retries: 2
+    - type: menu
+      name: "Recent"
+      dynamic: recent
+"#,
+        )
+        .unwrap();
+        let context = context_for(&config);
+        let root = build_root_view(&config, context.clone()).await.unwrap();
+        assert!(root.submenu("Recent").unwrap().keys.iter().all(|key| key.kind == KeyKind::Back));
+
+        let commander_ctx = context.get_context::<CommanderContext>().await.unwrap();
+        for (name, args) in [("Build", "1"), ("Lint", "2"), ("Test", "3"), ("Build", "4")] {
+            commander_ctx.run_command(name, "true", &[args.to_string()], false, None, &[0], Default::default()).await;
+        }
+        let root = build_root_view(&config, context).await.unwrap();
+        let recent = root.submenu("Recent").unwrap();
+        let labels: Vec<_> = recent.keys.iter().filter(|key| key.kind == KeyKind::Command).map(|key| key.label.as_str()).collect();
+        assert_eq!(labels, ["Build", "Test", "Lint"]);
+
+        // Re-runs of configured buttons keep their settings, with the args of the run
+        let buttons = recent_buttons(&commander_ctx.execution_history, &config, DECK_GRID.menu_button_slots());
+        match &buttons[0] {
+            Button::Command { args, icon, retries, .. } => {
+                assert_eq!(args, &["4"]);
+                assert_eq!(icon.as_deref(), Some("build"));
+                assert_eq!(*retries, 2);
+            }
+            other => panic!("expected a command button, got {:?}", other),
+        }
+
+        // The menu never holds more buttons than fit next to the back button
+        for index in 0..20 {
+            commander_ctx.execution_history.record(crate::history::Execution {
+                name: format!("Run {}", index),
+                command: "true".to_string(),
+                args: Vec::new(),
+                no_prefix: false,
+            });
+        }
+        let buttons = recent_buttons(&commander_ctx.execution_history, &config, DECK_GRID.menu_button_slots());
+        assert_eq!(buttons.len(), DECK_GRID.menu_button_slots());
+        assert_eq!(buttons[0].name(), "Run 19");
+    }
+
+    #[tokio::test]
+    async fn test_sorted_menu_layout() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+menu:
+  name: "Main"
+  sort: name
+  buttons:
+    - type: command
+      name: "terminal"
+      command: "true"
+    - type: back
+    - type: command
+      name: "Browser"
+      command: "true"
+    - type: menu
+      name: "Admin"
+      sort_key: "zzz"
+      buttons:
+        - type: command
+          name: "Zsh"
+          command: "true"
+        - type: command
+          name: "Bash"
+          command: "true"
+    - type: toggle
+      name: "WiFi"
+      sort_key: "0"
+      mode: single
+      command: "true"
+"#,
+        )
+        .unwrap();
+        let root = build_root_view(&config, context_for(&config)).await.unwrap();
+        let labels: Vec<_> = (0..4).map(|col| root.key_at(col, 0).unwrap().label.as_str()).collect();
+        // Names ignore case, sort keys replace the name and the back button stays last
+        assert_eq!(labels, ["WiFi", "Browser", "terminal", "Admin"]);
+        assert_eq!(root.key_at(4, 0), None);
+
+        // Submenus without `sort` keep their config order
+        let admin = root.submenu("Admin").unwrap();
+        assert_eq!(admin.key_at(0, 0).unwrap().label, "Zsh");
+        assert_eq!(admin.key_at(1, 0).unwrap().label, "Bash");
+    }
+
+    #[tokio::test]
+    async fn test_build_root_view_limits_keys_without_context() {
+        let buttons = (0..20)
+            .map(|i| format!("    - type: command\n      name: \"Cmd {}\"\n      command: \"true\"\n", i))
+            .collect::<String>();
+        let config: Config =
+            serde_yaml::from_str(&format!("menu:\n  name: \"Main\"\n  buttons:\n{}", buttons)).unwrap();
+
+        let root = build_root_view(&config, PluginContext::new(BTreeMap::new())).await.unwrap();
+        // The last key stays reserved for the back button
+        assert_eq!(root.keys.len(), 14);
+        assert_eq!(root.key_at(3, 2).unwrap().label, "Cmd 13");
+        assert_eq!(root.key_at(4, 2), None);
+    }
+}